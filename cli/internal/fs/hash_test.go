@@ -3,6 +3,9 @@ package fs
 import (
 	"testing"
 
+	"github.com/vercel/turbo/cli/internal/env"
+	"github.com/vercel/turbo/cli/internal/turbopath"
+	"github.com/vercel/turbo/cli/internal/util"
 	"gotest.tools/v3/assert"
 )
 
@@ -51,3 +54,101 @@ func Test_HashObjectStability(t *testing.T) {
 		}
 	}
 }
+
+// The inputs and hashes below are shared with test_global_hash in
+// crates/turborepo-lib/src/run/global_hash.rs. Both implementations have to
+// agree for cache entries to be shared.
+func Test_HashGlobalGolden(t *testing.T) {
+	testCases := []struct {
+		name           string
+		passThroughEnv []string
+		envMode        util.EnvMode
+		expected       string
+	}{
+		{
+			name:     "infer",
+			envMode:  util.Infer,
+			expected: "51608f07a0a97cd6",
+		},
+		{
+			name:           "strict",
+			passThroughEnv: []string{},
+			envMode:        util.Strict,
+			expected:       "2cd3c15f22a1535b",
+		},
+		{
+			name:     "loose",
+			envMode:  util.Loose,
+			expected: "29ae6014414527bf",
+		},
+	}
+
+	for _, tc := range testCases {
+		hash, err := HashGlobal(GlobalHashable{
+			GlobalCacheKey: "You don't understand! I coulda had class. I coulda been a contender. I could've been somebody, instead of a bum, which is what I am.",
+			GlobalFileHashMap: map[turbopath.AnchoredUnixPath]string{
+				"package.json": "2b4a2b6c5f4a37c4e4c9a8e1ea7d7cd5c4a2d1f5",
+			},
+			RootExternalDepsHash: "",
+			Env:                  []string{"API_URL"},
+			ResolvedEnvVars:      env.EnvironmentVariablePairs{"API_URL=https://example.com"},
+			PassThroughEnv:       tc.passThroughEnv,
+			EnvMode:              tc.envMode,
+			FrameworkInference:   true,
+			DotEnv:               nil,
+		})
+		assert.NilError(t, err, tc.name)
+		assert.Equal(t, hash, tc.expected, tc.name)
+	}
+}
+
+// The inputs and hashes below are shared with test_task_hash in
+// crates/turborepo-lib/src/run/task_hash.rs
+func Test_HashTaskGolden(t *testing.T) {
+	testCases := []struct {
+		name           string
+		passThroughEnv []string
+		envMode        util.EnvMode
+		expected       string
+	}{
+		{
+			name:           "strict with pass through env",
+			passThroughEnv: []string{"AWS_SECRET_KEY"},
+			envMode:        util.Strict,
+			expected:       "83616b1d59d9fd94",
+		},
+		{
+			name:     "loose",
+			envMode:  util.Loose,
+			expected: "e3d6b6aacdd40a80",
+		},
+		{
+			name:     "strict without pass through env",
+			envMode:  util.Strict,
+			expected: "74440c073b56a988",
+		},
+	}
+
+	for _, tc := range testCases {
+		hash, err := HashTask(&TaskHashable{
+			GlobalHash:           "a5c1f2b8e5c0f8d4",
+			TaskDependencyHashes: []string{},
+			PackageDir:           "apps/web",
+			HashOfFiles:          "7f4a5c4c2f6d8e1b",
+			ExternalDepsHash:     "",
+			Task:                 "build",
+			Outputs: TaskOutputs{
+				Inclusions: []string{".next/**", ".turbo/turbo-build.log"},
+				Exclusions: []string{".next/cache/**"},
+			},
+			PassThruArgs:    []string{"--watch"},
+			Env:             []string{"API_URL", "NEXT_PUBLIC_*"},
+			ResolvedEnvVars: env.EnvironmentVariablePairs{"API_URL=https://example.com", "NEXT_PUBLIC_ID=1"},
+			PassThroughEnv:  tc.passThroughEnv,
+			EnvMode:         tc.envMode,
+			DotEnv:          turbopath.AnchoredUnixPathArray{".env.local", ".env"},
+		})
+		assert.NilError(t, err, tc.name)
+		assert.Equal(t, hash, tc.expected, tc.name)
+	}
+}
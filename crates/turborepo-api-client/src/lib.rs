@@ -132,6 +132,13 @@ pub struct PreflightResponse {
     allow_authorization_header: bool,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct APIAuth {
+    pub team_id: String,
+    pub token: String,
+    pub team_slug: Option<String>,
+}
+
 pub struct APIClient {
     client: reqwest::Client,
     base_url: String,
@@ -448,6 +455,10 @@ impl APIClient {
         })
     }
 
+    pub fn use_preflight(&self) -> bool {
+        self.use_preflight
+    }
+
     fn make_url(&self, endpoint: &str) -> String {
         format!("{}{}", self.base_url, endpoint)
     }
@@ -0,0 +1,88 @@
+use std::{collections::HashSet, path::Path};
+
+use axum::{
+    extract::State,
+    http::{header::AUTHORIZATION, Method, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{AppState, Error};
+
+/// The bearer tokens that are allowed to use the cache
+#[derive(Debug, Clone)]
+pub struct Tokens(HashSet<String>);
+
+impl Tokens {
+    /// Reads tokens from a file with one token per line. Blank lines and lines
+    /// starting with `#` are skipped.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, Error> {
+        let tokens = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect::<HashSet<_>>();
+
+        // A server without tokens would reject every request
+        if tokens.is_empty() {
+            return Err(Error::NoTokens);
+        }
+        Ok(Self(tokens))
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        self.0.contains(token)
+    }
+}
+
+// Rejects requests without a valid `Authorization: Bearer <token>` header.
+// Preflight requests are let through since the client only uses them to learn
+// where to send the real request.
+pub(crate) async fn require_token<B>(
+    State(state): State<AppState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, Error> {
+    if request.method() == Method::OPTIONS {
+        return Ok(next.run(request).await);
+    }
+
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match token {
+        Some(token) if state.tokens.contains(token) => Ok(next.run(request).await),
+        _ => Err(Error::Unauthorized),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_tokens() {
+        let tokens = Tokens::parse("# ci\nci-token\n\n  dev-token  \n").unwrap();
+
+        assert!(tokens.contains("ci-token"));
+        assert!(tokens.contains("dev-token"));
+        assert!(!tokens.contains("# ci"));
+        assert!(!tokens.contains(""));
+    }
+
+    #[test]
+    fn test_parse_no_tokens() {
+        assert!(matches!(
+            Tokens::parse("# nothing here\n"),
+            Err(Error::NoTokens)
+        ));
+    }
+}
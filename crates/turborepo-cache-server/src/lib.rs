@@ -0,0 +1,450 @@
+//! A self-hosted remote cache for turbo. It speaks the same `/v8/artifacts`
+//! protocol as the Vercel remote cache, so `turborepo-api-client` can point
+//! at it without any changes. Artifacts are kept on local disk, capped at a
+//! maximum size with least recently used artifacts evicted first, and
+//! requests are checked against a static list of bearer tokens.
+//!
+//! The team parameters sent by the client are ignored, every token has access
+//! to every artifact.
+
+mod auth;
+mod store;
+
+use std::{io, net::SocketAddr, sync::Arc};
+
+use axum::{
+    body::StreamBody,
+    extract::{BodyStream, Path, State},
+    http::{
+        header::{
+            ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+            ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_LENGTH, CONTENT_TYPE,
+        },
+        HeaderMap, HeaderValue, StatusCode,
+    },
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use futures::StreamExt;
+pub use store::{ArtifactMetadata, ArtifactStore};
+use thiserror::Error;
+use tokio_util::io::ReaderStream;
+use tracing::error;
+use turborepo_api_client::{CachingStatus, CachingStatusResponse};
+
+pub use crate::auth::Tokens;
+
+const ARTIFACT_DURATION_HEADER: &str = "x-artifact-duration";
+const ARTIFACT_TAG_HEADER: &str = "x-artifact-tag";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("missing or invalid bearer token")]
+    Unauthorized,
+    #[error("artifact not found")]
+    NotFound,
+    #[error("invalid artifact hash: {0}")]
+    InvalidHash(String),
+    #[error("invalid {ARTIFACT_DURATION_HEADER} header")]
+    InvalidDuration,
+    #[error("artifact is larger than the cache size limit of {0} bytes")]
+    ArtifactTooLarge(u64),
+    #[error("no tokens found in token file")]
+    NoTokens,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("invalid artifact metadata: {0}")]
+    Metadata(#[from] serde_json::Error),
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::NotFound => StatusCode::NOT_FOUND,
+            Error::InvalidHash(_) | Error::InvalidDuration => StatusCode::BAD_REQUEST,
+            Error::ArtifactTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::NoTokens | Error::Io(_) | Error::Metadata(_) => {
+                error!("{}", self);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct AppState {
+    store: Arc<ArtifactStore>,
+    tokens: Arc<Tokens>,
+}
+
+/// The routes of the cache server
+pub fn router(store: ArtifactStore, tokens: Tokens) -> Router {
+    let state = AppState {
+        store: Arc::new(store),
+        tokens: Arc::new(tokens),
+    };
+
+    Router::new()
+        .route("/v8/artifacts/status", get(caching_status))
+        .route(
+            "/v8/artifacts/:hash",
+            get(fetch_artifact)
+                .head(artifact_exists)
+                .put(put_artifact)
+                .options(preflight),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_token,
+        ))
+        .with_state(state)
+}
+
+/// Serves the cache on `addr` until the process is stopped
+pub async fn serve(addr: SocketAddr, store: ArtifactStore, tokens: Tokens) -> Result<(), Error> {
+    axum_server::bind(addr)
+        .serve(router(store, tokens).into_make_service())
+        .await?;
+    Ok(())
+}
+
+fn validate_hash(hash: String) -> Result<String, Error> {
+    match store::is_valid_hash(&hash) {
+        true => Ok(hash),
+        false => Err(Error::InvalidHash(hash)),
+    }
+}
+
+fn artifact_headers(metadata: &ArtifactMetadata) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        ARTIFACT_DURATION_HEADER,
+        HeaderValue::from(metadata.duration),
+    );
+    if let Some(tag) = metadata
+        .tag
+        .as_deref()
+        .and_then(|tag| HeaderValue::from_str(tag).ok())
+    {
+        headers.insert(ARTIFACT_TAG_HEADER, tag);
+    }
+    headers
+}
+
+async fn caching_status() -> Json<CachingStatusResponse> {
+    Json(CachingStatusResponse {
+        status: CachingStatus::Enabled,
+    })
+}
+
+async fn put_artifact(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+    headers: HeaderMap,
+    body: BodyStream,
+) -> Result<impl IntoResponse, Error> {
+    let hash = validate_hash(hash)?;
+    let duration = match headers.get(ARTIFACT_DURATION_HEADER) {
+        Some(duration) => duration
+            .to_str()
+            .ok()
+            .and_then(|duration| duration.parse().ok())
+            .ok_or(Error::InvalidDuration)?,
+        None => 0,
+    };
+    let tag = headers
+        .get(ARTIFACT_TAG_HEADER)
+        .and_then(|tag| tag.to_str().ok())
+        .map(str::to_string);
+
+    let body = body.map(|chunk| chunk.map_err(|err| io::Error::new(io::ErrorKind::Other, err)));
+    state
+        .store
+        .put(&hash, &ArtifactMetadata { duration, tag }, body)
+        .await?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({ "urls": [hash] })),
+    ))
+}
+
+async fn fetch_artifact(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+) -> Result<impl IntoResponse, Error> {
+    let hash = validate_hash(hash)?;
+    let artifact = state.store.get(&hash).await?.ok_or(Error::NotFound)?;
+
+    let mut headers = artifact_headers(&artifact.metadata);
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(artifact.size));
+
+    Ok((headers, StreamBody::new(ReaderStream::new(artifact.file))))
+}
+
+async fn artifact_exists(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+) -> Result<impl IntoResponse, Error> {
+    let hash = validate_hash(hash)?;
+    let metadata = state.store.exists(&hash).await?.ok_or(Error::NotFound)?;
+
+    Ok(artifact_headers(&metadata))
+}
+
+// The client checks which headers it's allowed to send before making a
+// request when preflight is enabled
+async fn preflight() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+    headers.insert(
+        ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("GET, HEAD, PUT, OPTIONS"),
+    );
+    headers.insert(
+        ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static(
+            "Authorization, Content-Type, User-Agent, x-artifact-duration, x-artifact-tag",
+        ),
+    );
+    headers
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+
+    use anyhow::Result;
+    use test_case::test_case;
+    use tokio::task::JoinHandle;
+    use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+    use turborepo_api_client::APIClient;
+    use turborepo_cache::{
+        http::HttpCache, signature_authentication::ArtifactSignatureAuthenticator, CacheError,
+        CacheSource,
+    };
+
+    use super::*;
+
+    const TOKEN: &str = "secret-token";
+
+    fn start_server(cache_dir: &std::path::Path, max_size: u64) -> (u16, JoinHandle<()>) {
+        let port = port_scanner::request_open_port().unwrap();
+        let store = ArtifactStore::open(cache_dir, max_size).unwrap();
+        let tokens = Tokens::parse(TOKEN).unwrap();
+        let handle = tokio::spawn(async move {
+            serve(SocketAddr::from(([127, 0, 0, 1], port)), store, tokens)
+                .await
+                .unwrap()
+        });
+        (port, handle)
+    }
+
+    // Waits for the server to start accepting connections
+    async fn wait_for_server(port: u16) {
+        for _ in 0..50 {
+            if tokio::net::TcpStream::connect(("127.0.0.1", port))
+                .await
+                .is_ok()
+            {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("cache server never started");
+    }
+
+    fn http_cache(
+        port: u16,
+        repo_root: &AbsoluteSystemPathBuf,
+        use_preflight: bool,
+        signer: Option<ArtifactSignatureAuthenticator>,
+    ) -> HttpCache {
+        let api_client = APIClient::new(
+            format!("http://localhost:{}", port),
+            200,
+            "2.0.0",
+            use_preflight,
+        )
+        .unwrap();
+        HttpCache::new(api_client, signer, repo_root.clone())
+    }
+
+    #[test_case(false ; "without preflight")]
+    #[test_case(true ; "with preflight")]
+    #[tokio::test]
+    async fn test_round_trip(use_preflight: bool) -> Result<()> {
+        let cache_dir = tempfile::tempdir()?;
+        let (port, handle) = start_server(cache_dir.path(), 1 << 20);
+        wait_for_server(port).await;
+
+        let repo_root = tempfile::tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+        let file = AnchoredSystemPathBuf::from_raw("dist/index.js")?;
+        let file_path = repo_root.resolve(&file);
+        std::fs::create_dir_all(file_path.parent().unwrap())?;
+        std::fs::write(&file_path, "console.log('hello')")?;
+
+        let cache = http_cache(port, &repo_root, use_preflight, None);
+        cache
+            .put(&repo_root, "0123abcd", vec![file.clone()], 58, TOKEN)
+            .await?;
+
+        let response = cache
+            .exists("0123abcd", TOKEN, "", None, use_preflight)
+            .await?;
+        assert_eq!(response.time_saved(), 58);
+        assert_eq!(*response.source(), CacheSource::Remote);
+
+        std::fs::remove_file(&file_path)?;
+        let (response, files) = cache
+            .retrieve("0123abcd", TOKEN, "", None, use_preflight)
+            .await?;
+        assert_eq!(response.time_saved(), 58);
+        assert_eq!(files, vec![file]);
+        assert_eq!(std::fs::read_to_string(&file_path)?, "console.log('hello')");
+
+        let missing = cache.exists("ffff", TOKEN, "", None, use_preflight).await;
+        assert!(matches!(missing, Err(CacheError::CacheMiss(_))));
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_signed_artifacts() -> Result<()> {
+        let cache_dir = tempfile::tempdir()?;
+        let (port, handle) = start_server(cache_dir.path(), 1 << 20);
+        wait_for_server(port).await;
+
+        let repo_root = tempfile::tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+        let file = AnchoredSystemPathBuf::from_raw("package.json")?;
+        std::fs::write(repo_root.resolve(&file), "{}")?;
+
+        let signer = || {
+            ArtifactSignatureAuthenticator::new(b"team_id".to_vec(), Some(b"signing key".to_vec()))
+        };
+        let cache = http_cache(port, &repo_root, false, Some(signer()));
+        cache
+            .put(&repo_root, "signed", vec![file.clone()], 0, TOKEN)
+            .await?;
+
+        // The tag has to make it back to the client for it to verify the
+        // artifact
+        let (_, files) = cache.retrieve("signed", TOKEN, "", None, false).await?;
+        assert_eq!(files, vec![file]);
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ed25519_signed_artifacts() -> Result<()> {
+        use base64::{prelude::BASE64_STANDARD, Engine};
+        use ring::signature::{Ed25519KeyPair, KeyPair};
+
+        let cache_dir = tempfile::tempdir()?;
+        let (port, handle) = start_server(cache_dir.path(), 1 << 20);
+        wait_for_server(port).await;
+
+        let repo_root = tempfile::tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+        let file = AnchoredSystemPathBuf::from_raw("package.json")?;
+        std::fs::write(repo_root.resolve(&file), "{}")?;
+
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let private_key = BASE64_STANDARD.encode(pkcs8.as_ref());
+        let public_key = BASE64_STANDARD.encode(key_pair.public_key().as_ref());
+
+        let signer = ArtifactSignatureAuthenticator::new_ed25519(
+            b"team_id".to_vec(),
+            Some(public_key.clone()),
+            Some(private_key),
+        );
+        http_cache(port, &repo_root, false, Some(signer))
+            .put(&repo_root, "ed25519", vec![file.clone()], 0, TOKEN)
+            .await?;
+
+        // A machine with only the public key can still verify the artifact
+        let verifier = ArtifactSignatureAuthenticator::new_ed25519(
+            b"team_id".to_vec(),
+            Some(public_key),
+            None,
+        );
+        let (_, files) = http_cache(port, &repo_root, false, Some(verifier))
+            .retrieve("ed25519", TOKEN, "", None, false)
+            .await?;
+        assert_eq!(files, vec![file]);
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_tokens() -> Result<()> {
+        let cache_dir = tempfile::tempdir()?;
+        let (port, handle) = start_server(cache_dir.path(), 1 << 20);
+        wait_for_server(port).await;
+
+        let repo_root = tempfile::tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+        let cache = http_cache(port, &repo_root, false, None);
+
+        let put = cache
+            .put(&repo_root, "0123abcd", Vec::new(), 0, "wrong-token")
+            .await;
+        assert!(put.is_err());
+        let exists = cache
+            .exists("0123abcd", "wrong-token", "", None, false)
+            .await;
+        assert!(matches!(exists, Err(CacheError::ApiClientError(..))));
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_evicts_over_size_cap() -> Result<()> {
+        let cache_dir = tempfile::tempdir()?;
+        let repo_root = tempfile::tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+        let file = AnchoredSystemPathBuf::from_raw("package.json")?;
+        std::fs::write(repo_root.resolve(&file), "{}")?;
+
+        // Find out how big a single artifact is so the cap can fit just one
+        let (port, handle) = start_server(cache_dir.path(), 1 << 20);
+        wait_for_server(port).await;
+        let cache = http_cache(port, &repo_root, false, None);
+        cache
+            .put(&repo_root, "first", vec![file.clone()], 0, TOKEN)
+            .await?;
+        handle.abort();
+        let artifact_size = std::fs::metadata(cache_dir.path().join("first"))?.len();
+
+        let (port, handle) = start_server(cache_dir.path(), artifact_size);
+        wait_for_server(port).await;
+        let cache = http_cache(port, &repo_root, false, None);
+        cache
+            .put(&repo_root, "second", vec![file.clone()], 0, TOKEN)
+            .await?;
+
+        assert!(matches!(
+            cache.exists("first", TOKEN, "", None, false).await,
+            Err(CacheError::CacheMiss(_))
+        ));
+        assert!(cache.exists("second", TOKEN, "", None, false).await.is_ok());
+
+        handle.abort();
+        Ok(())
+    }
+}
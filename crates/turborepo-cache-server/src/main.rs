@@ -0,0 +1,50 @@
+use std::{net::IpAddr, path::PathBuf};
+
+use anyhow::Result;
+use clap::Parser;
+use tracing::info;
+use turborepo_cache::size::parse_size;
+use turborepo_cache_server::{serve, ArtifactStore, Tokens};
+
+/// A self-hosted remote cache for turbo
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// The address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    host: IpAddr,
+    #[arg(long, default_value_t = 3000)]
+    port: u16,
+    /// Where artifacts are stored
+    #[arg(long)]
+    cache_dir: PathBuf,
+    /// The most space artifacts can take up, e.g. 512M or 10G. The least
+    /// recently used artifacts are evicted once this is reached.
+    #[arg(long, default_value = "10G", value_parser = parse_size)]
+    max_size: u64,
+    /// A file with the bearer tokens that are allowed to use the cache, one
+    /// per line
+    #[arg(long)]
+    token_file: PathBuf,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+
+    let tokens = Tokens::read(&args.token_file)?;
+    std::fs::create_dir_all(&args.cache_dir)?;
+    let store = ArtifactStore::open(&args.cache_dir, args.max_size)?;
+    info!(
+        "serving {} ({} of {} bytes used) on {}:{}",
+        args.cache_dir.display(),
+        store.size(),
+        store.max_size(),
+        args.host,
+        args.port
+    );
+
+    serve((args.host, args.port).into(), store, tokens).await?;
+    Ok(())
+}
@@ -0,0 +1,424 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::SystemTime,
+};
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::{fs::File, io::AsyncWriteExt};
+use tracing::{debug, warn};
+
+use crate::Error;
+
+// Uploads are written here first and moved into place once complete, so a
+// reader never sees a partial artifact
+const INCOMING_DIR: &str = ".incoming";
+const METADATA_EXTENSION: &str = "json";
+
+/// The headers that were sent along with an artifact, returned whenever it's
+/// fetched
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ArtifactMetadata {
+    pub duration: u32,
+    pub tag: Option<String>,
+}
+
+/// An artifact that was found in the store
+#[derive(Debug)]
+pub struct StoredArtifact {
+    pub metadata: ArtifactMetadata,
+    pub size: u64,
+    pub file: File,
+}
+
+#[derive(Debug)]
+struct Entry {
+    size: u64,
+    last_used: u64,
+}
+
+// Tracks every artifact in the store along with when it was last used.
+// `by_last_used` is ordered so that the least recently used artifact comes
+// first.
+#[derive(Debug, Default)]
+struct Index {
+    entries: HashMap<String, Entry>,
+    by_last_used: BTreeMap<u64, String>,
+    total_size: u64,
+    clock: u64,
+}
+
+impl Index {
+    fn insert(&mut self, hash: String, size: u64) {
+        self.remove(&hash);
+        self.clock += 1;
+        self.by_last_used.insert(self.clock, hash.clone());
+        self.entries.insert(
+            hash,
+            Entry {
+                size,
+                last_used: self.clock,
+            },
+        );
+        self.total_size += size;
+    }
+
+    fn remove(&mut self, hash: &str) -> Option<Entry> {
+        let entry = self.entries.remove(hash)?;
+        self.by_last_used.remove(&entry.last_used);
+        self.total_size -= entry.size;
+        Some(entry)
+    }
+
+    // Marks the artifact as used, returning whether it's in the index
+    fn touch(&mut self, hash: &str) -> bool {
+        let Some(entry) = self.entries.get_mut(hash) else {
+            return false;
+        };
+        self.by_last_used.remove(&entry.last_used);
+        self.clock += 1;
+        entry.last_used = self.clock;
+        self.by_last_used.insert(self.clock, hash.to_string());
+        true
+    }
+
+    // Drops least recently used artifacts until the store fits in `max_size`,
+    // returning the hashes that were dropped
+    fn evict(&mut self, max_size: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.total_size > max_size {
+            let Some((_, hash)) = self.by_last_used.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&hash) {
+                self.total_size -= entry.size;
+            }
+            evicted.push(hash);
+        }
+        evicted
+    }
+}
+
+/// Artifacts stored on local disk, capped at a maximum total size. Once the
+/// cap is reached the least recently used artifacts are evicted.
+#[derive(Debug)]
+pub struct ArtifactStore {
+    dir: PathBuf,
+    max_size: u64,
+    index: Mutex<Index>,
+    // Used to give concurrent uploads of the same artifact distinct files
+    upload_count: AtomicU64,
+}
+
+impl ArtifactStore {
+    /// Opens the store in `dir`, picking up any artifacts that are already
+    /// there. Artifacts found on disk are ordered by when they were last
+    /// written.
+    pub fn open(dir: impl Into<PathBuf>, max_size: u64) -> Result<Self, Error> {
+        let dir = dir.into();
+        let incoming = dir.join(INCOMING_DIR);
+        // Anything left over here is from an upload that never finished
+        if incoming.exists() {
+            std::fs::remove_dir_all(&incoming)?;
+        }
+        std::fs::create_dir_all(&incoming)?;
+
+        let mut artifacts = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let Some(hash) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if !is_valid_hash(&hash) {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            artifacts.push((modified, hash, metadata.len()));
+        }
+        artifacts.sort();
+
+        let mut index = Index::default();
+        for (_, hash, size) in artifacts {
+            index.insert(hash, size);
+        }
+        let store = Self {
+            dir,
+            max_size,
+            index: Mutex::default(),
+            upload_count: AtomicU64::new(0),
+        };
+        for hash in index.evict(max_size) {
+            store.remove_files_sync(&hash);
+        }
+        debug!(
+            "opened artifact store with {} artifacts ({} bytes)",
+            index.entries.len(),
+            index.total_size
+        );
+        *store.index.lock().expect("artifact index lock poisoned") = index;
+
+        Ok(store)
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    /// The combined size of every artifact in the store
+    pub fn size(&self) -> u64 {
+        self.lock_index().total_size
+    }
+
+    /// Writes an artifact to the store, evicting older artifacts if the store
+    /// goes over its size cap
+    pub async fn put(
+        &self,
+        hash: &str,
+        metadata: &ArtifactMetadata,
+        mut body: impl Stream<Item = io::Result<Bytes>> + Unpin,
+    ) -> Result<(), Error> {
+        let upload = self.upload_count.fetch_add(1, Ordering::Relaxed);
+        let incoming = self.dir.join(INCOMING_DIR);
+        let artifact_upload = incoming.join(format!("{}-{}", hash, upload));
+        let metadata_upload = incoming.join(format!("{}-{}.{}", hash, upload, METADATA_EXTENSION));
+
+        let result = async {
+            let mut file = File::create(&artifact_upload).await?;
+            let mut size = 0u64;
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk?;
+                size += chunk.len() as u64;
+                if size > self.max_size {
+                    return Err(Error::ArtifactTooLarge(self.max_size));
+                }
+                file.write_all(&chunk).await?;
+            }
+            file.sync_all().await?;
+
+            tokio::fs::write(&metadata_upload, serde_json::to_vec(metadata)?).await?;
+            tokio::fs::rename(&metadata_upload, self.metadata_path(hash)).await?;
+            tokio::fs::rename(&artifact_upload, self.artifact_path(hash)).await?;
+            Ok(size)
+        }
+        .await;
+
+        let size = match result {
+            Ok(size) => size,
+            Err(err) => {
+                let _ = tokio::fs::remove_file(&artifact_upload).await;
+                let _ = tokio::fs::remove_file(&metadata_upload).await;
+                return Err(err);
+            }
+        };
+
+        let evicted = {
+            let mut index = self.lock_index();
+            index.insert(hash.to_string(), size);
+            index.evict(self.max_size)
+        };
+        for hash in evicted {
+            debug!("evicting artifact {}", hash);
+            self.remove_files(&hash).await;
+        }
+
+        Ok(())
+    }
+
+    /// Looks up an artifact, opening it for reading
+    pub async fn get(&self, hash: &str) -> Result<Option<StoredArtifact>, Error> {
+        let Some(metadata) = self.exists(hash).await? else {
+            return Ok(None);
+        };
+        let file = match File::open(self.artifact_path(hash)).await {
+            Ok(file) => file,
+            // The artifact was evicted after we looked it up
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let size = file.metadata().await?.len();
+
+        Ok(Some(StoredArtifact {
+            metadata,
+            size,
+            file,
+        }))
+    }
+
+    /// Looks up the metadata of an artifact. Both fetching an artifact and
+    /// checking that it exists count as using it.
+    pub async fn exists(&self, hash: &str) -> Result<Option<ArtifactMetadata>, Error> {
+        if !self.lock_index().touch(hash) {
+            return Ok(None);
+        }
+
+        match tokio::fs::read(self.metadata_path(hash)).await {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            // Artifacts written by hand or by an older server might not have
+            // any metadata
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                match tokio::fs::try_exists(self.artifact_path(hash)).await? {
+                    true => Ok(Some(ArtifactMetadata::default())),
+                    false => {
+                        self.lock_index().remove(hash);
+                        Ok(None)
+                    }
+                }
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn artifact_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(hash)
+    }
+
+    fn metadata_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", hash, METADATA_EXTENSION))
+    }
+
+    fn lock_index(&self) -> std::sync::MutexGuard<'_, Index> {
+        self.index.lock().expect("artifact index lock poisoned")
+    }
+
+    async fn remove_files(&self, hash: &str) {
+        for path in [self.artifact_path(hash), self.metadata_path(hash)] {
+            if let Err(err) = tokio::fs::remove_file(&path).await {
+                log_remove_error(&path, err);
+            }
+        }
+    }
+
+    fn remove_files_sync(&self, hash: &str) {
+        for path in [self.artifact_path(hash), self.metadata_path(hash)] {
+            if let Err(err) = std::fs::remove_file(&path) {
+                log_remove_error(&path, err);
+            }
+        }
+    }
+}
+
+fn log_remove_error(path: &Path, err: io::Error) {
+    if err.kind() != io::ErrorKind::NotFound {
+        warn!("failed to remove {}: {}", path.display(), err);
+    }
+}
+
+/// Hashes are used as file names, so we only accept the characters turbo
+/// produces to keep requests from escaping the store
+pub fn is_valid_hash(hash: &str) -> bool {
+    !hash.is_empty()
+        && hash
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use futures::stream;
+    use test_case::test_case;
+
+    use super::*;
+
+    fn body(contents: &'static [u8]) -> impl Stream<Item = io::Result<Bytes>> + Unpin {
+        stream::iter(vec![Ok(Bytes::from_static(contents))])
+    }
+
+    #[test_case("0123456789abcdef", true ; "hex")]
+    #[test_case("Faces-Places_2", true ; "dashes and underscores")]
+    #[test_case("", false ; "empty")]
+    #[test_case("../secrets", false ; "traversal")]
+    #[test_case("abc.json", false ; "extension")]
+    fn test_is_valid_hash(hash: &str, expected: bool) {
+        assert_eq!(is_valid_hash(hash), expected);
+    }
+
+    #[tokio::test]
+    async fn test_put_and_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ArtifactStore::open(dir.path(), 1024).unwrap();
+        let metadata = ArtifactMetadata {
+            duration: 58,
+            tag: Some("tag".to_string()),
+        };
+
+        store
+            .put("abc", &metadata, body(b"artifact"))
+            .await
+            .unwrap();
+
+        let artifact = store.get("abc").await.unwrap().unwrap();
+        assert_eq!(artifact.metadata, metadata);
+        assert_eq!(artifact.size, 8);
+        assert_eq!(store.size(), 8);
+        assert!(store.get("def").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ArtifactStore::open(dir.path(), 10).unwrap();
+        let metadata = ArtifactMetadata::default();
+
+        store.put("a", &metadata, body(b"1234")).await.unwrap();
+        store.put("b", &metadata, body(b"1234")).await.unwrap();
+        // Using `a` makes `b` the least recently used artifact
+        assert!(store.exists("a").await.unwrap().is_some());
+        store.put("c", &metadata, body(b"1234")).await.unwrap();
+
+        assert!(store.exists("a").await.unwrap().is_some());
+        assert!(store.exists("b").await.unwrap().is_none());
+        assert!(store.exists("c").await.unwrap().is_some());
+        assert!(!dir.path().join("b").exists());
+        assert_eq!(store.size(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_artifacts_over_the_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ArtifactStore::open(dir.path(), 4).unwrap();
+
+        let result = store
+            .put("a", &ArtifactMetadata::default(), body(b"12345"))
+            .await;
+
+        assert!(matches!(result, Err(Error::ArtifactTooLarge(4))));
+        assert!(store.exists("a").await.unwrap().is_none());
+        assert_eq!(
+            std::fs::read_dir(dir.path().join(INCOMING_DIR))
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reopen_existing_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let metadata = ArtifactMetadata {
+            duration: 12,
+            tag: None,
+        };
+        {
+            let store = ArtifactStore::open(dir.path(), 1024).unwrap();
+            store
+                .put("abc", &metadata, body(b"artifact"))
+                .await
+                .unwrap();
+        }
+
+        let store = ArtifactStore::open(dir.path(), 1024).unwrap();
+        assert_eq!(store.size(), 8);
+        assert_eq!(store.exists("abc").await.unwrap(), Some(metadata));
+    }
+}
@@ -0,0 +1,299 @@
+use std::{backtrace::Backtrace, io, sync::Arc};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tracing::warn;
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+use turborepo_api_client::{APIAuth, APIClient};
+
+use crate::{multiplexer::CacheMultiplexer, CacheError, CacheOpts, CacheResponse};
+
+// Local writes happen before `put` returns, remote uploads are queued and
+// handed off to at most `CacheOpts::workers` concurrent uploads. The queue is
+// unbounded so that a slow remote cache never holds up task execution.
+pub struct AsyncCache {
+    real_cache: Arc<CacheMultiplexer>,
+    writer_sender: mpsc::UnboundedSender<WorkerRequest>,
+}
+
+#[derive(Debug)]
+enum WorkerRequest {
+    Upload {
+        anchor: AbsoluteSystemPathBuf,
+        key: String,
+        duration: u32,
+        files: Vec<AnchoredSystemPathBuf>,
+    },
+    Flush(oneshot::Sender<()>),
+}
+
+impl AsyncCache {
+    pub fn new(
+        opts: &CacheOpts,
+        repo_root: &AbsoluteSystemPath,
+        api_client: APIClient,
+        api_auth: Option<APIAuth>,
+    ) -> Result<AsyncCache, CacheError> {
+        let max_workers = usize::try_from(opts.workers)
+            .expect("usize is smaller than u32")
+            .max(1);
+        let real_cache = Arc::new(CacheMultiplexer::new(
+            opts, repo_root, api_client, api_auth,
+        )?);
+        let (writer_sender, mut write_consumer) = mpsc::unbounded_channel();
+
+        // Start a task to manage the upload workers
+        let worker_real_cache = real_cache.clone();
+        tokio::spawn(async move {
+            let semaphore = Arc::new(Semaphore::new(max_workers));
+            let mut workers = FuturesUnordered::new();
+            loop {
+                tokio::select! {
+                    request = write_consumer.recv() => match request {
+                        Some(WorkerRequest::Upload {
+                            anchor,
+                            key,
+                            duration,
+                            files,
+                        }) => {
+                            let worker_real_cache = worker_real_cache.clone();
+                            let semaphore = semaphore.clone();
+                            let handle = tokio::spawn(async move {
+                                // Only `max_workers` uploads run at once, the
+                                // rest wait here rather than in the queue
+                                let Ok(_permit) = semaphore.acquire_owned().await else {
+                                    return;
+                                };
+                                if let Err(err) = worker_real_cache
+                                    .put_http(&anchor, &key, &files, duration)
+                                    .await
+                                {
+                                    warn!("failed to upload {} to remote cache: {}", key, err);
+                                }
+                            });
+                            workers.push(handle);
+                        }
+                        Some(WorkerRequest::Flush(callback)) => {
+                            // Wait on all in-flight uploads to finish
+                            while workers.next().await.is_some() {}
+                            // The caller may have stopped waiting, that's fine
+                            let _ = callback.send(());
+                        }
+                        None => break,
+                    },
+                    // Reap finished uploads as they complete
+                    Some(_) = workers.next(), if !workers.is_empty() => {}
+                }
+            }
+        });
+
+        Ok(AsyncCache {
+            real_cache,
+            writer_sender,
+        })
+    }
+
+    pub async fn put(
+        &self,
+        anchor: AbsoluteSystemPathBuf,
+        key: String,
+        files: Vec<AnchoredSystemPathBuf>,
+        duration: u32,
+    ) -> Result<(), CacheError> {
+        // Writing the archive is blocking IO, keep it off the executor
+        let real_cache = self.real_cache.clone();
+        let (anchor, key, files) = tokio::task::spawn_blocking(move || {
+            real_cache
+                .put_fs(&anchor, &key, &files, duration)
+                .map(|_| (anchor, key, files))
+        })
+        .await
+        .map_err(io::Error::from)??;
+
+        if self.real_cache.has_http_cache() {
+            let request = WorkerRequest::Upload {
+                anchor,
+                key,
+                duration,
+                files,
+            };
+            self.writer_sender
+                .send(request)
+                .map_err(|_| CacheError::WorkerPoolShutDown(Backtrace::capture()))?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn fetch(
+        &self,
+        anchor: &AbsoluteSystemPath,
+        key: &str,
+    ) -> Result<(CacheResponse, Vec<AnchoredSystemPathBuf>), CacheError> {
+        self.real_cache.fetch(anchor, key).await
+    }
+
+    pub async fn exists(&self, key: &str) -> Result<CacheResponse, CacheError> {
+        self.real_cache.exists(key).await
+    }
+
+    // Blocks until all pending uploads have completed. Must be called before
+    // the process exits, otherwise queued uploads are dropped.
+    pub async fn wait(&self) -> Result<(), CacheError> {
+        let (tx, rx) = oneshot::channel();
+        self.writer_sender
+            .send(WorkerRequest::Flush(tx))
+            .map_err(|_| CacheError::WorkerPoolShutDown(Backtrace::capture()))?;
+        rx.await
+            .map_err(|_| CacheError::WorkerPoolShutDown(Backtrace::capture()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use tempfile::tempdir;
+    use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+    use turborepo_api_client::{APIAuth, APIClient};
+    use vercel_api_mock::start_test_server;
+
+    use crate::{async_cache::AsyncCache, CacheError, CacheOpts, CacheSource};
+
+    fn api_auth() -> Option<APIAuth> {
+        Some(APIAuth {
+            team_id: "my-team".to_string(),
+            token: "my-token".to_string(),
+            team_slug: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_fs_and_http_round_trip() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+        let file = AnchoredSystemPathBuf::from_raw("dist/index.js")?;
+        let file_path = repo_root_path.resolve(&file);
+        file_path.parent().unwrap().create_dir_all()?;
+        file_path.create_with_contents("console.log('hi')")?;
+
+        let api_client =
+            || APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", true);
+
+        let opts = CacheOpts {
+            workers: 2,
+            ..CacheOpts::default()
+        };
+        let cache = AsyncCache::new(&opts, &repo_root_path, api_client()?, api_auth())?;
+
+        let hash = "the-hash";
+        cache
+            .put(
+                repo_root_path.clone(),
+                hash.to_string(),
+                vec![file.clone()],
+                58,
+            )
+            .await?;
+        cache.wait().await?;
+
+        // The local write happens before `put` returns
+        let response = cache.exists(hash).await?;
+        assert_eq!(response.source, CacheSource::Local);
+        assert_eq!(response.time_saved, 58);
+
+        // After flushing the upload is visible to a remote-only cache
+        let remote_only_opts = CacheOpts {
+            skip_filesystem: true,
+            override_dir: Some("remote-only-cache"),
+            ..CacheOpts::default()
+        };
+        let remote_only = AsyncCache::new(
+            &remote_only_opts,
+            &repo_root_path,
+            api_client()?,
+            api_auth(),
+        )?;
+        let response = remote_only.exists(hash).await?;
+        assert_eq!(response.source, CacheSource::Remote);
+        assert!(!repo_root_path.join_component("remote-only-cache").exists());
+
+        // A remote hit gets written back to an empty local cache
+        let backfill_opts = CacheOpts {
+            override_dir: Some("backfill-cache"),
+            ..CacheOpts::default()
+        };
+        let backfill = AsyncCache::new(&backfill_opts, &repo_root_path, api_client()?, api_auth())?;
+        let (response, files) = backfill.fetch(&repo_root_path, hash).await?;
+        assert_eq!(response.source, CacheSource::Remote);
+        assert_eq!(response.time_saved, 58);
+        assert_eq!(files, vec![file]);
+
+        let response = backfill.exists(hash).await?;
+        assert_eq!(response.source, CacheSource::Local);
+        assert_eq!(response.time_saved, 58);
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_skip_remote() -> Result<()> {
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+        // Nothing is listening here, any remote request would fail
+        let api_client = APIClient::new("http://localhost:1", 200, "2.0.0", true)?;
+
+        let opts = CacheOpts {
+            skip_remote: true,
+            ..CacheOpts::default()
+        };
+        let cache = AsyncCache::new(&opts, &repo_root_path, api_client, api_auth())?;
+
+        cache
+            .put(repo_root_path.clone(), "hash".to_string(), vec![], 10)
+            .await?;
+        cache.wait().await?;
+
+        assert_eq!(cache.exists("hash").await?.source, CacheSource::Local);
+        assert!(matches!(
+            cache.exists("missing").await,
+            Err(CacheError::CacheMiss(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_put_does_not_wait_on_uploads() -> Result<()> {
+        // Accepts connections but never responds, so every upload hangs
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+        let api_client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", true)?;
+        let opts = CacheOpts {
+            workers: 1,
+            ..CacheOpts::default()
+        };
+        let cache = AsyncCache::new(&opts, &repo_root_path, api_client, api_auth())?;
+
+        // Far more uploads than there are workers
+        let puts = async {
+            for i in 0..10 {
+                cache
+                    .put(repo_root_path.clone(), format!("hash-{}", i), vec![], 10)
+                    .await?;
+            }
+            Ok::<_, CacheError>(())
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(10), puts).await??;
+        assert_eq!(cache.exists("hash-9").await?.source, CacheSource::Local);
+
+        drop(listener);
+        Ok(())
+    }
+}
@@ -20,6 +20,17 @@ pub struct CacheReader<'a> {
     reader: Box<dyn Read + 'a>,
 }
 
+/// A file, directory or symlink stored in a cache artifact
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactEntry {
+    /// The path of the entry within the artifact, using `/` as the separator
+    pub path: String,
+    pub entry_type: tar::EntryType,
+    pub mode: u32,
+    pub size: u64,
+    pub link_target: Option<String>,
+}
+
 impl<'a> CacheReader<'a> {
     pub fn from_reader(reader: impl Read + 'a, is_compressed: bool) -> Result<Self, CacheError> {
         let reader: Box<dyn Read> = if is_compressed {
@@ -58,6 +69,50 @@ impl<'a> CacheReader<'a> {
         Ok(context.finalize().to_vec())
     }
 
+    /// Lists the entries of the artifact without restoring any of them
+    pub fn entries(&mut self) -> Result<Vec<ArtifactEntry>, CacheError> {
+        let mut tr = tar::Archive::new(&mut self.reader);
+        let mut entries = Vec::new();
+        for entry in tr.entries()? {
+            let entry = entry?;
+            let header = entry.header();
+            entries.push(ArtifactEntry {
+                path: entry.path()?.to_string_lossy().into_owned(),
+                entry_type: header.entry_type(),
+                // A missing mode doesn't stop us from restoring the entry, so
+                // it shouldn't stop us from listing it either
+                mode: header.mode().unwrap_or_default(),
+                size: header.size()?,
+                link_target: header
+                    .link_name()?
+                    .map(|target| target.to_string_lossy().into_owned()),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// Reads the contents of the regular file at `path` in the artifact.
+    /// Returns `None` if the artifact doesn't contain a regular file at that
+    /// path.
+    pub fn read_file(&mut self, path: &str) -> Result<Option<Vec<u8>>, CacheError> {
+        let mut tr = tar::Archive::new(&mut self.reader);
+        for entry in tr.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type() != tar::EntryType::Regular
+                || entry.path()?.to_string_lossy() != path
+            {
+                continue;
+            }
+
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents)?;
+            return Ok(Some(contents));
+        }
+
+        Ok(None)
+    }
+
     pub fn restore(
         &mut self,
         anchor: &AbsoluteSystemPath,
@@ -334,6 +389,62 @@ mod tests {
             .collect()
     }
 
+    #[test]
+    fn test_entries_and_read_file() -> Result<()> {
+        let test_dir = tempdir()?;
+        let files = vec![
+            TarFile::Directory {
+                path: AnchoredSystemPathBuf::from_raw("dist")?,
+            },
+            TarFile::File {
+                path: AnchoredSystemPathBuf::from_raw("dist/index.js")?,
+                body: b"console.log('hi')".to_vec(),
+            },
+            TarFile::Symlink {
+                link_path: AnchoredSystemPathBuf::from_raw("dist/main.js")?,
+                link_target: AnchoredSystemPathBuf::from_raw("index.js")?,
+            },
+        ];
+        let archive_path = compress_tar(&generate_tar(&test_dir, &files)?)?;
+
+        let entries = CacheReader::open(&archive_path)?.entries()?;
+        let summary = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.path.as_str(),
+                    entry.entry_type,
+                    entry.size,
+                    entry.link_target.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("dist", tar::EntryType::Directory, 0, None),
+                ("dist/index.js", tar::EntryType::Regular, 17, None),
+                ("dist/main.js", tar::EntryType::Symlink, 0, Some("index.js")),
+            ]
+        );
+
+        assert_eq!(
+            CacheReader::open(&archive_path)?.read_file("dist/index.js")?,
+            Some(b"console.log('hi')".to_vec())
+        );
+        // Only regular files can be read
+        assert_eq!(
+            CacheReader::open(&archive_path)?.read_file("dist/main.js")?,
+            None
+        );
+        assert_eq!(
+            CacheReader::open(&archive_path)?.read_file("missing.js")?,
+            None
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_name_traversal() -> Result<()> {
         let uncompressed_tar = include_bytes!("../../fixtures/name-traversal.tar");
@@ -1,6 +1,7 @@
 use std::{fs::OpenOptions, io, io::Read, path::Path};
 
 use tar::Entry;
+use tracing::debug;
 use turbopath::{AbsoluteSystemPath, AnchoredSystemPath, AnchoredSystemPathBuf};
 
 use crate::{cache_archive::restore_directory::CachedDirTree, CacheError};
@@ -31,7 +32,7 @@ pub fn restore_regular(
         open_options.mode(header.mode()?);
     }
 
-    println!("resolved path: {}", resolved_path);
+    debug!("resolved path: {}", resolved_path);
     let mut file = open_options.open(resolved_path.as_path())?;
     io::copy(entry, &mut file)?;
 
@@ -0,0 +1,449 @@
+use std::{
+    backtrace::Backtrace,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+
+use crate::{
+    cache_archive::{CacheReader, CacheWriter},
+    CacheError, CacheResponse, CacheSource,
+};
+
+pub struct FSCache {
+    cache_directory: AbsoluteSystemPathBuf,
+}
+
+// Matches the metadata file written by the Go implementation, so that
+// artifacts are interchangeable between the two.
+#[derive(Debug, Deserialize, Serialize)]
+struct CacheMetadata {
+    hash: String,
+    duration: u32,
+}
+
+impl CacheMetadata {
+    fn read(path: &AbsoluteSystemPath) -> Result<CacheMetadata, CacheError> {
+        let contents = std::fs::read_to_string(path.as_std_path())?;
+        serde_json::from_str(&contents)
+            .map_err(|e| CacheError::InvalidMetadata(e, Backtrace::capture()))
+    }
+}
+
+/// What `FSCache::prune` removed from the cache
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PruneSummary {
+    pub removed: Vec<String>,
+    pub freed_bytes: u64,
+    pub remaining_bytes: u64,
+}
+
+// An artifact in the cache directory along with its metadata file
+struct CachedArtifact {
+    hash: String,
+    paths: Vec<AbsoluteSystemPathBuf>,
+    size: u64,
+    modified: SystemTime,
+}
+
+impl FSCache {
+    fn resolve_cache_dir(
+        repo_root: &AbsoluteSystemPath,
+        override_dir: Option<&str>,
+    ) -> AbsoluteSystemPathBuf {
+        if let Some(override_dir) = override_dir {
+            AbsoluteSystemPathBuf::from_unknown(repo_root, override_dir)
+        } else {
+            repo_root.join_components(&["node_modules", ".cache", "turbo"])
+        }
+    }
+
+    pub fn new(
+        override_dir: Option<&str>,
+        repo_root: &AbsoluteSystemPath,
+    ) -> Result<Self, CacheError> {
+        let cache_directory = Self::resolve_cache_dir(repo_root, override_dir);
+        cache_directory.create_dir_all()?;
+
+        Ok(FSCache { cache_directory })
+    }
+
+    pub fn cache_directory(&self) -> &AbsoluteSystemPath {
+        &self.cache_directory
+    }
+
+    // Legacy uncompressed artifacts take precedence over compressed ones,
+    // mirroring the lookup order of the Go implementation.
+    fn artifact_path(&self, hash: &str) -> Option<AbsoluteSystemPathBuf> {
+        let uncompressed_cache_path = self
+            .cache_directory
+            .join_component(&format!("{}.tar", hash));
+        let compressed_cache_path = self
+            .cache_directory
+            .join_component(&format!("{}.tar.zst", hash));
+
+        if uncompressed_cache_path.exists() {
+            Some(uncompressed_cache_path)
+        } else if compressed_cache_path.exists() {
+            Some(compressed_cache_path)
+        } else {
+            None
+        }
+    }
+
+    fn metadata_path(&self, hash: &str) -> AbsoluteSystemPathBuf {
+        self.cache_directory
+            .join_component(&format!("{}-meta.json", hash))
+    }
+
+    /// Opens the artifact for `hash` without restoring it
+    pub fn open(&self, hash: &str) -> Result<CacheReader<'static>, CacheError> {
+        let Some(cache_path) = self.artifact_path(hash) else {
+            return Err(CacheError::CacheMiss(Backtrace::capture()));
+        };
+
+        CacheReader::open(&cache_path)
+    }
+
+    pub fn fetch(
+        &self,
+        anchor: &AbsoluteSystemPath,
+        hash: &str,
+    ) -> Result<(CacheResponse, Vec<AnchoredSystemPathBuf>), CacheError> {
+        let Some(cache_path) = self.artifact_path(hash) else {
+            return Err(CacheError::CacheMiss(Backtrace::capture()));
+        };
+
+        // Resolved before anything gets restored, so that a hit looks the same
+        // here as it does to `exists`
+        let time_saved = self.time_saved(hash);
+        let mut cache_reader = CacheReader::open(&cache_path)?;
+        let restored_files = cache_reader.restore(anchor)?;
+
+        Ok((
+            CacheResponse {
+                source: CacheSource::Local,
+                time_saved,
+            },
+            restored_files,
+        ))
+    }
+
+    pub fn exists(&self, hash: &str) -> Result<CacheResponse, CacheError> {
+        if self.artifact_path(hash).is_none() {
+            return Err(CacheError::CacheMiss(Backtrace::capture()));
+        }
+
+        Ok(CacheResponse {
+            source: CacheSource::Local,
+            time_saved: self.time_saved(hash),
+        })
+    }
+
+    // A missing or unreadable metadata file only means we can't report the
+    // time saved, the artifact itself is still usable.
+    fn time_saved(&self, hash: &str) -> u32 {
+        CacheMetadata::read(&self.metadata_path(hash))
+            .map(|meta| meta.duration)
+            .unwrap_or(0)
+    }
+
+    pub fn put(
+        &self,
+        anchor: &AbsoluteSystemPath,
+        hash: &str,
+        files: Vec<AnchoredSystemPathBuf>,
+        duration: u32,
+    ) -> Result<(), CacheError> {
+        let cache_path = self
+            .cache_directory
+            .join_component(&format!("{}.tar.zst", hash));
+
+        let mut cache_item = CacheWriter::create(&cache_path)?;
+
+        for file in files {
+            cache_item.add_file(anchor, &file)?;
+        }
+
+        cache_item.finish()?;
+
+        let metadata_path = self.metadata_path(hash);
+        let meta = CacheMetadata {
+            hash: hash.to_string(),
+            duration,
+        };
+
+        let meta_json = serde_json::to_string(&meta)
+            .map_err(|e| CacheError::InvalidMetadata(e, Backtrace::capture()))?;
+
+        metadata_path.create_with_contents(&meta_json)?;
+
+        Ok(())
+    }
+
+    /// Removes artifacts that haven't been written in `max_age`, and then the
+    /// least recently written artifacts until the cache fits in `max_size`
+    /// bytes.
+    pub fn prune(
+        &self,
+        max_size: Option<u64>,
+        max_age: Option<Duration>,
+    ) -> Result<PruneSummary, CacheError> {
+        let mut artifacts = self.artifacts()?;
+        // Oldest first, so that we evict the least recently written artifacts
+        artifacts.sort_by_key(|artifact| artifact.modified);
+
+        let now = SystemTime::now();
+        let mut remaining_bytes = artifacts.iter().map(|artifact| artifact.size).sum::<u64>();
+        let mut summary = PruneSummary::default();
+        for artifact in artifacts {
+            let too_old = max_age.map_or(false, |max_age| {
+                now.duration_since(artifact.modified)
+                    .map_or(false, |age| age > max_age)
+            });
+            let too_big = max_size.map_or(false, |max_size| remaining_bytes > max_size);
+            if !too_old && !too_big {
+                continue;
+            }
+
+            for path in &artifact.paths {
+                path.remove_file()?;
+            }
+            remaining_bytes -= artifact.size;
+            summary.freed_bytes += artifact.size;
+            summary.removed.push(artifact.hash);
+        }
+        summary.remaining_bytes = remaining_bytes;
+
+        Ok(summary)
+    }
+
+    // Collects the artifacts in the cache directory. Files that don't look like
+    // artifacts or their metadata are left alone.
+    fn artifacts(&self) -> Result<Vec<CachedArtifact>, CacheError> {
+        let mut artifacts = Vec::new();
+        for entry in std::fs::read_dir(self.cache_directory.as_std_path())? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(hash) = file_name.to_str().and_then(|name| {
+                name.strip_suffix(".tar.zst")
+                    .or_else(|| name.strip_suffix(".tar"))
+            }) else {
+                continue;
+            };
+
+            let metadata = entry.metadata()?;
+            let mut artifact = CachedArtifact {
+                hash: hash.to_string(),
+                paths: vec![self
+                    .cache_directory
+                    .join_component(&file_name.to_string_lossy())],
+                size: metadata.len(),
+                modified: metadata.modified()?,
+            };
+            let metadata_path = self.metadata_path(hash);
+            if let Ok(metadata) = metadata_path.symlink_metadata() {
+                artifact.size += metadata.len();
+                artifact.paths.push(metadata_path);
+            }
+            artifacts.push(artifact);
+        }
+
+        Ok(artifacts)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use tempfile::tempdir;
+    use test_case::test_case;
+    use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+
+    use super::*;
+
+    struct TestFile {
+        path: AnchoredSystemPathBuf,
+        contents: &'static str,
+    }
+
+    #[test_case(vec![
+        TestFile {
+            path: AnchoredSystemPathBuf::from_raw("package.json").unwrap(),
+            contents: "hello world"
+        }
+    ], 58, "Faces Places")]
+    #[test_case(vec![
+        TestFile {
+            path: AnchoredSystemPathBuf::from_raw("package.json").unwrap(),
+            contents: "Days of Heaven"
+        },
+        TestFile {
+            path: AnchoredSystemPathBuf::from_raw("package-lock.json").unwrap(),
+            contents: "Badlands"
+        }
+    ], 1284, "Cleo from 5 to 7")]
+    #[test_case(vec![
+        TestFile {
+            path: AnchoredSystemPathBuf::from_raw("package.json").unwrap(),
+            contents: "Days of Heaven"
+        },
+        TestFile {
+             path: AnchoredSystemPathBuf::from_raw("package-lock.json").unwrap(),
+             contents: "Badlands"
+        },
+        TestFile {
+            path: AnchoredSystemPathBuf::from_raw("src/main.js").unwrap(),
+            contents: "Tree of Life"
+        }
+    ], 12845, "The Gleaners and I")]
+    fn test_round_trip(files: Vec<TestFile>, duration: u32, hash: &str) -> Result<()> {
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+
+        for file in &files {
+            let file_path = repo_root_path.resolve(&file.path);
+            std::fs::create_dir_all(file_path.parent().unwrap())?;
+            std::fs::write(file_path, file.contents)?;
+        }
+
+        let cache = FSCache::new(None, &repo_root_path)?;
+
+        assert!(matches!(cache.exists(hash), Err(CacheError::CacheMiss(_))));
+
+        cache.put(
+            &repo_root_path,
+            hash,
+            files.iter().map(|f| f.path.clone()).collect(),
+            duration,
+        )?;
+
+        let cache_response = cache.exists(hash)?;
+        assert_eq!(cache_response.time_saved, duration);
+        assert_eq!(cache_response.source, CacheSource::Local);
+
+        let restore_dir = tempdir()?;
+        let restore_dir_path = AbsoluteSystemPathBuf::try_from(restore_dir.path())?;
+
+        let (cache_response, received_files) = cache.fetch(&restore_dir_path, hash)?;
+        assert_eq!(cache_response.time_saved, duration);
+        assert_eq!(cache_response.source, CacheSource::Local);
+
+        for (test_file, received_file) in files.iter().zip(received_files) {
+            assert_eq!(received_file, test_file.path);
+            let file_path = restore_dir_path.resolve(&received_file);
+            assert_eq!(std::fs::read_to_string(file_path)?, test_file.contents);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_override_dir() -> Result<()> {
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+
+        let default_cache = FSCache::new(None, &repo_root_path)?;
+        assert_eq!(
+            default_cache.cache_directory(),
+            &*repo_root_path.join_components(&["node_modules", ".cache", "turbo"])
+        );
+
+        let override_cache = FSCache::new(Some(".turbo-cache"), &repo_root_path)?;
+        assert_eq!(
+            override_cache.cache_directory(),
+            &*repo_root_path.join_component(".turbo-cache")
+        );
+        assert!(override_cache.cache_directory().as_std_path().is_dir());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reads_go_metadata() -> Result<()> {
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+        let cache = FSCache::new(None, &repo_root_path)?;
+
+        // An empty but valid artifact alongside metadata as written by Go.
+        CacheWriter::create(&cache.cache_directory().join_component("abc123.tar.zst"))?.finish()?;
+        cache
+            .cache_directory()
+            .join_component("abc123-meta.json")
+            .create_with_contents(r#"{"hash":"abc123","duration":42}"#)?;
+
+        let (response, files) = cache.fetch(&repo_root_path, "abc123")?;
+        assert_eq!(response.time_saved, 42);
+        assert!(files.is_empty());
+
+        Ok(())
+    }
+
+    #[test_case(None ; "missing metadata")]
+    #[test_case(Some("not json") ; "corrupt metadata")]
+    fn test_unreadable_metadata(metadata: Option<&str>) -> Result<()> {
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+        let file = AnchoredSystemPathBuf::from_raw("package.json")?;
+        std::fs::write(repo_root_path.resolve(&file), "{}")?;
+
+        let cache = FSCache::new(None, &repo_root_path)?;
+        cache.put(&repo_root_path, "abc123", vec![file.clone()], 42)?;
+        let metadata_path = cache.metadata_path("abc123");
+        match metadata {
+            Some(contents) => metadata_path.create_with_contents(contents)?,
+            None => metadata_path.remove_file()?,
+        }
+
+        // Both agree that it's a hit, there's just no time saved to report
+        assert_eq!(cache.exists("abc123")?.time_saved, 0);
+        let restore_dir = tempdir()?;
+        let restore_dir_path = AbsoluteSystemPathBuf::try_from(restore_dir.path())?;
+        let (response, files) = cache.fetch(&restore_dir_path, "abc123")?;
+        assert_eq!(response.time_saved, 0);
+        assert_eq!(files, vec![file]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune() -> Result<()> {
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+        let file = AnchoredSystemPathBuf::from_raw("package.json")?;
+        std::fs::write(repo_root_path.resolve(&file), "{}")?;
+
+        let cache = FSCache::new(None, &repo_root_path)?;
+        let now = SystemTime::now();
+        for (hash, age) in [
+            ("old", Duration::from_secs(10 * 24 * 60 * 60)),
+            ("recent", Duration::from_secs(2 * 60 * 60)),
+            ("new", Duration::ZERO),
+        ] {
+            cache.put(&repo_root_path, hash, vec![file.clone()], 0)?;
+            let modified = filetime::FileTime::from_system_time(now - age);
+            filetime::set_file_mtime(
+                cache
+                    .cache_directory()
+                    .join_component(&format!("{}.tar.zst", hash)),
+                modified,
+            )?;
+        }
+
+        let summary = cache.prune(None, Some(Duration::from_secs(24 * 60 * 60)))?;
+        assert_eq!(summary.removed, vec!["old".to_string()]);
+        assert!(matches!(cache.exists("old"), Err(CacheError::CacheMiss(_))));
+        assert!(!cache.metadata_path("old").exists());
+
+        // Shrinking the cache by a single byte evicts the oldest artifact left
+        let summary = cache.prune(Some(summary.remaining_bytes - 1), None)?;
+        assert_eq!(summary.removed, vec!["recent".to_string()]);
+        assert!(cache.exists("new").is_ok());
+
+        let summary = cache.prune(Some(summary.remaining_bytes), None)?;
+        assert!(summary.removed.is_empty());
+
+        Ok(())
+    }
+}
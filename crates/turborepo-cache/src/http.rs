@@ -1,14 +1,43 @@
-use std::{backtrace::Backtrace, io::Write};
+use std::{
+    backtrace::Backtrace,
+    io,
+    io::{Read, Write},
+};
 
+use futures::TryStreamExt;
+use tokio_util::io::{StreamReader, SyncIoBridge};
+use tracing::debug;
 use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
 use turborepo_api_client::{APIClient, Response};
 
 use crate::{
     cache_archive::{CacheReader, CacheWriter},
-    signature_authentication::ArtifactSignatureAuthenticator,
+    signature_authentication::{ArtifactSignatureAuthenticator, IncrementalValidator},
     CacheError, CacheResponse, CacheSource,
 };
 
+/// A compressed artifact as it was downloaded from the remote cache
+#[derive(Debug, Clone)]
+pub struct RemoteArtifact {
+    pub body: Vec<u8>,
+    pub tag: Option<String>,
+    pub duration: u32,
+}
+
+// Passes everything that's read through to the validator
+struct ValidatingReader<R> {
+    reader: R,
+    validator: IncrementalValidator,
+}
+
+impl<R: Read> Read for ValidatingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.validator.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
 pub struct HttpCache {
     client: APIClient,
     signer_verifier: Option<ArtifactSignatureAuthenticator>,
@@ -36,6 +65,18 @@ impl HttpCache {
         duration: u32,
         token: &str,
     ) -> Result<(), CacheError> {
+        // Without a private key we can't produce a tag that anyone else would
+        // accept, so there's no point in uploading
+        if let Some(signer) = self.signer_verifier.as_ref().filter(|s| !s.can_sign()) {
+            debug!(
+                "skipping upload of {}, artifacts are signed with {:?} and no private key is \
+                 available",
+                hash,
+                signer.algorithm()
+            );
+            return Ok(());
+        }
+
         let mut artifact_body = Vec::new();
         self.write(&mut artifact_body, anchor, files).await?;
 
@@ -66,6 +107,10 @@ impl HttpCache {
         Ok(())
     }
 
+    pub fn use_preflight(&self) -> bool {
+        self.client.use_preflight()
+    }
+
     pub async fn exists(
         &self,
         hash: &str,
@@ -77,7 +122,8 @@ impl HttpCache {
         let response = self
             .client
             .artifact_exists(hash, token, team_id, team_slug, use_preflight)
-            .await?;
+            .await
+            .map_err(Self::map_missing_artifact)?;
 
         let duration = Self::get_duration_from_response(&response)?;
 
@@ -87,6 +133,19 @@ impl HttpCache {
         })
     }
 
+    // The API responds with a 404 for artifacts it doesn't have, which we want
+    // to surface as a regular cache miss rather than a request failure.
+    fn map_missing_artifact(err: turborepo_api_client::Error) -> CacheError {
+        match err {
+            turborepo_api_client::Error::ReqwestError(e)
+                if e.status().map(|status| status.as_u16()) == Some(404) =>
+            {
+                CacheError::CacheMiss(Backtrace::capture())
+            }
+            e => e.into(),
+        }
+    }
+
     fn get_duration_from_response(response: &Response) -> Result<u32, CacheError> {
         if let Some(duration_value) = response.headers().get("x-artifact-duration") {
             let duration = duration_value
@@ -109,47 +168,63 @@ impl HttpCache {
         team_slug: Option<&str>,
         use_preflight: bool,
     ) -> Result<(CacheResponse, Vec<AnchoredSystemPathBuf>), CacheError> {
+        let validator = match &self.signer_verifier {
+            Some(signer_verifier) => {
+                match signer_verifier.incremental_validator(hash.as_bytes())? {
+                    Some(validator) => Some(validator),
+                    // Without an incremental validator we have to hold on to the
+                    // whole body to check it before anything gets restored
+                    None => {
+                        let artifact = self
+                            .download(hash, token, team_id, team_slug, use_preflight)
+                            .await?;
+                        self.verify(hash, &artifact)?;
+                        let files = Self::restore_tar(&self.repo_root, &artifact.body)?;
+
+                        return Ok((
+                            CacheResponse {
+                                source: CacheSource::Remote,
+                                time_saved: artifact.duration,
+                            },
+                            files,
+                        ));
+                    }
+                }
+            }
+            None => None,
+        };
+
         let response = self
             .client
             .fetch_artifact(hash, token, team_id, team_slug, use_preflight)
-            .await?;
+            .await
+            .map_err(Self::map_missing_artifact)?;
 
         let duration = Self::get_duration_from_response(&response)?;
-
-        let body = if let Some(signer_verifier) = &self.signer_verifier {
-            let expected_tag = response
-                .headers()
-                .get("x-artifact-tag")
-                .ok_or(CacheError::ArtifactTagMissing(Backtrace::capture()))?;
-
-            let expected_tag = expected_tag
-                .to_str()
-                .map_err(|_| CacheError::InvalidTag(Backtrace::capture()))?
-                .to_string();
-
-            let body = response.bytes().await.map_err(|e| {
-                CacheError::ApiClientError(
-                    turborepo_api_client::Error::ReqwestError(e),
-                    Backtrace::capture(),
-                )
-            })?;
-            let is_valid = signer_verifier.validate(hash.as_bytes(), &body, &expected_tag)?;
-
-            if !is_valid {
-                return Err(CacheError::InvalidTag(Backtrace::capture()));
-            }
-
-            body
-        } else {
-            response.bytes().await.map_err(|e| {
-                CacheError::ApiClientError(
-                    turborepo_api_client::Error::ReqwestError(e),
-                    Backtrace::capture(),
-                )
-            })?
+        let verification = match validator {
+            Some(validator) => Some((
+                validator,
+                Self::get_tag_from_response(&response)?
+                    .ok_or(CacheError::ArtifactTagMissing(Backtrace::capture()))?,
+            )),
+            None => None,
         };
 
-        let files = Self::restore_tar(&self.repo_root, &body)?;
+        // The archive is decompressed and written to disk as the body comes
+        // in, rather than after the whole artifact has been downloaded
+        let body = response
+            .bytes_stream()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+        let reader = SyncIoBridge::new(StreamReader::new(Box::pin(body)));
+        let repo_root = self.repo_root.clone();
+        let files = tokio::task::spawn_blocking(move || match verification {
+            Some((validator, expected_tag)) => {
+                Self::restore_verified(reader, &repo_root, validator, &expected_tag)
+            }
+            None => CacheReader::from_reader(reader, true)?.restore(&repo_root),
+        })
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
 
         Ok((
             CacheResponse {
@@ -160,6 +235,122 @@ impl HttpCache {
         ))
     }
 
+    // Restores the artifact into a staging directory while feeding the body to
+    // `validator`. Files are only moved into the repo once the tag checks out,
+    // otherwise the staging directory is thrown away along with them.
+    fn restore_verified(
+        reader: impl Read,
+        root: &AbsoluteSystemPath,
+        validator: IncrementalValidator,
+        expected_tag: &str,
+    ) -> Result<Vec<AnchoredSystemPathBuf>, CacheError> {
+        // The staging directory lives in the repo so that moving files out of
+        // it is a rename rather than a copy across filesystems
+        let staging_dir = tempfile::Builder::new()
+            .prefix(".turbo-restore-")
+            .tempdir_in(root)?;
+        let staging_root = AbsoluteSystemPathBuf::try_from(staging_dir.path())?;
+
+        let mut reader = ValidatingReader { reader, validator };
+        let files = CacheReader::from_reader(&mut reader, true)?.restore(&staging_root)?;
+        // The archive can end before the body does, every byte has to go
+        // through the validator for the tag to match
+        io::copy(&mut reader, &mut io::sink())?;
+
+        if !reader.validator.validate(expected_tag)? {
+            return Err(CacheError::InvalidTag(Backtrace::capture()));
+        }
+
+        for file in &files {
+            let staged = staging_root.resolve(file);
+            let destination = root.resolve(file);
+            if staged.symlink_metadata()?.is_dir() {
+                destination.create_dir_all()?;
+                continue;
+            }
+
+            if let Some(parent) = destination.parent() {
+                parent.create_dir_all()?;
+            }
+            // Renaming over an existing file isn't allowed everywhere
+            if destination
+                .symlink_metadata()
+                .map_or(false, |metadata| !metadata.is_dir())
+            {
+                destination.remove()?;
+            }
+            std::fs::rename(staged.as_std_path(), destination.as_std_path())?;
+        }
+
+        Ok(files)
+    }
+
+    fn get_tag_from_response(response: &Response) -> Result<Option<String>, CacheError> {
+        response
+            .headers()
+            .get("x-artifact-tag")
+            .map(|tag| {
+                tag.to_str()
+                    .map(|tag| tag.to_string())
+                    .map_err(|_| CacheError::InvalidTag(Backtrace::capture()))
+            })
+            .transpose()
+    }
+
+    /// Downloads the artifact for `hash` without verifying or restoring it
+    pub async fn download(
+        &self,
+        hash: &str,
+        token: &str,
+        team_id: &str,
+        team_slug: Option<&str>,
+        use_preflight: bool,
+    ) -> Result<RemoteArtifact, CacheError> {
+        let response = self
+            .client
+            .fetch_artifact(hash, token, team_id, team_slug, use_preflight)
+            .await
+            .map_err(Self::map_missing_artifact)?;
+
+        let duration = Self::get_duration_from_response(&response)?;
+        let tag = Self::get_tag_from_response(&response)?;
+
+        let body = response.bytes().await.map_err(|e| {
+            CacheError::ApiClientError(
+                Box::new(turborepo_api_client::Error::ReqwestError(e)),
+                Backtrace::capture(),
+            )
+        })?;
+
+        Ok(RemoteArtifact {
+            body: body.to_vec(),
+            tag,
+            duration,
+        })
+    }
+
+    /// Checks the tag of a downloaded artifact. Artifacts always pass when
+    /// signature verification isn't enabled.
+    pub fn verify(&self, hash: &str, artifact: &RemoteArtifact) -> Result<(), CacheError> {
+        let Some(signer_verifier) = &self.signer_verifier else {
+            return Ok(());
+        };
+
+        let expected_tag = artifact
+            .tag
+            .as_deref()
+            .ok_or(CacheError::ArtifactTagMissing(Backtrace::capture()))?;
+        if !signer_verifier.validate(hash.as_bytes(), &artifact.body, expected_tag)? {
+            return Err(CacheError::InvalidTag(Backtrace::capture()));
+        }
+
+        Ok(())
+    }
+
+    pub fn signer_verifier(&self) -> Option<&ArtifactSignatureAuthenticator> {
+        self.signer_verifier.as_ref()
+    }
+
     pub(crate) fn restore_tar(
         root: &AbsoluteSystemPath,
         body: &[u8],
@@ -178,7 +369,10 @@ mod test {
     use turborepo_api_client::APIClient;
     use vercel_api_mock::start_test_server;
 
-    use crate::{http::HttpCache, CacheSource};
+    use crate::{
+        cache_archive::CacheWriter, http::HttpCache,
+        signature_authentication::ArtifactSignatureAuthenticator, CacheError, CacheSource,
+    };
 
     struct TestFile {
         path: AnchoredSystemPathBuf,
@@ -260,4 +454,61 @@ mod test {
         handle.abort();
         Ok(())
     }
+
+    #[test_case(true ; "valid tag")]
+    #[test_case(false ; "invalid tag")]
+    fn test_restore_verified(valid_tag: bool) -> Result<()> {
+        let source = tempdir()?;
+        let source_path = AbsoluteSystemPathBuf::try_from(source.path())?;
+        std::fs::create_dir(source_path.join_component("dist").as_std_path())?;
+        std::fs::write(
+            source_path
+                .join_components(&["dist", "index.js"])
+                .as_std_path(),
+            "new",
+        )?;
+
+        let mut body = Vec::new();
+        let mut writer = CacheWriter::from_writer(&mut body, true)?;
+        writer.add_file(
+            &source_path,
+            &AnchoredSystemPathBuf::from_raw("dist/index.js")?,
+        )?;
+        writer.finish()?;
+
+        let signer =
+            ArtifactSignatureAuthenticator::new(b"team".to_vec(), Some(b"secret".to_vec()));
+        let tag = match valid_tag {
+            true => signer.generate_tag(b"hash", &body)?,
+            false => signer.generate_tag(b"other hash", &body)?,
+        };
+
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::try_from(repo_root.path())?;
+        let index = repo_root_path.join_components(&["dist", "index.js"]);
+        std::fs::create_dir(repo_root_path.join_component("dist").as_std_path())?;
+        std::fs::write(index.as_std_path(), "old")?;
+
+        let result = HttpCache::restore_verified(
+            &body[..],
+            &repo_root_path,
+            signer.incremental_validator(b"hash")?.unwrap(),
+            &tag,
+        );
+        if valid_tag {
+            assert_eq!(result?.len(), 1);
+            assert_eq!(std::fs::read_to_string(index.as_std_path())?, "new");
+        } else {
+            assert!(matches!(result, Err(CacheError::InvalidTag(_))));
+            assert_eq!(std::fs::read_to_string(index.as_std_path())?, "old");
+        }
+
+        // The staging directory never outlives the restore
+        let entries = std::fs::read_dir(repo_root.path())?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(entries, vec!["dist".to_string()]);
+
+        Ok(())
+    }
 }
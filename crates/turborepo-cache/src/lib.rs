@@ -1,15 +1,20 @@
 #![feature(error_generic_member_access)]
 #![feature(provide_any)]
 
+pub mod async_cache;
 pub mod cache_archive;
+pub mod fs;
 pub mod http;
+mod multiplexer;
 pub mod signature_authentication;
+pub mod size;
 
 use std::{backtrace, backtrace::Backtrace};
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::signature_authentication::SignatureError;
+use crate::signature_authentication::{SignatureAlgorithm, SignatureError};
 
 #[derive(Debug, Error)]
 pub enum CacheError {
@@ -25,7 +30,7 @@ pub enum CacheError {
     #[error("cannot untar file to {0}")]
     InvalidFilePath(String, #[backtrace] Backtrace),
     #[error("artifact verification failed: {0}")]
-    ApiClientError(#[from] turborepo_api_client::Error, #[backtrace] Backtrace),
+    ApiClientError(Box<turborepo_api_client::Error>, #[backtrace] Backtrace),
     #[error("signing artifact failed: {0}")]
     SignatureError(#[from] SignatureError, #[backtrace] Backtrace),
     #[error("invalid duration")]
@@ -50,6 +55,20 @@ pub enum CacheError {
     WindowsUnsafeName(String, #[backtrace] Backtrace),
     #[error("tar attempts to write outside of directory: {0}")]
     LinkOutsideOfDirectory(String, #[backtrace] Backtrace),
+    #[error("cache miss")]
+    CacheMiss(#[backtrace] Backtrace),
+    #[error("invalid cache metadata: {0}")]
+    InvalidMetadata(serde_json::Error, #[backtrace] Backtrace),
+    #[error("cache worker pool shut down unexpectedly")]
+    WorkerPoolShutDown(#[backtrace] Backtrace),
+}
+
+// The API client error is boxed to keep `CacheError` small, since it's
+// returned from nearly every function in this crate.
+impl From<turborepo_api_client::Error> for CacheError {
+    fn from(value: turborepo_api_client::Error) -> Self {
+        CacheError::ApiClientError(Box::new(value), Backtrace::capture())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -63,3 +82,35 @@ pub struct CacheResponse {
     source: CacheSource,
     time_saved: u32,
 }
+
+impl CacheResponse {
+    pub fn source(&self) -> &CacheSource {
+        &self.source
+    }
+
+    /// How long the task took when it originally ran, in milliseconds
+    pub fn time_saved(&self) -> u32 {
+        self.time_saved
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CacheOpts<'a> {
+    pub override_dir: Option<&'a str>,
+    pub skip_remote: bool,
+    pub skip_filesystem: bool,
+    pub workers: u32,
+    pub remote_cache_opts: Option<RemoteCacheOpts>,
+}
+
+/// The `remoteCache` options from `turbo.json`
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RemoteCacheOpts {
+    pub team_id: String,
+    pub signature: bool,
+    pub signature_algorithm: SignatureAlgorithm,
+    // The Ed25519 public key that artifacts are verified with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
@@ -0,0 +1,209 @@
+use std::{
+    backtrace::Backtrace,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use tracing::{debug, warn};
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf};
+use turborepo_api_client::{APIAuth, APIClient};
+
+use crate::{
+    fs::FSCache, http::HttpCache, signature_authentication::ArtifactSignatureAuthenticator,
+    CacheError, CacheOpts, CacheResponse,
+};
+
+// Combines the local and remote caches. Reads check the local cache first and
+// fall back to the remote cache, writes go to every enabled cache.
+pub struct CacheMultiplexer {
+    // If the remote cache reports that caching is disabled, we stop using it
+    // for the remainder of the run rather than failing every request.
+    should_use_http_cache: AtomicBool,
+    fs: Option<FSCache>,
+    http: Option<HttpCache>,
+    api_auth: Option<APIAuth>,
+}
+
+impl CacheMultiplexer {
+    pub fn new(
+        opts: &CacheOpts,
+        repo_root: &AbsoluteSystemPath,
+        api_client: APIClient,
+        api_auth: Option<APIAuth>,
+    ) -> Result<Self, CacheError> {
+        let use_fs_cache = !opts.skip_filesystem;
+        let use_http_cache = !opts.skip_remote && api_auth.is_some();
+
+        // Since the above two flags are not mutually exclusive it is possible to
+        // configure yourself out of having a cache. We should tell you about it
+        // but we shouldn't fail your build for that reason.
+        if !use_fs_cache && !use_http_cache {
+            warn!("no caches are enabled");
+        }
+
+        let fs_cache = use_fs_cache
+            .then(|| FSCache::new(opts.override_dir, repo_root))
+            .transpose()?;
+
+        let http_cache = use_http_cache.then(|| {
+            let signer_verifier = opts
+                .remote_cache_opts
+                .as_ref()
+                .and_then(ArtifactSignatureAuthenticator::from_remote_cache_opts);
+
+            HttpCache::new(api_client, signer_verifier, repo_root.to_owned())
+        });
+
+        Ok(CacheMultiplexer {
+            should_use_http_cache: AtomicBool::new(http_cache.is_some()),
+            fs: fs_cache,
+            http: http_cache,
+            api_auth,
+        })
+    }
+
+    // This is technically a TOCTOU bug, but at worst it'll cause
+    // a few extra cache requests.
+    fn get_http_cache(&self) -> Option<(&HttpCache, &APIAuth)> {
+        if self.should_use_http_cache.load(Ordering::Relaxed) {
+            self.http.as_ref().zip(self.api_auth.as_ref())
+        } else {
+            None
+        }
+    }
+
+    fn handle_http_error(&self, err: &CacheError) {
+        let CacheError::ApiClientError(api_error, _) = err else {
+            return;
+        };
+
+        if matches!(
+            **api_error,
+            turborepo_api_client::Error::CacheDisabled { .. }
+        ) {
+            warn!("remote caching is disabled, skipping the http cache for the rest of the run");
+            self.should_use_http_cache.store(false, Ordering::Relaxed);
+        }
+    }
+
+    pub fn has_http_cache(&self) -> bool {
+        self.get_http_cache().is_some()
+    }
+
+    pub fn put_fs(
+        &self,
+        anchor: &AbsoluteSystemPath,
+        key: &str,
+        files: &[AnchoredSystemPathBuf],
+        duration: u32,
+    ) -> Result<(), CacheError> {
+        if let Some(fs) = &self.fs {
+            fs.put(anchor, key, files.to_vec(), duration)?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn put_http(
+        &self,
+        anchor: &AbsoluteSystemPath,
+        key: &str,
+        files: &[AnchoredSystemPathBuf],
+        duration: u32,
+    ) -> Result<(), CacheError> {
+        let Some((http, api_auth)) = self.get_http_cache() else {
+            return Ok(());
+        };
+
+        let result = http
+            .put(anchor, key, files.to_vec(), duration, &api_auth.token)
+            .await;
+
+        if let Err(err) = &result {
+            self.handle_http_error(err);
+        }
+
+        result
+    }
+
+    pub async fn fetch(
+        &self,
+        anchor: &AbsoluteSystemPath,
+        key: &str,
+    ) -> Result<(CacheResponse, Vec<AnchoredSystemPathBuf>), CacheError> {
+        if let Some(fs) = &self.fs {
+            match fs.fetch(anchor, key) {
+                Ok(cache_response) => return Ok(cache_response),
+                Err(CacheError::CacheMiss(_)) => {}
+                Err(err) => debug!("failed to fetch {} from fs cache: {}", key, err),
+            }
+        }
+
+        if let Some((http, api_auth)) = self.get_http_cache() {
+            let result = http
+                .retrieve(
+                    key,
+                    &api_auth.token,
+                    &api_auth.team_id,
+                    api_auth.team_slug.as_deref(),
+                    http.use_preflight(),
+                )
+                .await;
+
+            match result {
+                Ok((cache_response, files)) => {
+                    // Write the artifact back to the local cache so the next
+                    // run doesn't need to go over the network.
+                    if let Some(fs) = &self.fs {
+                        if let Err(err) =
+                            fs.put(anchor, key, files.clone(), cache_response.time_saved)
+                        {
+                            warn!("failed to replicate {} to fs cache: {}", key, err);
+                        }
+                    }
+
+                    return Ok((cache_response, files));
+                }
+                Err(CacheError::CacheMiss(_)) => {}
+                Err(err) => {
+                    self.handle_http_error(&err);
+                    return Err(err);
+                }
+            }
+        }
+
+        Err(CacheError::CacheMiss(Backtrace::capture()))
+    }
+
+    pub async fn exists(&self, key: &str) -> Result<CacheResponse, CacheError> {
+        if let Some(fs) = &self.fs {
+            match fs.exists(key) {
+                Ok(cache_response) => return Ok(cache_response),
+                Err(CacheError::CacheMiss(_)) => {}
+                Err(err) => debug!("failed to check fs cache for {}: {}", key, err),
+            }
+        }
+
+        if let Some((http, api_auth)) = self.get_http_cache() {
+            let result = http
+                .exists(
+                    key,
+                    &api_auth.token,
+                    &api_auth.team_id,
+                    api_auth.team_slug.as_deref(),
+                    http.use_preflight(),
+                )
+                .await;
+
+            match result {
+                Ok(cache_response) => return Ok(cache_response),
+                Err(CacheError::CacheMiss(_)) => {}
+                Err(err) => {
+                    self.handle_http_error(&err);
+                    return Err(err);
+                }
+            }
+        }
+
+        Err(CacheError::CacheMiss(Backtrace::capture()))
+    }
+}
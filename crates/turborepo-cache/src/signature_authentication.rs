@@ -3,11 +3,29 @@ use std::env;
 use base64::{prelude::BASE64_STANDARD, Engine};
 use hmac::{Hmac, Mac};
 use os_str_bytes::OsStringBytes;
+use ring::signature::{Ed25519KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use thiserror::Error;
 
+use crate::RemoteCacheOpts;
+
 type HmacSha256 = Hmac<Sha256>;
 
+const PUBLIC_KEY_ENV_VAR: &str = "TURBO_REMOTE_CACHE_SIGNATURE_PUBLIC_KEY";
+const PRIVATE_KEY_ENV_VAR: &str = "TURBO_REMOTE_CACHE_SIGNATURE_PRIVATE_KEY";
+
+// Tags are either a bare base64 HMAC, which is what every version of turbo
+// before versioned tags produced, or `v2.<algorithm>.<base64 signature>`.
+const TAG_VERSION: &str = "v2";
+
+// The DER encoding of an Ed25519 SubjectPublicKeyInfo is this prefix followed
+// by the 32 byte key
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+const ED25519_KEY_LENGTH: usize = 32;
+
 #[derive(Debug, Error)]
 pub enum SignatureError {
     #[error(
@@ -15,6 +33,20 @@ pub enum SignatureError {
          TURBO_REMOTE_CACHE_SIGNATURE_KEY environment variable"
     )]
     NoSignatureSecretKey,
+    #[error(
+        "signature public key not found. You must specify a public key in remoteCache.publicKey \
+         in turbo.json or the TURBO_REMOTE_CACHE_SIGNATURE_PUBLIC_KEY environment variable"
+    )]
+    NoSignaturePublicKey,
+    #[error(
+        "signature private key not found. You must specify a private key in the \
+         TURBO_REMOTE_CACHE_SIGNATURE_PRIVATE_KEY environment variable"
+    )]
+    NoSignaturePrivateKey,
+    #[error("invalid Ed25519 {0} key")]
+    InvalidKey(&'static str),
+    #[error("unsupported artifact tag format: {0}")]
+    UnsupportedTag(String),
     #[error("serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
     #[error("base64 encoding error: {0}")]
@@ -23,11 +55,41 @@ pub enum SignatureError {
     Hmac(#[from] hmac::digest::InvalidLength),
 }
 
+/// How artifacts are signed, configured with `remoteCache.signatureAlgorithm`
+/// in `turbo.json`
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SignatureAlgorithm {
+    /// A shared secret that's used to both sign and verify artifacts
+    #[default]
+    #[serde(rename = "hmac-sha256")]
+    HmacSha256,
+    /// A key pair, where only machines with the private key can sign artifacts
+    /// and everyone else verifies them with the public key
+    #[serde(rename = "ed25519")]
+    Ed25519,
+}
+
+impl SignatureAlgorithm {
+    /// The name of the algorithm as it appears in `turbo.json` and in tags
+    pub fn tag_name(&self) -> &'static str {
+        match self {
+            SignatureAlgorithm::HmacSha256 => "hmac-sha256",
+            SignatureAlgorithm::Ed25519 => "ed25519",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ArtifactSignatureAuthenticator {
     team_id: Vec<u8>,
     // An override for testing purposes (to avoid env var race conditions)
     secret_key_override: Option<Vec<u8>>,
+    algorithm: SignatureAlgorithm,
+    // Ed25519 keys, either base64 or PEM encoded. The public key usually comes
+    // from turbo.json, while the private key should only ever be provided
+    // through the environment.
+    public_key: Option<String>,
+    private_key_override: Option<String>,
 }
 
 impl ArtifactSignatureAuthenticator {
@@ -35,6 +97,72 @@ impl ArtifactSignatureAuthenticator {
         Self {
             team_id,
             secret_key_override,
+            algorithm: SignatureAlgorithm::HmacSha256,
+            public_key: None,
+            private_key_override: None,
+        }
+    }
+
+    /// Signs and verifies artifacts with an Ed25519 key pair. The public key
+    /// from the environment takes precedence over `public_key`.
+    pub fn new_ed25519(
+        team_id: Vec<u8>,
+        public_key: Option<String>,
+        private_key_override: Option<String>,
+    ) -> Self {
+        Self {
+            team_id,
+            secret_key_override: None,
+            algorithm: SignatureAlgorithm::Ed25519,
+            public_key,
+            private_key_override,
+        }
+    }
+
+    /// Builds the authenticator described by the `remoteCache` options of
+    /// `turbo.json`, or `None` if signing isn't enabled
+    pub fn from_remote_cache_opts(opts: &RemoteCacheOpts) -> Option<Self> {
+        if !opts.signature {
+            return None;
+        }
+
+        let team_id = opts.team_id.as_bytes().to_vec();
+        Some(match opts.signature_algorithm {
+            SignatureAlgorithm::HmacSha256 => Self::new(team_id, None),
+            SignatureAlgorithm::Ed25519 => {
+                Self::new_ed25519(team_id, opts.public_key.clone(), None)
+            }
+        })
+    }
+
+    /// Starts verifying an artifact whose body is fed in as it's downloaded.
+    /// Ed25519 signatures can only be checked over the whole body, so there's
+    /// no incremental validator for them.
+    pub fn incremental_validator(
+        &self,
+        hash: &[u8],
+    ) -> Result<Option<IncrementalValidator>, SignatureError> {
+        match self.algorithm {
+            SignatureAlgorithm::HmacSha256 => Ok(Some(IncrementalValidator {
+                mac: self.get_tag_generator(hash)?,
+            })),
+            SignatureAlgorithm::Ed25519 => Ok(None),
+        }
+    }
+
+    pub fn algorithm(&self) -> SignatureAlgorithm {
+        self.algorithm
+    }
+
+    /// Whether this machine has the key needed to sign artifacts. With Ed25519
+    /// signing, machines that only have the public key can verify artifacts
+    /// but not produce them.
+    pub fn can_sign(&self) -> bool {
+        match self.algorithm {
+            SignatureAlgorithm::HmacSha256 => true,
+            SignatureAlgorithm::Ed25519 => {
+                self.private_key_override.is_some() || env::var_os(PRIVATE_KEY_ENV_VAR).is_some()
+            }
         }
     }
 
@@ -52,6 +180,42 @@ impl ArtifactSignatureAuthenticator {
             .into_raw_vec())
     }
 
+    fn key_pair(&self) -> Result<Ed25519KeyPair, SignatureError> {
+        let private_key = match &self.private_key_override {
+            Some(private_key) => private_key.clone(),
+            None => {
+                env::var(PRIVATE_KEY_ENV_VAR).map_err(|_| SignatureError::NoSignaturePrivateKey)?
+            }
+        };
+        let der = decode_key(&private_key)?;
+
+        // Either a bare 32 byte seed or a PKCS#8 document, like the ones
+        // produced by `openssl genpkey -algorithm ed25519`
+        let key_pair = match der.len() {
+            ED25519_KEY_LENGTH => Ed25519KeyPair::from_seed_unchecked(&der),
+            _ => Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der),
+        };
+        key_pair.map_err(|_| SignatureError::InvalidKey("private"))
+    }
+
+    fn public_key(&self) -> Result<Vec<u8>, SignatureError> {
+        let public_key = match env::var(PUBLIC_KEY_ENV_VAR) {
+            Ok(public_key) => public_key,
+            Err(_) => self
+                .public_key
+                .clone()
+                .ok_or(SignatureError::NoSignaturePublicKey)?,
+        };
+        let der = decode_key(&public_key)?;
+
+        // Either the bare key or a SubjectPublicKeyInfo, like the ones
+        // produced by `openssl pkey -pubout`
+        match der.strip_prefix(&ED25519_SPKI_PREFIX).unwrap_or(&der) {
+            key if key.len() == ED25519_KEY_LENGTH => Ok(key.to_vec()),
+            _ => Err(SignatureError::InvalidKey("public")),
+        }
+    }
+
     fn construct_metadata(&self, hash: &[u8]) -> Result<Vec<u8>, SignatureError> {
         let mut metadata = hash.to_vec();
         metadata.extend_from_slice(&self.team_id);
@@ -73,44 +237,137 @@ impl ArtifactSignatureAuthenticator {
         hash: &[u8],
         artifact_body: &[u8],
     ) -> Result<Vec<u8>, SignatureError> {
-        let mut mac = self.get_tag_generator(hash)?;
-
-        mac.update(artifact_body);
-        let hmac_output = mac.finalize();
-        Ok(hmac_output.into_bytes().to_vec())
+        match self.algorithm {
+            SignatureAlgorithm::HmacSha256 => {
+                let mut mac = self.get_tag_generator(hash)?;
+
+                mac.update(artifact_body);
+                let hmac_output = mac.finalize();
+                Ok(hmac_output.into_bytes().to_vec())
+            }
+            SignatureAlgorithm::Ed25519 => {
+                let mut message = self.construct_metadata(hash)?;
+                message.extend_from_slice(artifact_body);
+                Ok(self.key_pair()?.sign(&message).as_ref().to_vec())
+            }
+        }
     }
 
+    /// Generates the `x-artifact-tag` for an artifact. HMAC tags keep the
+    /// original unversioned format so that older versions of turbo can still
+    /// verify them.
     pub fn generate_tag(
         &self,
         hash: &[u8],
         artifact_body: &[u8],
     ) -> Result<String, SignatureError> {
-        let mut hmac_ctx = self.get_tag_generator(hash)?;
-
-        hmac_ctx.update(artifact_body);
-        let hmac_output = hmac_ctx.finalize();
-        Ok(BASE64_STANDARD.encode(hmac_output.into_bytes()))
+        let tag = BASE64_STANDARD.encode(self.generate_tag_bytes(hash, artifact_body)?);
+        match self.algorithm {
+            SignatureAlgorithm::HmacSha256 => Ok(tag),
+            SignatureAlgorithm::Ed25519 => Ok(format!(
+                "{}.{}.{}",
+                TAG_VERSION,
+                self.algorithm.tag_name(),
+                tag
+            )),
+        }
     }
 
+    /// Checks an artifact against its tag. Tags produced with a different
+    /// algorithm than the configured one are never valid, otherwise anyone
+    /// with the shared HMAC secret could get around Ed25519 signing.
     pub fn validate(
         &self,
         hash: &[u8],
         artifact_body: &[u8],
         expected_tag: &str,
     ) -> Result<bool, SignatureError> {
-        let mut mac = HmacSha256::new_from_slice(&self.secret_key()?)?;
-        let message = self.construct_metadata(hash)?;
-        mac.update(&message);
-        mac.update(artifact_body);
+        let (algorithm, expected_tag) = parse_tag(expected_tag)?;
+        if algorithm != self.algorithm {
+            return Ok(false);
+        }
+        let expected_bytes = BASE64_STANDARD.decode(expected_tag)?;
+        let mut message = self.construct_metadata(hash)?;
+
+        match self.algorithm {
+            SignatureAlgorithm::HmacSha256 => {
+                let mut mac = HmacSha256::new_from_slice(&self.secret_key()?)?;
+                mac.update(&message);
+                mac.update(artifact_body);
+
+                Ok(mac.verify_slice(&expected_bytes).is_ok())
+            }
+            SignatureAlgorithm::Ed25519 => {
+                message.extend_from_slice(artifact_body);
+                let public_key = self.public_key()?;
+
+                Ok(UnparsedPublicKey::new(&ED25519, public_key)
+                    .verify(&message, &expected_bytes)
+                    .is_ok())
+            }
+        }
+    }
+}
+
+/// Checks an HMAC tag against an artifact body that's fed in one chunk at a
+/// time
+pub struct IncrementalValidator {
+    mac: HmacSha256,
+}
 
+impl IncrementalValidator {
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.mac.update(chunk);
+    }
+
+    pub fn validate(self, expected_tag: &str) -> Result<bool, SignatureError> {
+        let (algorithm, expected_tag) = parse_tag(expected_tag)?;
+        if algorithm != SignatureAlgorithm::HmacSha256 {
+            return Ok(false);
+        }
         let expected_bytes = BASE64_STANDARD.decode(expected_tag)?;
-        Ok(mac.verify_slice(&expected_bytes).is_ok())
+
+        Ok(self.mac.verify_slice(&expected_bytes).is_ok())
     }
 }
 
+// Splits a tag into the algorithm that produced it and the base64 encoded
+// signature
+fn parse_tag(tag: &str) -> Result<(SignatureAlgorithm, &str), SignatureError> {
+    let Some(versioned) = tag
+        .strip_prefix(TAG_VERSION)
+        .and_then(|tag| tag.strip_prefix('.'))
+    else {
+        return Ok((SignatureAlgorithm::HmacSha256, tag));
+    };
+
+    let (algorithm, signature) = versioned
+        .split_once('.')
+        .ok_or_else(|| SignatureError::UnsupportedTag(tag.to_string()))?;
+    let algorithm = [SignatureAlgorithm::HmacSha256, SignatureAlgorithm::Ed25519]
+        .into_iter()
+        .find(|known| known.tag_name() == algorithm)
+        .ok_or_else(|| SignatureError::UnsupportedTag(tag.to_string()))?;
+
+    Ok((algorithm, signature))
+}
+
+// Keys can be given either as PEM or as the base64 encoding of their DER
+// bytes
+fn decode_key(key: &str) -> Result<Vec<u8>, SignatureError> {
+    let body = key
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("-----"))
+        .collect::<String>();
+
+    Ok(BASE64_STANDARD.decode(body)?)
+}
+
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
+    use ring::signature::KeyPair;
 
     use super::*;
 
@@ -242,10 +499,7 @@ mod tests {
 
     fn test_signature(test_case: TestCase) -> Result<()> {
         env::set_var("TURBO_REMOTE_CACHE_SIGNATURE_KEY", test_case.secret_key);
-        let signature = ArtifactSignatureAuthenticator {
-            team_id: test_case.team_id.to_vec(),
-            secret_key_override: None,
-        };
+        let signature = ArtifactSignatureAuthenticator::new(test_case.team_id.to_vec(), None);
 
         let hash = test_case.artifact_hash;
         let artifact_body = &test_case.artifact_body;
@@ -270,4 +524,150 @@ mod tests {
         assert!(signature.validate(hash, artifact_body, &tag)?);
         Ok(())
     }
+
+    const TEAM_ID: &[u8] = b"team_id";
+    const HASH: &[u8] = b"d5b7e4688f";
+    const BODY: &[u8] = &[5, 72, 219, 39, 156];
+
+    fn generate_key_pair() -> (String, String) {
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let mut spki = ED25519_SPKI_PREFIX.to_vec();
+        spki.extend_from_slice(key_pair.public_key().as_ref());
+
+        (
+            BASE64_STANDARD.encode(pkcs8.as_ref()),
+            BASE64_STANDARD.encode(spki),
+        )
+    }
+
+    #[test]
+    fn test_ed25519_signatures() -> Result<()> {
+        let (private_key, public_key) = generate_key_pair();
+        let signer = ArtifactSignatureAuthenticator::new_ed25519(
+            TEAM_ID.to_vec(),
+            Some(public_key.clone()),
+            Some(private_key),
+        );
+        let verifier =
+            ArtifactSignatureAuthenticator::new_ed25519(TEAM_ID.to_vec(), Some(public_key), None);
+
+        let tag = signer.generate_tag(HASH, BODY)?;
+        assert!(tag.starts_with("v2.ed25519."));
+        assert!(verifier.validate(HASH, BODY, &tag)?);
+        assert!(!verifier.validate(HASH, &[1, 2, 3], &tag)?);
+        assert!(!verifier.validate(b"other hash", BODY, &tag)?);
+
+        // A key pair that the verifier doesn't trust can't produce valid tags
+        let (other_private_key, _) = generate_key_pair();
+        let forger = ArtifactSignatureAuthenticator::new_ed25519(
+            TEAM_ID.to_vec(),
+            None,
+            Some(other_private_key),
+        );
+        let forged_tag = forger.generate_tag(HASH, BODY)?;
+        assert!(!verifier.validate(HASH, BODY, &forged_tag)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ed25519_pem_keys() -> Result<()> {
+        // Generated with `openssl genpkey -algorithm ed25519` and
+        // `openssl pkey -pubout`
+        let private_key =
+            "-----BEGIN PRIVATE \
+             KEY-----\nMC4CAQAwBQYDK2VwBCIEIPQ+dx1CzqMhp95jk5o3J6HSUz+vOvRebvWDz3tENVdC\n-----END \
+             PRIVATE KEY-----\n";
+        let key_pair = ArtifactSignatureAuthenticator::new_ed25519(
+            TEAM_ID.to_vec(),
+            None,
+            Some(private_key.to_string()),
+        )
+        .key_pair()?;
+        let mut spki = ED25519_SPKI_PREFIX.to_vec();
+        spki.extend_from_slice(key_pair.public_key().as_ref());
+        let public_key = format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+            BASE64_STANDARD.encode(spki)
+        );
+
+        let authenticator = ArtifactSignatureAuthenticator::new_ed25519(
+            TEAM_ID.to_vec(),
+            Some(public_key),
+            Some(private_key.to_string()),
+        );
+        let tag = authenticator.generate_tag(HASH, BODY)?;
+        assert!(authenticator.validate(HASH, BODY, &tag)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tags_from_other_algorithms_are_rejected() -> Result<()> {
+        let (private_key, public_key) = generate_key_pair();
+        let hmac = ArtifactSignatureAuthenticator::new(TEAM_ID.to_vec(), Some(b"secret".to_vec()));
+        let ed25519 = ArtifactSignatureAuthenticator::new_ed25519(
+            TEAM_ID.to_vec(),
+            Some(public_key),
+            Some(private_key),
+        );
+
+        let hmac_tag = hmac.generate_tag(HASH, BODY)?;
+        let ed25519_tag = ed25519.generate_tag(HASH, BODY)?;
+
+        assert!(!ed25519.validate(HASH, BODY, &hmac_tag)?);
+        assert!(!hmac.validate(HASH, BODY, &ed25519_tag)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_versioned_hmac_tags() -> Result<()> {
+        let hmac = ArtifactSignatureAuthenticator::new(TEAM_ID.to_vec(), Some(b"secret".to_vec()));
+
+        let tag = hmac.generate_tag(HASH, BODY)?;
+        // HMAC tags stay unversioned so older clients can read them, but the
+        // versioned form is understood as well
+        assert!(!tag.starts_with("v2."));
+        assert!(hmac.validate(HASH, BODY, &format!("v2.hmac-sha256.{}", tag))?);
+        assert!(matches!(
+            hmac.validate(HASH, BODY, &format!("v2.rsa.{}", tag)),
+            Err(SignatureError::UnsupportedTag(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_incremental_validation() -> Result<()> {
+        let hmac = ArtifactSignatureAuthenticator::new(TEAM_ID.to_vec(), Some(b"secret".to_vec()));
+        let tag = hmac.generate_tag(HASH, BODY)?;
+
+        let validate_in_chunks = |tag: &str| -> Result<bool> {
+            let mut validator = hmac.incremental_validator(HASH)?.unwrap();
+            for chunk in BODY.chunks(2) {
+                validator.update(chunk);
+            }
+            Ok(validator.validate(tag)?)
+        };
+        assert!(validate_in_chunks(&tag)?);
+        assert!(!validate_in_chunks(&BASE64_STANDARD.encode(b"bad tag"))?);
+
+        let ed25519 = ArtifactSignatureAuthenticator::new_ed25519(TEAM_ID.to_vec(), None, None);
+        assert!(ed25519.incremental_validator(HASH)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_can_sign() {
+        let verifier = ArtifactSignatureAuthenticator::new_ed25519(TEAM_ID.to_vec(), None, None);
+        let signer = ArtifactSignatureAuthenticator::new_ed25519(
+            TEAM_ID.to_vec(),
+            None,
+            Some(generate_key_pair().0),
+        );
+
+        // The private key env var is never set in tests
+        assert!(!verifier.can_sign());
+        assert!(signer.can_sign());
+        assert!(ArtifactSignatureAuthenticator::new(TEAM_ID.to_vec(), None).can_sign());
+    }
 }
@@ -0,0 +1,82 @@
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("invalid size '{0}', expected a number of bytes with an optional K, M, G or T suffix")]
+pub struct InvalidSize(pub String);
+
+/// Parses a size in bytes, e.g. `1048576`, `512M` or `10GB`. Suffixes are
+/// powers of 1024 and case insensitive.
+pub fn parse_size(size: &str) -> Result<u64, InvalidSize> {
+    let invalid = || InvalidSize(size.to_string());
+    let trimmed = size.trim();
+    let trimmed = trimmed
+        .strip_suffix(['B', 'b'])
+        .filter(|rest| rest.ends_with(|c: char| c.is_ascii_alphabetic()))
+        .unwrap_or(trimmed);
+
+    let (number, multiplier) = match trimmed.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&trimmed[..trimmed.len() - 1], 1 << 10),
+        Some('M') => (&trimmed[..trimmed.len() - 1], 1 << 20),
+        Some('G') => (&trimmed[..trimmed.len() - 1], 1 << 30),
+        Some('T') => (&trimmed[..trimmed.len() - 1], 1 << 40),
+        _ => (trimmed, 1),
+    };
+
+    number
+        .parse::<u64>()
+        .map_err(|_| invalid())?
+        .checked_mul(multiplier)
+        .ok_or_else(invalid)
+}
+
+/// Formats a size in bytes for humans, e.g. `1.5 KB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("0", 0 ; "zero")]
+    #[test_case("1024", 1024 ; "plain number")]
+    #[test_case("512K", 512 << 10 ; "kilobytes")]
+    #[test_case("512M", 512 << 20 ; "megabytes")]
+    #[test_case("10G", 10 << 30 ; "gigabytes")]
+    #[test_case("10GB", 10 << 30 ; "gigabytes with suffix")]
+    #[test_case("10gb", 10 << 30 ; "lowercase with suffix")]
+    #[test_case("2t", 2 << 40 ; "lowercase")]
+    fn test_parse_size(size: &str, expected: u64) {
+        assert_eq!(parse_size(size), Ok(expected));
+    }
+
+    #[test_case("" ; "empty")]
+    #[test_case("G" ; "no number")]
+    #[test_case("lots" ; "not a number")]
+    #[test_case("1.5G" ; "fraction")]
+    #[test_case("99999999999T" ; "overflow")]
+    fn test_parse_invalid_size(size: &str) {
+        assert_eq!(parse_size(size), Err(InvalidSize(size.to_string())));
+    }
+
+    #[test_case(512, "512 B" ; "bytes")]
+    #[test_case(1536, "1.5 KB" ; "kilobytes")]
+    #[test_case(3 << 30, "3.0 GB" ; "gigabytes")]
+    #[test_case(5 << 50, "5120.0 TB" ; "beyond terabytes")]
+    fn test_format_size(bytes: u64, expected: &str) {
+        assert_eq!(format_size(bytes), expected);
+    }
+}
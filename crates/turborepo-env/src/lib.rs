@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     env,
     ops::{Deref, DerefMut},
     string::ToString,
@@ -7,9 +7,11 @@ use std::{
 
 use regex::Regex;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
-const DEFAULT_ENV_VARS: [&str; 1] = ["VERCEL_ANALYTICS_ID"];
+// Environment variables that are always included in the global hash
+pub const DEFAULT_ENV_VARS: [&str; 1] = ["VERCEL_ANALYTICS_ID"];
 
 #[derive(Clone, Debug, Error)]
 pub enum Error {
@@ -22,6 +24,9 @@ pub enum Error {
 #[serde(transparent)]
 pub struct EnvironmentVariableMap(HashMap<String, String>);
 
+// A list of "k=v" strings for env variables and their values
+pub type EnvironmentVariablePairs = Vec<String>;
+
 // BySource contains a map of environment variables broken down by the source
 #[derive(Debug, Serialize)]
 pub struct BySource {
@@ -45,6 +50,20 @@ pub struct WildcardMaps {
     pub exclusions: EnvironmentVariableMap,
 }
 
+// WildcardMatch records which patterns matched an environment variable.
+// Exclusions always win over inclusions.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct WildcardMatch {
+    pub included_by: Option<String>,
+    pub excluded_by: Option<String>,
+}
+
+impl WildcardMatch {
+    pub fn is_included(&self) -> bool {
+        self.included_by.is_some() && self.excluded_by.is_none()
+    }
+}
+
 impl WildcardMaps {
     // Resolve collapses a WildcardSet into a single EnvironmentVariableMap.
     fn resolve(self) -> EnvironmentVariableMap {
@@ -83,6 +102,27 @@ impl EnvironmentVariableMap {
         self.0
     }
 
+    // Returns a deterministically sorted set of EnvironmentVariablePairs from an
+    // EnvironmentVariableMap. This is the value used as a task hash input.
+    pub fn to_hashable(&self) -> EnvironmentVariablePairs {
+        let mut pairs: Vec<String> = self.0.iter().map(|(k, v)| format!("{k}={v}")).collect();
+        pairs.sort();
+        pairs
+    }
+
+    // Returns a deterministically sorted set of EnvironmentVariablePairs from an
+    // EnvironmentVariableMap. This is the value used to print out the task hash
+    // input, so the values are cryptographically hashed.
+    pub fn to_secret_hashable(&self) -> EnvironmentVariablePairs {
+        let mut pairs: Vec<String> = self
+            .0
+            .iter()
+            .map(|(k, v)| format!("{k}={}", secret_hash(v)))
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
     // Takes another EnvironmentVariableMap and adds it into `self`
     // Overwrites values if they already exist.
     pub fn union(&mut self, another: &EnvironmentVariableMap) {
@@ -113,16 +153,9 @@ impl EnvironmentVariableMap {
         let mut exclude_patterns = Vec::new();
 
         for wildcard_pattern in wildcard_patterns {
-            let wildcard_pattern = wildcard_pattern.as_ref();
-            if let Some(rest) = wildcard_pattern.strip_prefix('!') {
-                let exclude_pattern = wildcard_to_regex_pattern(rest);
-                exclude_patterns.push(exclude_pattern);
-            } else if wildcard_pattern.starts_with("\\!") {
-                let include_pattern = wildcard_to_regex_pattern(&wildcard_pattern[1..]);
-                include_patterns.push(include_pattern);
-            } else {
-                let include_pattern = wildcard_to_regex_pattern(wildcard_pattern);
-                include_patterns.push(include_pattern);
+            match parse_wildcard(wildcard_pattern.as_ref()) {
+                Wildcard::Exclude(exclude_pattern) => exclude_patterns.push(exclude_pattern),
+                Wildcard::Include(include_pattern) => include_patterns.push(include_pattern),
             }
         }
 
@@ -173,6 +206,74 @@ impl EnvironmentVariableMap {
 
         self.wildcard_map_from_wildcards(wildcard_patterns)
     }
+
+    // Returns every variable in the environment that is matched by at least one
+    // of the wildcard patterns, along with the first pattern that included it
+    // and the first one that excluded it. Used to explain why a variable was or
+    // wasn't included by `from_wildcards`.
+    pub fn explain_wildcards(
+        &self,
+        wildcard_patterns: &[impl AsRef<str>],
+    ) -> Result<BTreeMap<String, WildcardMatch>, regex::Error> {
+        let patterns = wildcard_patterns
+            .iter()
+            .map(|wildcard_pattern| {
+                let wildcard_pattern = wildcard_pattern.as_ref();
+                let (is_exclusion, regex_pattern) = match parse_wildcard(wildcard_pattern) {
+                    Wildcard::Exclude(pattern) => (true, pattern),
+                    Wildcard::Include(pattern) => (false, pattern),
+                };
+                let regex = Regex::new(&format!("^({})$", regex_pattern))?;
+                Ok((wildcard_pattern, is_exclusion, regex))
+            })
+            .collect::<Result<Vec<_>, regex::Error>>()?;
+
+        let mut output = BTreeMap::new();
+        for env_var in self.0.keys() {
+            let mut wildcard_match = WildcardMatch::default();
+            for (wildcard_pattern, is_exclusion, regex) in &patterns {
+                if !regex.is_match(env_var) {
+                    continue;
+                }
+                let matched_by = match is_exclusion {
+                    true => &mut wildcard_match.excluded_by,
+                    false => &mut wildcard_match.included_by,
+                };
+                matched_by.get_or_insert_with(|| wildcard_pattern.to_string());
+            }
+            if wildcard_match != WildcardMatch::default() {
+                output.insert(env_var.clone(), wildcard_match);
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+// Returns the value of an environment variable in the form that is shown to
+// users, so that secrets never get printed
+pub fn secret_hash(value: &str) -> String {
+    match value.is_empty() {
+        true => String::new(),
+        false => format!("{:x}", Sha256::digest(value.as_bytes())),
+    }
+}
+
+enum Wildcard {
+    Include(String),
+    Exclude(String),
+}
+
+// Splits a wildcard pattern into whether it's an inclusion or a "!" exclusion,
+// and the regex that matches it. A leading "\!" is a literal "!".
+fn parse_wildcard(wildcard_pattern: &str) -> Wildcard {
+    if let Some(rest) = wildcard_pattern.strip_prefix('!') {
+        Wildcard::Exclude(wildcard_to_regex_pattern(rest))
+    } else if wildcard_pattern.starts_with("\\!") {
+        Wildcard::Include(wildcard_to_regex_pattern(&wildcard_pattern[1..]))
+    } else {
+        Wildcard::Include(wildcard_to_regex_pattern(wildcard_pattern))
+    }
 }
 
 const WILDCARD: char = '*';
@@ -262,4 +363,63 @@ mod tests {
         let actual = super::wildcard_to_regex_pattern(pattern);
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_to_secret_hashable() {
+        let env = super::EnvironmentVariableMap(
+            [
+                ("SECRET".to_string(), "hunter2".to_string()),
+                ("EMPTY".to_string(), String::new()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            env.to_secret_hashable(),
+            vec![
+                "EMPTY=",
+                "SECRET=f52fbd32b2b3b86ff88ef6c490628285f482af15ddcb29541f94bcf526a3f6c7",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explain_wildcards() {
+        let env = super::EnvironmentVariableMap(
+            ["API_URL", "API_SECRET", "NEXT_PUBLIC_URL", "HOME"]
+                .into_iter()
+                .map(|name| (name.to_string(), "value".to_string()))
+                .collect(),
+        );
+        let explanation = env
+            .explain_wildcards(&["API_*", "!API_SECRET", "API_URL", "!OTHER"])
+            .unwrap();
+        let matched_by = |included_by: &str, excluded_by: Option<&str>| super::WildcardMatch {
+            included_by: Some(included_by.to_string()),
+            excluded_by: excluded_by.map(|pattern| pattern.to_string()),
+        };
+        assert_eq!(
+            explanation.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    "API_SECRET".to_string(),
+                    matched_by("API_*", Some("!API_SECRET"))
+                ),
+                ("API_URL".to_string(), matched_by("API_*", None)),
+            ]
+        );
+        assert_eq!(
+            env.explain_wildcards(&["API_*", "!API_SECRET"])
+                .unwrap()
+                .into_iter()
+                .filter(|(_, wildcard_match)| wildcard_match.is_included())
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>(),
+            env.from_wildcards(&["API_*", "!API_SECRET"])
+                .unwrap()
+                .into_inner()
+                .into_keys()
+                .collect::<Vec<_>>(),
+        );
+    }
 }
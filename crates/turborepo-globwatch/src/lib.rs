@@ -48,6 +48,7 @@ use tracing::{error, event, span, trace, warn, Level};
 pub struct GlobWatcher {
     stream: UnboundedReceiver<Event>,
     flush_dir: PathBuf,
+    stats: Arc<WatcherStats>,
 
     config: UnboundedReceiver<WatcherCommand>,
     setup_handle: tokio::task::JoinHandle<Result<(), notify::Error>>,
@@ -63,6 +64,8 @@ impl GlobWatcher {
     ) -> Result<(Self, WatchConfig<notify::RecommendedWatcher>), notify::Error> {
         let (send_event, receive_event) = tokio::sync::mpsc::unbounded_channel();
         let (send_config, receive_config) = tokio::sync::mpsc::unbounded_channel();
+        let stats = Arc::new(WatcherStats::default());
+        let watcher_stats = stats.clone();
 
         // even if this fails, we may still be able to continue
         std::fs::create_dir_all(&flush_dir).ok();
@@ -74,16 +77,24 @@ impl GlobWatcher {
 
             let result = event.map(|e| {
                 trace!(parent: &span, "sending event: {:?}", e);
+                // the os dropped events and is asking us to rescan
+                if e.need_rescan() {
+                    watcher_stats.dropped.fetch_add(1, Ordering::Relaxed);
+                }
                 let tx = send_event.clone();
+                watcher_stats.received.fetch_add(1, Ordering::Relaxed);
                 futures::executor::block_on(async move { tx.send(e) })
             });
 
             match result {
                 Ok(Ok(_)) => {}
                 Ok(Err(e)) => {
+                    watcher_stats.received.fetch_sub(1, Ordering::Relaxed);
+                    watcher_stats.dropped.fetch_add(1, Ordering::Relaxed);
                     warn!(parent: &span, "watch server closed: {:?}", e);
                 }
                 Err(e) => {
+                    watcher_stats.dropped.fetch_add(1, Ordering::Relaxed);
                     warn!(parent: &span, "error from notify: {:?}", e);
                 }
             }
@@ -121,6 +132,7 @@ impl GlobWatcher {
             Self {
                 flush_dir,
                 stream: receive_event,
+                stats: stats.clone(),
                 config: receive_config,
                 setup_handle,
             },
@@ -128,6 +140,7 @@ impl GlobWatcher {
                 flush: send_config,
                 watcher,
                 setup_receiver,
+                stats,
             },
         ))
     }
@@ -151,6 +164,7 @@ impl GlobWatcher {
         let Self {
             setup_handle,
             flush_dir,
+            stats,
             ..
         } = self;
         let flush_id = Arc::new(AtomicU64::new(1));
@@ -189,10 +203,13 @@ impl GlobWatcher {
                     let flush_id = flush_id.clone();
                     let flush_dir = flush_dir.clone();
                     let flush = flush.clone();
+                    let stats = stats.clone();
 
                     async move {
                         match f {
                             Either::Left(mut e) => {
+                                stats.processed.fetch_add(1, Ordering::Relaxed);
+
                                 // if we receive an event for a file in the flush dir, we need to
                                 // remove it from the events list, and send a signal to the flush
                                 // requestor. flushes should not be considered as events.
@@ -275,6 +292,37 @@ pub struct WatchConfig<T> {
     flush: UnboundedSender<WatcherCommand>,
     watcher: Arc<Mutex<T>>,
     setup_receiver: watch::Receiver<Option<bool>>,
+    stats: Arc<WatcherStats>,
+}
+
+/// Counters describing how well the watcher is keeping up with the
+/// filesystem.
+#[derive(Debug, Default)]
+pub struct WatcherStats {
+    received: AtomicU64,
+    processed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl WatcherStats {
+    /// The number of events that have been taken off the queue, including
+    /// the ones used for flushing.
+    pub fn processed(&self) -> u64 {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    /// The number of events that are waiting to be processed.
+    pub fn backlog(&self) -> u64 {
+        self.received
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.processed())
+    }
+
+    /// The number of times events were lost, either because the os dropped
+    /// them or because they couldn't be queued.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
 }
 
 /// The server is no longer running.
@@ -291,6 +339,11 @@ pub enum ConfigError {
 }
 
 impl<T: Watcher> WatchConfig<T> {
+    /// The counters of the watcher this config belongs to.
+    pub fn stats(&self) -> &WatcherStats {
+        &self.stats
+    }
+
     /// Register a glob to be included by the watcher.
     #[tracing::instrument(skip(self))]
     pub async fn include(&self, relative_to: &Path, glob: &str) -> Result<(), ConfigError> {
@@ -616,6 +669,7 @@ mod test {
             setup_receiver: setup_rx,
             // Flush doesn't depend on watcher so we create a watcher for the unit type
             watcher: Arc::new(Mutex::new(())),
+            stats: Default::default(),
         };
         setup_tx
             .send(Some(false))
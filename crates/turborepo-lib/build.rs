@@ -11,6 +11,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::configure()
         .build_server(true)
         .file_descriptor_set_path("src/daemon/file_descriptor_set.bin")
+        // metrics are printed as is by `turbo daemon status --json`
+        .type_attribute(
+            ".turbodprotocol.DaemonMetrics",
+            "#[derive(serde::Serialize)]",
+        )
+        .type_attribute(".turbodprotocol.RpcLatency", "#[derive(serde::Serialize)]")
+        .type_attribute(
+            ".turbodprotocol.LatencyBucket",
+            "#[derive(serde::Serialize)]",
+        )
+        .type_attribute(".turbodprotocol.LastError", "#[derive(serde::Serialize)]")
         .compile(&["turbod.proto"], &["../../cli/internal/turbodprotocol"])?;
     Ok(())
 }
@@ -11,8 +11,11 @@ use turbopath::AbsoluteSystemPathBuf;
 #[cfg(feature = "run-stub")]
 use crate::commands::run;
 use crate::{
-    commands::{bin, daemon, generate, info, link, login, logout, unlink, CommandBase},
-    get_version,
+    commands::{
+        self, bin, cache, daemon, generate, info, link, lint_config, login, logout, prune, unlink,
+        watch, CommandBase,
+    },
+    config, get_version,
     shim::{RepoMode, RepoState},
     tracing::TurboSubscriber,
     ui::UI,
@@ -196,6 +199,80 @@ pub enum DaemonCommand {
     Clean,
 }
 
+#[derive(Subcommand, Clone, Debug, Serialize, PartialEq)]
+#[serde(tag = "command")]
+pub enum CacheCommand {
+    /// List the files in a cached artifact
+    Ls {
+        hash: String,
+        #[clap(flatten)]
+        #[serde(flatten)]
+        source: CacheSourceArgs,
+    },
+    /// Print a file from a cached artifact
+    Cat {
+        hash: String,
+        /// The path of the file within the artifact
+        path: String,
+        #[clap(flatten)]
+        #[serde(flatten)]
+        source: CacheSourceArgs,
+    },
+    /// Check the signature of an artifact in the remote cache
+    Verify { hash: String },
+    /// Restore a cached artifact into a directory
+    Restore {
+        hash: String,
+        /// The directory to restore the artifact into
+        #[clap(long)]
+        to: String,
+        #[clap(flatten)]
+        #[serde(flatten)]
+        source: CacheSourceArgs,
+    },
+    /// Remove artifacts from the local cache
+    Prune {
+        /// Remove the least recently written artifacts until the cache is at
+        /// most this size, e.g. 10G
+        #[clap(long, required_unless_present = "max_age")]
+        max_size: Option<String>,
+        /// Remove artifacts that were written longer ago than this, e.g. 7d
+        #[clap(long)]
+        max_age: Option<String>,
+        /// Override the filesystem cache directory
+        #[clap(long)]
+        cache_dir: Option<String>,
+    },
+}
+
+/// Where `turbo cache` looks for an artifact
+#[derive(Parser, Clone, Debug, Default, Serialize, PartialEq)]
+pub struct CacheSourceArgs {
+    /// Override the filesystem cache directory
+    #[clap(long)]
+    pub cache_dir: Option<String>,
+    /// Only look for the artifact in the remote cache
+    #[clap(long)]
+    pub remote: bool,
+}
+
+#[derive(Subcommand, Clone, Debug, Serialize, PartialEq)]
+#[serde(tag = "command")]
+pub enum EnvCommand {
+    /// Show which environment variables go into the hash of a task and which
+    /// rule included or excluded each of them. Values are shown as hashes.
+    Explain {
+        /// The task to explain, e.g. web#build
+        task: String,
+        /// The environment variable mode the task would be run with
+        #[clap(long = "env-mode", default_value = "infer")]
+        env_mode: EnvMode,
+        /// Specify whether or not to do framework inference for the task
+        #[clap(long, value_name = "BOOL", action = ArgAction::Set, default_value = "true", default_missing_value = "true", num_args = 0..=1)]
+        framework_inference: bool,
+    },
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, ValueEnum)]
 pub enum LinkTarget {
     RemoteCache,
@@ -249,7 +326,8 @@ impl Args {
 
     pub fn get_tasks(&self) -> &[String] {
         match &self.command {
-            Some(Command::Run(box RunArgs { tasks, .. })) => tasks,
+            Some(Command::Run(box RunArgs { tasks, .. }))
+            | Some(Command::Watch(box RunArgs { tasks, .. })) => tasks,
             _ => self
                 .run_args
                 .as_ref()
@@ -268,6 +346,12 @@ pub enum Command {
     // them as `{ "Bin": {} }` instead of as `"Bin"`.
     /// Get the path to the Turbo binary
     Bin {},
+    /// Inspect and manage cached task artifacts
+    Cache {
+        #[clap(subcommand)]
+        #[serde(flatten)]
+        command: CacheCommand,
+    },
     /// Generate the autocompletion script for the specified shell
     #[serde(skip)]
     Completion { shell: Shell },
@@ -280,6 +364,11 @@ pub enum Command {
         #[serde(flatten)]
         command: Option<DaemonCommand>,
     },
+    /// Inspect the environment variables of tasks
+    Env {
+        #[clap(subcommand)]
+        command: EnvCommand,
+    },
     /// Generate a new app / package
     #[clap(aliases = ["g", "gen"])]
     Generate {
@@ -316,6 +405,9 @@ pub enum Command {
         #[clap(long, value_enum, default_value_t = LinkTarget::RemoteCache)]
         target: LinkTarget,
     },
+    /// Check the turbo.json files of the repository for problems without
+    /// running any tasks
+    LintConfig {},
     /// Login to your Vercel account
     Login {
         #[clap(long = "sso-team")]
@@ -343,6 +435,9 @@ pub enum Command {
     ///
     /// Arguments passed after '--' will be passed through to the named tasks.
     Run(Box<RunArgs>),
+    /// Print the JSON Schema for turbo.json
+    #[clap(hide = true)]
+    Schema {},
     /// Unlink the current directory from your Vercel organization and disable
     /// Remote Caching
     Unlink {
@@ -350,6 +445,12 @@ pub enum Command {
         #[clap(long, value_enum, default_value_t = LinkTarget::RemoteCache)]
         target: LinkTarget,
     },
+    /// Run tasks and re-run them as files in the monorepo change
+    ///
+    /// Only the tasks of the workspaces whose files changed, and the tasks
+    /// that depend on them, are run again. Persistent tasks are started once
+    /// and kept running.
+    Watch(Box<RunArgs>),
 }
 
 #[derive(Parser, Clone, Debug, Default, Serialize, PartialEq)]
@@ -457,6 +558,11 @@ pub struct RunArgs {
     /// Strict uses an allowlist specified in turbo.json.
     #[clap(long = "env-mode", default_value = "infer", num_args = 0..=1, default_missing_value = "infer", hide = true)]
     pub env_mode: EnvMode,
+    /// Record the environment variables tasks read, so that turbo can warn
+    /// about the ones that aren't declared in turbo.json. Only node processes
+    /// are traced.
+    #[clap(long, hide = true)]
+    pub env_access_tracing: bool,
     /// Files to ignore when calculating changed files (i.e. --since).
     /// Supports globs.
     #[clap(long)]
@@ -597,7 +703,7 @@ pub async fn run(
     };
 
     // Set some run flags if we have the data and are executing a Run
-    if let Command::Run(run_args) = &mut command {
+    if let Command::Run(run_args) | Command::Watch(run_args) = &mut command {
         // Don't overwrite the flag if it's already been set for whatever reason
         run_args.single_package = run_args.single_package
             || repo_state
@@ -651,6 +757,13 @@ pub async fn run(
 
             Ok(Payload::Rust(Ok(0)))
         }
+        Command::Cache { command } => {
+            let command = command.clone();
+            let base = CommandBase::new(cli_args, repo_root, version, ui)?;
+            let exit_code = cache::run(&base, &command).await?;
+
+            Ok(Payload::Rust(Ok(exit_code)))
+        }
         #[allow(unused_variables)]
         Command::Daemon { command, idle_time } => {
             let base = CommandBase::new(cli_args.clone(), repo_root, version, ui)?;
@@ -667,6 +780,13 @@ pub async fn run(
 
             Ok(Payload::Rust(Ok(0)))
         }
+        Command::Env { command } => {
+            let command = command.clone();
+            let base = CommandBase::new(cli_args, repo_root, version, ui)?;
+            let exit_code = commands::env::run(&base, &command)?;
+
+            Ok(Payload::Rust(Ok(exit_code)))
+        }
         Command::Generate {
             tag,
             generator_name,
@@ -693,6 +813,12 @@ pub async fn run(
 
             Ok(Payload::Rust(Ok(0)))
         }
+        Command::LintConfig {} => {
+            let base = CommandBase::new(cli_args, repo_root, version, ui)?;
+            let exit_code = lint_config::run(&base)?;
+
+            Ok(Payload::Rust(Ok(exit_code)))
+        }
         Command::Link {
             no_gitignore,
             target,
@@ -752,9 +878,9 @@ pub async fn run(
         #[cfg(feature = "run-stub")]
         Command::Run(args) => {
             let base = CommandBase::new(cli_args, repo_root, version, ui)?;
-            run::run(base).await?;
+            let exit_code = run::run(base).await?;
 
-            Ok(Payload::Rust(Ok(0)))
+            Ok(Payload::Rust(Ok(exit_code)))
         }
         #[cfg(not(feature = "run-stub"))]
         Command::Run(args) => {
@@ -764,9 +890,37 @@ pub async fn run(
             let base = CommandBase::new(cli_args, repo_root, version, UI::new(true))?;
             Ok(Payload::Go(Box::new(base)))
         }
-        Command::Prune { .. } => {
-            let base = CommandBase::new(cli_args, repo_root, version, UI::new(true))?;
-            Ok(Payload::Go(Box::new(base)))
+        Command::Watch(args) => {
+            if args.tasks.is_empty() {
+                return Err(anyhow!("at least one task must be specified"));
+            }
+            if args.dry_run.is_some() || args.graph.is_some() {
+                return Err(anyhow!(
+                    "--dry-run and --graph are not supported in watch mode"
+                ));
+            }
+            let base = CommandBase::new(cli_args, repo_root, version, ui)?;
+            let exit_code = watch::run(base).await?;
+
+            Ok(Payload::Rust(Ok(exit_code)))
+        }
+        Command::Prune {
+            scope,
+            docker,
+            output_dir,
+        } => {
+            let scope = scope.clone();
+            let docker = *docker;
+            let output_dir = output_dir.clone();
+            let base = CommandBase::new(cli_args, repo_root, version, ui)?;
+            prune::prune(&base, &scope, docker, &output_dir)?;
+
+            Ok(Payload::Rust(Ok(0)))
+        }
+        Command::Schema {} => {
+            print!("{}", config::render_schema());
+
+            Ok(Payload::Rust(Ok(0)))
         }
         Command::Completion { shell } => {
             generate(*shell, &mut Args::command(), "turbo", &mut io::stdout());
@@ -835,7 +989,8 @@ mod test {
     use anyhow::Result;
 
     use crate::cli::{
-        Args, Command, DryRunMode, EnvMode, LogOrder, LogPrefix, OutputLogsMode, RunArgs, Verbosity,
+        Args, CacheCommand, CacheSourceArgs, Command, DryRunMode, EnvCommand, EnvMode, LogOrder,
+        LogPrefix, OutputLogsMode, RunArgs, Verbosity,
     };
 
     #[test]
@@ -970,6 +1125,19 @@ mod test {
             "env_mode: specified strict"
         );
 
+        assert_eq!(
+            Args::try_parse_from(["turbo", "run", "build", "--env-access-tracing"]).unwrap(),
+            Args {
+                command: Some(Command::Run(Box::new(RunArgs {
+                    tasks: vec!["build".to_string()],
+                    env_access_tracing: true,
+                    ..get_default_run_args()
+                }))),
+                ..Args::default()
+            },
+            "env_access_tracing"
+        );
+
         assert_eq!(
             Args::try_parse_from(["turbo", "run", "build", "lint", "test"]).unwrap(),
             Args {
@@ -1524,6 +1692,92 @@ mod test {
         .test();
     }
 
+    #[test]
+    fn test_parse_cache() {
+        assert_eq!(
+            Args::try_parse_from(["turbo", "cache", "ls", "abc123"]).unwrap(),
+            Args {
+                command: Some(Command::Cache {
+                    command: CacheCommand::Ls {
+                        hash: "abc123".to_string(),
+                        source: CacheSourceArgs::default(),
+                    }
+                }),
+                ..Args::default()
+            }
+        );
+
+        assert_eq!(
+            Args::try_parse_from([
+                "turbo",
+                "cache",
+                "cat",
+                "abc123",
+                "dist/index.js",
+                "--remote"
+            ])
+            .unwrap(),
+            Args {
+                command: Some(Command::Cache {
+                    command: CacheCommand::Cat {
+                        hash: "abc123".to_string(),
+                        path: "dist/index.js".to_string(),
+                        source: CacheSourceArgs {
+                            cache_dir: None,
+                            remote: true,
+                        },
+                    }
+                }),
+                ..Args::default()
+            }
+        );
+
+        assert_eq!(
+            Args::try_parse_from([
+                "turbo",
+                "cache",
+                "restore",
+                "abc123",
+                "--to",
+                "out",
+                "--cache-dir",
+                ".cache"
+            ])
+            .unwrap(),
+            Args {
+                command: Some(Command::Cache {
+                    command: CacheCommand::Restore {
+                        hash: "abc123".to_string(),
+                        to: "out".to_string(),
+                        source: CacheSourceArgs {
+                            cache_dir: Some(".cache".to_string()),
+                            remote: false,
+                        },
+                    }
+                }),
+                ..Args::default()
+            }
+        );
+
+        assert_eq!(
+            Args::try_parse_from(["turbo", "cache", "prune", "--max-age", "7d"]).unwrap(),
+            Args {
+                command: Some(Command::Cache {
+                    command: CacheCommand::Prune {
+                        max_size: None,
+                        max_age: Some("7d".to_string()),
+                        cache_dir: None,
+                    }
+                }),
+                ..Args::default()
+            }
+        );
+
+        // Pruning without a limit would either do nothing or empty the cache
+        assert!(Args::try_parse_from(["turbo", "cache", "prune"]).is_err());
+        assert!(Args::try_parse_from(["turbo", "cache", "restore", "abc123"]).is_err());
+    }
+
     #[test]
     fn test_parse_login() {
         assert_eq!(
@@ -1561,6 +1815,66 @@ mod test {
         .test();
     }
 
+    #[test]
+    fn test_parse_lint_config() {
+        assert_eq!(
+            Args::try_parse_from(["turbo", "lint-config"]).unwrap(),
+            Args {
+                command: Some(Command::LintConfig {}),
+                ..Args::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_env_explain() {
+        assert_eq!(
+            Args::try_parse_from(["turbo", "env", "explain", "web#build"]).unwrap(),
+            Args {
+                command: Some(Command::Env {
+                    command: EnvCommand::Explain {
+                        task: "web#build".to_string(),
+                        env_mode: EnvMode::Infer,
+                        framework_inference: true,
+                    }
+                }),
+                ..Args::default()
+            }
+        );
+        assert_eq!(
+            Args::try_parse_from([
+                "turbo",
+                "env",
+                "explain",
+                "web#build",
+                "--env-mode=strict",
+                "--framework-inference=false"
+            ])
+            .unwrap(),
+            Args {
+                command: Some(Command::Env {
+                    command: EnvCommand::Explain {
+                        task: "web#build".to_string(),
+                        env_mode: EnvMode::Strict,
+                        framework_inference: false,
+                    }
+                }),
+                ..Args::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_schema() {
+        assert_eq!(
+            Args::try_parse_from(["turbo", "schema"]).unwrap(),
+            Args {
+                command: Some(Command::Schema {}),
+                ..Args::default()
+            }
+        );
+    }
+
     #[test]
     fn test_parse_logout() {
         assert_eq!(
@@ -1611,6 +1925,28 @@ mod test {
         .test();
     }
 
+    #[test]
+    fn test_parse_watch() {
+        assert_eq!(
+            Args::try_parse_from(["turbo", "watch", "build", "dev", "--filter", "web"]).unwrap(),
+            Args {
+                command: Some(Command::Watch(Box::new(RunArgs {
+                    tasks: vec!["build".to_string(), "dev".to_string()],
+                    filter: vec!["web".to_string()],
+                    ..get_default_run_args()
+                }))),
+                ..Args::default()
+            }
+        );
+
+        assert_eq!(
+            Args::try_parse_from(["turbo", "watch", "test", "--", "--watch=false"])
+                .unwrap()
+                .get_tasks(),
+            &["test".to_string()]
+        );
+    }
+
     #[test]
     fn test_parse_prune() {
         let default_prune = Command::Prune {
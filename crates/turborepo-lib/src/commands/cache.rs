@@ -0,0 +1,260 @@
+use std::{
+    io::{Cursor, Write},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use turbopath::AbsoluteSystemPathBuf;
+use turborepo_api_client::APIAuth;
+use turborepo_cache::{
+    cache_archive::{ArtifactEntry, CacheReader},
+    fs::FSCache,
+    http::HttpCache,
+    signature_authentication::ArtifactSignatureAuthenticator,
+    size::{format_size, parse_size},
+    CacheError,
+};
+
+use crate::{
+    cli::{CacheCommand, CacheSourceArgs},
+    commands::CommandBase,
+    config::TurboJson,
+    package_json::PackageJson,
+    ui::{BOLD_GREEN, BOLD_RED, GREY},
+};
+
+pub async fn run(base: &CommandBase, command: &CacheCommand) -> Result<i32> {
+    match command {
+        CacheCommand::Ls { hash, source } => {
+            let entries = open_artifact(base, hash, source).await?.entries()?;
+            for entry in &entries {
+                println!("{}", format_entry(entry));
+            }
+        }
+        CacheCommand::Cat { hash, path, source } => {
+            let path = path.trim_start_matches("./").replace('\\', "/");
+            let contents = open_artifact(base, hash, source)
+                .await?
+                .read_file(&path)?
+                .ok_or_else(|| anyhow!("{} is not a file in artifact {}", path, hash))?;
+            std::io::stdout().write_all(&contents)?;
+        }
+        CacheCommand::Verify { hash } => return verify(base, hash).await,
+        CacheCommand::Restore { hash, to, source } => {
+            let to = AbsoluteSystemPathBuf::from_unknown(&base.repo_root, to);
+            let restored = open_artifact(base, hash, source).await?.restore(&to)?;
+            println!(
+                "{}",
+                base.ui.apply(GREY.apply_to(format!(
+                    "> Restored {} files from {} to {}",
+                    restored.len(),
+                    hash,
+                    to
+                )))
+            );
+        }
+        CacheCommand::Prune {
+            max_size,
+            max_age,
+            cache_dir,
+        } => {
+            let max_size = max_size.as_deref().map(parse_size).transpose()?;
+            let max_age = max_age
+                .as_deref()
+                .map(|max_age| {
+                    humantime::parse_duration(max_age)
+                        .with_context(|| format!("invalid max age: {}", max_age))
+                })
+                .transpose()?;
+            prune(base, cache_dir.as_deref(), max_size, max_age)?;
+        }
+    }
+
+    Ok(0)
+}
+
+// Opens the artifact from the local cache, falling back to the remote cache if
+// the repo is linked. Remote artifacts are verified the same way they are
+// during a run.
+async fn open_artifact(
+    base: &CommandBase,
+    hash: &str,
+    source: &CacheSourceArgs,
+) -> Result<CacheReader<'static>> {
+    if !source.remote {
+        let fs_cache = FSCache::new(source.cache_dir.as_deref(), &base.repo_root)?;
+        match fs_cache.open(hash) {
+            Ok(reader) => return Ok(reader),
+            Err(CacheError::CacheMiss(_)) if base.api_auth()?.is_some() => {}
+            Err(CacheError::CacheMiss(_)) => {
+                bail!("artifact {} is not in the local cache", hash)
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let (http_cache, api_auth) = http_cache(base)?;
+    let artifact = match http_cache
+        .download(
+            hash,
+            &api_auth.token,
+            &api_auth.team_id,
+            api_auth.team_slug.as_deref(),
+            http_cache.use_preflight(),
+        )
+        .await
+    {
+        Ok(artifact) => artifact,
+        Err(CacheError::CacheMiss(_)) => bail!("artifact {} is not in the cache", hash),
+        Err(e) => return Err(e.into()),
+    };
+    http_cache.verify(hash, &artifact)?;
+
+    Ok(CacheReader::from_reader(Cursor::new(artifact.body), true)?)
+}
+
+async fn verify(base: &CommandBase, hash: &str) -> Result<i32> {
+    let (http_cache, api_auth) = http_cache(base)?;
+    let Some(verifier) = http_cache.signer_verifier() else {
+        bail!("artifact signing is not enabled, set `remoteCache.signature` in turbo.json")
+    };
+
+    let artifact = match http_cache
+        .download(
+            hash,
+            &api_auth.token,
+            &api_auth.team_id,
+            api_auth.team_slug.as_deref(),
+            http_cache.use_preflight(),
+        )
+        .await
+    {
+        Ok(artifact) => artifact,
+        Err(CacheError::CacheMiss(_)) => bail!("artifact {} is not in the remote cache", hash),
+        Err(e) => return Err(e.into()),
+    };
+
+    let Some(tag) = &artifact.tag else {
+        println!(
+            "{}",
+            base.ui
+                .apply(BOLD_RED.apply_to(format!("{} has no signature tag", hash)))
+        );
+        return Ok(1);
+    };
+
+    if verifier.validate(hash.as_bytes(), &artifact.body, tag)? {
+        println!(
+            "{}",
+            base.ui.apply(BOLD_GREEN.apply_to(format!(
+                "{} has a valid {} signature",
+                hash,
+                verifier.algorithm().tag_name()
+            )))
+        );
+        Ok(0)
+    } else {
+        println!(
+            "{}",
+            base.ui
+                .apply(BOLD_RED.apply_to(format!("{} has an invalid signature", hash)))
+        );
+        Ok(1)
+    }
+}
+
+fn http_cache(base: &CommandBase) -> Result<(HttpCache, APIAuth)> {
+    let api_auth = base.api_auth()?.ok_or_else(|| {
+        anyhow!("remote caching is not enabled, run `turbo login` and `turbo link` first")
+    })?;
+
+    let root_package_json = PackageJson::load(&base.repo_root.join_component("package.json"))
+        .context("failed to read package.json")?;
+    let turbo_json = TurboJson::load(&base.repo_root, &root_package_json, false)?;
+    let signer_verifier = turbo_json
+        .remote_cache_options
+        .as_ref()
+        .and_then(ArtifactSignatureAuthenticator::from_remote_cache_opts);
+
+    Ok((
+        HttpCache::new(base.api_client()?, signer_verifier, base.repo_root.clone()),
+        api_auth,
+    ))
+}
+
+fn prune(
+    base: &CommandBase,
+    cache_dir: Option<&str>,
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+) -> Result<()> {
+    let fs_cache = FSCache::new(cache_dir, &base.repo_root)?;
+    let summary = fs_cache.prune(max_size, max_age)?;
+    println!(
+        "{}",
+        base.ui.apply(GREY.apply_to(format!(
+            "> Removed {} artifacts, freeing {}. {} remaining in {}",
+            summary.removed.len(),
+            format_size(summary.freed_bytes),
+            format_size(summary.remaining_bytes),
+            fs_cache.cache_directory()
+        )))
+    );
+
+    Ok(())
+}
+
+// Lays out an entry like `ls -l` would, e.g.
+// `-rw-r--r--      17 dist/index.js`
+fn format_entry(entry: &ArtifactEntry) -> String {
+    let kind = match entry.entry_type {
+        tar::EntryType::Directory => 'd',
+        tar::EntryType::Symlink => 'l',
+        tar::EntryType::Regular => '-',
+        _ => '?',
+    };
+    let permissions = (0..9)
+        .map(|bit| {
+            if entry.mode & (1 << (8 - bit)) == 0 {
+                '-'
+            } else {
+                ['r', 'w', 'x'][bit % 3]
+            }
+        })
+        .collect::<String>();
+
+    let mut line = format!("{}{} {:>10} {}", kind, permissions, entry.size, entry.path);
+    if let Some(target) = &entry.link_target {
+        line.push_str(" -> ");
+        line.push_str(target);
+    }
+    line
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case(tar::EntryType::Regular, 0o644, 17, "dist/index.js", None, "-rw-r--r--         17 dist/index.js" ; "file")]
+    #[test_case(tar::EntryType::Directory, 0o755, 0, "dist", None, "drwxr-xr-x          0 dist" ; "directory")]
+    #[test_case(tar::EntryType::Symlink, 0o777, 0, "dist/main.js", Some("index.js"), "lrwxrwxrwx          0 dist/main.js -> index.js" ; "symlink")]
+    fn test_format_entry(
+        entry_type: tar::EntryType,
+        mode: u32,
+        size: u64,
+        path: &str,
+        link_target: Option<&str>,
+        expected: &str,
+    ) {
+        let entry = ArtifactEntry {
+            path: path.to_string(),
+            entry_type,
+            mode,
+            size,
+            link_target: link_target.map(|target| target.to_string()),
+        };
+        assert_eq!(format_entry(&entry), expected);
+    }
+}
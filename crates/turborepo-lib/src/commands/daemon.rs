@@ -3,13 +3,13 @@ use std::time::Duration;
 use camino::Utf8PathBuf;
 use pidlock::PidlockError::AlreadyOwned;
 use time::{format_description, OffsetDateTime};
-use tracing::{trace, warn};
+use tracing::{debug, trace, warn};
 use turbopath::AbsoluteSystemPathBuf;
 
 use super::CommandBase;
 use crate::{
     cli::DaemonCommand,
-    daemon::{endpoint::SocketOpenError, CloseReason, DaemonConnector, DaemonError},
+    daemon::{endpoint::SocketOpenError, proto, CloseReason, DaemonConnector, DaemonError},
     tracing::TurboSubscriber,
 };
 
@@ -48,11 +48,20 @@ pub async fn daemon_client(command: &DaemonCommand, base: &CommandBase) -> Resul
             let mut client = connector.connect().await?;
             let status = client.status().await?;
             let log_file = log_filename(&status.log_file)?;
+            // older daemons don't collect metrics
+            let metrics = match client.get_metrics().await {
+                Ok(metrics) => Some(metrics),
+                Err(e) => {
+                    debug!("unable to get daemon metrics: {}", e);
+                    None
+                }
+            };
             let status = DaemonStatus {
                 uptime_ms: status.uptime_msec,
                 log_file: log_file.into(),
                 pid_file: client.pid_file().to_owned(),
                 sock_file: client.sock_file().to_owned(),
+                metrics,
             };
             if *json {
                 println!("{}", serde_json::to_string_pretty(&status)?);
@@ -64,6 +73,9 @@ pub async fn daemon_client(command: &DaemonCommand, base: &CommandBase) -> Resul
                 );
                 println!("Daemon pid file: {}", status.pid_file);
                 println!("Daemon socket file: {}", status.sock_file);
+                if let Some(metrics) = &status.metrics {
+                    print_metrics(metrics);
+                }
             }
         }
         DaemonCommand::Clean => {
@@ -183,6 +195,38 @@ pub async fn daemon_server(
     Ok(())
 }
 
+fn print_metrics(metrics: &proto::DaemonMetrics) {
+    println!(
+        "Watched globs: {} (for {} hashes)",
+        metrics.watched_globs, metrics.watched_hashes
+    );
+    println!("File events processed: {}", metrics.file_events);
+    println!(
+        "Watcher backlog: {} events ({} dropped)",
+        metrics.watcher_backlog, metrics.dropped_events
+    );
+    println!("Memory usage: {:.1} MB", metrics.memory_bytes as f64 / 1e6);
+    if let Some(last_error) = &metrics.last_error {
+        let at = OffsetDateTime::from_unix_timestamp_nanos(
+            last_error.timestamp_msec as i128 * 1_000_000,
+        )
+        .ok()
+        .and_then(|at| at.format(&format_description::well_known::Rfc3339).ok())
+        .unwrap_or_default();
+        println!("Last error: {} ({})", last_error.message, at);
+    }
+    for latency in &metrics.rpc_latencies {
+        let average = Duration::from_micros(latency.total_usec / latency.count.max(1));
+        println!(
+            "RPC {}: {} calls, avg {:?}, max {:?}",
+            latency.method,
+            latency.count,
+            average,
+            Duration::from_micros(latency.max_usec)
+        );
+    }
+}
+
 #[derive(serde::Serialize)]
 pub struct DaemonStatus {
     pub uptime_ms: u64,
@@ -191,4 +235,6 @@ pub struct DaemonStatus {
     pub log_file: Utf8PathBuf,
     pub pid_file: turbopath::AbsoluteSystemPathBuf,
     pub sock_file: turbopath::AbsoluteSystemPathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<proto::DaemonMetrics>,
 }
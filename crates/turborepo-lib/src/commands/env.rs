@@ -0,0 +1,554 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use anyhow::{anyhow, bail, Context, Result};
+use itertools::Itertools;
+use turborepo_env::{
+    get_global_hashable_env_vars, secret_hash, EnvironmentVariableMap, WildcardMatch,
+    DEFAULT_ENV_VARS,
+};
+
+use crate::{
+    cli::{EnvCommand, EnvMode},
+    commands::CommandBase,
+    config::TurboJson,
+    engine::Engine,
+    framework::{infer_framework, Framework},
+    package_graph::PackageGraph,
+    package_json::PackageJson,
+    run::{
+        framework_env_wildcards, global_env_mode, task_env_mode, task_env_vars,
+        task_id::{get_package_task_from_id, is_package_task},
+        workspace_name,
+    },
+    task_graph::TaskDefinitionHashable,
+    ui::{BOLD, GREY, UI},
+};
+
+pub fn run(base: &CommandBase, command: &EnvCommand) -> Result<i32> {
+    match command {
+        EnvCommand::Explain {
+            task,
+            env_mode,
+            framework_inference,
+        } => explain_task(base, task, *env_mode, *framework_inference)?,
+    }
+
+    Ok(0)
+}
+
+fn explain_task(
+    base: &CommandBase,
+    task_id: &str,
+    env_mode: EnvMode,
+    framework_inference: bool,
+) -> Result<()> {
+    if !is_package_task(task_id) {
+        bail!(
+            "expected a task in the form <package>#<task>, e.g. web#build, but got {}",
+            task_id
+        );
+    }
+    let (package, task) = get_package_task_from_id(task_id);
+
+    let root_package_json = PackageJson::load(&base.repo_root.join_component("package.json"))
+        .context("failed to read package.json")?;
+    let package_graph =
+        PackageGraph::builder(&base.repo_root, root_package_json.clone()).build()?;
+    let root_turbo_json = TurboJson::load(&base.repo_root, &root_package_json, false)?;
+    let workspace_turbo_jsons = package_graph
+        .workspace_turbo_jsons()
+        .context("failed to load workspace turbo.json")?;
+
+    let workspace_name = workspace_name(&package);
+    let workspace = package_graph
+        .workspace_info(&workspace_name)
+        .ok_or_else(|| anyhow!("could not find package {}", package))?;
+    let engine = Engine::builder(&package_graph, &root_turbo_json.pipeline)
+        .with_workspace_turbo_jsons(&workspace_turbo_jsons)
+        .with_workspaces(vec![workspace_name])
+        .with_tasks(vec![task])
+        .with_tasks_only(true)
+        .build()?;
+    let task_definition = engine
+        .task_definition(task_id)
+        .ok_or_else(|| anyhow!("could not find task {} in turbo.json", task_id))?;
+
+    let framework = framework_inference
+        .then(|| infer_framework(workspace))
+        .flatten();
+    let explanation = explain(
+        &EnvironmentVariableMap::infer(),
+        task_definition,
+        framework,
+        &root_turbo_json,
+        env_mode,
+    )?;
+    print!("{}", explanation.render(task_id, &base.ui));
+
+    Ok(())
+}
+
+/// An environment variable and why it is, or isn't, part of a task's
+/// environment
+#[derive(Debug, PartialEq, Eq)]
+struct ExplainedVar {
+    name: String,
+    // The hash of the value, None if the variable was excluded
+    value: Option<String>,
+    reason: String,
+}
+
+#[derive(Debug, PartialEq)]
+struct EnvExplanation {
+    env_mode: EnvMode,
+    framework: Option<(&'static str, Vec<String>)>,
+    hashed: Vec<ExplainedVar>,
+    pass_through: Vec<ExplainedVar>,
+    global: Vec<ExplainedVar>,
+}
+
+// Mirrors how the task hasher and the global hash resolve environment
+// variables, so that every variable in the hash can be traced back to the
+// wildcard that put it there
+fn explain(
+    env: &EnvironmentVariableMap,
+    task_definition: &TaskDefinitionHashable,
+    framework: Option<&'static Framework>,
+    root_turbo_json: &TurboJson,
+    env_mode: EnvMode,
+) -> Result<EnvExplanation> {
+    let global_env_mode =
+        global_env_mode(env_mode, root_turbo_json.global_pass_through_env.as_deref());
+    let env_mode = task_env_mode(global_env_mode, task_definition);
+
+    let hashed_env_vars = task_env_vars(env, task_definition, framework)?.all;
+    let env_matches = env.explain_wildcards(&task_definition.env)?;
+    let framework_wildcards = framework
+        .map(|framework| framework_env_wildcards(framework, env))
+        .unwrap_or_default();
+    let framework_matches = env.explain_wildcards(&framework_wildcards)?;
+    let mut hashed = Explanations::new(env);
+    for name in env_matches.keys().chain(framework_matches.keys()) {
+        let env_match = env_matches.get(name);
+        let framework_match = framework_matches.get(name);
+        let reason = match (env_match, framework_match) {
+            (Some(env_match), _) if env_match.is_included() => included_by(env_match, "in env"),
+            (
+                Some(WildcardMatch {
+                    excluded_by: Some(pattern),
+                    ..
+                }),
+                Some(framework_match),
+            ) if framework_match.included_by.is_some() => {
+                format!("excluded by \"{}\" in env", pattern)
+            }
+            (_, Some(framework_match)) if framework_match.included_by.is_some() => {
+                let slug = framework.map(|framework| framework.slug());
+                match &framework_match.excluded_by {
+                    Some(pattern) => format!(
+                        "excluded by \"{}\" since TURBO_CI_VENDOR_ENV_KEY is set",
+                        pattern
+                    ),
+                    None => format!(
+                        "inferred from {} by \"{}\"",
+                        slug.unwrap_or_default(),
+                        framework_match.included_by.as_deref().unwrap_or_default()
+                    ),
+                }
+            }
+            (Some(env_match), _) => match &env_match.excluded_by {
+                // Only exclusions that actually removed something are interesting
+                Some(pattern) if env_match.included_by.is_some() => {
+                    format!("excluded by \"{}\" in env", pattern)
+                }
+                _ => continue,
+            },
+            _ => continue,
+        };
+        hashed.insert(name, hashed_env_vars.contains_key(name), reason);
+    }
+
+    // Pass through env vars are ignored entirely in loose mode
+    let mut pass_through = Explanations::new(env);
+    if env_mode != EnvMode::Loose {
+        let sources = [
+            (
+                task_definition.pass_through_env.as_deref(),
+                "passThroughEnv",
+            ),
+            (
+                root_turbo_json.global_pass_through_env.as_deref(),
+                "globalPassThroughEnv",
+            ),
+        ];
+        for (wildcards, key) in sources {
+            let matches = env.explain_wildcards(wildcards.unwrap_or_default())?;
+            for (name, wildcard_match) in &matches {
+                if wildcard_match.included_by.is_some() {
+                    pass_through.insert(
+                        name,
+                        wildcard_match.is_included(),
+                        explain_match(wildcard_match, key),
+                    );
+                }
+            }
+        }
+    }
+
+    let global_env_vars =
+        get_global_hashable_env_vars(env.clone(), &root_turbo_json.global_env)?.all;
+    let global_env_matches = env.explain_wildcards(&root_turbo_json.global_env)?;
+    let default_matches = env.explain_wildcards(&DEFAULT_ENV_VARS)?;
+    let mut global = Explanations::new(env);
+    for name in global_env_matches.keys().chain(default_matches.keys()) {
+        let reason = match global_env_matches.get(name) {
+            Some(global_env_match) if global_env_match.included_by.is_some() => {
+                explain_match(global_env_match, "globalEnv")
+            }
+            Some(WildcardMatch {
+                excluded_by: Some(pattern),
+                ..
+            }) if default_matches.contains_key(name) => {
+                format!("excluded by \"{}\" in globalEnv", pattern)
+            }
+            _ if default_matches.contains_key(name) => "always included".to_string(),
+            _ => continue,
+        };
+        global.insert(name, global_env_vars.contains_key(name), reason);
+    }
+
+    Ok(EnvExplanation {
+        env_mode,
+        framework: framework.map(|framework| (framework.slug(), framework_wildcards)),
+        hashed: hashed.into_vars(),
+        pass_through: pass_through.into_vars(),
+        global: global.into_vars(),
+    })
+}
+
+fn included_by(wildcard_match: &WildcardMatch, location: &str) -> String {
+    format!(
+        "included by \"{}\" {}",
+        wildcard_match.included_by.as_deref().unwrap_or_default(),
+        location
+    )
+}
+
+fn explain_match(wildcard_match: &WildcardMatch, key: &str) -> String {
+    match &wildcard_match.excluded_by {
+        Some(pattern) => format!("excluded by \"{}\" in {}", pattern, key),
+        None => included_by(wildcard_match, &format!("in {}", key)),
+    }
+}
+
+// Collects explanations keyed by variable name, the first explanation of a
+// variable wins
+struct Explanations<'a> {
+    env: &'a EnvironmentVariableMap,
+    vars: BTreeMap<String, ExplainedVar>,
+}
+
+impl<'a> Explanations<'a> {
+    fn new(env: &'a EnvironmentVariableMap) -> Self {
+        Self {
+            env,
+            vars: BTreeMap::new(),
+        }
+    }
+
+    fn insert(&mut self, name: &str, included: bool, reason: String) {
+        let value = included
+            .then(|| self.env.get(name))
+            .flatten()
+            .map(|value| secret_hash(value));
+        self.vars
+            .entry(name.to_string())
+            .or_insert_with(|| ExplainedVar {
+                name: name.to_string(),
+                value,
+                reason,
+            });
+    }
+
+    // Included variables first, then the excluded ones
+    fn into_vars(self) -> Vec<ExplainedVar> {
+        let (included, excluded): (Vec<_>, Vec<_>) =
+            self.vars.into_values().partition(|var| var.value.is_some());
+        included.into_iter().chain(excluded).collect()
+    }
+}
+
+impl EnvExplanation {
+    fn render(&self, task_id: &str, ui: &UI) -> String {
+        let mut output = String::new();
+        let env_mode = match self.env_mode {
+            EnvMode::Infer => "infer",
+            EnvMode::Loose => "loose",
+            EnvMode::Strict => "strict",
+        };
+        writeln!(
+            output,
+            "{} {}",
+            ui.apply(BOLD.apply_to(task_id)),
+            ui.apply(GREY.apply_to(format!("(env mode: {})", env_mode)))
+        )
+        .unwrap();
+        if let Some((slug, wildcards)) = &self.framework {
+            writeln!(
+                output,
+                "Framework: {} ({})",
+                slug,
+                wildcards.iter().join(", ")
+            )
+            .unwrap();
+        }
+
+        let pass_through_note = match self.env_mode {
+            EnvMode::Loose => "the task sees the whole environment in loose mode",
+            _ => "available to the task, but not hashed",
+        };
+        let sections = [
+            ("Hashed environment variables", None, &self.hashed),
+            (
+                "Pass-through environment variables",
+                Some(pass_through_note),
+                &self.pass_through,
+            ),
+            (
+                "Global environment variables",
+                Some("hashed for every task"),
+                &self.global,
+            ),
+        ];
+        for (title, note, vars) in sections {
+            output.push('\n');
+            match note {
+                Some(note) => writeln!(
+                    output,
+                    "{} {}",
+                    ui.apply(BOLD.apply_to(title)),
+                    ui.apply(GREY.apply_to(format!("({})", note)))
+                ),
+                None => writeln!(output, "{}", ui.apply(BOLD.apply_to(title))),
+            }
+            .unwrap();
+            if vars.is_empty() {
+                writeln!(output, "  none").unwrap();
+            }
+            for var in vars {
+                let var_display = match &var.value {
+                    Some(value) => format!("+ {}={}", var.name, value),
+                    None => format!("- {}", var.name),
+                };
+                writeln!(
+                    output,
+                    "  {} {}",
+                    var_display,
+                    ui.apply(GREY.apply_to(&var.reason))
+                )
+                .unwrap();
+            }
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::framework::framework_by_slug;
+
+    fn env(names: &[&str]) -> EnvironmentVariableMap {
+        names
+            .iter()
+            .map(|name| (name.to_string(), format!("{}_value", name)))
+            .collect::<HashMap<_, _>>()
+            .into()
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn var(name: &str, included: bool, reason: &str) -> ExplainedVar {
+        ExplainedVar {
+            name: name.to_string(),
+            value: included.then(|| secret_hash(&format!("{}_value", name))),
+            reason: reason.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_explain_task_env() -> Result<()> {
+        let env = env(&[
+            "API_URL",
+            "API_SECRET",
+            "NEXT_PUBLIC_URL",
+            "NEXT_PUBLIC_DEBUG",
+            "AWS_SECRET_KEY",
+            "CI",
+            "VERCEL_ANALYTICS_ID",
+            "HOME",
+        ]);
+        let task_definition = TaskDefinitionHashable {
+            env: strings(&["API_*", "!API_SECRET", "!NEXT_PUBLIC_DEBUG"]),
+            pass_through_env: Some(strings(&["AWS_*"])),
+            ..Default::default()
+        };
+        let root_turbo_json = TurboJson {
+            global_env: strings(&["CI"]),
+            ..Default::default()
+        };
+        let framework = framework_by_slug("nextjs");
+
+        let explanation = explain(
+            &env,
+            &task_definition,
+            framework,
+            &root_turbo_json,
+            EnvMode::Infer,
+        )?;
+        assert_eq!(
+            explanation,
+            EnvExplanation {
+                env_mode: EnvMode::Strict,
+                framework: Some(("nextjs", strings(&["NEXT_PUBLIC_*"]))),
+                hashed: vec![
+                    var("API_URL", true, "included by \"API_*\" in env"),
+                    var(
+                        "NEXT_PUBLIC_URL",
+                        true,
+                        "inferred from nextjs by \"NEXT_PUBLIC_*\""
+                    ),
+                    var("API_SECRET", false, "excluded by \"!API_SECRET\" in env"),
+                    var(
+                        "NEXT_PUBLIC_DEBUG",
+                        false,
+                        "excluded by \"!NEXT_PUBLIC_DEBUG\" in env"
+                    ),
+                ],
+                pass_through: vec![var(
+                    "AWS_SECRET_KEY",
+                    true,
+                    "included by \"AWS_*\" in passThroughEnv"
+                )],
+                global: vec![
+                    var("CI", true, "included by \"CI\" in globalEnv"),
+                    var("VERCEL_ANALYTICS_ID", true, "always included"),
+                ],
+            }
+        );
+
+        // Everything that's explained as hashed is exactly what gets hashed
+        let hashed = task_env_vars(&env, &task_definition, framework)?.all;
+        assert_eq!(
+            explanation
+                .hashed
+                .iter()
+                .filter(|var| var.value.is_some())
+                .map(|var| var.name.as_str())
+                .collect::<Vec<_>>(),
+            hashed
+                .keys()
+                .sorted()
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_loose_mode_ignores_pass_through_env() -> Result<()> {
+        let env = env(&["AWS_SECRET_KEY", "API_URL"]);
+        let task_definition = TaskDefinitionHashable {
+            env: strings(&["API_URL"]),
+            pass_through_env: Some(strings(&["AWS_*"])),
+            ..Default::default()
+        };
+
+        let explanation = explain(
+            &env,
+            &task_definition,
+            None,
+            &TurboJson::default(),
+            EnvMode::Loose,
+        )?;
+        assert_eq!(explanation.env_mode, EnvMode::Loose);
+        assert_eq!(explanation.pass_through, vec![]);
+        assert_eq!(
+            explanation.hashed,
+            vec![var("API_URL", true, "included by \"API_URL\" in env")]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_vendor_exclusion() -> Result<()> {
+        let env: EnvironmentVariableMap = [
+            ("TURBO_CI_VENDOR_ENV_KEY", "NEXT_PUBLIC_VERCEL_"),
+            ("NEXT_PUBLIC_VERCEL_URL", "https://example.com"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect::<HashMap<_, _>>()
+        .into();
+
+        let explanation = explain(
+            &env,
+            &TaskDefinitionHashable::default(),
+            framework_by_slug("nextjs"),
+            &TurboJson::default(),
+            EnvMode::Infer,
+        )?;
+        assert_eq!(
+            explanation.hashed,
+            vec![ExplainedVar {
+                name: "NEXT_PUBLIC_VERCEL_URL".to_string(),
+                value: None,
+                reason: "excluded by \"!NEXT_PUBLIC_VERCEL_*\" since TURBO_CI_VENDOR_ENV_KEY is \
+                         set"
+                .to_string(),
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_hides_values() -> Result<()> {
+        let env = env(&["API_URL"]);
+        let task_definition = TaskDefinitionHashable {
+            env: strings(&["API_URL"]),
+            ..Default::default()
+        };
+        let explanation = explain(
+            &env,
+            &task_definition,
+            None,
+            &TurboJson::default(),
+            EnvMode::Infer,
+        )?;
+
+        let output = explanation.render("web#build", &UI::new(true));
+        assert!(!output.contains("API_URL_value"));
+        assert_eq!(
+            output,
+            format!(
+                "web#build (env mode: loose)\n\nHashed environment variables\n  + API_URL={} \
+                 included by \"API_URL\" in env\n\nPass-through environment variables (the task \
+                 sees the whole environment in loose mode)\n  none\n\nGlobal environment \
+                 variables (hashed for every task)\n  none\n",
+                secret_hash("API_URL_value")
+            )
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::Result;
+use itertools::Itertools;
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+
+use crate::{
+    commands::CommandBase,
+    config::{self, TurboJson},
+    package_graph::{PackageGraph, WorkspaceName},
+    package_json::PackageJson,
+    package_manager::PackageManager,
+    ui::{BOLD_GREEN, BOLD_RED},
+};
+
+const CONFIG_FILE: &str = "turbo.json";
+
+/// Checks the root turbo.json and every workspace turbo.json, printing each
+/// problem found. Exits with 1 if any of them has errors so that it can be
+/// used in CI.
+pub fn run(base: &CommandBase) -> Result<i32> {
+    let root_package_json = PackageJson::load(&base.repo_root.join_component("package.json"))?;
+    let package_manager =
+        PackageManager::get_package_manager(&base.repo_root, Some(&root_package_json))?;
+    let package_graph = PackageGraph::builder(&base.repo_root, root_package_json)
+        .with_package_manger(Some(package_manager))
+        .build()?;
+
+    let repo_root: &AbsoluteSystemPath = &base.repo_root;
+    let mut paths = vec![(true, repo_root.join_component(CONFIG_FILE))];
+    paths.extend(
+        package_graph
+            .workspaces()
+            .filter(|(workspace, _)| !matches!(workspace, WorkspaceName::Root))
+            .map(|(_, entry)| {
+                repo_root
+                    .resolve(entry.package_path())
+                    .join_component(CONFIG_FILE)
+            })
+            .filter(|path| path.exists())
+            .sorted()
+            .map(|path| (false, path)),
+    );
+
+    let mut errors = 0;
+    let mut warnings = 0;
+    for (is_root, path) in &paths {
+        match TurboJson::lint(path, *is_root) {
+            Ok(report) => {
+                warnings += report.warnings();
+                if !report.is_empty() {
+                    eprintln!("{}", report.render(&base.ui));
+                }
+            }
+            Err(config::Error::InvalidTurboJson(report)) => {
+                errors += report.errors();
+                warnings += report.warnings();
+                eprintln!("{}", report.render(&base.ui));
+            }
+            Err(err) => {
+                errors += 1;
+                print_error(base, path, &err);
+            }
+        }
+    }
+
+    let checked = match paths.len() {
+        1 => "1 turbo.json".to_string(),
+        count => format!("{} turbo.json files", count),
+    };
+    if errors > 0 {
+        println!(
+            "{}",
+            base.ui.apply(BOLD_RED.apply_to(format!(
+                "Found {} errors and {} warnings in {}",
+                errors, warnings, checked
+            )))
+        );
+        Ok(1)
+    } else {
+        println!(
+            "{}",
+            base.ui.apply(BOLD_GREEN.apply_to(format!(
+                "No errors found in {} ({} warnings)",
+                checked, warnings
+            )))
+        );
+        Ok(0)
+    }
+}
+
+fn print_error(base: &CommandBase, path: &AbsoluteSystemPathBuf, err: &config::Error) {
+    let err = match err {
+        config::Error::Io(_) => format!("unable to read {}: {}", path, err),
+        err => err.to_string(),
+    };
+    eprintln!("{} {}", base.ui.apply(BOLD_RED.apply_to("error:")), err);
+}
@@ -4,7 +4,7 @@ use anyhow::Result;
 use sha2::{Digest, Sha256};
 use tokio::sync::OnceCell;
 use turbopath::AbsoluteSystemPathBuf;
-use turborepo_api_client::APIClient;
+use turborepo_api_client::{APIAuth, APIClient};
 
 use crate::{
     config::{
@@ -16,14 +16,19 @@ use crate::{
 };
 
 pub(crate) mod bin;
+pub(crate) mod cache;
 pub(crate) mod daemon;
+pub(crate) mod env;
 pub(crate) mod generate;
 pub(crate) mod info;
 pub(crate) mod link;
+pub(crate) mod lint_config;
 pub(crate) mod login;
 pub(crate) mod logout;
+pub(crate) mod prune;
 pub(crate) mod run;
 pub(crate) mod unlink;
+pub(crate) mod watch;
 
 #[derive(Debug)]
 pub struct CommandBase {
@@ -142,7 +147,11 @@ impl CommandBase {
         &self.args
     }
 
-    pub fn api_client(&mut self) -> Result<APIClient> {
+    pub fn version(&self) -> &'static str {
+        self.version
+    }
+
+    pub fn api_client(&self) -> Result<APIClient> {
         let repo_config = self.repo_config()?;
         let client_config = self.client_config()?;
         let args = self.args();
@@ -157,6 +166,22 @@ impl CommandBase {
         )?)
     }
 
+    // The remote cache is only usable once the repo has been linked, i.e. we
+    // have both a token and a team to use it with.
+    pub fn api_auth(&self) -> Result<Option<APIAuth>> {
+        let repo_config = self.repo_config()?;
+        let user_config = self.user_config()?;
+
+        Ok(user_config
+            .token()
+            .zip(repo_config.team_id())
+            .map(|(token, team_id)| APIAuth {
+                team_id: team_id.to_string(),
+                token: token.to_string(),
+                team_slug: repo_config.team_slug().map(|slug| slug.to_string()),
+            }))
+    }
+
     pub fn daemon_file_root(&self) -> AbsoluteSystemPathBuf {
         AbsoluteSystemPathBuf::new(std::env::temp_dir().to_str().expect("UTF-8 path"))
             .expect("temp dir is valid")
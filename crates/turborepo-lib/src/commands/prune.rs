@@ -0,0 +1,479 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use tracing::trace;
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+
+use crate::{
+    commands::CommandBase,
+    config::RawTurboJSON,
+    package_graph::{PackageGraph, WorkspaceName, WorkspaceNode},
+    package_json::PackageJson,
+    package_manager::PackageManager,
+    run::task_id::ROOT_PKG_NAME,
+    ui::BOLD,
+};
+
+/// Creates a smaller monorepo in `output_dir` containing only the workspaces
+/// in `scope` and their internal dependencies.
+pub fn prune(base: &CommandBase, scope: &[String], docker: bool, output_dir: &str) -> Result<()> {
+    if scope.is_empty() {
+        bail!("at least one target must be specified");
+    }
+
+    let repo_root = &base.repo_root;
+    let root_package_json = PackageJson::load(&repo_root.join_component("package.json"))
+        .context("failed to read package.json")?;
+    let package_manager = PackageManager::get_package_manager(repo_root, Some(&root_package_json))?;
+    let package_graph = PackageGraph::builder(repo_root, root_package_json.clone())
+        .with_package_manger(Some(package_manager))
+        .build()
+        .context("could not construct graph")?;
+
+    let out_dir = AbsoluteSystemPathBuf::from_unknown(repo_root, output_dir);
+    let prune = Prune {
+        repo_root,
+        full_dir: match docker {
+            true => out_dir.join_component("full"),
+            false => out_dir.clone(),
+        },
+        json_dir: docker.then(|| out_dir.join_component("json")),
+        out_dir,
+    };
+    trace!("scope: {}", scope.join(", "));
+    trace!("docker: {}", docker);
+    trace!("out dir: {}", prune.out_dir);
+
+    let workspaces = internal_dependencies(&package_graph, scope)?;
+    trace!("targets: {:?}", workspaces);
+
+    if package_graph.lockfile().is_none() {
+        bail!("Cannot prune without parsed lockfile");
+    }
+    let package_manager = package_graph.package_manager();
+
+    // Package keys of the root and every included workspace's external
+    // dependencies, these are what the pruned lockfile needs to contain
+    let mut lockfile_keys = BTreeSet::new();
+    let mut workspace_paths = Vec::with_capacity(workspaces.len());
+    for workspace in workspaces
+        .iter()
+        .map(|name| WorkspaceName::Other(name.clone()))
+        .chain(std::iter::once(WorkspaceName::Root))
+    {
+        let entry = package_graph
+            .workspace_info(&workspace)
+            .ok_or_else(|| anyhow!("missing workspace info for {}", workspace))?;
+        if workspace != WorkspaceName::Root {
+            workspace_paths.push(entry.package_path().to_owned().to_unix()?.to_string());
+        }
+        lockfile_keys.extend(
+            entry
+                .transitive_dependencies()
+                .into_iter()
+                .flatten()
+                .map(|package| package.key.clone()),
+        );
+    }
+    trace!("new workspaces: {:?}", workspace_paths);
+
+    let lockfile_contents = package_manager.read_lockfile_contents(repo_root)?;
+    let pruned_lockfile = package_manager
+        .prune_lockfile(
+            &lockfile_contents,
+            &root_package_json,
+            &workspace_paths,
+            &lockfile_keys.into_iter().collect::<Vec<_>>(),
+        )
+        .context("Failed creating pruned lockfile")?;
+
+    println!(
+        "Generating pruned monorepo for {} in {}",
+        base.ui.apply(BOLD.apply_to(scope.join(", "))),
+        base.ui.apply(BOLD.apply_to(prune.out_dir.as_str())),
+    );
+
+    prune
+        .out_dir
+        .create_dir_all()
+        .context("could not create output directory")?;
+    if let Some(workspace_config) = package_manager.workspace_configuration_path() {
+        let workspace_config = AnchoredSystemPathBuf::from_raw(workspace_config)?;
+        if repo_root.resolve(&workspace_config).exists() {
+            prune
+                .copy_file(&workspace_config, true)
+                .with_context(|| format!("could not copy {}", workspace_config))?;
+            // The workspace configuration is needed next to the lockfile as well
+            turborepo_fs::copy_file(
+                repo_root.resolve(&workspace_config),
+                prune.out_dir.resolve(&workspace_config),
+            )
+            .with_context(|| format!("could not copy {}", workspace_config))?;
+        }
+    }
+
+    for workspace in &workspaces {
+        let entry = package_graph
+            .workspace_info(&WorkspaceName::Other(workspace.clone()))
+            .ok_or_else(|| anyhow!("missing workspace info for {}", workspace))?;
+        prune
+            .copy_workspace(entry.package_path().to_owned(), entry.package_json_path())
+            .with_context(|| format!("failed to copy {}", workspace))?;
+        println!(" - Added {}", workspace);
+    }
+
+    // The lockfile is needed to install dependencies, so it's part of the json
+    // output as well
+    for dir in std::iter::once(&prune.out_dir).chain(&prune.json_dir) {
+        let lockfile = dir.join_component(package_manager.pruned_lockfile_name());
+        lockfile
+            .ensure_dir()
+            .context("could not create output directory")?;
+        fs::write(lockfile.as_std_path(), &pruned_lockfile.contents)
+            .context("Failed to write pruned lockfile")?;
+    }
+
+    let gitignore = AnchoredSystemPathBuf::from_raw(".gitignore")?;
+    if repo_root.resolve(&gitignore).exists() {
+        prune
+            .copy_file(&gitignore, false)
+            .context("failed to copy root .gitignore")?;
+    }
+    let npmrc = AnchoredSystemPathBuf::from_raw(".npmrc")?;
+    if repo_root.resolve(&npmrc).exists() {
+        prune
+            .copy_file(&npmrc, true)
+            .context("failed to copy root .npmrc")?;
+    }
+
+    let turbo_json_path = repo_root.join_component("turbo.json");
+    if turbo_json_path.exists() {
+        // Copying turbo.json isn't enough as tasks might refer to workspaces
+        // that are no longer part of the monorepo
+        let included_workspaces = workspaces
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(ROOT_PKG_NAME))
+            .collect::<Vec<_>>();
+        let turbo_json = RawTurboJSON::read(&turbo_json_path)
+            .context("failed to read turbo.json")?
+            .prune_tasks(&included_workspaces);
+        fs::write(
+            prune.full_dir.join_component("turbo.json").as_std_path(),
+            serde_json::to_string_pretty(&turbo_json)?,
+        )
+        .context("failed to prune workspace tasks from turbo.json")?;
+    }
+
+    prune
+        .write_root_package_json(package_manager, &pruned_lockfile.patches)
+        .context("failed to write root package.json")?;
+    for patch in &pruned_lockfile.patches {
+        let patch = repo_root.anchor(&repo_root.join_unix_path(patch)?)?;
+        prune
+            .copy_file(&patch, true)
+            .context("Failed copying patch file")?;
+    }
+
+    Ok(())
+}
+
+/// Returns the names of the workspaces in `scope` along with all of the
+/// workspaces they, or the root workspace, depend on.
+fn internal_dependencies(
+    package_graph: &PackageGraph,
+    scope: &[String],
+) -> Result<BTreeSet<String>> {
+    let mut workspaces = BTreeSet::new();
+    let mut add_closure = |closure: HashSet<&WorkspaceNode>| {
+        workspaces.extend(closure.into_iter().filter_map(|node| match node {
+            WorkspaceNode::Workspace(WorkspaceName::Other(name)) => Some(name.clone()),
+            // The root workspace gets handled separately
+            WorkspaceNode::Root | WorkspaceNode::Workspace(WorkspaceName::Root) => None,
+        }));
+    };
+    for name in scope {
+        let node = WorkspaceNode::Workspace(WorkspaceName::Other(name.clone()));
+        let Some(closure) = package_graph.transitive_closure(&node) else {
+            bail!("invalid scope: package {} not found", name);
+        };
+        add_closure(closure);
+    }
+    if let Some(closure) =
+        package_graph.transitive_closure(&WorkspaceNode::Workspace(WorkspaceName::Root))
+    {
+        add_closure(closure);
+    }
+    Ok(workspaces)
+}
+
+struct Prune<'a> {
+    repo_root: &'a AbsoluteSystemPath,
+    out_dir: AbsoluteSystemPathBuf,
+    // Where the pruned monorepo is written to
+    full_dir: AbsoluteSystemPathBuf,
+    // Only set when pruning for docker, contains only the files necessary for
+    // installing dependencies
+    json_dir: Option<AbsoluteSystemPathBuf>,
+}
+
+impl<'a> Prune<'a> {
+    /// Copies a file from the repo root into the pruned monorepo. Files
+    /// needed to install dependencies can also be copied into the json
+    /// output.
+    fn copy_file(&self, file: &AnchoredSystemPathBuf, include_in_json: bool) -> Result<()> {
+        let from = self.repo_root.resolve(file);
+        turborepo_fs::copy_file(&from, self.full_dir.resolve(file))?;
+        if let Some(json_dir) = self.json_dir.as_ref().filter(|_| include_in_json) {
+            turborepo_fs::copy_file(&from, json_dir.resolve(file))?;
+        }
+        Ok(())
+    }
+
+    fn copy_workspace(
+        &self,
+        workspace_dir: AnchoredSystemPathBuf,
+        package_json: &AnchoredSystemPathBuf,
+    ) -> Result<()> {
+        turborepo_fs::recursive_copy(
+            self.repo_root.resolve(&workspace_dir),
+            self.full_dir.resolve(&workspace_dir),
+        )?;
+        if let Some(json_dir) = &self.json_dir {
+            turborepo_fs::copy_file(
+                self.repo_root.resolve(package_json),
+                json_dir.resolve(package_json),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the root package.json, removing any references to patches that
+    /// are no longer part of the pruned lockfile.
+    fn write_root_package_json(
+        &self,
+        package_manager: &PackageManager,
+        patches: &[turbopath::RelativeUnixPathBuf],
+    ) -> Result<()> {
+        let package_json = AnchoredSystemPathBuf::from_raw("package.json")?;
+        let original_path = self.repo_root.resolve(&package_json);
+        let mut contents: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(original_path.as_std_path())?)?;
+
+        let new_path = self.full_dir.resolve(&package_json);
+        if package_manager.prune_patched_packages(&mut contents, patches) {
+            fs::write(
+                new_path.as_std_path(),
+                serde_json::to_string_pretty(&contents)?,
+            )?;
+        } else {
+            turborepo_fs::copy_file(&original_path, &new_path)?;
+        }
+
+        // Copy from the pruned package.json so the json output gets the pruned version
+        if let Some(json_dir) = &self.json_dir {
+            turborepo_fs::copy_file(&new_path, json_dir.resolve(&package_json))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use tempfile::TempDir;
+    use test_case::test_case;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::*;
+    use crate::{ui::UI, Args};
+
+    const PACKAGE_LOCK: &str = r#"{
+  "name": "monorepo",
+  "lockfileVersion": 3,
+  "requires": true,
+  "packages": {
+    "": {
+      "name": "monorepo",
+      "workspaces": ["apps/*", "packages/*"]
+    },
+    "apps/docs": {
+      "name": "docs",
+      "dependencies": { "lodash": "^4.17.21" }
+    },
+    "apps/web": {
+      "name": "web",
+      "dependencies": { "ui": "*" }
+    },
+    "node_modules/docs": { "resolved": "apps/docs", "link": true },
+    "node_modules/lodash": {
+      "version": "4.17.21",
+      "resolved": "https://registry.npmjs.org/lodash/-/lodash-4.17.21.tgz",
+      "integrity": "sha512-v2kDEe57lecTulaDIuNTPy3Ry4gLGJ6Z1O3vE1krgXZNrsQ+LFTGHVxVjcXPs17LhbZVGedAJv8XZ1tvj5FvSg=="
+    },
+    "node_modules/ui": { "resolved": "packages/ui", "link": true },
+    "node_modules/web": { "resolved": "apps/web", "link": true },
+    "packages/ui": { "name": "ui" }
+  }
+}"#;
+
+    // What bun prints for its binary lockfile
+    const BUN_LOCK: &str = r#"# THIS IS AN AUTOGENERATED FILE. DO NOT EDIT THIS FILE DIRECTLY.
+# yarn lockfile v1
+# bun ./bun.lockb --hash: 3A4C1A1D33F4D6D8-6d5b4ffb93a4a3a5-0D2C6B7B8E6E9C1A-5f9b8d9e3c2a1b0f
+
+
+"lodash@^4.17.21":
+  version "4.17.21"
+  resolved "https://registry.npmjs.org/lodash/-/lodash-4.17.21.tgz"
+  integrity sha512-v2kDEe57lecTulaDIuNTPy3Ry4gLGJ6Z1O3vE1krgXZNrsQ+LFTGHVxVjcXPs17LhbZVGedAJv8XZ1tvj5FvSg==
+"#;
+
+    // Puts a `bun` on the PATH that prints the lockfile it's given as is, the
+    // test repos store the text form in place of the binary one
+    #[cfg(unix)]
+    fn install_fake_bun() {
+        use std::{os::unix::fs::PermissionsExt, sync::Once};
+
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            let bin_dir =
+                std::env::temp_dir().join(format!("turbo-fake-bun-{}", std::process::id()));
+            fs::create_dir_all(&bin_dir).unwrap();
+            let bun = bin_dir.join("bun");
+            fs::write(&bun, "#!/bin/sh\ncat \"$1\"\n").unwrap();
+            fs::set_permissions(&bun, fs::Permissions::from_mode(0o755)).unwrap();
+            let path = std::env::var_os("PATH").unwrap_or_default();
+            let path =
+                std::env::join_paths(std::iter::once(bin_dir).chain(std::env::split_paths(&path)))
+                    .unwrap();
+            std::env::set_var("PATH", path);
+        });
+    }
+
+    fn write(root: &AbsoluteSystemPath, path: &str, contents: &str) {
+        let path = root
+            .join_unix_path(turbopath::RelativeUnixPathBuf::new(path).unwrap())
+            .unwrap();
+        path.ensure_dir().unwrap();
+        fs::write(path.as_std_path(), contents).unwrap();
+    }
+
+    fn setup(package_manager: &PackageManager) -> (TempDir, CommandBase) {
+        let tmp = TempDir::new().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::try_from(tmp.path()).unwrap();
+        let (version, lockfile) = match package_manager {
+            PackageManager::Npm => ("npm@8.19.2", PACKAGE_LOCK),
+            PackageManager::Bun => {
+                #[cfg(unix)]
+                install_fake_bun();
+                ("bun@1.0.0", BUN_LOCK)
+            }
+            _ => unimplemented!("no test repo for {}", package_manager),
+        };
+        write(
+            &repo_root,
+            "package.json",
+            &format!(
+                r#"{{"name": "monorepo", "packageManager": "{}", "workspaces": ["apps/*", "packages/*"]}}"#,
+                version
+            ),
+        );
+        write(&repo_root, package_manager.lockfile_name(), lockfile);
+        write(
+            &repo_root,
+            "turbo.json",
+            r#"{"pipeline": {"build": {}, "web#build": {}, "docs#build": {}}}"#,
+        );
+        write(
+            &repo_root,
+            "apps/web/package.json",
+            r#"{"name": "web", "dependencies": {"ui": "*"}}"#,
+        );
+        write(&repo_root, "apps/web/index.js", "");
+        write(
+            &repo_root,
+            "apps/docs/package.json",
+            r#"{"name": "docs", "dependencies": {"lodash": "^4.17.21"}}"#,
+        );
+        write(&repo_root, "packages/ui/package.json", r#"{"name": "ui"}"#);
+
+        let base = CommandBase::new(Args::default(), repo_root, "test", UI::new(true)).unwrap();
+        (tmp, base)
+    }
+
+    #[test_case(PackageManager::Npm ; "npm")]
+    #[cfg_attr(unix, test_case(PackageManager::Bun ; "bun"))]
+    fn test_prune(package_manager: PackageManager) -> Result<()> {
+        let (_tmp, base) = setup(&package_manager);
+        prune(&base, &["web".to_string()], false, "out")?;
+
+        let out = base.repo_root.join_component("out");
+        assert!(out.join_components(&["apps", "web", "index.js"]).exists());
+        assert!(out
+            .join_components(&["packages", "ui", "package.json"])
+            .exists());
+        assert!(!out.join_components(&["apps", "docs"]).exists());
+        assert!(out.join_component("package.json").exists());
+
+        let lockfile = fs::read_to_string(
+            out.join_component(package_manager.pruned_lockfile_name())
+                .as_std_path(),
+        )?;
+        match package_manager {
+            PackageManager::Npm => assert!(lockfile.contains("node_modules/ui")),
+            _ => assert!(lockfile.contains("# yarn lockfile v1")),
+        }
+        assert!(!lockfile.contains("lodash"));
+        assert!(!out.join_component("bun.lockb").exists());
+
+        let turbo_json: serde_json::Value = serde_json::from_str(&fs::read_to_string(
+            out.join_component("turbo.json").as_std_path(),
+        )?)?;
+        assert_eq!(
+            turbo_json,
+            serde_json::json!({"pipeline": {"build": {}, "web#build": {}}})
+        );
+
+        Ok(())
+    }
+
+    #[test_case(PackageManager::Npm ; "npm")]
+    #[cfg_attr(unix, test_case(PackageManager::Bun ; "bun"))]
+    fn test_prune_docker(package_manager: PackageManager) -> Result<()> {
+        let (_tmp, base) = setup(&package_manager);
+        prune(&base, &["web".to_string()], true, "out")?;
+
+        let out = base.repo_root.join_component("out");
+        let lockfile = package_manager.pruned_lockfile_name();
+        assert!(out.join_component(lockfile).exists());
+        assert!(out.join_components(&["json", lockfile]).exists());
+        assert!(out
+            .join_components(&["full", "apps", "web", "index.js"])
+            .exists());
+        assert!(out.join_components(&["full", "turbo.json"]).exists());
+        assert!(out
+            .join_components(&["json", "apps", "web", "package.json"])
+            .exists());
+        assert!(!out
+            .join_components(&["json", "apps", "web", "index.js"])
+            .exists());
+        assert!(out
+            .join_components(&["json", "packages", "ui", "package.json"])
+            .exists());
+        assert!(out.join_components(&["json", "package.json"]).exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_invalid_scope() {
+        let (_tmp, base) = setup(&PackageManager::Npm);
+        let err = prune(&base, &["missing".to_string()], false, "out").unwrap_err();
+        assert_eq!(err.to_string(), "invalid scope: package missing not found");
+    }
+}
@@ -4,13 +4,13 @@ use tracing::{error, info};
 use crate::{commands::CommandBase, run::Run};
 
 #[allow(dead_code)]
-pub async fn run(base: CommandBase) -> Result<()> {
+pub async fn run(base: CommandBase) -> Result<i32> {
     info!("Executing run stub");
     let mut run = Run::new(base);
     info!("configured run struct: {:?}", run);
 
     match run.run().await {
-        Ok(_) => Ok(()),
+        Ok(exit_code) => Ok(exit_code),
         Err(err) => {
             error!("run failed: {}", err);
             Err(err)
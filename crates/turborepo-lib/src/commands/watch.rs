@@ -0,0 +1,16 @@
+use anyhow::Result;
+use tracing::error;
+
+use crate::{commands::CommandBase, run::Run};
+
+pub async fn run(base: CommandBase) -> Result<i32> {
+    let mut run = Run::new(base);
+
+    match run.watch().await {
+        Ok(exit_code) => Ok(exit_code),
+        Err(err) => {
+            error!("watch failed: {}", err);
+            Err(err)
+        }
+    }
+}
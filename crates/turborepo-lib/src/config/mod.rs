@@ -1,8 +1,10 @@
 mod client;
 mod env;
 mod repo;
+mod schema;
 mod turbo;
 mod user;
+mod validate;
 
 use std::path::PathBuf;
 
@@ -19,10 +21,15 @@ use dirs_next::config_dir;
 use dirs_next::data_local_dir as config_dir;
 pub use env::MappedEnvironment;
 pub use repo::{get_repo_config_path, RepoConfig, RepoConfigLoader};
+pub use schema::{render_schema, turbo_json_schema};
 use serde::Serialize;
 use thiserror::Error;
 pub use turbo::{RawTurboJSON, SpacesJson, TurboJson};
+use turbopath::AbsoluteSystemPathBuf;
 pub use user::{UserConfig, UserConfigLoader};
+pub use validate::{validate, ValidationError, ValidationReport};
+
+use crate::ui::UI;
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -61,6 +68,30 @@ pub enum Error {
     },
     #[error(transparent)]
     PathError(#[from] turbopath::PathError),
+    #[error("{}", .0.render(&UI::infer()))]
+    InvalidTurboJson(Box<ValidationReport>),
+    #[error("No \"extends\" key found in {path}")]
+    NoExtends { path: AbsoluteSystemPathBuf },
+    #[error(
+        "Invalid \"extends\" in {path}: workspace turbo.json files can only extend from the root \
+         workspace with [\"//\"]"
+    )]
+    ExtendFromNonRoot { path: AbsoluteSystemPathBuf },
+    #[error("\"extends\" is only allowed in workspace turbo.json files, found it in {path}")]
+    ExtendsInRoot { path: AbsoluteSystemPathBuf },
+    #[error("\"{key}\" is only allowed in the root turbo.json, found it in {path}")]
+    RootOnlyKey {
+        key: &'static str,
+        path: AbsoluteSystemPathBuf,
+    },
+    #[error(
+        "Package tasks (<package>#<task>) are not allowed in workspace turbo.json files: found \
+         {task_id} in {path}"
+    )]
+    PackageTaskInWorkspace {
+        task_id: String,
+        path: AbsoluteSystemPathBuf,
+    },
 }
 
 pub fn default_user_config_path() -> Result<Utf8PathBuf, Error> {
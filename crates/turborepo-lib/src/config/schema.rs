@@ -0,0 +1,427 @@
+//! The JSON Schema for turbo.json. It's generated from the types that
+//! turbo.json is deserialized into, and checked in at
+//! `packages/turbo-types/schema.json` so that editors autocomplete exactly
+//! what turbo accepts. Run `turbo schema` to print it.
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use turborepo_cache::{signature_authentication::SignatureAlgorithm, RemoteCacheOpts};
+
+use crate::{
+    config::turbo::{RawTaskDefinition, RawTurboJSON},
+    task_graph::TaskOutputMode,
+};
+
+const SCHEMA_URL: &str = "https://turbo.build/schema.json";
+const DOCS_URL: &str = "https://turbo.build/repo/docs/reference/configuration";
+
+/// A type that can describe its JSON representation as a JSON Schema
+pub(crate) trait JsonSchema {
+    fn json_schema() -> Value;
+}
+
+/// Returns the schema for turbo.json
+pub fn turbo_json_schema() -> Value {
+    let mut schema = RawTurboJSON::json_schema();
+    let root = schema
+        .as_object_mut()
+        .expect("turbo.json schema is an object");
+    root.insert(
+        "$schema".to_string(),
+        json!("http://json-schema.org/draft-07/schema#"),
+    );
+    root.insert("$id".to_string(), json!(SCHEMA_URL));
+    root.insert("title".to_string(), json!("turbo.json"));
+    root.insert(
+        "definitions".to_string(),
+        json!({
+            "Pipeline": RawTaskDefinition::json_schema(),
+            "RemoteCache": RemoteCacheOpts::json_schema(),
+        }),
+    );
+    schema
+}
+
+/// Renders the schema the way it's checked in. Keys are sorted so that the
+/// output doesn't depend on whether serde_json preserves insertion order.
+pub fn render_schema() -> String {
+    let mut schema =
+        serde_json::to_string_pretty(&sorted(turbo_json_schema())).expect("schema is valid JSON");
+    schema.push('\n');
+    schema
+}
+
+fn sorted(value: Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut entries = object.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sorted(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(array) => Value::Array(array.into_iter().map(sorted).collect()),
+        value => value,
+    }
+}
+
+fn docs(description: &str, anchor: &str) -> String {
+    format!("{description}\n\nDocumentation: {DOCS_URL}#{anchor}")
+}
+
+fn object<'a>(properties: impl IntoIterator<Item = (&'a str, Value)>) -> Value {
+    let properties = properties
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect::<Map<_, _>>();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+fn string(description: String) -> Value {
+    json!({ "type": "string", "description": description })
+}
+
+fn boolean(description: String, default: bool) -> Value {
+    json!({ "type": "boolean", "description": description, "default": default })
+}
+
+fn strings(description: String) -> Value {
+    json!({
+        "type": "array",
+        "description": description,
+        "items": { "type": "string" },
+        "default": [],
+    })
+}
+
+// Lists that distinguish between not being configured and being empty
+fn nullable_strings(description: String) -> Value {
+    json!({
+        "type": ["array", "null"],
+        "description": description,
+        "items": { "type": "string" },
+        "default": null,
+    })
+}
+
+// The allowed values are serialized so that they're spelled the way serde
+// expects them
+fn one_of<T: Serialize>(description: String, values: &[T], default: &T) -> Value {
+    json!({
+        "type": "string",
+        "description": description,
+        "enum": values
+            .iter()
+            .map(|value| serde_json::to_value(value).expect("enum values serialize"))
+            .collect::<Vec<_>>(),
+        "default": serde_json::to_value(default).expect("enum values serialize"),
+    })
+}
+
+fn reference(definition: &str, description: String) -> Value {
+    json!({ "$ref": format!("#/definitions/{definition}"), "description": description })
+}
+
+impl JsonSchema for RawTurboJSON {
+    fn json_schema() -> Value {
+        let mut schema = object([
+            (
+                "$schema",
+                string(format!("The JSON Schema of turbo.json, usually {SCHEMA_URL}")),
+            ),
+            (
+                "experimentalSpaces",
+                json!({
+                    "type": "object",
+                    "description": "Experimental configuration for Vercel Spaces",
+                    "properties": {
+                        "id": string("The ID of the Space that runs are reported to".to_string()),
+                    },
+                }),
+            ),
+            (
+                "extends",
+                strings(docs(
+                    "Tells turbo to extend the root turbo.json and override it with the keys \
+                     provided in this workspace's turbo.json.\n\nThis key is only available in \
+                     workspace configs and cannot be used in the root turbo.json. Currently, \
+                     only the \"//\" value is allowed.",
+                    "extends",
+                )),
+            ),
+            (
+                "globalDependencies",
+                strings(docs(
+                    "A list of globs to include in the set of implicit global hash \
+                     dependencies.\n\nThe contents of these files will be included in the \
+                     global hashing algorithm and affect the hashes of all tasks. This is useful \
+                     for busting the cache based on root level files that impact package tasks \
+                     but are not represented in the dependency graph, e.g. a root tsconfig.json \
+                     or .eslintrc.",
+                    "globaldependencies",
+                )),
+            ),
+            (
+                "globalDotEnv",
+                nullable_strings(docs(
+                    "A priority-ordered (most-significant to least-significant) array of \
+                     project-anchored Unix-style paths to .env files to include in the global \
+                     hash.",
+                    "globaldotenv",
+                )),
+            ),
+            (
+                "globalEnv",
+                strings(docs(
+                    "A list of environment variables for implicit global hash \
+                     dependencies.\n\nThe variables included in this list will affect all task \
+                     hashes.",
+                    "globalenv",
+                )),
+            ),
+            (
+                "globalPassThroughEnv",
+                nullable_strings(docs(
+                    "An allowlist of environment variables that should be made available to all \
+                     tasks, but should not contribute to their cache keys, e.g. \
+                     AWS_SECRET_KEY.",
+                    "globalpassthroughenv",
+                )),
+            ),
+            (
+                "pipeline",
+                json!({
+                    "type": "object",
+                    "description": docs(
+                        "An object representing the task dependency graph of your project. \
+                         turbo interprets these conventions to schedule, execute, and cache the \
+                         outputs of tasks in your project.\n\nEach key is the name of a task, \
+                         or a <workspace>#<task> pair, that turbo can execute.",
+                        "pipeline",
+                    ),
+                    "additionalProperties": { "$ref": "#/definitions/Pipeline" },
+                    "default": {},
+                }),
+            ),
+            (
+                "remoteCache",
+                reference(
+                    "RemoteCache",
+                    "Configuration options that control how turbo interfaces with the remote \
+                     cache.\n\nDocumentation: \
+                     https://turbo.build/repo/docs/core-concepts/remote-caching"
+                        .to_string(),
+                ),
+            ),
+        ]);
+        schema["description"] = json!("The configuration of a Turborepo repository or workspace");
+        schema
+    }
+}
+
+impl JsonSchema for RawTaskDefinition {
+    fn json_schema() -> Value {
+        let mut schema = object([
+            (
+                "cache",
+                boolean(
+                    docs(
+                        "Whether or not to cache the outputs of the task.\n\nSetting cache to \
+                         false is useful for long-running \"watch\" or development mode tasks.",
+                        "cache",
+                    ),
+                    true,
+                ),
+            ),
+            (
+                "dependsOn",
+                strings(docs(
+                    "The list of tasks that this task depends on.\n\nPrefixing an item with ^ \
+                     tells turbo that this task depends on the package's topological dependencies \
+                     completing the task first, e.g. \"^build\". Items without a ^ prefix express \
+                     the relationships between tasks within the same package.",
+                    "dependson",
+                )),
+            ),
+            (
+                "dotEnv",
+                nullable_strings(docs(
+                    "A priority-ordered (most-significant to least-significant) array of \
+                     workspace-anchored Unix-style paths to .env files to include in the task \
+                     hash.",
+                    "dotenv",
+                )),
+            ),
+            (
+                "env",
+                strings(docs(
+                    "A list of environment variables that this task depends on.\n\nVariables \
+                     should not be prefixed with $.",
+                    "env",
+                )),
+            ),
+            (
+                "inputs",
+                strings(docs(
+                    "The set of glob patterns to consider as inputs to this task.\n\nChanges to \
+                     files covered by these globs will cause a cache miss and the task will be \
+                     rerun. If omitted or empty, all files in the package are considered as \
+                     inputs.",
+                    "inputs",
+                )),
+            ),
+            (
+                "outputMode",
+                one_of(
+                    docs(
+                        &format!(
+                            "Output mode for the task.\n\n{}",
+                            TaskOutputMode::ALL
+                                .iter()
+                                .map(|mode| format!(
+                                    "\"{}\": {}",
+                                    serde_json::to_value(mode)
+                                        .expect("output modes serialize")
+                                        .as_str()
+                                        .expect("output modes are strings"),
+                                    output_mode_description(mode)
+                                ))
+                                .collect::<Vec<_>>()
+                                .join("\n\n")
+                        ),
+                        "outputmode",
+                    ),
+                    &TaskOutputMode::ALL,
+                    &TaskOutputMode::default(),
+                ),
+            ),
+            (
+                "outputs",
+                strings(docs(
+                    "The set of glob patterns indicating a task's cacheable filesystem \
+                     outputs.\n\nLogs are always cached and never need to be specified.",
+                    "outputs",
+                )),
+            ),
+            (
+                "passThroughEnv",
+                nullable_strings(docs(
+                    "An allowlist of environment variables that should be made available in this \
+                     task's environment, but should not contribute to its cache key, e.g. \
+                     AWS_SECRET_KEY.",
+                    "passthroughenv",
+                )),
+            ),
+            (
+                "persistent",
+                boolean(
+                    docs(
+                        "Indicates whether the task exits or not. Setting persistent to true \
+                         tells turbo that this is a long-running task and will ensure that other \
+                         tasks cannot depend on it.",
+                        "persistent",
+                    ),
+                    false,
+                ),
+            ),
+        ]);
+        schema["description"] = json!("The configuration of a task");
+        schema
+    }
+}
+
+impl JsonSchema for RemoteCacheOpts {
+    fn json_schema() -> Value {
+        object([
+            (
+                "publicKey",
+                string(
+                    "The Ed25519 public key that artifacts are verified with, when \
+                     signatureAlgorithm is \"ed25519\""
+                        .to_string(),
+                ),
+            ),
+            (
+                "signature",
+                boolean(
+                    "Indicates if signature verification is enabled for requests to the remote \
+                     cache. When true, turbo signs every uploaded artifact using the value of \
+                     TURBO_REMOTE_CACHE_SIGNATURE_KEY and rejects any downloaded artifact that \
+                     has an invalid or missing signature."
+                        .to_string(),
+                    false,
+                ),
+            ),
+            (
+                "signatureAlgorithm",
+                one_of(
+                    "How artifacts are signed. \"hmac-sha256\" uses a shared secret to both sign \
+                     and verify artifacts, while \"ed25519\" uses a key pair so that only \
+                     machines with the private key can sign artifacts."
+                        .to_string(),
+                    &[SignatureAlgorithm::HmacSha256, SignatureAlgorithm::Ed25519],
+                    &SignatureAlgorithm::default(),
+                ),
+            ),
+            (
+                "teamId",
+                string("The ID of the team that owns the remote cache".to_string()),
+            ),
+        ])
+    }
+}
+
+fn output_mode_description(mode: &TaskOutputMode) -> &'static str {
+    match mode {
+        TaskOutputMode::Full => "Displays all output",
+        TaskOutputMode::None => "Hides all task output",
+        TaskOutputMode::Hash => "Show only the hashes of the tasks",
+        TaskOutputMode::New => "Only show output from cache misses",
+        TaskOutputMode::Error => "Only show output from task failures",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, path::PathBuf};
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_checked_in_schema_is_up_to_date() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../../packages/turbo-types/schema.json");
+        let schema = render_schema();
+        if env::var_os("TURBO_UPDATE_SCHEMA").is_some() {
+            fs::write(&path, &schema).unwrap();
+        }
+
+        let checked_in = fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            checked_in == schema,
+            "{} is out of date, run `TURBO_UPDATE_SCHEMA=1 cargo test -p turborepo-lib schema` to \
+             update it",
+            path.display()
+        );
+    }
+
+    #[test]
+    fn test_output_modes() {
+        let schema = turbo_json_schema();
+        let output_mode = &schema["definitions"]["Pipeline"]["properties"]["outputMode"];
+        assert_eq!(
+            output_mode["enum"],
+            json!(["full", "none", "hash-only", "new-only", "errors-only"])
+        );
+        assert_eq!(output_mode["default"], json!("full"));
+    }
+}
@@ -1,21 +1,22 @@
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
     fs,
-    path::Path,
 };
 
-use camino::Utf8Path;
 use serde::{Deserialize, Serialize};
-use turbopath::{AbsoluteSystemPath, RelativeUnixPathBuf};
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPath, RelativeUnixPathBuf};
+use turborepo_cache::RemoteCacheOpts;
 
 use crate::{
-    config::Error,
-    opts::RemoteCacheOpts,
+    config::{validate, validate::PARSE_OPTIONS, Error, ValidationReport},
     package_json::PackageJson,
-    run::task_id::{get_package_task_from_id, is_package_task, root_task_id},
+    run::task_id::{
+        get_package_task_from_id, is_package_task, is_task_in_package, root_task_id, ROOT_PKG_NAME,
+    },
     task_graph::{
         BookkeepingTaskDefinition, Pipeline, TaskDefinitionHashable, TaskOutputMode, TaskOutputs,
     },
+    ui::UI,
 };
 
 #[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
@@ -29,11 +30,11 @@ pub struct SpacesJson {
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 // The processed TurboJSON ready for use by Turborepo.
 pub struct TurboJson {
-    extends: Vec<String>,
-    global_deps: Vec<String>,
-    global_dot_env: Vec<RelativeUnixPathBuf>,
-    global_env: Vec<String>,
-    global_pass_through_env: Vec<String>,
+    pub(crate) extends: Vec<String>,
+    pub(crate) global_deps: Vec<String>,
+    pub(crate) global_dot_env: Vec<RelativeUnixPathBuf>,
+    pub(crate) global_env: Vec<String>,
+    pub(crate) global_pass_through_env: Option<Vec<String>>,
     pub(crate) pipeline: Pipeline,
     pub(crate) remote_cache_options: Option<RemoteCacheOpts>,
     pub(crate) space_id: Option<String>,
@@ -43,39 +44,56 @@ pub struct TurboJson {
 #[serde(rename_all = "camelCase")]
 // The raw deserialized turbo.json file.
 pub struct RawTurboJSON {
-    #[serde(rename = "$schema")]
+    #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
     schema: Option<String>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub experimental_spaces: Option<SpacesJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     extends: Option<Vec<String>>,
     // Global root filesystem dependencies
+    #[serde(skip_serializing_if = "Option::is_none")]
     global_dependencies: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     global_env: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     global_pass_through_env: Option<Vec<String>>,
     // .env files to consider, in order.
+    #[serde(skip_serializing_if = "Option::is_none")]
     global_dot_env: Option<Vec<String>>,
     // Pipeline is a map of Turbo pipeline entries which define the task graph
     // and cache behavior on a per task or per package-task basis.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pipeline: Option<RawPipeline>,
     // Configuration options when interfacing with the remote cache
+    #[serde(rename = "remoteCache", skip_serializing_if = "Option::is_none")]
     pub(crate) remote_cache_options: Option<RemoteCacheOpts>,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
 #[serde(transparent)]
-struct RawPipeline(BTreeMap<String, RawTaskDefinition>);
+pub(crate) struct RawPipeline(BTreeMap<String, RawTaskDefinition>);
 
 #[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
-struct RawTaskDefinition {
+pub(crate) struct RawTaskDefinition {
+    #[serde(skip_serializing_if = "Option::is_none")]
     cache: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     depends_on: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     dot_env: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     env: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     inputs: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pass_through_env: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     persistent: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     outputs: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     output_mode: Option<TaskOutputMode>,
 }
 
@@ -90,24 +108,8 @@ impl From<Vec<String>> for TaskOutputs {
 
         for glob in outputs {
             if let Some(glob) = glob.strip_prefix('!') {
-                if Utf8Path::new(glob).is_absolute() {
-                    println!(
-                        "[WARNING] Using an absolute path in \"outputs\" ({}) will not work and \
-                         will be an error in a future version",
-                        glob
-                    )
-                }
-
                 exclusions.push(glob.to_string());
             } else {
-                if Utf8Path::new(&glob).is_absolute() {
-                    println!(
-                        "[WARNING] Using an absolute path in \"outputs\" ({}) will not work and \
-                         will be an error in a future version",
-                        glob
-                    )
-                }
-
                 inclusions.push(glob);
             }
         }
@@ -157,12 +159,6 @@ impl TryFrom<RawTaskDefinition> for BookkeepingTaskDefinition {
 
             for dependency in depends_on {
                 if let Some(dependency) = dependency.strip_prefix(ENV_PIPELINE_DELIMITER) {
-                    println!(
-                        "[DEPRECATED] Declaring an environment variable in \"dependsOn\" is \
-                         deprecated, found {}. Use the \"env\" key or use `npx @turbo/codemod \
-                         migrate-env-var-dependencies`.\n",
-                        dependency
-                    );
                     defined_fields.insert("Env".to_string());
                     env_var_dependencies.insert(dependency.to_string());
                 } else if let Some(topo_dependency) =
@@ -178,33 +174,19 @@ impl TryFrom<RawTaskDefinition> for BookkeepingTaskDefinition {
         task_dependencies.sort();
         topological_dependencies.sort();
 
-        let env = raw_task
-            .env
-            .map(|env| -> Result<Vec<String>, Error> {
-                defined_fields.insert("Env".to_string());
-                gather_env_vars(env, "env", &mut env_var_dependencies)?;
-                let mut env_var_dependencies: Vec<String> =
-                    env_var_dependencies.into_iter().collect();
-                env_var_dependencies.sort();
-                Ok(env_var_dependencies)
-            })
-            .transpose()?
-            .unwrap_or_default();
+        // Env vars declared with `$` in dependsOn still count even without an
+        // `env` key
+        if let Some(env) = raw_task.env {
+            defined_fields.insert("Env".to_string());
+            gather_env_vars(env, "env", &mut env_var_dependencies)?;
+        }
+        let mut env: Vec<String> = env_var_dependencies.into_iter().collect();
+        env.sort();
 
         let inputs = raw_task
             .inputs
             .map(|inputs| {
                 defined_fields.insert("Inputs".to_string());
-                for input in &inputs {
-                    if Path::new(&input).is_absolute() {
-                        println!(
-                            "[WARNING] Using an absolute path in \"inputs\" ({}) will not work \
-                             and will be an error in a future version",
-                            input
-                        )
-                    }
-                }
-
                 inputs
             })
             .unwrap_or_default();
@@ -219,8 +201,7 @@ impl TryFrom<RawTaskDefinition> for BookkeepingTaskDefinition {
                 pass_through_env.sort();
                 Ok(pass_through_env)
             })
-            .transpose()?
-            .unwrap_or_default();
+            .transpose()?;
 
         let dot_env = raw_task
             .dot_env
@@ -266,6 +247,110 @@ impl TryFrom<RawTaskDefinition> for BookkeepingTaskDefinition {
     }
 }
 
+impl RawTurboJSON {
+    /// Reads a `RawTurboJSON` from the given path, printing any warnings
+    /// found while validating it
+    pub fn read(path: &AbsoluteSystemPath) -> Result<RawTurboJSON, Error> {
+        let (raw_turbo_json, report) = Self::read_validated(path)?;
+        if !report.is_empty() {
+            eprintln!("{}", report.render(&UI::infer()));
+        }
+        Ok(raw_turbo_json)
+    }
+
+    /// Reads a `RawTurboJSON` from the given path along with the warnings
+    /// found while validating it. Any validation errors fail the read.
+    pub fn read_validated(
+        path: &AbsoluteSystemPath,
+    ) -> Result<(RawTurboJSON, ValidationReport), Error> {
+        let contents = fs::read_to_string(path)?;
+        let report = validate(path, &contents);
+        if report.has_errors() {
+            return Err(Error::InvalidTurboJson(Box::new(report)));
+        }
+        // Any syntax errors have been reported by the validation above
+        let value = jsonc_parser::parse_to_serde_value(&contents, &PARSE_OPTIONS)
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        Ok((serde_json::from_value(value)?, report))
+    }
+
+    /// Checks that the root turbo.json doesn't extend from anything
+    fn validate_root(&self, path: &AbsoluteSystemPath) -> Result<(), Error> {
+        match self.extends.as_deref() {
+            None | Some([]) => Ok(()),
+            Some(_) => Err(Error::ExtendsInRoot {
+                path: path.to_owned(),
+            }),
+        }
+    }
+
+    /// Removes any package tasks that belong to workspaces not in
+    /// `workspaces`. Used when pruning as the remaining tasks might
+    /// otherwise refer to workspaces that no longer exist.
+    pub fn prune_tasks<S: AsRef<str>>(mut self, workspaces: &[S]) -> Self {
+        if let Some(pipeline) = &mut self.pipeline {
+            pipeline.0.retain(|task_name, _| {
+                workspaces
+                    .iter()
+                    .any(|workspace| is_task_in_package(task_name, workspace.as_ref()))
+            });
+        }
+        self
+    }
+
+    /// Checks that a workspace turbo.json extends from the root turbo.json and
+    /// only configures tasks for its own workspace
+    fn validate_workspace(&self, path: &AbsoluteSystemPath) -> Result<(), Error> {
+        match self.extends.as_deref() {
+            None | Some([]) => {
+                return Err(Error::NoExtends {
+                    path: path.to_owned(),
+                })
+            }
+            Some([workspace]) if workspace == ROOT_PKG_NAME => (),
+            Some(_) => {
+                return Err(Error::ExtendFromNonRoot {
+                    path: path.to_owned(),
+                })
+            }
+        }
+
+        let root_only_keys = [
+            ("globalDependencies", self.global_dependencies.is_some()),
+            ("globalEnv", self.global_env.is_some()),
+            (
+                "globalPassThroughEnv",
+                self.global_pass_through_env.is_some(),
+            ),
+            ("globalDotEnv", self.global_dot_env.is_some()),
+            ("remoteCache", self.remote_cache_options.is_some()),
+            ("experimentalSpaces", self.experimental_spaces.is_some()),
+        ];
+        if let Some((key, _)) = root_only_keys.into_iter().find(|(_, defined)| *defined) {
+            return Err(Error::RootOnlyKey {
+                key,
+                path: path.to_owned(),
+            });
+        }
+
+        if let Some(task_id) = self
+            .pipeline
+            .iter()
+            .flat_map(|pipeline| pipeline.0.keys())
+            .find(|task_id| is_package_task(task_id))
+        {
+            return Err(Error::PackageTaskInWorkspace {
+                task_id: task_id.clone(),
+                path: path.to_owned(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
 impl TryFrom<RawTurboJSON> for TurboJson {
     type Error = Error;
 
@@ -277,27 +362,12 @@ impl TryFrom<RawTurboJSON> for TurboJson {
             gather_env_vars(global_env_from_turbo, "globalEnv", &mut global_env)?;
         }
 
-        // TODO: In the rust port, warnings should be refactored to a post-parse
-        // validation step
+        // Deprecated env var dependencies and absolute paths are reported as
+        // warnings when validating the file, see `config::validate`
         for value in raw_turbo.global_dependencies.into_iter().flatten() {
             if let Some(env_var) = value.strip_prefix(ENV_PIPELINE_DELIMITER) {
-                println!(
-                    "[DEPRECATED] Declaring an environment variable in \"dependsOn\" is \
-                     deprecated, found {}. Use the \"env\" key or use `npx @turbo/codemod \
-                     migrate-env-var-dependencies`.\n",
-                    env_var
-                );
-
                 global_env.insert(env_var.to_string());
             } else {
-                if Path::new(&value).is_absolute() {
-                    println!(
-                        "[WARNING] Using an absolute path in \"globalDependencies\" ({}) will not \
-                         work and will be an error in a future version",
-                        value
-                    )
-                }
-
                 global_file_dependencies.insert(value);
             }
         }
@@ -318,8 +388,7 @@ impl TryFrom<RawTurboJSON> for TurboJson {
                     global_pass_through_env.sort();
                     Ok(global_pass_through_env)
                 })
-                .transpose()?
-                .unwrap_or_default(),
+                .transpose()?,
             global_deps: {
                 let mut global_deps: Vec<_> = global_file_dependencies.into_iter().collect();
                 global_deps.sort();
@@ -370,7 +439,11 @@ impl TurboJson {
             );
         }
 
-        let turbo_from_files = Self::read(&dir.join_component(CONFIG_FILE));
+        let turbo_json_path = dir.join_component(CONFIG_FILE);
+        let turbo_from_files = RawTurboJSON::read(&turbo_json_path).and_then(|raw_turbo_json| {
+            raw_turbo_json.validate_root(&turbo_json_path)?;
+            raw_turbo_json.try_into()
+        });
 
         let mut turbo_json = match (include_synthesized_from_root_package_json, turbo_from_files) {
             // If the file didn't exist, throw a custom error here instead of propagating
@@ -431,6 +504,25 @@ impl TurboJson {
         Ok(turbo_json)
     }
 
+    /// Loads the turbo.json of a workspace if it has one. Workspace configs
+    /// have to extend the root turbo.json, and can only override the
+    /// definitions of its tasks.
+    pub fn load_workspace(
+        repo_root: &AbsoluteSystemPath,
+        workspace_path: &AnchoredSystemPath,
+    ) -> Result<Option<TurboJson>, Error> {
+        let path = repo_root
+            .resolve(workspace_path)
+            .join_component(CONFIG_FILE);
+        let raw_turbo_json = match RawTurboJSON::read(&path) {
+            Ok(raw_turbo_json) => raw_turbo_json,
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        raw_turbo_json.validate_workspace(&path)?;
+        raw_turbo_json.try_into().map(Some)
+    }
+
     fn has_task(&self, task: &str) -> bool {
         for key in self.pipeline.keys() {
             if key == task {
@@ -447,13 +539,17 @@ impl TurboJson {
         false
     }
 
-    /// Reads a `RawTurboJson` from the given path
-    /// and then converts it into `TurboJson`
-    fn read(path: &AbsoluteSystemPath) -> Result<TurboJson, Error> {
-        let contents = fs::read_to_string(path)?;
-        let turbo_json: RawTurboJSON = serde_json::from_str(&contents)?;
-
-        turbo_json.try_into()
+    /// Checks the turbo.json at `path` without loading the rest of the
+    /// repository's config, returning any warnings. Used by `turbo
+    /// lint-config`.
+    pub fn lint(path: &AbsoluteSystemPath, is_root: bool) -> Result<ValidationReport, Error> {
+        let (raw_turbo_json, report) = RawTurboJSON::read_validated(path)?;
+        match is_root {
+            true => raw_turbo_json.validate_root(path)?,
+            false => raw_turbo_json.validate_workspace(path)?,
+        }
+        TurboJson::try_from(raw_turbo_json)?;
+        Ok(report)
     }
 }
 
@@ -477,15 +573,23 @@ fn gather_env_vars(vars: Vec<String>, key: &str, into: &mut HashSet<String>) ->
 
 #[cfg(test)]
 mod tests {
-    use std::{collections::HashSet, fs};
+    use std::{
+        collections::{BTreeMap, HashSet},
+        fs,
+    };
 
     use anyhow::Result;
+    use itertools::Itertools;
     use tempfile::tempdir;
     use test_case::test_case;
-    use turbopath::{AbsoluteSystemPath, RelativeUnixPathBuf};
+    use turbopath::{AbsoluteSystemPath, AnchoredSystemPath, RelativeUnixPathBuf};
+    use turborepo_cache::{signature_authentication::SignatureAlgorithm, RemoteCacheOpts};
 
     use crate::{
-        config::{turbo::RawTaskDefinition, TurboJson},
+        config::{
+            turbo::{RawPipeline, RawTaskDefinition, SpacesJson},
+            turbo_json_schema, RawTurboJSON, TurboJson,
+        },
         package_json::PackageJson,
         task_graph::{
             BookkeepingTaskDefinition, TaskDefinitionExperiments, TaskDefinitionHashable,
@@ -508,10 +612,21 @@ mod tests {
     ; "global dot env (unsorted)")]
     #[test_case(r#"{ "globalPassThroughEnv": ["GITHUB_TOKEN", "AWS_SECRET_KEY"] }"#,
         TurboJson {
-            global_pass_through_env: vec!["AWS_SECRET_KEY".to_string(), "GITHUB_TOKEN".to_string()],
+            global_pass_through_env: Some(vec!["AWS_SECRET_KEY".to_string(), "GITHUB_TOKEN".to_string()]),
             ..TurboJson::default()
         }
     )]
+    #[test_case(r#"{ "remoteCache": { "signature": true, "signatureAlgorithm": "ed25519", "publicKey": "MCowBQYDK2VwAyEA" } }"#,
+        TurboJson {
+            remote_cache_options: Some(RemoteCacheOpts {
+                signature: true,
+                signature_algorithm: SignatureAlgorithm::Ed25519,
+                public_key: Some("MCowBQYDK2VwAyEA".to_string()),
+                ..RemoteCacheOpts::default()
+            }),
+            ..TurboJson::default()
+        }
+    ; "remote cache signing")]
     fn test_get_root_turbo_no_synthesizing(
         turbo_json_content: &str,
         expected_turbo_json: TurboJson,
@@ -679,13 +794,30 @@ mod tests {
                 cache: false,
                 inputs: vec!["package/a/src/**".to_string()],
                 output_mode: TaskOutputMode::Full,
-                pass_through_env: vec!["AWS_SECRET_KEY".to_string()],
+                pass_through_env: Some(vec!["AWS_SECRET_KEY".to_string()]),
                 task_dependencies: vec!["cli#build".to_string()],
                 topological_dependencies: vec![],
                 persistent: true,
             }
         }
     )]
+    #[test_case(
+        r#"{ "dependsOn": ["$API_URL", "^build"] }"#,
+        RawTaskDefinition {
+            depends_on: Some(vec!["$API_URL".to_string(), "^build".to_string()]),
+            ..RawTaskDefinition::default()
+        },
+        BookkeepingTaskDefinition {
+            defined_fields: ["DependsOn".to_string(), "Env".to_string()].into_iter().collect(),
+            experimental_fields: HashSet::new(),
+            experimental: TaskDefinitionExperiments::default(),
+            task_definition: TaskDefinitionHashable {
+                env: vec!["API_URL".to_string()],
+                topological_dependencies: vec!["build".to_string()],
+                ..TaskDefinitionHashable::default()
+            }
+        }
+    ; "env var in dependsOn")]
     fn test_deserialize_task_definition(
         task_definition_content: &str,
         expected_raw_task_definition: RawTaskDefinition,
@@ -719,4 +851,175 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_load_workspace() -> Result<()> {
+        let root_dir = tempdir()?;
+        let repo_root = AbsoluteSystemPath::from_std_path(root_dir.path())?;
+        let workspace_path = AnchoredSystemPath::new("apps/web")?;
+        assert_eq!(TurboJson::load_workspace(repo_root, workspace_path)?, None);
+
+        let workspace_dir = repo_root.resolve(workspace_path);
+        workspace_dir.create_dir_all()?;
+        fs::write(
+            workspace_dir.join_component("turbo.json"),
+            r#"{ "extends": ["//"], "pipeline": { "build": { "outputs": [".next/**"] } } }"#,
+        )?;
+        let turbo_json = TurboJson::load_workspace(repo_root, workspace_path)?.unwrap();
+        assert_eq!(turbo_json.extends, vec!["//".to_string()]);
+        let build = &turbo_json.pipeline["build"];
+        assert_eq!(
+            build.defined_fields,
+            ["Outputs".to_string()].into_iter().collect()
+        );
+        assert_eq!(
+            build.task_definition.outputs.inclusions,
+            vec![".next/**".to_string()]
+        );
+
+        Ok(())
+    }
+
+    #[test_case(r#"{ "pipeline": {} }"#, "No \"extends\" key found" ; "no extends")]
+    #[test_case(r#"{ "extends": ["docs"] }"#, "can only extend from the root" ; "extends from workspace")]
+    #[test_case(r#"{ "extends": ["//"], "globalEnv": ["CI"] }"#, "\"globalEnv\" is only allowed in the root" ; "root only key")]
+    #[test_case(r#"{ "extends": ["//"], "pipeline": { "docs#build": {} } }"#, "found docs#build" ; "package task")]
+    fn test_load_workspace_errors(turbo_json_content: &str, expected: &str) -> Result<()> {
+        let root_dir = tempdir()?;
+        let repo_root = AbsoluteSystemPath::from_std_path(root_dir.path())?;
+        let workspace_path = AnchoredSystemPath::new("apps/web")?;
+        let workspace_dir = repo_root.resolve(workspace_path);
+        workspace_dir.create_dir_all()?;
+        fs::write(
+            workspace_dir.join_component("turbo.json"),
+            turbo_json_content,
+        )?;
+
+        let err = TurboJson::load_workspace(repo_root, workspace_path).unwrap_err();
+        assert!(err.to_string().contains(expected), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_with_comments() -> Result<()> {
+        let root_dir = tempdir()?;
+        let repo_root = AbsoluteSystemPath::from_std_path(root_dir.path())?;
+        fs::write(
+            repo_root.join_component("turbo.json"),
+            r#"{
+                // tasks
+                "pipeline": { "build": { /* no outputs */ "cache": false } }
+            }"#,
+        )?;
+
+        let turbo_json = TurboJson::load(repo_root, &PackageJson::default(), false)?;
+        assert!(!turbo_json.pipeline["build"].task_definition.cache);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extends_in_root() -> Result<()> {
+        let root_dir = tempdir()?;
+        let repo_root = AbsoluteSystemPath::from_std_path(root_dir.path())?;
+        fs::write(
+            repo_root.join_component("turbo.json"),
+            r#"{ "extends": ["//"] }"#,
+        )?;
+
+        let err = TurboJson::load(repo_root, &PackageJson::default(), false).unwrap_err();
+        assert!(matches!(err, crate::config::Error::ExtendsInRoot { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_tasks() -> Result<()> {
+        let turbo_json: RawTurboJSON = serde_json::from_str(
+            r#"{
+                "pipeline": {
+                    "build": { "outputs": ["dist/**"] },
+                    "web#build": { "dependsOn": ["^build"] },
+                    "docs#build": {},
+                    "//#lint": {}
+                }
+            }"#,
+        )?;
+
+        let pruned = turbo_json.prune_tasks(&["web", "//"]);
+        assert_eq!(
+            serde_json::to_value(pruned)?,
+            serde_json::json!({
+                "pipeline": {
+                    "build": { "outputs": ["dist/**"] },
+                    "web#build": { "dependsOn": ["^build"] },
+                    "//#lint": {}
+                }
+            })
+        );
+
+        Ok(())
+    }
+
+    fn keys(value: &serde_json::Value) -> Vec<&str> {
+        value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|key| key.as_str())
+            .sorted()
+            .collect()
+    }
+
+    // The structs are constructed without `..Default::default()` so that adding
+    // a field fails to compile until it's accounted for here and in the schema
+    #[test]
+    fn test_schema_describes_every_field() -> Result<()> {
+        let strings = || Some(vec!["a".to_string()]);
+        let task_definition = RawTaskDefinition {
+            cache: Some(true),
+            depends_on: strings(),
+            dot_env: strings(),
+            env: strings(),
+            inputs: strings(),
+            pass_through_env: strings(),
+            persistent: Some(false),
+            outputs: strings(),
+            output_mode: Some(TaskOutputMode::Full),
+        };
+        let remote_cache = RemoteCacheOpts {
+            team_id: "team".to_string(),
+            signature: true,
+            signature_algorithm: SignatureAlgorithm::Ed25519,
+            public_key: Some("key".to_string()),
+        };
+        let turbo_json = RawTurboJSON {
+            schema: Some("schema.json".to_string()),
+            experimental_spaces: Some(SpacesJson::default()),
+            extends: strings(),
+            global_dependencies: strings(),
+            global_env: strings(),
+            global_pass_through_env: strings(),
+            global_dot_env: strings(),
+            pipeline: Some(RawPipeline(BTreeMap::new())),
+            remote_cache_options: Some(remote_cache.clone()),
+        };
+
+        let schema = turbo_json_schema();
+        assert_eq!(
+            keys(&serde_json::to_value(turbo_json)?),
+            keys(&schema["properties"])
+        );
+        assert_eq!(
+            keys(&serde_json::to_value(task_definition)?),
+            keys(&schema["definitions"]["Pipeline"]["properties"])
+        );
+        assert_eq!(
+            keys(&serde_json::to_value(remote_cache)?),
+            keys(&schema["definitions"]["RemoteCache"]["properties"])
+        );
+
+        Ok(())
+    }
 }
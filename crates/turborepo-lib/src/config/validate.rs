@@ -0,0 +1,635 @@
+//! turbo.json validation
+//!
+//! Walks the syntax tree of a turbo.json before it is deserialized so that
+//! every problem in the file can be reported at once, each pointing at the
+//! span of the offending key or value. Errors prevent the file from being
+//! loaded, warnings are printed but otherwise ignored.
+
+use camino::Utf8Path;
+use jsonc_parser::{
+    ast::{ObjectProp, StringLit, Value},
+    common::Ranged,
+    CollectOptions, ParseOptions,
+};
+use miette::{
+    Diagnostic, GraphicalReportHandler, GraphicalTheme, NamedSource, Severity, SourceSpan,
+};
+use thiserror::Error;
+use turbopath::AbsoluteSystemPath;
+use wax::Glob;
+
+use crate::ui::UI;
+
+const ROOT_KEYS: &[&str] = &[
+    "$schema",
+    "experimentalSpaces",
+    "extends",
+    "globalDependencies",
+    "globalDotEnv",
+    "globalEnv",
+    "globalPassThroughEnv",
+    "pipeline",
+    "remoteCache",
+];
+const TASK_KEYS: &[&str] = &[
+    "cache",
+    "dependsOn",
+    "dotEnv",
+    "env",
+    "inputs",
+    "outputMode",
+    "outputs",
+    "passThroughEnv",
+    "persistent",
+];
+const OUTPUT_MODES: &[&str] = &["full", "none", "hash-only", "new-only", "errors-only"];
+const ENV_PIPELINE_DELIMITER: &str = "$";
+
+/// turbo.json may contain comments, but is strict JSON otherwise
+pub(crate) const PARSE_OPTIONS: ParseOptions = ParseOptions {
+    allow_comments: true,
+    allow_loose_object_property_names: false,
+    allow_trailing_commas: false,
+};
+
+#[derive(Debug, Error, Diagnostic, PartialEq, Eq)]
+pub enum ValidationError {
+    #[error("Failed to parse turbo.json: {message}")]
+    #[diagnostic(code(turbo_json::parse))]
+    Parse {
+        message: String,
+        #[label]
+        span: SourceSpan,
+    },
+    #[error("Unknown key \"{key}\"")]
+    #[diagnostic(code(turbo_json::unknown_key))]
+    UnknownKey {
+        key: String,
+        #[label("not a known {location} key")]
+        span: SourceSpan,
+        location: &'static str,
+        #[help]
+        suggestion: Option<String>,
+    },
+    #[error("\"{key}\" must be {expected}")]
+    #[diagnostic(code(turbo_json::invalid_type))]
+    InvalidType {
+        key: String,
+        expected: &'static str,
+        found: &'static str,
+        #[label("found {found}")]
+        span: SourceSpan,
+    },
+    #[error("Environment variables in \"{key}\" should not be prefixed with \"$\"")]
+    #[diagnostic(code(turbo_json::env_prefix), help("remove the leading \"$\""))]
+    EnvPrefix {
+        key: &'static str,
+        #[label]
+        span: SourceSpan,
+    },
+    #[error("Declaring an environment variable in \"{key}\" is deprecated")]
+    #[diagnostic(
+        code(turbo_json::env_dependency),
+        severity(Warning),
+        help(
+            "use the \"env\" key or run `npx @turbo/codemod migrate-env-var-dependencies` instead"
+        )
+    )]
+    EnvDependency {
+        key: &'static str,
+        #[label]
+        span: SourceSpan,
+    },
+    #[error(
+        "Using an absolute path in \"{key}\" will not work and will be an error in a future \
+         version"
+    )]
+    #[diagnostic(code(turbo_json::absolute_path), severity(Warning))]
+    AbsolutePath {
+        key: &'static str,
+        #[label]
+        span: SourceSpan,
+    },
+    #[error("Invalid glob in \"{key}\": {reason}")]
+    #[diagnostic(code(turbo_json::invalid_glob))]
+    InvalidGlob {
+        key: &'static str,
+        reason: String,
+        #[label]
+        span: SourceSpan,
+    },
+    #[error("Invalid output mode \"{value}\"")]
+    #[diagnostic(
+        code(turbo_json::invalid_output_mode),
+        help("expected one of: full, none, hash-only, new-only, errors-only")
+    )]
+    InvalidOutputMode {
+        value: String,
+        #[label]
+        span: SourceSpan,
+    },
+}
+
+/// All of the problems found in a single turbo.json
+#[derive(Debug, Error, Diagnostic)]
+#[error("Found {} in {path}", self.summary())]
+pub struct ValidationReport {
+    path: String,
+    #[source_code]
+    contents: NamedSource,
+    #[related]
+    diagnostics: Vec<ValidationError>,
+}
+
+impl ValidationReport {
+    pub fn diagnostics(&self) -> &[ValidationError] {
+        &self.diagnostics
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn errors(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| is_error(diagnostic))
+            .count()
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.diagnostics.len() - self.errors()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors() > 0
+    }
+
+    /// Renders every diagnostic along with a code frame of where it occurred
+    pub fn render(&self, ui: &UI) -> String {
+        let theme = match ui.should_strip_ansi {
+            true => GraphicalTheme::unicode_nocolor(),
+            false => GraphicalTheme::unicode(),
+        };
+        let mut out = String::new();
+        GraphicalReportHandler::new_themed(theme)
+            .render_report(&mut out, self)
+            .expect("writing to a string cannot fail");
+        out
+    }
+
+    fn summary(&self) -> String {
+        let plural = |count: usize, noun: &str| match count {
+            1 => format!("1 {noun}"),
+            count => format!("{count} {noun}s"),
+        };
+        match (self.errors(), self.warnings()) {
+            (errors, 0) => plural(errors, "error"),
+            (0, warnings) => plural(warnings, "warning"),
+            (errors, warnings) => {
+                format!(
+                    "{} and {}",
+                    plural(errors, "error"),
+                    plural(warnings, "warning")
+                )
+            }
+        }
+    }
+}
+
+fn is_error(diagnostic: &ValidationError) -> bool {
+    matches!(diagnostic.severity(), None | Some(Severity::Error))
+}
+
+/// Validates the contents of the turbo.json at `path`
+pub fn validate(path: &AbsoluteSystemPath, contents: &str) -> ValidationReport {
+    let mut validator = Validator::default();
+    match jsonc_parser::parse_to_ast(contents, &CollectOptions::default(), &PARSE_OPTIONS) {
+        Ok(result) => match result.value {
+            Some(Value::Object(root)) => {
+                for prop in &root.properties {
+                    validator.root_prop(prop);
+                }
+            }
+            Some(value) => validator.invalid_type("turbo.json", "an object", &value),
+            None => validator.diagnostics.push(ValidationError::Parse {
+                message: "file is empty".to_string(),
+                span: (0, 0).into(),
+            }),
+        },
+        Err(err) => validator.diagnostics.push(ValidationError::Parse {
+            message: err.message,
+            span: span(err.range.start, err.range.end),
+        }),
+    }
+
+    ValidationReport {
+        path: path.to_string(),
+        contents: NamedSource::new(path.to_string(), contents.to_string()),
+        diagnostics: validator.diagnostics,
+    }
+}
+
+#[derive(Default)]
+struct Validator {
+    diagnostics: Vec<ValidationError>,
+}
+
+impl Validator {
+    fn root_prop(&mut self, prop: &ObjectProp) {
+        let value = &prop.value;
+        // Every key is optional, so an explicit null is the same as leaving it out
+        if matches!(value, Value::NullKeyword(_)) && ROOT_KEYS.contains(&prop.name.as_str()) {
+            return;
+        }
+        match prop.name.as_str() {
+            "$schema" => {
+                self.string("$schema", value);
+            }
+            "extends" | "globalDotEnv" => {
+                self.strings(prop.name.as_str(), value);
+            }
+            "globalDependencies" => {
+                for dependency in self.strings("globalDependencies", value) {
+                    if dependency.value.starts_with(ENV_PIPELINE_DELIMITER) {
+                        self.diagnostics.push(ValidationError::EnvDependency {
+                            key: "globalDependencies",
+                            span: range(dependency),
+                        });
+                    } else {
+                        self.glob("globalDependencies", dependency);
+                    }
+                }
+            }
+            "globalEnv" => self.env_vars("globalEnv", value),
+            "globalPassThroughEnv" => self.env_vars("globalPassThroughEnv", value),
+            "pipeline" => match value {
+                Value::Object(pipeline) => {
+                    for task in &pipeline.properties {
+                        match &task.value {
+                            Value::Object(task_definition) => {
+                                for prop in &task_definition.properties {
+                                    self.task_prop(prop);
+                                }
+                            }
+                            value => self.invalid_type(task.name.as_str(), "an object", value),
+                        }
+                    }
+                }
+                value => self.invalid_type("pipeline", "an object", value),
+            },
+            "remoteCache" | "experimentalSpaces" => {
+                if !matches!(value, Value::Object(_)) {
+                    self.invalid_type(prop.name.as_str(), "an object", value)
+                }
+            }
+            key => self.unknown_key(key, "turbo.json", ROOT_KEYS, prop),
+        }
+    }
+
+    fn task_prop(&mut self, prop: &ObjectProp) {
+        let value = &prop.value;
+        if matches!(value, Value::NullKeyword(_)) && TASK_KEYS.contains(&prop.name.as_str()) {
+            return;
+        }
+        match prop.name.as_str() {
+            "cache" | "persistent" => {
+                if !matches!(value, Value::BooleanLit(_)) {
+                    self.invalid_type(prop.name.as_str(), "a boolean", value)
+                }
+            }
+            "dependsOn" => {
+                for dependency in self.strings("dependsOn", value) {
+                    if dependency.value.starts_with(ENV_PIPELINE_DELIMITER) {
+                        self.diagnostics.push(ValidationError::EnvDependency {
+                            key: "dependsOn",
+                            span: range(dependency),
+                        });
+                    }
+                }
+            }
+            "dotEnv" => {
+                self.strings("dotEnv", value);
+            }
+            "env" => self.env_vars("env", value),
+            "passThroughEnv" => self.env_vars("passThroughEnv", value),
+            "inputs" => {
+                for input in self.strings("inputs", value) {
+                    self.glob("inputs", input);
+                }
+            }
+            "outputs" => {
+                for output in self.strings("outputs", value) {
+                    self.glob("outputs", output);
+                }
+            }
+            "outputMode" => {
+                if let Some(output_mode) = self.string("outputMode", value) {
+                    if !OUTPUT_MODES.contains(&output_mode.value.as_ref()) {
+                        self.diagnostics.push(ValidationError::InvalidOutputMode {
+                            value: output_mode.value.to_string(),
+                            span: range(output_mode),
+                        });
+                    }
+                }
+            }
+            key => self.unknown_key(key, "task", TASK_KEYS, prop),
+        }
+    }
+
+    fn env_vars(&mut self, key: &'static str, value: &Value) {
+        for env_var in self.strings(key, value) {
+            if env_var.value.starts_with(ENV_PIPELINE_DELIMITER) {
+                self.diagnostics.push(ValidationError::EnvPrefix {
+                    key,
+                    span: range(env_var),
+                });
+            }
+        }
+    }
+
+    // Checks that a glob in `key` can be compiled the same way globwalk will
+    // compile it, pointing at the part of the glob that wax's diagnostics
+    // label when possible
+    fn glob(&mut self, key: &'static str, glob: &StringLit) {
+        let (prefix, pattern) = match glob.value.strip_prefix('!') {
+            Some(pattern) => (1, pattern),
+            None => (0, glob.value.as_ref()),
+        };
+        if Utf8Path::new(pattern).is_absolute() {
+            self.diagnostics.push(ValidationError::AbsolutePath {
+                key,
+                span: range(glob),
+            });
+            return;
+        }
+
+        let fixed_pattern = globwalk::fix_glob_pattern(pattern);
+        // Only errors are reported, wax also warns about globs that are valid
+        let Err(diagnostics) = Glob::diagnosed(&fixed_pattern) else {
+            return;
+        };
+        let diagnostic = diagnostics.first();
+        // Spans within the glob only line up with the file if the glob wasn't
+        // rewritten and the string literal has no escapes
+        let is_verbatim =
+            fixed_pattern == pattern && glob.range.end - glob.range.start == glob.value.len() + 2;
+        let label = diagnostic
+            .labels()
+            .and_then(|mut labels| labels.next())
+            .filter(|_| is_verbatim);
+        let span = match label {
+            Some(label) => {
+                let start = glob.range.start + 1 + prefix + label.offset();
+                (start, label.len()).into()
+            }
+            None => range(glob),
+        };
+        self.diagnostics.push(ValidationError::InvalidGlob {
+            key,
+            reason: diagnostic.to_string(),
+            span,
+        });
+    }
+
+    fn string<'a, 'b>(&mut self, key: &str, value: &'b Value<'a>) -> Option<&'b StringLit<'a>> {
+        match value {
+            Value::StringLit(string) => Some(string),
+            value => {
+                self.invalid_type(key, "a string", value);
+                None
+            }
+        }
+    }
+
+    fn strings<'a, 'b>(&mut self, key: &str, value: &'b Value<'a>) -> Vec<&'b StringLit<'a>> {
+        let Value::Array(array) = value else {
+            self.invalid_type(key, "an array of strings", value);
+            return Vec::new();
+        };
+        array
+            .elements
+            .iter()
+            .filter_map(|element| match element {
+                Value::StringLit(string) => Some(string),
+                element => {
+                    self.invalid_type(key, "an array of strings", element);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn invalid_type(&mut self, key: &str, expected: &'static str, value: &Value) {
+        let found = match value {
+            Value::StringLit(_) => "a string",
+            Value::NumberLit(_) => "a number",
+            Value::BooleanLit(_) => "a boolean",
+            Value::Object(_) => "an object",
+            Value::Array(_) => "an array",
+            Value::NullKeyword(_) => "null",
+        };
+        self.diagnostics.push(ValidationError::InvalidType {
+            key: key.to_string(),
+            expected,
+            found,
+            span: range(value),
+        });
+    }
+
+    fn unknown_key(
+        &mut self,
+        key: &str,
+        location: &'static str,
+        known_keys: &[&str],
+        prop: &ObjectProp,
+    ) {
+        let suggestion = known_keys
+            .iter()
+            .map(|known_key| (strsim::jaro(key, known_key), known_key))
+            .filter(|(similarity, _)| *similarity > 0.8)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, known_key)| format!("did you mean \"{known_key}\"?"));
+        let name = prop.name.range();
+        self.diagnostics.push(ValidationError::UnknownKey {
+            key: key.to_string(),
+            span: span(name.start, name.end),
+            location,
+            suggestion,
+        });
+    }
+}
+
+fn range(node: &impl Ranged) -> SourceSpan {
+    span(node.range().start, node.range().end)
+}
+
+fn span(start: usize, end: usize) -> SourceSpan {
+    (start, end - start).into()
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::{validate, ValidationError, OUTPUT_MODES, ROOT_KEYS, TASK_KEYS};
+    use crate::{config::turbo_json_schema, ui::UI};
+
+    fn path() -> AbsoluteSystemPathBuf {
+        AbsoluteSystemPathBuf::new(if cfg!(windows) {
+            r"C:\repo\turbo.json"
+        } else {
+            "/repo/turbo.json"
+        })
+        .unwrap()
+    }
+
+    fn diagnostics(contents: &str) -> Vec<ValidationError> {
+        validate(&path(), contents).diagnostics
+    }
+
+    #[test]
+    fn test_valid() {
+        let contents = r#"{
+            // comments are allowed
+            "$schema": "https://turbo.build/schema.json",
+            "globalDependencies": ["tsconfig.json", "!.env"],
+            "globalEnv": ["CI"],
+            "globalDotEnv": null,
+            "pipeline": {
+                "build": {
+                    "dependsOn": ["^build"],
+                    "outputs": ["dist/**", "!dist/cache/**"],
+                    "env": ["API_URL"],
+                    "outputMode": "new-only",
+                    "cache": true
+                },
+                "web#dev": { "persistent": true, "cache": false }
+            }
+        }"#;
+        assert_eq!(diagnostics(contents), vec![]);
+    }
+
+    #[test_case(
+        r#"{ "pipeline": { "build": { "dependOn": ["^build"] } } }"#,
+        ValidationError::UnknownKey {
+            key: "dependOn".to_string(),
+            span: (27, 10).into(),
+            location: "task",
+            suggestion: Some("did you mean \"dependsOn\"?".to_string()),
+        }
+        ; "typo in task key"
+    )]
+    #[test_case(
+        r#"{ "colors": true }"#,
+        ValidationError::UnknownKey {
+            key: "colors".to_string(),
+            span: (2, 8).into(),
+            location: "turbo.json",
+            suggestion: None,
+        }
+        ; "unknown root key"
+    )]
+    #[test_case(
+        r#"{ "pipeline": { "build": { "outputs": "dist/**" } } }"#,
+        ValidationError::InvalidType {
+            key: "outputs".to_string(),
+            expected: "an array of strings",
+            found: "a string",
+            span: (38, 9).into(),
+        }
+        ; "wrong type"
+    )]
+    #[test_case(
+        r#"{ "pipeline": { "build": { "dependsOn": ["$API_URL"] } } }"#,
+        ValidationError::EnvDependency { key: "dependsOn", span: (41, 10).into() }
+        ; "env var in dependsOn"
+    )]
+    #[test_case(
+        r#"{ "pipeline": { "build": { "env": ["$API_URL"] } } }"#,
+        ValidationError::EnvPrefix { key: "env", span: (35, 10).into() }
+        ; "env var with prefix"
+    )]
+    #[test_case(
+        r#"{ "pipeline": { "build": { "outputs": ["/dist/**"] } } }"#,
+        ValidationError::AbsolutePath { key: "outputs", span: (39, 10).into() }
+        ; "absolute output"
+    )]
+    #[test_case(
+        r#"{ "pipeline": { "build": { "outputMode": "quiet" } } }"#,
+        ValidationError::InvalidOutputMode { value: "quiet".to_string(), span: (41, 7).into() }
+        ; "invalid output mode"
+    )]
+    #[test_case(
+        r#"{ "pipeline": { "build": { "cache": true, } } }"#,
+        ValidationError::Parse {
+            message: "Trailing commas are not allowed".to_string(),
+            span: (40, 1).into(),
+        }
+        ; "trailing comma"
+    )]
+    fn test_diagnostic(contents: &str, expected: ValidationError) {
+        assert_eq!(diagnostics(contents), vec![expected]);
+    }
+
+    #[test]
+    fn test_invalid_glob_span() {
+        let contents = r#"{ "pipeline": { "build": { "inputs": ["!src/{a,b"] } } }"#;
+        let diagnostics = diagnostics(contents);
+        let [ValidationError::InvalidGlob { key, span, .. }] = &diagnostics[..] else {
+            panic!("expected an invalid glob, got {:?}", diagnostics);
+        };
+        assert_eq!(*key, "inputs");
+        // points at the unclosed brace rather than the whole string
+        assert_eq!(*span, (44, 1).into());
+        assert_eq!(&contents[44..45], "{");
+    }
+
+    #[test]
+    fn test_reports_every_problem() {
+        let contents = r#"{
+            "globalEnv": ["$CI"],
+            "pipeline": {
+                "build": { "outputs": ["/dist"], "cahce": false },
+                "lint": []
+            }
+        }"#;
+        let report = validate(&path(), contents);
+        assert_eq!(report.errors(), 3);
+        assert_eq!(report.warnings(), 1);
+        assert!(report
+            .to_string()
+            .starts_with("Found 3 errors and 1 warning in"));
+
+        let rendered = report.render(&UI::new(true));
+        assert!(rendered.contains("did you mean \"cache\"?"));
+        assert!(rendered.contains(r#""build": { "outputs": ["/dist"], "cahce": false },"#));
+    }
+
+    // The validator and the schema both describe what turbo.json accepts, so
+    // they must never disagree
+    #[test]
+    fn test_keys_match_schema() {
+        let schema = turbo_json_schema();
+        let keys = |properties: &serde_json::Value| {
+            let mut keys = properties
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
+        assert_eq!(keys(&schema["properties"]), ROOT_KEYS);
+        assert_eq!(
+            keys(&schema["definitions"]["Pipeline"]["properties"]),
+            TASK_KEYS
+        );
+        assert_eq!(
+            schema["definitions"]["Pipeline"]["properties"]["outputMode"]["enum"],
+            serde_json::json!(OUTPUT_MODES)
+        );
+    }
+}
@@ -1,18 +1,17 @@
 use thiserror::Error;
 use tonic::{Code, Status};
 use tracing::info;
+use turbopath::RelativeUnixPathBuf;
+use turborepo_scm::package_deps::GitHashes;
 
 use self::proto::turbod_client::TurbodClient;
+pub(crate) use super::proto;
 use super::{
     connector::{DaemonConnector, DaemonConnectorError},
     endpoint::SocketOpenError,
 };
 use crate::get_version;
 
-pub mod proto {
-    tonic::include_proto!("turbodprotocol");
-}
-
 #[derive(Debug)]
 pub struct DaemonClient<T> {
     client: TurbodClient<tonic::transport::Channel>,
@@ -104,6 +103,50 @@ impl DaemonClient<DaemonConnector> {
         Ok(())
     }
 
+    /// Get the hashes of the files in a package, filtered by the task's
+    /// input globs.
+    pub async fn get_package_file_hashes(
+        &mut self,
+        package_path: String,
+        input_globs: Vec<String>,
+    ) -> Result<GitHashes, DaemonError> {
+        self.client
+            .get_package_file_hashes(proto::GetPackageFileHashesRequest {
+                package_path,
+                input_globs,
+            })
+            .await?
+            .into_inner()
+            .file_hashes
+            .into_iter()
+            .map(|(path, hash)| {
+                let path =
+                    RelativeUnixPathBuf::new(path).map_err(|_| DaemonError::MalformedResponse)?;
+                Ok((path, hash))
+            })
+            .collect()
+    }
+
+    /// Get the package graph the daemon has built for the repo.
+    pub async fn get_package_graph(&mut self) -> Result<proto::PackageGraph, DaemonError> {
+        self.client
+            .get_package_graph(proto::GetPackageGraphRequest {})
+            .await?
+            .into_inner()
+            .package_graph
+            .ok_or(DaemonError::MalformedResponse)
+    }
+
+    /// Get the metrics the daemon has collected since it started.
+    pub async fn get_metrics(&mut self) -> Result<proto::DaemonMetrics, DaemonError> {
+        self.client
+            .get_metrics(proto::GetMetricsRequest {})
+            .await?
+            .into_inner()
+            .metrics
+            .ok_or(DaemonError::MalformedResponse)
+    }
+
     /// Get the status of the daemon.
     pub async fn status(&mut self) -> Result<proto::DaemonStatus, DaemonError> {
         self.client
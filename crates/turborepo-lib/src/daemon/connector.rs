@@ -628,6 +628,27 @@ mod test {
         ) -> tonic::Result<tonic::Response<proto::GetChangedOutputsResponse>> {
             unimplemented!()
         }
+
+        async fn get_package_file_hashes(
+            &self,
+            _req: tonic::Request<proto::GetPackageFileHashesRequest>,
+        ) -> tonic::Result<tonic::Response<proto::GetPackageFileHashesResponse>> {
+            unimplemented!()
+        }
+
+        async fn get_package_graph(
+            &self,
+            _req: tonic::Request<proto::GetPackageGraphRequest>,
+        ) -> tonic::Result<tonic::Response<proto::GetPackageGraphResponse>> {
+            unimplemented!()
+        }
+
+        async fn get_metrics(
+            &self,
+            _req: tonic::Request<proto::GetMetricsRequest>,
+        ) -> tonic::Result<tonic::Response<proto::GetMetricsResponse>> {
+            unimplemented!()
+        }
     }
 
     #[tokio::test]
@@ -0,0 +1,356 @@
+//! Package File Hashes
+//!
+//! Hashing the files of a package means asking git for the index and the
+//! status of the package directory, which adds up quickly in large repos.
+//! Since the daemon is already watching the filesystem, it can keep the hashes
+//! of each package around and only compute them again once a file in the
+//! package changes.
+//!
+//! Ignore files decide which files of a package get hashed, so along with the
+//! package itself, the `.gitignore` files of the directories above it and
+//! `.git/info/exclude` are watched. A change to any of them drops the hashes
+//! of every package.
+//!
+//! This uses its own `GlobWatcher` rather than sharing the one used for task
+//! outputs, since that one stops watching globs as soon as they change.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use camino::Utf8PathBuf;
+use futures::StreamExt;
+use globwatch::{ConfigError, GlobWatcher, StopToken, WatchConfig, Watcher, WatcherStats};
+use notify::RecommendedWatcher;
+use thiserror::Error;
+use tokio::time::timeout;
+use tracing::{debug, trace, warn};
+use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+use turborepo_scm::{package_deps::GitHashes, SCM};
+
+/// timeout for flushing the watcher
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Scm(#[from] turborepo_scm::Error),
+    #[error("file hashing task failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
+    #[error("unable to watch package: {0:?}")]
+    Watch(ConfigError),
+}
+
+pub struct FileHashWatcher<T: Watcher> {
+    repo_root: AbsoluteSystemPathBuf,
+    // file events come in with canonical paths
+    canonical_repo_root: PathBuf,
+    // `.gitignore` files up to here apply to the repo, this is the root of the
+    // git repository when there is one
+    canonical_ignore_root: PathBuf,
+    // Holds `.git/info/exclude`
+    canonical_git_info_dir: Option<PathBuf>,
+    scm: Arc<SCM>,
+
+    packages: Mutex<HashMap<AnchoredSystemPathBuf, PackageHashes>>,
+
+    #[allow(dead_code)]
+    watcher: Mutex<Option<GlobWatcher>>,
+    config: WatchConfig<T>,
+}
+
+#[derive(Debug, Default)]
+struct PackageHashes {
+    // Bumped every time a file in the package changes, so that hashes which
+    // were being computed during the change don't get stored
+    generation: u64,
+    by_inputs: HashMap<Vec<String>, Arc<GitHashes>>,
+}
+
+impl FileHashWatcher<RecommendedWatcher> {
+    #[tracing::instrument]
+    pub fn new(
+        repo_root: AbsoluteSystemPathBuf,
+        flush_folder: Utf8PathBuf,
+    ) -> Result<Self, notify::Error> {
+        let (watcher, config) = GlobWatcher::new(flush_folder)?;
+        let scm = SCM::new(&repo_root);
+        let canonical_repo_root = repo_root.as_path().canonicalize()?;
+        let canonical_git_root = scm
+            .git_root()
+            .map(|git_root| git_root.as_path().canonicalize())
+            .transpose()?;
+        Ok(Self {
+            canonical_ignore_root: canonical_git_root
+                .clone()
+                .unwrap_or_else(|| canonical_repo_root.clone()),
+            canonical_git_info_dir: canonical_git_root
+                .map(|git_root| git_root.join(".git").join("info")),
+            canonical_repo_root,
+            scm: Arc::new(scm),
+            repo_root,
+            packages: Default::default(),
+            watcher: Mutex::new(Some(watcher)),
+            config,
+        })
+    }
+}
+
+impl<T: Watcher> FileHashWatcher<T> {
+    /// Processes file events until the stop token is triggered, dropping the
+    /// hashes of every package that a change touches.
+    #[tracing::instrument(skip(self, token))]
+    pub async fn watch(&self, token: StopToken) -> Result<(), ConfigError> {
+        let watcher = self.watcher.lock().expect("only fails if poisoned").take();
+        let mut stream = match watcher {
+            Some(watcher) => watcher.into_stream(token),
+            None => {
+                warn!("watcher already consumed");
+                return Err(ConfigError::WatchingAlready);
+            }
+        };
+
+        while let Some(Ok(result)) = stream.next().await {
+            let event = result?;
+            self.invalidate(event.paths.iter().map(|path| path.as_path()));
+        }
+
+        Ok(())
+    }
+
+    /// Gets the hashes of the files in a package, the same way that
+    /// `SCM::get_package_file_hashes` would.
+    pub async fn get_file_hashes(
+        &self,
+        package_path: AnchoredSystemPathBuf,
+        inputs: Vec<String>,
+    ) -> Result<Arc<GitHashes>, Error> {
+        // Make sure we've seen every change made before the request, otherwise
+        // we could hand out hashes of files that have since been modified
+        let up_to_date = matches!(
+            timeout(FLUSH_TIMEOUT, self.config.flush()).await,
+            Ok(Ok(()))
+        );
+        // Watching the root package would mean watching the entire repo
+        let is_root = package_path.as_path().as_os_str().is_empty();
+        if !up_to_date || is_root {
+            trace!("not caching file hashes for {:?}", package_path);
+            return self.compute(package_path, inputs).await;
+        }
+
+        let generation = {
+            let packages = self.packages.lock().expect("only fails if poisoned");
+            match packages.get(&package_path) {
+                Some(package) => match package.by_inputs.get(&inputs) {
+                    Some(hashes) => return Ok(hashes.clone()),
+                    None => Some(package.generation),
+                },
+                None => None,
+            }
+        };
+
+        // The package has to be watched before hashing starts, so that changes
+        // made while we hash aren't missed
+        let generation = match generation {
+            Some(generation) => generation,
+            None => {
+                if let Err(e) = self.watch_package(&package_path).await {
+                    debug!("unable to watch {:?}: {:?}", package_path, e);
+                    return self.compute(package_path, inputs).await;
+                }
+                let mut packages = self.packages.lock().expect("only fails if poisoned");
+                packages.entry(package_path.clone()).or_default().generation
+            }
+        };
+
+        let hashes = self.compute(package_path.clone(), inputs.clone()).await?;
+
+        let mut packages = self.packages.lock().expect("only fails if poisoned");
+        if let Some(package) = packages
+            .get_mut(&package_path)
+            .filter(|package| package.generation == generation)
+        {
+            package.by_inputs.insert(inputs, hashes.clone());
+        }
+
+        Ok(hashes)
+    }
+
+    pub fn watcher_stats(&self) -> &WatcherStats {
+        self.config.stats()
+    }
+
+    // Watches the package's files and every ignore file that applies to them
+    async fn watch_package(&self, package_path: &AnchoredSystemPathBuf) -> Result<(), Error> {
+        let glob = format!(
+            "{}/**",
+            package_path.to_unix().map_err(|e| Error::Scm(e.into()))?
+        );
+        self.config
+            .include(&self.canonical_repo_root, &glob)
+            .await
+            .map_err(Error::Watch)?;
+
+        // Watching the directories rather than the `.gitignore` files in them
+        // means we also hear about ones that don't exist yet
+        let package_dir = self.canonical_repo_root.join(package_path.as_path());
+        for dir in package_dir
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.canonical_ignore_root))
+        {
+            self.config.include_path(dir).await.map_err(Error::Watch)?;
+        }
+        if let Some(git_info_dir) = &self.canonical_git_info_dir {
+            self.config
+                .include_path(git_info_dir)
+                .await
+                .map_err(Error::Watch)?;
+        }
+
+        Ok(())
+    }
+
+    async fn compute(
+        &self,
+        package_path: AnchoredSystemPathBuf,
+        inputs: Vec<String>,
+    ) -> Result<Arc<GitHashes>, Error> {
+        let scm = self.scm.clone();
+        let repo_root = self.repo_root.clone();
+        let hashes = tokio::task::spawn_blocking(move || {
+            scm.get_package_file_hashes(&repo_root, &package_path, &inputs)
+        })
+        .await??;
+
+        Ok(Arc::new(hashes))
+    }
+
+    fn invalidate<'a>(&self, paths: impl Iterator<Item = &'a Path>) {
+        let mut packages = self.packages.lock().expect("only fails if poisoned");
+        for path in paths {
+            // Ignore files decide which files get hashed in every package below
+            // them, it's simplest to start over
+            let is_ignore_file = path.file_name() == Some(".gitignore".as_ref())
+                || self
+                    .canonical_git_info_dir
+                    .as_ref()
+                    .map_or(false, |git_info_dir| path == git_info_dir.join("exclude"));
+            let relative_path = path.strip_prefix(&self.canonical_repo_root).ok();
+            if !is_ignore_file && relative_path.is_none() {
+                continue;
+            }
+
+            for (package_path, package) in packages.iter_mut() {
+                if is_ignore_file
+                    || relative_path.map_or(false, |relative_path| {
+                        relative_path.starts_with(package_path.as_path())
+                    })
+                {
+                    trace!("{:?} changed, dropping file hashes", package_path);
+                    package.generation += 1;
+                    package.by_inputs.clear();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, sync::Arc};
+
+    use camino::Utf8PathBuf;
+    use globwatch::StopSource;
+    use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf, RelativeUnixPathBuf};
+
+    use super::FileHashWatcher;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_file_hashes_are_invalidated() {
+        let repo = tempfile::tempdir().unwrap();
+        let flush = tempfile::tempdir().unwrap();
+        fs::create_dir_all(repo.path().join("packages/ui")).unwrap();
+        fs::create_dir_all(repo.path().join("packages/docs")).unwrap();
+        fs::write(repo.path().join("packages/ui/index.js"), "one").unwrap();
+        fs::write(repo.path().join("packages/docs/index.md"), "docs").unwrap();
+
+        let watcher = Arc::new(
+            FileHashWatcher::new(
+                AbsoluteSystemPathBuf::try_from(repo.path()).unwrap(),
+                Utf8PathBuf::try_from(flush.path().to_path_buf()).unwrap(),
+            )
+            .unwrap(),
+        );
+        let stop = StopSource::new();
+        let task_watcher = watcher.clone();
+        let token = stop.token();
+        // dropped when the test ends
+        let _s = tokio::task::spawn(async move { task_watcher.watch(token).await });
+
+        let ui = AnchoredSystemPathBuf::from_raw("packages/ui").unwrap();
+        let docs = AnchoredSystemPathBuf::from_raw("packages/docs").unwrap();
+        let index = RelativeUnixPathBuf::new("index.js").unwrap();
+
+        let first = watcher.get_file_hashes(ui.clone(), vec![]).await.unwrap();
+        let docs_hashes = watcher.get_file_hashes(docs.clone(), vec![]).await.unwrap();
+        assert!(first.contains_key(&index));
+        assert!(Arc::ptr_eq(
+            &first,
+            &watcher.get_file_hashes(ui.clone(), vec![]).await.unwrap()
+        ));
+
+        fs::write(repo.path().join("packages/ui/index.js"), "two").unwrap();
+
+        let second = watcher.get_file_hashes(ui.clone(), vec![]).await.unwrap();
+        assert_ne!(first.get(&index), second.get(&index));
+        // Other packages keep their hashes
+        assert!(Arc::ptr_eq(
+            &docs_hashes,
+            &watcher.get_file_hashes(docs, vec![]).await.unwrap()
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_file_hashes_follow_ignore_files() {
+        let repo = tempfile::tempdir().unwrap();
+        let flush = tempfile::tempdir().unwrap();
+        fs::create_dir_all(repo.path().join("packages/ui")).unwrap();
+        fs::write(repo.path().join("packages/ui/index.js"), "index").unwrap();
+        fs::write(repo.path().join("packages/ui/generated.js"), "generated").unwrap();
+
+        let watcher = Arc::new(
+            FileHashWatcher::new(
+                AbsoluteSystemPathBuf::try_from(repo.path()).unwrap(),
+                Utf8PathBuf::try_from(flush.path().to_path_buf()).unwrap(),
+            )
+            .unwrap(),
+        );
+        let stop = StopSource::new();
+        let task_watcher = watcher.clone();
+        let token = stop.token();
+        // dropped when the test ends
+        let _s = tokio::task::spawn(async move { task_watcher.watch(token).await });
+
+        let ui = AnchoredSystemPathBuf::from_raw("packages/ui").unwrap();
+        let index = RelativeUnixPathBuf::new("index.js").unwrap();
+        let generated = RelativeUnixPathBuf::new("generated.js").unwrap();
+
+        let first = watcher.get_file_hashes(ui.clone(), vec![]).await.unwrap();
+        assert!(first.contains_key(&generated));
+
+        // A .gitignore at the root of the repo, outside of the package
+        fs::write(repo.path().join(".gitignore"), "generated.js\n").unwrap();
+        let second = watcher.get_file_hashes(ui.clone(), vec![]).await.unwrap();
+        assert!(!second.contains_key(&generated));
+        assert!(second.contains_key(&index));
+
+        // One in between the root and the package
+        fs::write(repo.path().join("packages/.gitignore"), "index.js\n").unwrap();
+        let third = watcher.get_file_hashes(ui, vec![]).await.unwrap();
+        assert!(!third.contains_key(&index));
+    }
+}
@@ -0,0 +1,249 @@
+//! Daemon Metrics
+//!
+//! Keeps track of how long each RPC takes and the last thing that went wrong,
+//! so that `turbo daemon status` can tell why a daemon is slow. Latencies are
+//! recorded by a tower layer wrapping the grpc service, errors are recorded by
+//! the same layer when a response carries a non-ok grpc status, and by the
+//! server when one of its watchers fails.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tonic::{
+    codegen::http::{Request, Response},
+    transport::NamedService,
+};
+use tower::{Layer, Service};
+
+use super::proto;
+
+/// The upper bounds of the latency histogram buckets, requests slower than
+/// the last one go into a final overflow bucket
+const BUCKETS: [Duration; 8] = [
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+];
+
+#[derive(Debug, Default)]
+pub struct DaemonMetrics {
+    latencies: Mutex<HashMap<String, Histogram>>,
+    last_error: Mutex<Option<proto::LastError>>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    counts: [u64; BUCKETS.len() + 1],
+    total: Duration,
+    max: Duration,
+}
+
+impl Histogram {
+    fn record(&mut self, duration: Duration) {
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| duration <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+    }
+
+    fn to_proto(&self, method: String) -> proto::RpcLatency {
+        let bounds = BUCKETS
+            .iter()
+            .map(|bound| format!("{:?}", bound))
+            .chain(std::iter::once("+Inf".to_string()));
+        proto::RpcLatency {
+            method,
+            count: self.counts.iter().sum(),
+            total_usec: self.total.as_micros() as u64,
+            max_usec: self.max.as_micros() as u64,
+            buckets: bounds
+                .zip(self.counts)
+                .map(|(le, count)| proto::LatencyBucket { le, count })
+                .collect(),
+        }
+    }
+}
+
+impl DaemonMetrics {
+    pub fn record_latency(&self, method: &str, duration: Duration) {
+        self.latencies
+            .lock()
+            .expect("only fails if poisoned")
+            .entry(method.to_string())
+            .or_default()
+            .record(duration);
+    }
+
+    pub fn record_error(&self, message: String) {
+        let timestamp_msec = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_millis() as u64);
+        *self.last_error.lock().expect("only fails if poisoned") = Some(proto::LastError {
+            message,
+            timestamp_msec,
+        });
+    }
+
+    /// The latencies of every RPC that has been called, sorted by method
+    pub fn rpc_latencies(&self) -> Vec<proto::RpcLatency> {
+        let latencies = self.latencies.lock().expect("only fails if poisoned");
+        let mut rpc_latencies = latencies
+            .iter()
+            .map(|(method, histogram)| histogram.to_proto(method.clone()))
+            .collect::<Vec<_>>();
+        rpc_latencies.sort_by(|a, b| a.method.cmp(&b.method));
+        rpc_latencies
+    }
+
+    pub fn last_error(&self) -> Option<proto::LastError> {
+        self.last_error
+            .lock()
+            .expect("only fails if poisoned")
+            .clone()
+    }
+}
+
+/// A layer that records the latency and errors of every request.
+pub struct MetricsLayer(Arc<DaemonMetrics>);
+
+impl MetricsLayer {
+    #[allow(dead_code)]
+    pub fn new(metrics: Arc<DaemonMetrics>) -> Self {
+        Self(metrics)
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            metrics: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MetricsService<S> {
+    inner: S,
+    metrics: Arc<DaemonMetrics>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for MetricsService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        // paths look like `/turbodprotocol.Turbod/GetChangedOutputs`
+        let method = req
+            .uri()
+            .path()
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let metrics = self.metrics.clone();
+        let start = Instant::now();
+        let response = self.inner.call(req);
+        Box::pin(async move {
+            let response = response.await;
+            metrics.record_latency(&method, start.elapsed());
+            // unary calls that fail respond with the grpc status in the headers
+            if let Ok(response) = &response {
+                let header = |name| {
+                    response
+                        .headers()
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                };
+                if let Some(status) = header("grpc-status").filter(|status| *status != "0") {
+                    metrics.record_error(format!(
+                        "{} failed ({}): {}",
+                        method,
+                        status,
+                        header("grpc-message").unwrap_or_default()
+                    ));
+                }
+            }
+            response
+        })
+    }
+}
+
+impl<T: NamedService> NamedService for MetricsService<T> {
+    const NAME: &'static str = T::NAME;
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::DaemonMetrics;
+
+    #[test]
+    fn test_rpc_latencies() {
+        let metrics = DaemonMetrics::default();
+        metrics.record_latency("Status", Duration::from_micros(500));
+        metrics.record_latency("Status", Duration::from_millis(20));
+        metrics.record_latency("GetPackageGraph", Duration::from_secs(10));
+
+        let latencies = metrics.rpc_latencies();
+        assert_eq!(
+            latencies
+                .iter()
+                .map(|latency| (latency.method.as_str(), latency.count, latency.max_usec))
+                .collect::<Vec<_>>(),
+            vec![("GetPackageGraph", 1, 10_000_000), ("Status", 2, 20_000)]
+        );
+
+        let status = &latencies[1];
+        assert_eq!(status.total_usec, 20_500);
+        assert_eq!(
+            status
+                .buckets
+                .iter()
+                .filter(|bucket| bucket.count > 0)
+                .map(|bucket| bucket.le.as_str())
+                .collect::<Vec<_>>(),
+            vec!["1ms", "50ms"]
+        );
+        assert_eq!(latencies[0].buckets.last().unwrap().le, "+Inf");
+        assert_eq!(latencies[0].buckets.last().unwrap().count, 1);
+    }
+
+    #[test]
+    fn test_last_error() {
+        let metrics = DaemonMetrics::default();
+        assert!(metrics.last_error().is_none());
+
+        metrics.record_error("first".to_string());
+        metrics.record_error("second".to_string());
+        let last_error = metrics.last_error().unwrap();
+        assert_eq!(last_error.message, "second");
+        assert!(last_error.timestamp_msec > 0);
+    }
+}
@@ -3,6 +3,9 @@ mod bump_timeout_layer;
 mod client;
 mod connector;
 pub(crate) mod endpoint;
+mod file_hashes;
+mod metrics;
+mod package_graph;
 mod server;
 
 pub use client::{DaemonClient, DaemonError};
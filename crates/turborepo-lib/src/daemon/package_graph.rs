@@ -0,0 +1,512 @@
+//! Package Graph
+//!
+//! Building the package graph means globbing for workspaces, parsing every
+//! package.json and parsing the lockfile. The daemon holds on to all of these
+//! and only reads the files that changed since the graph was last built, so a
+//! warm daemon can hand the CLI a graph without touching the disk.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use camino::Utf8PathBuf;
+use futures::StreamExt;
+use globwatch::{ConfigError, GlobWatcher, StopToken, WatchConfig, Watcher, WatcherStats};
+use notify::RecommendedWatcher;
+use thiserror::Error;
+use tokio::time::timeout;
+use tracing::{trace, warn};
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+use turborepo_lockfiles::Lockfile;
+
+use super::proto;
+use crate::{
+    package_graph::PackageGraph, package_json::PackageJson, package_manager::PackageManager,
+};
+
+/// timeout for flushing the watcher
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("unable to flush file events")]
+    Flush,
+    #[error("unable to watch workspaces: {0:?}")]
+    Watch(ConfigError),
+    #[error(transparent)]
+    PackageGraph(#[from] crate::package_graph::Error),
+    #[error(transparent)]
+    PackageManager(#[from] crate::package_manager::Error),
+    #[error(transparent)]
+    PackageJson(#[from] crate::package_json::Error),
+    #[error("package graph task failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
+}
+
+pub struct PackageGraphWatcher<T: Watcher> {
+    repo_root: AbsoluteSystemPathBuf,
+    // file events come in with canonical paths
+    canonical_repo_root: PathBuf,
+
+    state: Mutex<State>,
+
+    #[allow(dead_code)]
+    watcher: Mutex<Option<GlobWatcher>>,
+    config: WatchConfig<T>,
+}
+
+#[derive(Default)]
+struct State {
+    // Bumped on every change, so that graphs which were being built during the
+    // change don't get stored
+    generation: u64,
+    workspaces: Workspaces,
+    snapshot: Option<Arc<proto::PackageGraph>>,
+}
+
+/// Everything read from disk to build the graph. `None` means it needs to be
+/// read again.
+#[derive(Default, Clone)]
+struct Workspaces {
+    root_package_json: Option<PackageJson>,
+    package_manager: Option<PackageManager>,
+    package_jsons: Option<HashMap<AbsoluteSystemPathBuf, PackageJson>>,
+    stale_package_jsons: HashSet<AbsoluteSystemPathBuf>,
+    lockfile: Option<SharedLockfile>,
+}
+
+/// A parsed lockfile that can be handed to the graph builder while staying in
+/// the cached state
+#[derive(Clone)]
+struct SharedLockfile(Arc<Mutex<Box<dyn Lockfile>>>);
+
+impl Lockfile for SharedLockfile {
+    fn resolve_package(
+        &self,
+        workspace_path: &str,
+        name: &str,
+        version: &str,
+    ) -> Result<Option<turborepo_lockfiles::Package>, turborepo_lockfiles::Error> {
+        self.0
+            .lock()
+            .expect("only fails if poisoned")
+            .resolve_package(workspace_path, name, version)
+    }
+
+    fn all_dependencies(
+        &self,
+        key: &str,
+    ) -> Result<Option<HashMap<String, String>>, turborepo_lockfiles::Error> {
+        self.0
+            .lock()
+            .expect("only fails if poisoned")
+            .all_dependencies(key)
+    }
+}
+
+impl PackageGraphWatcher<RecommendedWatcher> {
+    #[tracing::instrument]
+    pub fn new(
+        repo_root: AbsoluteSystemPathBuf,
+        flush_folder: Utf8PathBuf,
+    ) -> Result<Self, notify::Error> {
+        let (watcher, config) = GlobWatcher::new(flush_folder)?;
+        Ok(Self {
+            canonical_repo_root: repo_root.as_path().canonicalize()?,
+            repo_root,
+            state: Default::default(),
+            watcher: Mutex::new(Some(watcher)),
+            config,
+        })
+    }
+}
+
+impl<T: Watcher> PackageGraphWatcher<T> {
+    /// Processes file events until the stop token is triggered, dropping
+    /// whatever the changed files were used for.
+    #[tracing::instrument(skip(self, token))]
+    pub async fn watch(&self, token: StopToken) -> Result<(), ConfigError> {
+        let watcher = self.watcher.lock().expect("only fails if poisoned").take();
+        let mut stream = match watcher {
+            Some(watcher) => watcher.into_stream(token),
+            None => {
+                warn!("watcher already consumed");
+                return Err(ConfigError::WatchingAlready);
+            }
+        };
+
+        while let Some(Ok(result)) = stream.next().await {
+            let event = result?;
+            self.invalidate(event.paths.iter().map(|path| path.as_path()));
+        }
+
+        Ok(())
+    }
+
+    /// Gets the package graph for the repo, only reading the files that have
+    /// changed since it was last requested.
+    pub async fn get_package_graph(&self) -> Result<Arc<proto::PackageGraph>, Error> {
+        // Make sure we've seen every change made before the request
+        if !matches!(
+            timeout(FLUSH_TIMEOUT, self.config.flush()).await,
+            Ok(Ok(()))
+        ) {
+            return Err(Error::Flush);
+        }
+
+        let (generation, mut workspaces) = {
+            let state = self.state.lock().expect("only fails if poisoned");
+            if let Some(snapshot) = &state.snapshot {
+                return Ok(snapshot.clone());
+            }
+            // Build from a copy, the cached state is only replaced once the
+            // build succeeds and nothing changed in the meantime
+            (state.generation, state.workspaces.clone())
+        };
+
+        // Workspaces have to be watched before they're found, so that ones
+        // added while we look for them aren't missed
+        if workspaces.package_jsons.is_none() {
+            let package_manager = workspaces.package_manager(&self.repo_root)?.clone();
+            self.config
+                .include_path(&self.canonical_repo_root)
+                .await
+                .map_err(Error::Watch)?;
+            for glob in package_manager
+                .get_workspace_globs(&self.repo_root)?
+                .package_json_inclusions()
+            {
+                self.config
+                    .include(&self.canonical_repo_root, glob)
+                    .await
+                    .map_err(Error::Watch)?;
+            }
+        }
+
+        let repo_root = self.repo_root.clone();
+        let (workspaces, snapshot) = tokio::task::spawn_blocking(move || {
+            let snapshot = workspaces.build(&repo_root)?;
+            Ok::<_, Error>((workspaces, snapshot))
+        })
+        .await??;
+        let snapshot = Arc::new(snapshot);
+
+        let mut state = self.state.lock().expect("only fails if poisoned");
+        if state.generation == generation {
+            state.workspaces = workspaces;
+            state.snapshot = Some(snapshot.clone());
+        }
+
+        Ok(snapshot)
+    }
+
+    pub fn watcher_stats(&self) -> &WatcherStats {
+        self.config.stats()
+    }
+
+    fn invalidate<'a>(&self, paths: impl Iterator<Item = &'a Path>) {
+        let mut state = self.state.lock().expect("only fails if poisoned");
+        let mut changed = false;
+        for path in paths {
+            let Ok(relative_path) = path.strip_prefix(&self.canonical_repo_root) else {
+                continue;
+            };
+            if relative_path.components().any(|component| {
+                component == Component::Normal("node_modules".as_ref())
+                    || component == Component::Normal(".git".as_ref())
+            }) {
+                continue;
+            }
+            let Some(absolute_path) = relative_path
+                .to_str()
+                .and_then(|path| AnchoredSystemPathBuf::from_raw(path).ok())
+                .map(|path| self.repo_root.resolve(&path))
+            else {
+                continue;
+            };
+
+            if Self::invalidate_workspaces(&mut state.workspaces, &self.repo_root, &absolute_path) {
+                trace!("{:?} changed, dropping package graph", relative_path);
+                changed = true;
+            }
+        }
+
+        if changed {
+            state.generation += 1;
+            state.snapshot = None;
+        }
+    }
+
+    // Drops anything that was read from `path`. Returns whether anything was
+    // dropped.
+    fn invalidate_workspaces(
+        workspaces: &mut Workspaces,
+        repo_root: &AbsoluteSystemPath,
+        path: &AbsoluteSystemPathBuf,
+    ) -> bool {
+        if path.parent() == Some(repo_root) {
+            let file_name = path.file_name();
+            if file_name == Some("package.json") {
+                // The root package.json decides the package manager, which in
+                // turn decides everything else
+                *workspaces = Workspaces::default();
+                return true;
+            }
+            if let Some(package_manager) = &workspaces.package_manager {
+                if file_name == Some(package_manager.lockfile_name()) {
+                    workspaces.lockfile = None;
+                    return true;
+                }
+                if file_name.is_some()
+                    && file_name == package_manager.workspace_configuration_path()
+                {
+                    workspaces.package_jsons = None;
+                    return true;
+                }
+            }
+        }
+
+        let Some(package_jsons) = &workspaces.package_jsons else {
+            return false;
+        };
+        if path.file_name() == Some("package.json") {
+            if package_jsons.contains_key(path) {
+                workspaces.stale_package_jsons.insert(path.to_owned());
+            } else {
+                // Might be a new workspace
+                workspaces.package_jsons = None;
+            }
+            return true;
+        }
+
+        // Directories with workspaces in them being moved around, or new
+        // directories outside of any workspace. The package.json of a new
+        // workspace can be written before its directory gets watched, so we
+        // might never hear about it.
+        let in_workspace = package_jsons.keys().any(|json| {
+            json.parent()
+                .map_or(false, |dir| path.as_path().starts_with(dir.as_path()))
+        });
+        if !path.as_path().is_file()
+            && (package_jsons
+                .keys()
+                .any(|json| json.as_path().starts_with(path.as_path()))
+                || path.join_component("package.json").exists()
+                || (path.as_path().is_dir() && !in_workspace))
+        {
+            workspaces.package_jsons = None;
+            return true;
+        }
+
+        false
+    }
+}
+
+impl Workspaces {
+    fn root_package_json(&mut self, repo_root: &AbsoluteSystemPath) -> Result<&PackageJson, Error> {
+        if self.root_package_json.is_none() {
+            let package_json = PackageJson::load(&repo_root.join_component("package.json"))?;
+            self.root_package_json = Some(package_json);
+        }
+        Ok(self.root_package_json.as_ref().expect("just loaded"))
+    }
+
+    fn package_manager(
+        &mut self,
+        repo_root: &AbsoluteSystemPath,
+    ) -> Result<&PackageManager, Error> {
+        if self.package_manager.is_none() {
+            let root_package_json = self.root_package_json(repo_root)?;
+            let package_manager =
+                PackageManager::get_package_manager(repo_root, Some(root_package_json))?;
+            self.package_manager = Some(package_manager);
+        }
+        Ok(self.package_manager.as_ref().expect("just detected"))
+    }
+
+    // Reads whatever is missing and builds the graph
+    fn build(&mut self, repo_root: &AbsoluteSystemPath) -> Result<proto::PackageGraph, Error> {
+        let package_manager = self.package_manager(repo_root)?.clone();
+        let root_package_json = self.root_package_json(repo_root)?.clone();
+
+        // Only reparse the package.jsons that changed, unless one of them went
+        // away and we need to look for workspaces again
+        let mut package_jsons = self.package_jsons.take();
+        if let Some(jsons) = &mut package_jsons {
+            for path in self.stale_package_jsons.drain() {
+                if !path.exists() {
+                    package_jsons = None;
+                    break;
+                }
+                let package_json = PackageJson::load(&path)?;
+                jsons.insert(path, package_json);
+            }
+        }
+        self.stale_package_jsons.clear();
+        let package_jsons = match package_jsons {
+            Some(package_jsons) => package_jsons,
+            None => package_manager
+                .get_package_jsons(repo_root)?
+                .map(|path| {
+                    let package_json = PackageJson::load(&path)?;
+                    Ok((path, package_json))
+                })
+                .collect::<Result<_, Error>>()?,
+        };
+
+        let package_graph = PackageGraph::builder(repo_root, root_package_json)
+            .with_package_manger(Some(package_manager))
+            .with_package_jsons(Some(package_jsons.clone()))
+            .with_lockfile(
+                self.lockfile
+                    .clone()
+                    .map(|lockfile| Box::new(lockfile) as Box<dyn Lockfile>),
+            )
+            .build()?;
+        let snapshot = package_graph.to_snapshot()?;
+
+        self.package_jsons = Some(package_jsons);
+        if self.lockfile.is_none() {
+            self.lockfile = package_graph
+                .into_lockfile()
+                .map(|lockfile| SharedLockfile(Arc::new(Mutex::new(lockfile))));
+        }
+
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, sync::Arc};
+
+    use camino::Utf8PathBuf;
+    use globwatch::StopSource;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::PackageGraphWatcher;
+
+    fn workspace_names(snapshot: &super::proto::PackageGraph) -> Vec<&str> {
+        let mut names = snapshot
+            .workspaces
+            .iter()
+            .map(|workspace| workspace.name.as_str())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_package_graph_is_invalidated() {
+        let repo = tempfile::tempdir().unwrap();
+        let flush = tempfile::tempdir().unwrap();
+        fs::write(
+            repo.path().join("package.json"),
+            r#"{"name": "root", "packageManager": "npm@8.19.4", "workspaces": ["packages/*"]}"#,
+        )
+        .unwrap();
+        fs::create_dir_all(repo.path().join("packages/a")).unwrap();
+        fs::write(
+            repo.path().join("packages/a/package.json"),
+            r#"{"name": "a"}"#,
+        )
+        .unwrap();
+
+        let watcher = Arc::new(
+            PackageGraphWatcher::new(
+                AbsoluteSystemPathBuf::try_from(repo.path()).unwrap(),
+                Utf8PathBuf::try_from(flush.path().to_path_buf()).unwrap(),
+            )
+            .unwrap(),
+        );
+        let stop = StopSource::new();
+        let task_watcher = watcher.clone();
+        let token = stop.token();
+        // dropped when the test ends
+        let _s = tokio::task::spawn(async move { task_watcher.watch(token).await });
+
+        let first = watcher.get_package_graph().await.unwrap();
+        assert_eq!(workspace_names(&first), vec!["//", "a"]);
+        assert!(Arc::ptr_eq(
+            &first,
+            &watcher.get_package_graph().await.unwrap()
+        ));
+
+        // Changing a workspace only reparses that workspace
+        fs::write(
+            repo.path().join("packages/a/package.json"),
+            r#"{"name": "a", "dependencies": {"b": "*"}}"#,
+        )
+        .unwrap();
+        fs::create_dir_all(repo.path().join("packages/b")).unwrap();
+        fs::write(
+            repo.path().join("packages/b/package.json"),
+            r#"{"name": "b"}"#,
+        )
+        .unwrap();
+
+        let second = watcher.get_package_graph().await.unwrap();
+        assert_eq!(workspace_names(&second), vec!["//", "a", "b"]);
+        let a = second
+            .workspaces
+            .iter()
+            .find(|workspace| workspace.name == "a")
+            .unwrap();
+        assert_eq!(a.internal_dependencies, vec!["b".to_string()]);
+
+        fs::remove_dir_all(repo.path().join("packages/b")).unwrap();
+        let third = watcher.get_package_graph().await.unwrap();
+        assert_eq!(workspace_names(&third), vec!["//", "a"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_failed_build_keeps_state() {
+        let repo = tempfile::tempdir().unwrap();
+        let flush = tempfile::tempdir().unwrap();
+        fs::write(
+            repo.path().join("package.json"),
+            r#"{"name": "root", "packageManager": "npm@8.19.4", "workspaces": ["packages/*"]}"#,
+        )
+        .unwrap();
+        fs::create_dir_all(repo.path().join("packages/a")).unwrap();
+        fs::write(
+            repo.path().join("packages/a/package.json"),
+            r#"{"name": "a"}"#,
+        )
+        .unwrap();
+
+        let watcher = Arc::new(
+            PackageGraphWatcher::new(
+                AbsoluteSystemPathBuf::try_from(repo.path()).unwrap(),
+                Utf8PathBuf::try_from(flush.path().to_path_buf()).unwrap(),
+            )
+            .unwrap(),
+        );
+        let stop = StopSource::new();
+        let task_watcher = watcher.clone();
+        let token = stop.token();
+        // dropped when the test ends
+        let _s = tokio::task::spawn(async move { task_watcher.watch(token).await });
+
+        watcher.get_package_graph().await.unwrap();
+
+        fs::write(repo.path().join("packages/a/package.json"), "{").unwrap();
+        assert!(watcher.get_package_graph().await.is_err());
+        {
+            let state = watcher.state.lock().unwrap();
+            assert!(state.workspaces.root_package_json.is_some());
+            assert!(state.workspaces.package_jsons.is_some());
+        }
+
+        fs::write(
+            repo.path().join("packages/a/package.json"),
+            r#"{"name": "renamed"}"#,
+        )
+        .unwrap();
+        let graph = watcher.get_package_graph().await.unwrap();
+        assert_eq!(workspace_names(&graph), vec!["//", "renamed"]);
+    }
+}
@@ -11,6 +11,14 @@
 //! that hash, and files that have been updated for that hash. In addition, this
 //! server can be interrogated over grpc to register interest in particular
 //! globs, and to query for changes for those globs.
+//!
+//! It also keeps the hashes of the files in each package that has been asked
+//! about in a `FileHashWatcher`, so that runs don't need to go to git for
+//! packages that haven't changed since the last run. The package graph is
+//! kept the same way in a `PackageGraphWatcher`.
+//!
+//! Every request goes through a `MetricsLayer`, which records how long each
+//! RPC takes so that `turbo daemon status` can report it.
 
 use std::{
     collections::{HashMap, HashSet},
@@ -22,6 +30,7 @@ use std::{
 };
 
 use globwatch::{StopSource, Watcher};
+use sysinfo::{ProcessExt, ProcessRefreshKind, System, SystemExt};
 use tokio::{
     select,
     signal::ctrl_c,
@@ -33,11 +42,14 @@ use tokio::{
 use tonic::transport::{NamedService, Server};
 use tower::ServiceBuilder;
 use tracing::{error, trace};
-use turbopath::AbsoluteSystemPathBuf;
+use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
 
 use super::{
     bump_timeout::BumpTimeout,
     endpoint::SocketOpenError,
+    file_hashes::FileHashWatcher,
+    metrics::{DaemonMetrics, MetricsLayer},
+    package_graph::PackageGraphWatcher,
     proto::{self},
     DaemonError,
 };
@@ -56,6 +68,9 @@ pub struct DaemonServer<T: Watcher> {
     timeout: Arc<BumpTimeout>,
 
     watcher: Arc<HashGlobWatcher<T>>,
+    file_hashes: Arc<FileHashWatcher<T>>,
+    package_graph: Arc<PackageGraphWatcher<T>>,
+    metrics: Arc<DaemonMetrics>,
     shutdown: Mutex<Option<Sender<()>>>,
     #[allow(dead_code)]
     shutdown_rx: Option<Receiver<()>>,
@@ -89,6 +104,20 @@ impl DaemonServer<notify::RecommendedWatcher> {
             base.repo_root.clone(),
             daemon_root.join_component("flush").as_path().to_owned(),
         )?);
+        let file_hashes = Arc::new(FileHashWatcher::new(
+            base.repo_root.clone(),
+            daemon_root
+                .join_component("flush-file-hashes")
+                .as_path()
+                .to_owned(),
+        )?);
+        let package_graph = Arc::new(PackageGraphWatcher::new(
+            base.repo_root.clone(),
+            daemon_root
+                .join_component("flush-package-graph")
+                .as_path()
+                .to_owned(),
+        )?);
 
         let (send_shutdown, recv_shutdown) = tokio::sync::oneshot::channel::<()>();
 
@@ -100,6 +129,9 @@ impl DaemonServer<notify::RecommendedWatcher> {
             timeout: Arc::new(BumpTimeout::new(timeout)),
 
             watcher,
+            file_hashes,
+            package_graph,
+            metrics: Default::default(),
             shutdown: Mutex::new(Some(send_shutdown)),
             shutdown_rx: Some(recv_shutdown),
 
@@ -123,6 +155,13 @@ impl<T: Watcher + Send + 'static> DaemonServer<T> {
         let watcher = self.watcher.clone();
         let watcher_fut = watcher.watch(stop.token());
         tokio::pin!(watcher_fut);
+        let file_hashes = self.file_hashes.clone();
+        let file_hashes_fut = file_hashes.watch(stop.token());
+        tokio::pin!(file_hashes_fut);
+        let package_graph = self.package_graph.clone();
+        let package_graph_fut = package_graph.watch(stop.token());
+        tokio::pin!(package_graph_fut);
+        let metrics = self.metrics.clone();
 
         let timer = self.timeout.clone();
         let timeout_fut = timer.wait();
@@ -161,6 +200,7 @@ impl<T: Watcher + Send + 'static> DaemonServer<T> {
 
             let service = ServiceBuilder::new()
                 .layer(BumpTimeoutLayer::new(self.timeout.clone()))
+                .layer(MetricsLayer::new(self.metrics.clone()))
                 .service(crate::daemon::proto::turbod_server::TurbodServer::new(self));
 
             Server::builder()
@@ -185,6 +225,7 @@ impl<T: Watcher + Send + 'static> DaemonServer<T> {
 
             let service = ServiceBuilder::new()
                 .layer(BumpTimeoutLayer::new(self.timeout.clone()))
+                .layer(MetricsLayer::new(self.metrics.clone()))
                 .service(crate::daemon::proto::turbod_server::TurbodServer::new(self));
 
             (
@@ -199,6 +240,8 @@ impl<T: Watcher + Send + 'static> DaemonServer<T> {
         // necessary to make sure we don't try to poll the watcher_fut once it
         // has completed
         let mut watcher_done = false;
+        let mut file_hashes_done = false;
+        let mut package_graph_done = false;
         loop {
             select! {
                     _ = &mut server_fut => {
@@ -209,10 +252,34 @@ impl<T: Watcher + Send + 'static> DaemonServer<T> {
                         Ok(()) => return CloseReason::WatcherClosed,
                         Err(e) => {
                             error!("Globwatch config error: {:?}", e);
+                            metrics.record_error(format!("output watcher stopped: {:?}", e));
                             watcher_done = true;
                         },
                     }
                 },
+                watch_res = &mut file_hashes_fut, if !file_hashes_done => {
+                    match watch_res {
+                        Ok(()) => return CloseReason::WatcherClosed,
+                        Err(e) => {
+                            error!("File hash watcher config error: {:?}", e);
+                            metrics.record_error(format!("file hash watcher stopped: {:?}", e));
+                            file_hashes_done = true;
+                        },
+                    }
+                },
+                watch_res = &mut package_graph_fut, if !package_graph_done => {
+                    match watch_res {
+                        Ok(()) => return CloseReason::WatcherClosed,
+                        Err(e) => {
+                            error!("Package graph watcher config error: {:?}", e);
+                            metrics.record_error(format!(
+                                "package graph watcher stopped: {:?}",
+                                e
+                            ));
+                            package_graph_done = true;
+                        },
+                    }
+                },
             }
         }
 
@@ -263,6 +330,31 @@ impl<T: Watcher + Send + 'static> proto::turbod_server::Turbod for DaemonServer<
         }))
     }
 
+    async fn get_metrics(
+        &self,
+        _request: tonic::Request<proto::GetMetricsRequest>,
+    ) -> Result<tonic::Response<proto::GetMetricsResponse>, tonic::Status> {
+        let (watched_globs, watched_hashes) = self.watcher.watched();
+        let watcher_stats = [
+            self.watcher.watcher_stats(),
+            self.file_hashes.watcher_stats(),
+            self.package_graph.watcher_stats(),
+        ];
+
+        Ok(tonic::Response::new(proto::GetMetricsResponse {
+            metrics: Some(proto::DaemonMetrics {
+                watched_globs: watched_globs as u64,
+                watched_hashes: watched_hashes as u64,
+                file_events: watcher_stats.iter().map(|stats| stats.processed()).sum(),
+                watcher_backlog: watcher_stats.iter().map(|stats| stats.backlog()).sum(),
+                dropped_events: watcher_stats.iter().map(|stats| stats.dropped()).sum(),
+                memory_bytes: memory_usage(),
+                rpc_latencies: self.metrics.rpc_latencies(),
+                last_error: self.metrics.last_error(),
+            }),
+        }))
+    }
+
     async fn notify_outputs_written(
         &self,
         request: tonic::Request<proto::NotifyOutputsWrittenRequest>,
@@ -317,6 +409,72 @@ impl<T: Watcher + Send + 'static> proto::turbod_server::Turbod for DaemonServer<
             }
         }
     }
+
+    async fn get_package_file_hashes(
+        &self,
+        request: tonic::Request<proto::GetPackageFileHashesRequest>,
+    ) -> Result<tonic::Response<proto::GetPackageFileHashesResponse>, tonic::Status> {
+        let inner = request.into_inner();
+        let package_path = AnchoredSystemPathBuf::from_raw(&inner.package_path)
+            .ok()
+            .filter(|path| {
+                !path
+                    .as_path()
+                    .components()
+                    .any(|c| c == std::path::Component::ParentDir)
+            })
+            .ok_or_else(|| {
+                tonic::Status::invalid_argument(format!(
+                    "invalid package path: {}",
+                    inner.package_path
+                ))
+            })?;
+
+        match self
+            .file_hashes
+            .get_file_hashes(package_path, inner.input_globs)
+            .await
+        {
+            Ok(hashes) => Ok(tonic::Response::new(proto::GetPackageFileHashesResponse {
+                file_hashes: hashes
+                    .iter()
+                    .map(|(path, hash)| (path.to_string(), hash.clone()))
+                    .collect(),
+            })),
+            Err(e) => {
+                error!("failed to hash package files: {:?}", e);
+                Err(tonic::Status::internal("failed to hash package files"))
+            }
+        }
+    }
+
+    async fn get_package_graph(
+        &self,
+        _request: tonic::Request<proto::GetPackageGraphRequest>,
+    ) -> Result<tonic::Response<proto::GetPackageGraphResponse>, tonic::Status> {
+        match self.package_graph.get_package_graph().await {
+            Ok(package_graph) => Ok(tonic::Response::new(proto::GetPackageGraphResponse {
+                package_graph: Some(package_graph.as_ref().clone()),
+            })),
+            Err(e) => {
+                error!("failed to build package graph: {:?}", e);
+                Err(tonic::Status::internal(format!(
+                    "failed to build package graph: {}",
+                    e
+                )))
+            }
+        }
+    }
+}
+
+// The resident memory of the daemon process in bytes, 0 if it can't be read
+fn memory_usage() -> u64 {
+    let Ok(pid) = sysinfo::get_current_pid() else {
+        return 0;
+    };
+    let mut system = System::new();
+    system.refresh_process_specifics(pid, ProcessRefreshKind::new());
+    system.process(pid).map_or(0, |process| process.memory())
 }
 
 impl<T: Watcher> NamedService for DaemonServer<T> {
@@ -0,0 +1,667 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use itertools::Itertools;
+
+use super::{Engine, TaskNode};
+use crate::{
+    config::TurboJson,
+    package_graph::{PackageGraph, WorkspaceName, WorkspaceNode},
+    run::task_id::{
+        get_package_task_from_id, get_task_id, is_package_task, root_task_id, strip_package_name,
+        ROOT_PKG_NAME,
+    },
+    task_graph::{Pipeline, TaskDefinitionHashable},
+};
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("Could not find the following tasks in project: {}", .0.join(", "))]
+    MissingTasks(Vec<String>),
+    #[error(
+        "{task_id} needs an entry in turbo.json before it can be depended on because it is a task \
+         run from the root package"
+    )]
+    MissingRootTaskInTurboJson { task_id: String },
+    #[error("Could not find workspace \"{workspace}\" from task \"{task_id}\" in project")]
+    WorkspaceNotFound { workspace: String, task_id: String },
+    #[error("Could not find \"{task_id}\" or \"{task_name}\" in turbo.json")]
+    MissingTaskDefinition { task_id: String, task_name: String },
+}
+
+pub struct EngineBuilder<'a> {
+    package_graph: &'a PackageGraph,
+    pipeline: &'a Pipeline,
+    workspace_turbo_jsons: Option<&'a HashMap<WorkspaceName, TurboJson>>,
+    workspaces: Vec<WorkspaceName>,
+    tasks: Vec<String>,
+    tasks_only: bool,
+    parallel: bool,
+}
+
+impl<'a> EngineBuilder<'a> {
+    pub fn new(package_graph: &'a PackageGraph, pipeline: &'a Pipeline) -> Self {
+        Self {
+            package_graph,
+            pipeline,
+            workspace_turbo_jsons: None,
+            workspaces: Vec::new(),
+            tasks: Vec::new(),
+            tasks_only: false,
+            parallel: false,
+        }
+    }
+
+    /// The turbo.json files of workspaces that override task definitions from
+    /// the root turbo.json
+    pub fn with_workspace_turbo_jsons(
+        mut self,
+        workspace_turbo_jsons: &'a HashMap<WorkspaceName, TurboJson>,
+    ) -> Self {
+        self.workspace_turbo_jsons = Some(workspace_turbo_jsons);
+        self
+    }
+
+    /// The workspaces to run the tasks in, i.e. the result of filtering
+    pub fn with_workspaces(mut self, workspaces: Vec<WorkspaceName>) -> Self {
+        self.workspaces = workspaces;
+        self
+    }
+
+    /// The tasks requested on the command line
+    pub fn with_tasks(mut self, tasks: Vec<String>) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
+    /// Only run the requested tasks and skip any of their dependencies that
+    /// aren't requested as well (`--only`)
+    pub fn with_tasks_only(mut self, tasks_only: bool) -> Self {
+        self.tasks_only = tasks_only;
+        self
+    }
+
+    /// `--parallel` ignores dependencies between workspaces, so topological
+    /// dependencies (`^task`) don't end up in the graph at all
+    pub fn with_parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    pub fn build(self) -> Result<Engine, Error> {
+        let missing_tasks = self
+            .tasks
+            .iter()
+            .filter(|task| !self.has_task(task))
+            .sorted()
+            .cloned()
+            .collect::<Vec<_>>();
+        if !missing_tasks.is_empty() {
+            return Err(Error::MissingTasks(missing_tasks));
+        }
+
+        // Root tasks are opt-in: they only run if they have an explicit
+        // `//#<task>` entry in the pipeline.
+        let root_enabled_tasks = self
+            .tasks
+            .iter()
+            .filter(|task| self.pipeline.contains_key(&root_task_id(task)))
+            .collect::<HashSet<_>>();
+
+        let mut traversal_queue = VecDeque::with_capacity(self.workspaces.len() * self.tasks.len());
+        for workspace in &self.workspaces {
+            for task in &self.tasks {
+                if matches!(workspace, WorkspaceName::Root) && !root_enabled_tasks.contains(task) {
+                    continue;
+                }
+                if is_package_task(task) {
+                    // A fully qualified task only runs in its own workspace
+                    let (package, _) = get_package_task_from_id(task);
+                    if package == workspace.to_string() {
+                        traversal_queue.push_back(task.clone());
+                    }
+                } else {
+                    let task_id = get_task_id(workspace, task);
+                    // Requested tasks don't need to be defined for every workspace,
+                    // as long as some workspace defines it. Dependencies on the
+                    // other hand always need a definition.
+                    if self.task_definition(&task_id, task).is_ok() {
+                        traversal_queue.push_back(task_id);
+                    }
+                }
+            }
+        }
+
+        let mut engine = Engine::new();
+        let mut visited = HashSet::new();
+
+        while let Some(task_id) = traversal_queue.pop_front() {
+            if !visited.insert(task_id.clone()) {
+                continue;
+            }
+
+            let (package, task_name) = get_package_task_from_id(&task_id);
+            if package == ROOT_PKG_NAME && !self.pipeline.contains_key(&task_id) {
+                return Err(Error::MissingRootTaskInTurboJson { task_id });
+            }
+
+            let workspace = match package.as_str() {
+                ROOT_PKG_NAME => WorkspaceName::Root,
+                _ => WorkspaceName::from(package.as_str()),
+            };
+            if self.package_graph.workspace_info(&workspace).is_none() {
+                return Err(Error::WorkspaceNotFound {
+                    workspace: package,
+                    task_id,
+                });
+            }
+
+            let task_definition = self.task_definition(&task_id, &task_name)?;
+
+            let mut dependencies = Vec::new();
+            let dependency_workspaces = match self.parallel {
+                true => None,
+                false => self
+                    .package_graph
+                    .immediate_dependencies(&WorkspaceNode::Workspace(workspace)),
+            };
+            for dependency_workspace in dependency_workspaces.into_iter().flatten() {
+                let WorkspaceNode::Workspace(dependency_workspace) = dependency_workspace else {
+                    continue;
+                };
+                for topological_dependency in &task_definition.topological_dependencies {
+                    dependencies.push(get_task_id(dependency_workspace, topological_dependency));
+                }
+            }
+            for task_dependency in &task_definition.task_dependencies {
+                dependencies.push(get_task_id(&package, task_dependency));
+            }
+
+            if self.tasks_only {
+                dependencies.retain(|dependency| {
+                    let dependency_task = strip_package_name(dependency);
+                    self.tasks
+                        .iter()
+                        .any(|task| task == dependency || *task == dependency_task)
+                });
+            }
+
+            dependencies.sort();
+            dependencies.dedup();
+
+            if dependencies.is_empty() {
+                engine.connect(&task_id, &TaskNode::Root);
+            }
+            for dependency in dependencies {
+                engine.connect(&task_id, &TaskNode::Task(dependency.clone()));
+                traversal_queue.push_back(dependency);
+            }
+
+            engine.task_definitions.insert(task_id, task_definition);
+        }
+
+        Ok(engine)
+    }
+
+    // A task is defined if it is in the pipeline either on its own or
+    // qualified with any workspace, or if any workspace turbo.json defines it
+    fn has_task(&self, task: &str) -> bool {
+        let task_name = strip_package_name(task);
+        self.pipeline
+            .keys()
+            .any(|key| key == task || key == &task_name || strip_package_name(key) == task_name)
+            || self
+                .workspace_turbo_jsons
+                .into_iter()
+                .flat_map(|turbo_jsons| turbo_jsons.values())
+                .any(|turbo_json| turbo_json.pipeline.contains_key(&task_name))
+    }
+
+    // Workspace specific definitions in the root turbo.json take precedence
+    // over generic ones. Whichever is used gets merged with the definition
+    // from the workspace's own turbo.json, field by field.
+    fn task_definition(
+        &self,
+        task_id: &str,
+        task_name: &str,
+    ) -> Result<TaskDefinitionHashable, Error> {
+        let root_definition = self
+            .pipeline
+            .get(task_id)
+            .or_else(|| self.pipeline.get(task_name));
+
+        let (package, _) = get_package_task_from_id(task_id);
+        let workspace_definition = self
+            .workspace_turbo_jsons
+            .filter(|_| package != ROOT_PKG_NAME)
+            .and_then(|turbo_jsons| turbo_jsons.get(&WorkspaceName::from(package.as_str())))
+            .and_then(|turbo_json| turbo_json.pipeline.get(task_name));
+
+        match (root_definition, workspace_definition) {
+            (Some(definition), None) => Ok(definition.task_definition.clone()),
+            (root_definition, Some(workspace_definition)) => {
+                let mut definition = root_definition.cloned().unwrap_or_default();
+                definition.merge(workspace_definition);
+                Ok(definition.task_definition)
+            }
+            (None, None) => Err(Error::MissingTaskDefinition {
+                task_id: task_id.to_string(),
+                task_name: task_name.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+
+    use serde_json::json;
+    use test_case::test_case;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::*;
+    use crate::{
+        engine::ValidateError,
+        package_json::PackageJson,
+        package_manager::PackageManager,
+        task_graph::{BookkeepingTaskDefinition, TaskOutputs},
+    };
+
+    fn package_graph() -> PackageGraph {
+        let root =
+            AbsoluteSystemPathBuf::new(if cfg!(windows) { r"C:\repo" } else { "/repo" }).unwrap();
+        let package_json = |value| PackageJson::from_value(value).unwrap();
+        PackageGraph::builder(
+            &root,
+            package_json(json!({
+                "name": "root",
+                "scripts": { "lint": "eslint ." }
+            })),
+        )
+        .with_package_manger(Some(PackageManager::Npm))
+        .with_package_jsons(Some(HashMap::from([
+            (
+                root.join_components(&["apps", "web", "package.json"]),
+                package_json(json!({
+                    "name": "web",
+                    "dependencies": { "ui": "workspace:*" },
+                    "scripts": { "build": "next build", "dev": "next dev", "test": "jest" }
+                })),
+            ),
+            (
+                root.join_components(&["packages", "ui", "package.json"]),
+                package_json(json!({
+                    "name": "ui",
+                    "dependencies": { "config": "workspace:*" },
+                    "scripts": { "build": "tsc", "dev": "tsc --watch" }
+                })),
+            ),
+            (
+                root.join_components(&["packages", "config", "package.json"]),
+                package_json(json!({
+                    "name": "config",
+                    "scripts": { "test": "jest" }
+                })),
+            ),
+        ])))
+        .build()
+        .unwrap()
+    }
+
+    fn pipeline(tasks: &[(&str, &[&str], bool)]) -> Pipeline {
+        tasks
+            .iter()
+            .map(|(task, depends_on, persistent)| {
+                let (topological_dependencies, task_dependencies) = depends_on
+                    .iter()
+                    .map(|dependency| dependency.to_string())
+                    .partition::<Vec<_>, _>(|dependency| dependency.starts_with('^'));
+                (
+                    task.to_string(),
+                    BookkeepingTaskDefinition {
+                        task_definition: TaskDefinitionHashable {
+                            topological_dependencies: topological_dependencies
+                                .into_iter()
+                                .map(|dependency| dependency[1..].to_string())
+                                .collect(),
+                            task_dependencies,
+                            persistent: *persistent,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn workspaces() -> Vec<WorkspaceName> {
+        vec![
+            WorkspaceName::Root,
+            WorkspaceName::from("web"),
+            WorkspaceName::from("ui"),
+            WorkspaceName::from("config"),
+        ]
+    }
+
+    fn dependencies(engine: &Engine, task_id: &str) -> HashSet<TaskNode> {
+        engine
+            .dependencies(task_id)
+            .unwrap()
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    fn task(task_id: &str) -> TaskNode {
+        TaskNode::Task(task_id.to_string())
+    }
+
+    #[test]
+    fn test_topological_dependencies() {
+        let package_graph = package_graph();
+        let pipeline = pipeline(&[("build", &["^build"], false)]);
+        let engine = Engine::builder(&package_graph, &pipeline)
+            .with_workspaces(workspaces())
+            .with_tasks(vec!["build".to_string()])
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            engine.tasks().sorted().collect::<Vec<_>>(),
+            vec!["config#build", "ui#build", "web#build"]
+        );
+        assert_eq!(
+            dependencies(&engine, "web#build"),
+            HashSet::from([task("ui#build")])
+        );
+        assert_eq!(
+            dependencies(&engine, "ui#build"),
+            HashSet::from([task("config#build")])
+        );
+        assert_eq!(
+            dependencies(&engine, "config#build"),
+            HashSet::from([TaskNode::Root])
+        );
+    }
+
+    #[test]
+    fn test_task_and_package_task_dependencies() {
+        let package_graph = package_graph();
+        let pipeline = pipeline(&[
+            ("build", &[], false),
+            ("test", &["build", "//#lint"], false),
+            ("web#test", &["build", "ui#build"], false),
+            ("//#lint", &[], false),
+        ]);
+        let engine = Engine::builder(&package_graph, &pipeline)
+            .with_workspaces(workspaces())
+            .with_tasks(vec!["test".to_string()])
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            dependencies(&engine, "config#test"),
+            HashSet::from([task("config#build"), task("//#lint")])
+        );
+        // The workspace specific definition replaces the generic one
+        assert_eq!(
+            dependencies(&engine, "web#test"),
+            HashSet::from([task("web#build"), task("ui#build")])
+        );
+        // There is no //#test entry, so the root workspace doesn't run test
+        assert!(engine.dependencies("//#test").is_none());
+    }
+
+    #[test]
+    fn test_transitive_dependencies_and_dependents() {
+        let package_graph = package_graph();
+        let pipeline = pipeline(&[("build", &["^build"], false), ("test", &["build"], false)]);
+        let engine = Engine::builder(&package_graph, &pipeline)
+            .with_workspaces(workspaces())
+            .with_tasks(vec!["test".to_string()])
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            engine.transitive_dependencies("web#test").unwrap(),
+            vec!["config#build", "ui#build", "web#build"]
+        );
+        assert_eq!(
+            engine.transitive_dependents("config#build").unwrap(),
+            vec![
+                "config#test",
+                "ui#build",
+                "ui#test",
+                "web#build",
+                "web#test"
+            ]
+        );
+        assert_eq!(
+            engine.transitive_dependencies("config#build").unwrap(),
+            Vec::<&str>::new()
+        );
+        assert!(engine.transitive_dependents("//#test").is_none());
+    }
+
+    #[test]
+    fn test_requested_task_defined_for_some_workspaces() {
+        let package_graph = package_graph();
+        let pipeline = pipeline(&[("build", &[], false), ("//#lint", &[], false)]);
+        let engine = Engine::builder(&package_graph, &pipeline)
+            .with_workspaces(workspaces())
+            .with_tasks(vec!["build".to_string(), "lint".to_string()])
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            engine.tasks().sorted().collect::<Vec<_>>(),
+            vec!["//#lint", "config#build", "ui#build", "web#build"]
+        );
+    }
+
+    #[test]
+    fn test_tasks_only() {
+        let package_graph = package_graph();
+        let pipeline = pipeline(&[("build", &["^build"], false), ("test", &["build"], false)]);
+        let engine = Engine::builder(&package_graph, &pipeline)
+            .with_workspaces(vec![WorkspaceName::from("web")])
+            .with_tasks(vec!["test".to_string()])
+            .with_tasks_only(true)
+            .build()
+            .unwrap();
+
+        assert_eq!(engine.tasks().collect::<Vec<_>>(), vec!["web#test"]);
+    }
+
+    #[test]
+    fn test_parallel_skips_workspace_dependencies() {
+        let package_graph = package_graph();
+        let pipeline = pipeline(&[("build", &["^build"], false), ("test", &["build"], false)]);
+        let engine = Engine::builder(&package_graph, &pipeline)
+            .with_workspaces(vec![WorkspaceName::from("web")])
+            .with_tasks(vec!["test".to_string()])
+            .with_parallel(true)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            engine.tasks().sorted().collect::<Vec<_>>(),
+            vec!["web#build", "web#test"]
+        );
+        assert_eq!(
+            dependencies(&engine, "web#build"),
+            HashSet::from([TaskNode::Root])
+        );
+    }
+
+    #[test_case(&[("build", &["^build"], false)], &["build"] ; "generic root task")]
+    #[test_case(
+        &[("build", &["^build"], false), ("web#build", &[], false)],
+        &[]
+        ; "workspace specific root task"
+    )]
+    fn test_workspace_turbo_json_overrides(
+        root_tasks: &[(&str, &[&str], bool)],
+        web_topological_dependencies: &[&str],
+    ) {
+        let package_graph = package_graph();
+        let pipeline = pipeline(root_tasks);
+        let workspace_pipeline = |task: &str, defined_fields: &[&str], task_definition| TurboJson {
+            pipeline: Pipeline::from([(
+                task.to_string(),
+                BookkeepingTaskDefinition {
+                    defined_fields: defined_fields.iter().map(|f| f.to_string()).collect(),
+                    task_definition,
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        let workspace_turbo_jsons = HashMap::from([
+            (
+                WorkspaceName::from("web"),
+                workspace_pipeline(
+                    "build",
+                    &["Outputs", "Env"],
+                    TaskDefinitionHashable {
+                        outputs: TaskOutputs {
+                            inclusions: vec![".next/**".to_string()],
+                            exclusions: vec![],
+                        },
+                        env: vec!["NEXT_PUBLIC_API".to_string()],
+                        ..Default::default()
+                    },
+                ),
+            ),
+            (
+                WorkspaceName::from("config"),
+                workspace_pipeline("lint", &[], TaskDefinitionHashable::default()),
+            ),
+        ]);
+        let engine = Engine::builder(&package_graph, &pipeline)
+            .with_workspace_turbo_jsons(&workspace_turbo_jsons)
+            .with_workspaces(workspaces())
+            .with_tasks(vec!["build".to_string(), "lint".to_string()])
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            engine.tasks().sorted().collect::<Vec<_>>(),
+            vec!["config#build", "config#lint", "ui#build", "web#build"]
+        );
+        let web_build = engine.task_definition("web#build").unwrap();
+        assert_eq!(web_build.outputs.inclusions, vec![".next/**".to_string()]);
+        assert_eq!(web_build.env, vec!["NEXT_PUBLIC_API".to_string()]);
+        // fields that aren't overridden come from the root turbo.json
+        assert_eq!(
+            web_build.topological_dependencies,
+            web_topological_dependencies
+        );
+        assert!(engine
+            .task_definition("ui#build")
+            .unwrap()
+            .outputs
+            .inclusions
+            .is_empty());
+    }
+
+    #[test_case(
+        &[("build", &[], false)],
+        &["build", "deploy", "test"],
+        Error::MissingTasks(vec!["deploy".to_string(), "test".to_string()])
+        ; "missing tasks"
+    )]
+    #[test_case(
+        &[("build", &["missing#build"], false)],
+        &["build"],
+        Error::WorkspaceNotFound { workspace: "missing".to_string(), task_id: "missing#build".to_string() }
+        ; "dependency on unknown workspace"
+    )]
+    #[test_case(
+        &[("build", &["//#lint"], false)],
+        &["build"],
+        Error::MissingRootTaskInTurboJson { task_id: "//#lint".to_string() }
+        ; "root task dependency without definition"
+    )]
+    #[test_case(
+        &[("build", &["typecheck"], false)],
+        &["build"],
+        Error::MissingTaskDefinition {
+            task_id: "web#typecheck".to_string(),
+            task_name: "typecheck".to_string()
+        }
+        ; "dependency without definition"
+    )]
+    fn test_build_errors(
+        pipeline_tasks: &[(&str, &[&str], bool)],
+        tasks: &[&str],
+        expected: Error,
+    ) {
+        let package_graph = package_graph();
+        let pipeline = pipeline(pipeline_tasks);
+        let result = Engine::builder(&package_graph, &pipeline)
+            .with_workspaces(vec![WorkspaceName::from("web")])
+            .with_tasks(tasks.iter().map(|task| task.to_string()).collect())
+            .build();
+
+        assert_eq!(result.unwrap_err(), expected);
+    }
+
+    #[test]
+    fn test_validate_persistent_dependency() {
+        let package_graph = package_graph();
+        let pipeline = pipeline(&[("build", &["^dev"], false), ("dev", &[], true)]);
+        let engine = Engine::builder(&package_graph, &pipeline)
+            .with_workspaces(vec![WorkspaceName::from("web")])
+            .with_tasks(vec!["build".to_string()])
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            engine.validate(&package_graph, 10, false),
+            Err(vec![ValidateError::DependencyOnPersistentTask {
+                persistent_task: "ui#dev".to_string(),
+                dependant: "web#build".to_string(),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_validate_persistent_concurrency() {
+        let package_graph = package_graph();
+        let pipeline = pipeline(&[("dev", &[], true)]);
+        let engine = Engine::builder(&package_graph, &pipeline)
+            .with_workspaces(workspaces())
+            .with_tasks(vec!["dev".to_string()])
+            .build()
+            .unwrap();
+
+        // config has no dev script so only web and ui count
+        assert_eq!(
+            engine.validate(&package_graph, 2, false),
+            Err(vec![ValidateError::PersistentTasksExceedConcurrency {
+                persistent_count: 2,
+                concurrency: 2,
+            }])
+        );
+        assert_eq!(engine.validate(&package_graph, 3, false), Ok(()));
+        assert_eq!(engine.validate(&package_graph, 2, true), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_cycle() {
+        let package_graph = package_graph();
+        let pipeline = pipeline(&[("build", &["test"], false), ("test", &["build"], false)]);
+        let engine = Engine::builder(&package_graph, &pipeline)
+            .with_workspaces(vec![WorkspaceName::from("config")])
+            .with_tasks(vec!["build".to_string()])
+            .build()
+            .unwrap();
+
+        assert!(matches!(
+            engine.validate(&package_graph, 10, false).unwrap_err()[..],
+            [ValidateError::CyclicDependency { .. }]
+        ));
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use petgraph::Direction;
+
+use super::{Engine, TaskNode};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExecutionOptions {
+    // Ignore task dependencies and start every task right away
+    pub parallel: bool,
+    pub concurrency: usize,
+    // Keep scheduling tasks that don't depend on a failed task
+    pub continue_on_error: bool,
+}
+
+impl Engine {
+    /// Runs `visitor` for every task in the graph, never starting a task before
+    /// all of its dependencies have finished successfully and never running
+    /// more than `concurrency` tasks at once.
+    ///
+    /// Once a task fails no new tasks get started unless `continue_on_error`
+    /// is set, in which case only the dependents of the failed task are
+    /// skipped. Returns the errors of every failed task.
+    pub async fn execute<F, Fut, E>(&self, options: ExecutionOptions, visitor: F) -> Vec<E>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+    {
+        let ExecutionOptions {
+            parallel,
+            concurrency,
+            continue_on_error,
+        } = options;
+
+        // The number of unfinished dependencies for each task
+        let mut remaining_dependencies = HashMap::new();
+        for task_id in self.tasks() {
+            let count = match parallel {
+                true => 0,
+                false => self
+                    .dependencies(task_id)
+                    .into_iter()
+                    .flatten()
+                    .filter(|node| matches!(node, TaskNode::Task(_)))
+                    .count(),
+            };
+            remaining_dependencies.insert(task_id.to_string(), count);
+        }
+
+        let mut ready = remaining_dependencies
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(task_id, _)| task_id.clone())
+            .collect::<Vec<_>>();
+        ready.sort();
+        let mut ready = VecDeque::from(ready);
+
+        let mut errors = Vec::new();
+        let mut stopped = false;
+        let mut running = FuturesUnordered::new();
+
+        loop {
+            while !stopped && running.len() < concurrency.max(1) {
+                let Some(task_id) = ready.pop_front() else {
+                    break;
+                };
+                let task = visitor(task_id.clone());
+                running.push(async move { (task_id, task.await) });
+            }
+
+            let Some((task_id, result)) = running.next().await else {
+                break;
+            };
+
+            if let Err(err) = result {
+                errors.push(err);
+                stopped = !continue_on_error;
+                continue;
+            }
+
+            if parallel {
+                continue;
+            }
+
+            let index = self.task_lookup[&task_id];
+            let mut newly_ready = Vec::new();
+            for dependent in self
+                .task_graph
+                .neighbors_directed(index, Direction::Incoming)
+            {
+                let TaskNode::Task(dependent) = &self.task_graph[dependent] else {
+                    continue;
+                };
+                let count = remaining_dependencies
+                    .get_mut(dependent)
+                    .expect("every task in the graph has a dependency count");
+                *count -= 1;
+                if *count == 0 {
+                    newly_ready.push(dependent.clone());
+                }
+            }
+            newly_ready.sort();
+            ready.extend(newly_ready);
+        }
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use test_case::test_case;
+
+    use super::*;
+
+    // web#build -> ui#build -> config#build, docs#build has no dependencies
+    fn engine() -> Engine {
+        let mut engine = Engine::new();
+        engine.connect("web#build", &TaskNode::Task("ui#build".to_string()));
+        engine.connect("ui#build", &TaskNode::Task("config#build".to_string()));
+        engine.connect("config#build", &TaskNode::Root);
+        engine.connect("docs#build", &TaskNode::Root);
+        engine
+    }
+
+    #[tokio::test]
+    async fn test_dependencies_finish_first() {
+        let engine = engine();
+        let order = Mutex::new(Vec::new());
+        let errors: Vec<()> = engine
+            .execute(
+                ExecutionOptions {
+                    parallel: false,
+                    concurrency: 10,
+                    continue_on_error: false,
+                },
+                |task_id| {
+                    let order = &order;
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(5)).await;
+                        order.lock().unwrap().push(task_id);
+                        Ok(())
+                    }
+                },
+            )
+            .await;
+
+        assert!(errors.is_empty());
+        let order = order.into_inner().unwrap();
+        let position = order
+            .iter()
+            .enumerate()
+            .map(|(i, task_id)| (task_id.as_str(), i))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(order.len(), 4);
+        assert!(position["config#build"] < position["ui#build"]);
+        assert!(position["ui#build"] < position["web#build"]);
+    }
+
+    #[test_case(1, false, 1 ; "serial")]
+    #[test_case(2, false, 2 ; "respects concurrency")]
+    #[test_case(10, false, 2 ; "limited by the graph")]
+    #[test_case(10, true, 4 ; "parallel ignores dependencies")]
+    #[tokio::test]
+    async fn test_concurrency(concurrency: usize, parallel: bool, expected_max: usize) {
+        let engine = engine();
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let errors: Vec<()> = engine
+            .execute(
+                ExecutionOptions {
+                    parallel,
+                    concurrency,
+                    continue_on_error: false,
+                },
+                |_| {
+                    let (running, max_running) = (&running, &max_running);
+                    async move {
+                        let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_running.fetch_max(current, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        Ok(())
+                    }
+                },
+            )
+            .await;
+
+        assert!(errors.is_empty());
+        assert_eq!(max_running.into_inner(), expected_max);
+    }
+
+    #[test_case(false, &["config#build"] ; "stops on first failure")]
+    #[test_case(true, &["config#build", "docs#build"] ; "continue skips dependents")]
+    #[tokio::test]
+    async fn test_failure(continue_on_error: bool, expected_ran: &[&str]) {
+        let engine = engine();
+        let ran = Mutex::new(Vec::new());
+        let errors = engine
+            .execute(
+                ExecutionOptions {
+                    parallel: false,
+                    concurrency: 1,
+                    continue_on_error,
+                },
+                |task_id| {
+                    let ran = &ran;
+                    async move {
+                        ran.lock().unwrap().push(task_id.clone());
+                        match task_id.as_str() {
+                            "config#build" => Err(task_id),
+                            _ => Ok(()),
+                        }
+                    }
+                },
+            )
+            .await;
+
+        assert_eq!(errors, vec!["config#build".to_string()]);
+        assert_eq!(ran.into_inner().unwrap(), expected_ran);
+    }
+}
@@ -0,0 +1,236 @@
+mod builder;
+mod execute;
+
+use std::collections::{HashMap, HashSet};
+
+pub use builder::{EngineBuilder, Error as BuilderError};
+pub use execute::ExecutionOptions;
+use petgraph::{
+    graph::{Graph, NodeIndex},
+    Direction,
+};
+
+use crate::{
+    package_graph::{PackageGraph, WorkspaceName},
+    run::task_id::{get_package_task_from_id, ROOT_PKG_NAME},
+    task_graph::TaskDefinitionHashable,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TaskNode {
+    Root,
+    Task(String),
+}
+
+/// The graph of tasks to run. Edges point from a task to the tasks it depends
+/// on, tasks without any dependencies point to `TaskNode::Root`.
+#[derive(Debug)]
+pub struct Engine {
+    task_graph: Graph<TaskNode, ()>,
+    root_index: NodeIndex,
+    task_lookup: HashMap<String, NodeIndex>,
+    task_definitions: HashMap<String, TaskDefinitionHashable>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ValidateError {
+    #[error("\"{persistent_task}\" is a persistent task, \"{dependant}\" cannot depend on it")]
+    DependencyOnPersistentTask {
+        persistent_task: String,
+        dependant: String,
+    },
+    #[error(
+        "You have {persistent_count} persistent tasks but `turbo` is configured for concurrency \
+         of {concurrency}. Set --concurrency to at least {}",
+        persistent_count + 1
+    )]
+    PersistentTasksExceedConcurrency {
+        persistent_count: usize,
+        concurrency: u32,
+    },
+    #[error("Invalid task dependency graph: cyclic dependency detected involving \"{task_id}\"")]
+    CyclicDependency { task_id: String },
+}
+
+impl Engine {
+    pub fn builder<'a>(
+        package_graph: &'a PackageGraph,
+        pipeline: &'a crate::task_graph::Pipeline,
+    ) -> EngineBuilder<'a> {
+        EngineBuilder::new(package_graph, pipeline)
+    }
+
+    fn new() -> Self {
+        let mut task_graph = Graph::new();
+        let root_index = task_graph.add_node(TaskNode::Root);
+        Self {
+            task_graph,
+            root_index,
+            task_lookup: HashMap::new(),
+            task_definitions: HashMap::new(),
+        }
+    }
+
+    fn get_index(&mut self, task_id: &str) -> NodeIndex {
+        if let Some(index) = self.task_lookup.get(task_id) {
+            return *index;
+        }
+        let index = self
+            .task_graph
+            .add_node(TaskNode::Task(task_id.to_string()));
+        self.task_lookup.insert(task_id.to_string(), index);
+        index
+    }
+
+    fn connect(&mut self, task_id: &str, dependency: &TaskNode) {
+        let from = self.get_index(task_id);
+        let to = match dependency {
+            TaskNode::Root => self.root_index,
+            TaskNode::Task(dependency) => self.get_index(dependency),
+        };
+        self.task_graph.update_edge(from, to, ());
+    }
+
+    /// The tasks that `task_id` depends on
+    pub fn dependencies(&self, task_id: &str) -> Option<HashSet<&TaskNode>> {
+        let index = self.task_lookup.get(task_id)?;
+        Some(
+            self.task_graph
+                .neighbors_directed(*index, Direction::Outgoing)
+                .map(|index| {
+                    self.task_graph
+                        .node_weight(index)
+                        .expect("node index from neighbors should be present")
+                })
+                .collect(),
+        )
+    }
+
+    /// Every task that `task_id` depends on, directly or transitively, sorted
+    /// by task id
+    pub fn transitive_dependencies(&self, task_id: &str) -> Option<Vec<&str>> {
+        self.transitive_closure(task_id, Direction::Outgoing)
+    }
+
+    /// Every task that depends on `task_id`, directly or transitively, sorted
+    /// by task id
+    pub fn transitive_dependents(&self, task_id: &str) -> Option<Vec<&str>> {
+        self.transitive_closure(task_id, Direction::Incoming)
+    }
+
+    // The tasks reachable from `task_id` in the given direction, excluding
+    // `task_id` itself and the root node
+    fn transitive_closure(&self, task_id: &str, direction: Direction) -> Option<Vec<&str>> {
+        let start = *self.task_lookup.get(task_id)?;
+        let mut visited = HashSet::from([start]);
+        let mut stack = vec![start];
+        let mut closure = Vec::new();
+        while let Some(index) = stack.pop() {
+            for neighbor in self.task_graph.neighbors_directed(index, direction) {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                stack.push(neighbor);
+                if let TaskNode::Task(task_id) = &self.task_graph[neighbor] {
+                    closure.push(task_id.as_str());
+                }
+            }
+        }
+        closure.sort();
+        Some(closure)
+    }
+
+    /// All tasks in the graph, excluding the root node
+    pub fn tasks(&self) -> impl Iterator<Item = &str> {
+        self.task_lookup.keys().map(|task_id| task_id.as_str())
+    }
+
+    pub fn task_definition(&self, task_id: &str) -> Option<&TaskDefinitionHashable> {
+        self.task_definitions.get(task_id)
+    }
+
+    /// Checks that the graph can actually be run to completion: no task may
+    /// depend on a persistent task, there must be enough concurrency to keep
+    /// every persistent task running and there can't be any cycles.
+    pub fn validate(
+        &self,
+        package_graph: &PackageGraph,
+        concurrency: u32,
+        parallel: bool,
+    ) -> Result<(), Vec<ValidateError>> {
+        let mut errors = Vec::new();
+        let mut persistent_count = 0;
+
+        let mut task_ids = self.tasks().collect::<Vec<_>>();
+        task_ids.sort();
+        for task_id in task_ids {
+            let is_persistent = self
+                .task_definition(task_id)
+                .map_or(false, |definition| definition.persistent);
+            // Tasks without a script never get run, so they can't hold on to a
+            // concurrency slot.
+            if is_persistent && task_has_script(package_graph, task_id) {
+                persistent_count += 1;
+            }
+
+            let mut dependencies = self
+                .dependencies(task_id)
+                .into_iter()
+                .flatten()
+                .filter_map(|node| match node {
+                    TaskNode::Root => None,
+                    TaskNode::Task(dependency) => Some(dependency.as_str()),
+                })
+                .collect::<Vec<_>>();
+            dependencies.sort();
+
+            for dependency in dependencies {
+                let dependency_is_persistent = self
+                    .task_definition(dependency)
+                    .map_or(false, |definition| definition.persistent);
+                if dependency_is_persistent && task_has_script(package_graph, dependency) {
+                    errors.push(ValidateError::DependencyOnPersistentTask {
+                        persistent_task: dependency.to_string(),
+                        dependant: task_id.to_string(),
+                    });
+                }
+            }
+        }
+
+        // --parallel ignores the graph entirely so every task gets to run at once
+        if !parallel && persistent_count >= concurrency as usize {
+            errors.push(ValidateError::PersistentTasksExceedConcurrency {
+                persistent_count,
+                concurrency,
+            });
+        }
+
+        if let Err(cycle) = petgraph::algo::toposort(&self.task_graph, None) {
+            let task_id = match &self.task_graph[cycle.node_id()] {
+                TaskNode::Root => ROOT_PKG_NAME.to_string(),
+                TaskNode::Task(task_id) => task_id.clone(),
+            };
+            errors.push(ValidateError::CyclicDependency { task_id });
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+// Whether the workspace owning the task defines a script for it
+pub(crate) fn task_has_script(package_graph: &PackageGraph, task_id: &str) -> bool {
+    let (package, task) = get_package_task_from_id(task_id);
+    let workspace = match package.as_str() {
+        ROOT_PKG_NAME => WorkspaceName::Root,
+        _ => WorkspaceName::from(package),
+    };
+    package_graph
+        .package_json(&workspace)
+        .map_or(false, |package_json| {
+            package_json.scripts.contains_key(&task)
+        })
+}
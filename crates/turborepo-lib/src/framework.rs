@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+
+use crate::package_graph::Entry;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Strategy {
+    All,
+    Some,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Matcher {
+    strategy: Strategy,
+    dependencies: &'static [&'static str],
+}
+
+/// A framework we can infer from a workspace's dependencies. Environment
+/// variables matching its wildcards get included in task hashes
+/// automatically.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Framework {
+    slug: &'static str,
+    env_wildcards: &'static [&'static str],
+    dependency_match: Matcher,
+}
+
+impl Framework {
+    pub fn slug(&self) -> &'static str {
+        self.slug
+    }
+
+    pub fn env_wildcards(&self) -> &'static [&'static str] {
+        self.env_wildcards
+    }
+}
+
+// Order matters, the first framework that matches wins
+static FRAMEWORKS: [Framework; 12] = [
+    Framework {
+        slug: "blitzjs",
+        env_wildcards: &["NEXT_PUBLIC_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["blitz"],
+        },
+    },
+    Framework {
+        slug: "nextjs",
+        env_wildcards: &["NEXT_PUBLIC_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["next"],
+        },
+    },
+    Framework {
+        slug: "gatsby",
+        env_wildcards: &["GATSBY_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["gatsby"],
+        },
+    },
+    Framework {
+        slug: "astro",
+        env_wildcards: &["PUBLIC_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["astro"],
+        },
+    },
+    Framework {
+        slug: "solidstart",
+        env_wildcards: &["VITE_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["solid-js", "solid-start"],
+        },
+    },
+    Framework {
+        slug: "vue",
+        env_wildcards: &["VUE_APP_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["@vue/cli-service"],
+        },
+    },
+    Framework {
+        slug: "sveltekit",
+        env_wildcards: &["VITE_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["@sveltejs/kit"],
+        },
+    },
+    Framework {
+        slug: "create-react-app",
+        env_wildcards: &["REACT_APP_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::Some,
+            dependencies: &["react-scripts", "react-dev-utils"],
+        },
+    },
+    Framework {
+        slug: "nuxtjs",
+        env_wildcards: &["NUXT_ENV_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::Some,
+            dependencies: &["nuxt", "nuxt-edge", "nuxt3", "nuxt3-edge"],
+        },
+    },
+    Framework {
+        slug: "redwoodjs",
+        env_wildcards: &["REDWOOD_ENV_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["@redwoodjs/core"],
+        },
+    },
+    Framework {
+        slug: "vite",
+        env_wildcards: &["VITE_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["vite"],
+        },
+    },
+    Framework {
+        slug: "sanity",
+        env_wildcards: &["SANITY_STUDIO_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["@sanity/cli"],
+        },
+    },
+];
+
+impl Matcher {
+    fn test(&self, dependencies: &HashSet<&str>) -> bool {
+        match self.strategy {
+            Strategy::All => self
+                .dependencies
+                .iter()
+                .all(|dependency| dependencies.contains(dependency)),
+            Strategy::Some => self
+                .dependencies
+                .iter()
+                .any(|dependency| dependencies.contains(dependency)),
+        }
+    }
+}
+
+/// Returns the first framework whose dependencies are used by the workspace
+pub fn infer_framework(workspace: &Entry) -> Option<&'static Framework> {
+    let dependencies = workspace.external_dependency_names();
+    FRAMEWORKS
+        .iter()
+        .find(|framework| framework.dependency_match.test(&dependencies))
+}
+
+#[cfg(test)]
+pub fn framework_by_slug(slug: &str) -> Option<&'static Framework> {
+    FRAMEWORKS.iter().find(|framework| framework.slug == slug)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn matches(dependencies: &[&str]) -> Option<&'static str> {
+        let dependencies = dependencies.iter().copied().collect();
+        FRAMEWORKS
+            .iter()
+            .find(|framework| framework.dependency_match.test(&dependencies))
+            .map(|framework| framework.slug())
+    }
+
+    #[test_case(&[], None ; "no dependencies")]
+    #[test_case(&["next"], Some("nextjs") ; "nextjs")]
+    #[test_case(&["blitz", "next"], Some("blitzjs") ; "blitz wins over next")]
+    #[test_case(&["solid-js"], None ; "all dependencies are required")]
+    #[test_case(&["solid-js", "solid-start", "vite"], Some("solidstart") ; "solidstart before vite")]
+    #[test_case(&["react-dev-utils"], Some("create-react-app") ; "any dependency is enough")]
+    #[test_case(&["nuxt3"], Some("nuxtjs") ; "nuxt")]
+    #[test_case(&["@sanity/cli"], Some("sanity") ; "sanity")]
+    fn test_infer_framework(dependencies: &[&str], expected: Option<&str>) {
+        assert_eq!(matches(dependencies), expected);
+    }
+}
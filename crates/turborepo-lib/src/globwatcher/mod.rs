@@ -7,7 +7,7 @@ use std::{
 
 use camino::Utf8PathBuf;
 use futures::{stream::iter, StreamExt};
-use globwatch::{ConfigError, GlobWatcher, StopToken, WatchConfig, Watcher};
+use globwatch::{ConfigError, GlobWatcher, StopToken, WatchConfig, Watcher, WatcherStats};
 use itertools::Itertools;
 use notify::{EventKind, RecommendedWatcher};
 use tokio::time::timeout;
@@ -132,6 +132,26 @@ impl<T: Watcher> HashGlobWatcher<T> {
         Ok(())
     }
 
+    /// The number of globs being watched, and the number of hashes they
+    /// belong to
+    pub fn watched(&self) -> (usize, usize) {
+        let globs = self
+            .glob_statuses
+            .lock()
+            .expect("only fails if poisoned")
+            .len();
+        let hashes = self
+            .hash_globs
+            .lock()
+            .expect("only fails if poisoned")
+            .len();
+        (globs, hashes)
+    }
+
+    pub fn watcher_stats(&self) -> &WatcherStats {
+        self.config.stats()
+    }
+
     /// registers a hash with a set of globs to watch for changes
     pub async fn watch_globs<
         Iter: IntoIterator<Item = String>,
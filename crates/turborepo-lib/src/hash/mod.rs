@@ -0,0 +1,346 @@
+//! Hashable representations of everything that goes into the global and task
+//! hashes.
+//!
+//! The Go implementation hashes these objects by rendering them with `%v` and
+//! running the result through xxHash64. Cache entries are keyed by these hashes
+//! so we render the exact same strings: field order and formatting must stay
+//! in sync with `cli/internal/fs/hash.go`.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    hash::Hasher,
+};
+
+use itertools::Itertools;
+use turbopath::RelativeUnixPathBuf;
+use turborepo_env::EnvironmentVariablePairs;
+use twox_hash::XxHash64;
+
+use crate::{cli::EnvMode, task_graph::TaskOutputs};
+
+pub trait TurboHash {
+    fn hash(&self) -> String;
+}
+
+// A hashable list of the packages resolved from the lockfile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockFilePackages(pub Vec<turborepo_lockfiles::Package>);
+
+// A hashable map of files to the hash of their contents
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FileHashes(pub HashMap<RelativeUnixPathBuf, String>);
+
+// A hashable representation of a task to be run
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskHashable<'a> {
+    pub(crate) global_hash: &'a str,
+    pub(crate) task_dependency_hashes: Vec<String>,
+    pub(crate) package_dir: RelativeUnixPathBuf,
+    pub(crate) hash_of_files: &'a str,
+    pub(crate) external_deps_hash: String,
+    pub(crate) task: &'a str,
+    pub(crate) outputs: TaskOutputs,
+    pub(crate) pass_thru_args: &'a [String],
+    pub(crate) env: &'a [String],
+    pub(crate) resolved_env_vars: EnvironmentVariablePairs,
+    pub(crate) pass_through_env: Option<&'a [String]>,
+    pub(crate) env_mode: EnvMode,
+    pub(crate) dot_env: &'a [RelativeUnixPathBuf],
+}
+
+// A hashable representation of the global dependencies of every task
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobalHashable<'a> {
+    pub(crate) global_cache_key: &'static str,
+    pub(crate) global_file_hash_map: &'a HashMap<RelativeUnixPathBuf, String>,
+    pub(crate) root_external_deps_hash: &'a str,
+    pub(crate) env: &'a [String],
+    pub(crate) resolved_env_vars: EnvironmentVariablePairs,
+    pub(crate) pass_through_env: Option<&'a [String]>,
+    pub(crate) env_mode: EnvMode,
+    pub(crate) framework_inference: bool,
+    // NOTE! This field is _explicitly_ ordered and should not be sorted.
+    pub(crate) dot_env: &'a [RelativeUnixPathBuf],
+}
+
+impl<T: GoDisplay> TurboHash for T {
+    fn hash(&self) -> String {
+        let mut hasher = XxHash64::with_seed(0);
+        hasher.write(GoFormat(self).to_string().as_bytes());
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+// Renders a value the same way Go's `%v` verb renders the equivalent Go value
+trait GoDisplay {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+}
+
+struct GoFormat<'a, T: ?Sized>(&'a T);
+
+impl<'a, T: GoDisplay + ?Sized> Display for GoFormat<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.go_fmt(f)
+    }
+}
+
+impl GoDisplay for str {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl GoDisplay for String {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl GoDisplay for RelativeUnixPathBuf {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl GoDisplay for bool {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl GoDisplay for EnvMode {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EnvMode::Infer => "Infer",
+            EnvMode::Loose => "Loose",
+            EnvMode::Strict => "Strict",
+        })
+    }
+}
+
+// Go renders nil and empty slices identically
+impl<T: GoDisplay> GoDisplay for [T] {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}]", self.iter().map(GoFormat).join(" "))
+    }
+}
+
+impl<T: GoDisplay> GoDisplay for Vec<T> {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_slice().go_fmt(f)
+    }
+}
+
+impl<T: GoDisplay + ?Sized> GoDisplay for Option<&T> {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Some(value) => value.go_fmt(f),
+            None => f.write_str("[]"),
+        }
+    }
+}
+
+impl<T: GoDisplay + ?Sized> GoDisplay for &T {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (*self).go_fmt(f)
+    }
+}
+
+// Go prints maps with their keys sorted
+impl GoDisplay for HashMap<RelativeUnixPathBuf, String> {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "map[{}]",
+            self.iter()
+                .sorted_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()))
+                .map(|(path, hash)| format!("{}:{}", path, hash))
+                .join(" ")
+        )
+    }
+}
+
+impl GoDisplay for TaskOutputs {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{{{} {}}}",
+            GoFormat(&self.inclusions),
+            GoFormat(&self.exclusions)
+        )
+    }
+}
+
+impl GoDisplay for FileHashes {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.go_fmt(f)
+    }
+}
+
+impl GoDisplay for LockFilePackages {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Go sorts the packages by the concatenation of their key and version
+        // before hashing. Every package from a transitive closure was found.
+        let packages = self
+            .0
+            .iter()
+            .sorted_by_cached_key(|package| format!("{}{}", package.key, package.version))
+            .map(|package| format!("{{{} {} true}}", package.key, package.version));
+        write!(f, "[{}]", packages.format(" "))
+    }
+}
+
+// Go hashes tasks by pointer, hence the leading `&`
+impl<'a> GoDisplay for TaskHashable<'a> {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "&{{{} {} {} {} {} {} {} {} {} {} {} {} {}}}",
+            GoFormat(self.global_hash),
+            GoFormat(&self.task_dependency_hashes),
+            GoFormat(&self.package_dir),
+            GoFormat(self.hash_of_files),
+            GoFormat(&self.external_deps_hash),
+            GoFormat(self.task),
+            GoFormat(&self.outputs),
+            GoFormat(self.pass_thru_args),
+            GoFormat(self.env),
+            GoFormat(&self.resolved_env_vars),
+            GoFormat(&self.pass_through_env),
+            GoFormat(&self.env_mode),
+            GoFormat(self.dot_env),
+        )
+    }
+}
+
+impl<'a> GoDisplay for GlobalHashable<'a> {
+    fn go_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{{{} {} {} {} {} {} {} {} {}}}",
+            GoFormat(self.global_cache_key),
+            GoFormat(self.global_file_hash_map),
+            GoFormat(self.root_external_deps_hash),
+            GoFormat(self.env),
+            GoFormat(&self.resolved_env_vars),
+            GoFormat(&self.pass_through_env),
+            GoFormat(&self.env_mode),
+            GoFormat(&self.framework_inference),
+            GoFormat(self.dot_env),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn path(path: &str) -> RelativeUnixPathBuf {
+        RelativeUnixPathBuf::new(path).unwrap()
+    }
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(|s| s.to_string()).collect()
+    }
+
+    // Reference values from the XXH64 specification, seed 0
+    #[test_case("", "ef46db3751d8e999" ; "empty")]
+    #[test_case("a", "d24ec4f1a98c6e5b" ; "single character")]
+    #[test_case("abc", "44bc2cf5ad770999" ; "abc")]
+    fn test_xxhash(input: &str, expected: &str) {
+        assert_eq!(input.to_string().hash(), expected);
+    }
+
+    #[test]
+    fn test_file_hashes_are_sorted() {
+        let hashes = FileHashes(
+            [
+                (path("src/index.ts"), "bbbb".to_string()),
+                (path("package.json"), "aaaa".to_string()),
+                (path(".env"), "cccc".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            GoFormat(&hashes).to_string(),
+            "map[.env:cccc package.json:aaaa src/index.ts:bbbb]"
+        );
+        assert_eq!(GoFormat(&FileHashes::default()).to_string(), "map[]");
+    }
+
+    #[test]
+    fn test_lockfile_packages() {
+        let packages = LockFilePackages(vec![
+            turborepo_lockfiles::Package::new("node_modules/react", "18.2.0"),
+            turborepo_lockfiles::Package::new("node_modules/loose-envify", "1.4.0"),
+            turborepo_lockfiles::Package::new("node_modules/js-tokens", "4.0.0"),
+        ]);
+        assert_eq!(
+            GoFormat(&packages).to_string(),
+            "[{node_modules/js-tokens 4.0.0 true} {node_modules/loose-envify 1.4.0 true} \
+             {node_modules/react 18.2.0 true}]"
+        );
+        assert_eq!(GoFormat(&LockFilePackages(vec![])).to_string(), "[]");
+    }
+
+    #[test]
+    fn test_task_hashable() {
+        let global_hash = "a5c1f2b8e5c0f8d4";
+        let hash_of_files = "7f4a5c4c2f6d8e1b";
+        let pass_thru_args = strings(&["--watch"]);
+        let env = strings(&["API_URL", "NEXT_PUBLIC_*"]);
+        let pass_through_env = strings(&["AWS_SECRET_KEY"]);
+        let dot_env = vec![path(".env.local"), path(".env")];
+        let task = TaskHashable {
+            global_hash,
+            task_dependency_hashes: strings(&["0123456789abcdef", "fedcba9876543210"]),
+            package_dir: path("apps/web"),
+            hash_of_files,
+            external_deps_hash: "".to_string(),
+            task: "build",
+            outputs: TaskOutputs {
+                inclusions: strings(&[".next/**", ".turbo/turbo-build.log"]),
+                exclusions: strings(&[".next/cache/**"]),
+            },
+            pass_thru_args: &pass_thru_args,
+            env: &env,
+            resolved_env_vars: strings(&["API_URL=https://example.com", "NEXT_PUBLIC_ID=1"]),
+            pass_through_env: Some(&pass_through_env),
+            env_mode: EnvMode::Strict,
+            dot_env: &dot_env,
+        };
+        assert_eq!(
+            GoFormat(&task).to_string(),
+            "&{a5c1f2b8e5c0f8d4 [0123456789abcdef fedcba9876543210] apps/web 7f4a5c4c2f6d8e1b  \
+             build {[.next/** .turbo/turbo-build.log] [.next/cache/**]} [--watch] [API_URL \
+             NEXT_PUBLIC_*] [API_URL=https://example.com NEXT_PUBLIC_ID=1] [AWS_SECRET_KEY] \
+             Strict [.env.local .env]}"
+        );
+    }
+
+    #[test]
+    fn test_global_hashable() {
+        let file_hashes = [(path("tsconfig.json"), "1234".to_string())]
+            .into_iter()
+            .collect();
+        let global = GlobalHashable {
+            global_cache_key: "cache key",
+            global_file_hash_map: &file_hashes,
+            root_external_deps_hash: "5678",
+            env: &[],
+            resolved_env_vars: vec![],
+            pass_through_env: None,
+            env_mode: EnvMode::Infer,
+            framework_inference: true,
+            dot_env: &[],
+        };
+        assert_eq!(
+            GoFormat(&global).to_string(),
+            "{cache key map[tsconfig.json:1234] 5678 [] [] [] Infer true []}"
+        );
+    }
+}
@@ -8,8 +8,11 @@ mod cli;
 mod commands;
 mod config;
 mod daemon;
+mod engine;
 mod execution_state;
+mod framework;
 pub(crate) mod globwatcher;
+mod hash;
 mod manager;
 mod opts;
 mod package_graph;
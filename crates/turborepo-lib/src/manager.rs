@@ -1,11 +1,45 @@
-// Manager is a wrapper around child processes executed by turbo
-#[derive(Debug)]
+use std::{io, process::ExitStatus};
+
+use tokio::process::{Child, Command};
+use tokio_util::sync::CancellationToken;
+
+// Manager is a wrapper around child processes executed by turbo. Stopping the
+// manager kills every running child and prevents new ones from being spawned.
+#[derive(Debug, Clone, Default)]
 pub struct Manager {
-    // TODO
+    shutdown: CancellationToken,
 }
 
 impl Manager {
     pub fn new() -> Self {
-        Self {}
+        Self::default()
+    }
+
+    /// Spawns a child process, returns `None` if the manager has been stopped
+    pub fn spawn(&self, mut command: Command) -> Option<io::Result<Child>> {
+        if self.is_closing() {
+            return None;
+        }
+        Some(command.kill_on_drop(true).spawn())
+    }
+
+    /// Waits for a child spawned by this manager to exit. Returns `None` if
+    /// the child was killed because the manager was stopped.
+    pub async fn wait(&self, child: &mut Child) -> io::Result<Option<ExitStatus>> {
+        tokio::select! {
+            status = child.wait() => status.map(Some),
+            _ = self.shutdown.cancelled() => {
+                child.kill().await?;
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn stop(&self) {
+        self.shutdown.cancel();
+    }
+
+    pub fn is_closing(&self) -> bool {
+        self.shutdown.is_cancelled()
     }
 }
@@ -1,11 +1,12 @@
 #![allow(dead_code)]
 use anyhow::{anyhow, Result};
-use serde::{Deserialize, Serialize};
 use turbopath::AnchoredSystemPathBuf;
+use turborepo_cache::CacheOpts;
 
 use crate::{
-    cli::{Command, DryRunMode, EnvMode, LogPrefix, RunArgs},
+    cli::{Command, DryRunMode, EnvMode, LogPrefix, OutputLogsMode, RunArgs},
     daemon::{DaemonClient, DaemonConnector},
+    task_graph::TaskOutputMode,
     Args,
 };
 
@@ -17,15 +18,6 @@ pub struct Opts<'a> {
     pub scope_opts: ScopeOpts,
 }
 
-#[derive(Debug, Default)]
-pub struct CacheOpts<'a> {
-    override_dir: Option<&'a str>,
-    skip_remote: bool,
-    skip_filesystem: bool,
-    workers: u32,
-    pub(crate) remote_cache_opts: Option<RemoteCacheOpts>,
-}
-
 impl<'a> From<&'a RunArgs> for CacheOpts<'a> {
     fn from(run_args: &'a RunArgs) -> Self {
         CacheOpts {
@@ -37,27 +29,22 @@ impl<'a> From<&'a RunArgs> for CacheOpts<'a> {
     }
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
-pub struct RemoteCacheOpts {
-    team_id: String,
-    signature: bool,
-}
-
 impl<'a> TryFrom<&'a Args> for Opts<'a> {
     type Error = anyhow::Error;
 
     fn try_from(args: &'a Args) -> std::result::Result<Self, Self::Error> {
-        let Some(Command::Run(run_args)) = &args.command else {
+        let Some(Command::Run(run_args) | Command::Watch(run_args)) = &args.command else {
             return Err(anyhow!("Expected run command"));
         };
         let run_opts = RunOpts::try_from(run_args.as_ref())?;
         let cache_opts = CacheOpts::from(run_args.as_ref());
         let scope_opts = ScopeOpts::try_from(run_args.as_ref())?;
+        let runcache_opts = RunCacheOpts::from(run_args.as_ref());
         Ok(Self {
             run_opts,
             cache_opts,
             scope_opts,
-            runcache_opts: RunCacheOpts::default(),
+            runcache_opts,
         })
     }
 }
@@ -65,33 +52,95 @@ impl<'a> TryFrom<&'a Args> for Opts<'a> {
 #[derive(Debug, Default)]
 pub struct RunCacheOpts {
     pub(crate) output_watcher: Option<DaemonClient<DaemonConnector>>,
+    // `--force`: ignore existing cache entries and always execute
+    pub(crate) skip_reads: bool,
+    // `--no-cache`: execute as usual but don't save the results
+    pub(crate) skip_writes: bool,
+    // `--output-logs`: used in place of the `outputMode` of every task
+    pub(crate) task_output_mode_override: Option<TaskOutputMode>,
+}
+
+impl<'a> From<&'a RunArgs> for RunCacheOpts {
+    fn from(run_args: &'a RunArgs) -> Self {
+        RunCacheOpts {
+            skip_reads: matches!(run_args.force, Some(Some(true))),
+            skip_writes: run_args.no_cache,
+            task_output_mode_override: run_args.output_logs.map(|mode| match mode {
+                OutputLogsMode::Full => TaskOutputMode::Full,
+                OutputLogsMode::None => TaskOutputMode::None,
+                OutputLogsMode::HashOnly => TaskOutputMode::Hash,
+                OutputLogsMode::NewOnly => TaskOutputMode::New,
+                OutputLogsMode::ErrorsOnly => TaskOutputMode::Error,
+            }),
+            ..RunCacheOpts::default()
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct RunOpts<'a> {
-    tasks: &'a [String],
-    concurrency: u32,
-    parallel: bool,
+    pub(crate) tasks: &'a [String],
+    pub(crate) concurrency: u32,
+    pub(crate) parallel: bool,
     pub(crate) env_mode: EnvMode,
+    // Whether tasks get told where to record the env vars they read
+    pub(crate) env_access_tracing: bool,
     // Whether or not to infer the framework for each workspace.
     pub(crate) framework_inference: bool,
     profile: Option<&'a str>,
-    continue_on_error: bool,
-    passthrough_args: &'a [String],
-    only: bool,
-    dry_run: bool,
+    pub(crate) continue_on_error: bool,
+    pub(crate) passthrough_args: &'a [String],
+    pub(crate) only: bool,
+    pub(crate) dry_run: bool,
     pub(crate) dry_run_json: bool,
     pub graph_dot: bool,
     graph_file: Option<&'a str>,
     pub(crate) no_daemon: bool,
     pub(crate) single_package: bool,
-    log_prefix: LogPrefix,
-    summarize: Option<Option<bool>>,
+    pub(crate) log_prefix: LogPrefix,
+    pub(crate) summarize: bool,
     pub(crate) experimental_space_id: Option<String>,
 }
 
 const DEFAULT_CONCURRENCY: u32 = 10;
 
+impl<'a> Opts<'a> {
+    /// A `turbo run` invocation equivalent to this one, used to describe the
+    /// run in its summary
+    pub fn synthesize_command(&self) -> String {
+        let mut cmd = format!("turbo run {}", self.run_opts.tasks.join(" "));
+        for pattern in self
+            .scope_opts
+            .filter_patterns
+            .iter()
+            .chain(&self.scope_opts.legacy_filter.as_filter_patterns())
+        {
+            cmd.push_str(" --filter=");
+            cmd.push_str(pattern);
+        }
+        if self.run_opts.parallel {
+            cmd.push_str(" --parallel");
+        }
+        if self.run_opts.continue_on_error {
+            cmd.push_str(" --continue");
+        }
+        if self.run_opts.dry_run {
+            cmd.push_str(match self.run_opts.dry_run_json {
+                true => " --dry=json",
+                false => " --dry",
+            });
+        }
+        if self.run_opts.only {
+            cmd.push_str(" --only");
+        }
+        if !self.run_opts.passthrough_args.is_empty() {
+            cmd.push_str(" -- ");
+            cmd.push_str(&self.run_opts.passthrough_args.join(" "));
+        }
+        cmd
+    }
+}
+
 impl<'a> TryFrom<&'a RunArgs> for RunOpts<'a> {
     type Error = anyhow::Error;
 
@@ -112,10 +161,11 @@ impl<'a> TryFrom<&'a RunArgs> for RunOpts<'a> {
         Ok(Self {
             tasks: args.tasks.as_slice(),
             log_prefix: args.log_prefix,
-            summarize: args.summarize,
+            summarize: matches!(args.summarize, Some(Some(true))),
             experimental_space_id: args.experimental_space_id.clone(),
             framework_inference: args.framework_inference,
             env_mode: args.env_mode,
+            env_access_tracing: args.env_access_tracing,
             concurrency,
             parallel: args.parallel,
             profile: args.profile.as_deref(),
@@ -158,17 +208,109 @@ fn parse_concurrency(concurrency_raw: &str) -> Result<u32> {
 #[derive(Debug)]
 pub struct ScopeOpts {
     pub pkg_inference_root: Option<AnchoredSystemPathBuf>,
+    pub global_deps: Vec<String>,
+    pub filter_patterns: Vec<String>,
+    pub legacy_filter: LegacyFilter,
+    pub ignore_patterns: Vec<String>,
 }
 
 impl<'a> TryFrom<&'a RunArgs> for ScopeOpts {
     type Error = anyhow::Error;
 
     fn try_from(args: &'a RunArgs) -> std::result::Result<Self, Self::Error> {
+        // Invoking turbo from the repo root doesn't need any inference
         let pkg_inference_root = args
             .pkg_inference_root
             .as_ref()
+            .filter(|root| !root.is_empty())
             .map(AnchoredSystemPathBuf::from_raw)
             .transpose()?;
-        Ok(Self { pkg_inference_root })
+        let legacy_filter = LegacyFilter {
+            include_dependencies: args.include_dependencies,
+            skip_dependents: args.no_deps,
+            entrypoints: args.scope.clone(),
+            since: args.since.clone(),
+        };
+        Ok(Self {
+            pkg_inference_root,
+            global_deps: args.global_deps.clone(),
+            filter_patterns: args.filter.clone(),
+            legacy_filter,
+            ignore_patterns: args.ignore.clone(),
+        })
+    }
+}
+
+/// The `--scope`, `--since`, `--include-dependencies` and `--no-deps` flags
+/// that predate `--filter`
+#[derive(Debug, Default)]
+pub struct LegacyFilter {
+    include_dependencies: bool,
+    skip_dependents: bool,
+    entrypoints: Vec<String>,
+    since: Option<String>,
+}
+
+impl LegacyFilter {
+    pub fn as_filter_patterns(&self) -> Vec<String> {
+        let prefix = if self.skip_dependents { "" } else { "..." };
+        let suffix = if self.include_dependencies { "..." } else { "" };
+        let since = self.since.as_ref().map(|since| format!("[{}]", since));
+
+        if !self.entrypoints.is_empty() {
+            // --scope implies our tweaked syntax to see if any dependency matches
+            let since = since.map_or(String::new(), |since| format!("...{}", since));
+            self.entrypoints
+                .iter()
+                .map(|pattern| {
+                    if pattern.starts_with('!') {
+                        pattern.clone()
+                    } else {
+                        format!("{}{}{}{}", prefix, pattern, since, suffix)
+                    }
+                })
+                .collect()
+        } else if let Some(since) = since {
+            // no scopes specified, but --since was provided
+            vec![format!("{}{}{}", prefix, since, suffix)]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+    use test_case::test_case;
+
+    use super::{LegacyFilter, Opts};
+    use crate::{task_graph::TaskOutputMode, Args};
+
+    #[test_case(LegacyFilter::default(), &[] ; "no legacy flags")]
+    #[test_case(LegacyFilter { since: Some("main".into()), ..Default::default() }, &["...[main]"] ; "since")]
+    #[test_case(LegacyFilter { skip_dependents: true, since: Some("main".into()), ..Default::default() }, &["[main]"] ; "since without dependents")]
+    #[test_case(LegacyFilter { entrypoints: vec!["foo".into(), "!bar".into()], ..Default::default() }, &["...foo", "!bar"] ; "scope")]
+    #[test_case(LegacyFilter { include_dependencies: true, skip_dependents: true, entrypoints: vec!["foo".into()], since: Some("main".into()) }, &["foo...[main]..."] ; "scope with since and dependencies")]
+    fn test_legacy_filter_patterns(legacy_filter: LegacyFilter, expected: &[&str]) {
+        assert_eq!(legacy_filter.as_filter_patterns(), expected);
+    }
+
+    #[test_case(&["build"], "turbo run build" ; "single task")]
+    #[test_case(&["build", "test", "--filter=web", "--scope=ui", "--parallel"], "turbo run build test --filter=web --filter=...ui --parallel" ; "filters")]
+    #[test_case(&["build", "--continue", "--only", "--", "--verbose"], "turbo run build --continue --only -- --verbose" ; "flags and pass through args")]
+    fn test_synthesize_command(args: &[&str], expected: &str) {
+        let args = Args::try_parse_from(["turbo", "run"].iter().chain(args.iter())).unwrap();
+        let opts = Opts::try_from(&args).unwrap();
+        assert_eq!(opts.synthesize_command(), expected);
+    }
+
+    #[test_case(&["build"], None ; "not set")]
+    #[test_case(&["build", "--output-logs=hash-only"], Some(TaskOutputMode::Hash) ; "hash only")]
+    #[test_case(&["build", "--output-logs=errors-only"], Some(TaskOutputMode::Error) ; "errors only")]
+    fn test_task_output_mode_override(args: &[&str], expected: Option<TaskOutputMode>) {
+        let args = Args::try_parse_from(["turbo", "run"].iter().chain(args.iter())).unwrap();
+        let opts = Opts::try_from(&args).unwrap();
+        assert_eq!(opts.runcache_opts.task_output_mode_override, expected);
     }
 }
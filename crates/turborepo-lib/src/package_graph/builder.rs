@@ -1,6 +1,7 @@
 use std::{
     collections::{HashMap, HashSet},
     fmt,
+    sync::OnceLock,
 };
 
 use petgraph::graph::{Graph, NodeIndex};
@@ -43,8 +44,10 @@ pub enum Error {
     PackageJsonMissingName,
     #[error(transparent)]
     Lockfile(#[from] turborepo_lockfiles::Error),
-    #[error("TODO lockfile errors")]
-    Todo,
+    #[error("unknown package manager: {0}")]
+    UnknownPackageManager(String),
+    #[error("workspace {0} is not in the package graph")]
+    MissingWorkspace(String),
 }
 
 impl<'a> PackageGraphBuilder<'a> {
@@ -251,6 +254,7 @@ impl<'a> BuildState<'a, ResolvedPackageManager> {
     fn build_single_package_graph(mut self) -> PackageGraph {
         self.add_root_workspace();
         let Self {
+            repo_root,
             single,
             package_manager,
             workspaces,
@@ -261,11 +265,12 @@ impl<'a> BuildState<'a, ResolvedPackageManager> {
         } = self;
         debug_assert!(single, "expected single package graph");
         PackageGraph {
+            repo_root: repo_root.to_owned(),
             workspace_graph,
             node_lookup,
             workspaces,
             package_manager,
-            lockfile,
+            lockfile: OnceLock::from(lockfile),
         }
     }
 }
@@ -320,8 +325,17 @@ impl<'a> BuildState<'a, ResolvedWorkspaces> {
     }
 
     fn populate_lockfile(&mut self) -> Result<Box<dyn Lockfile>, Error> {
-        // TODO actual lockfile parsing
-        self.lockfile.take().map_or_else(|| Err(Error::Todo), Ok)
+        if let Some(lockfile) = self.lockfile.take() {
+            return Ok(lockfile);
+        }
+        let root_package_json = &self
+            .workspaces
+            .get(&WorkspaceName::Root)
+            .expect("root workspace should always be present")
+            .package_json;
+        Ok(self
+            .package_manager
+            .read_lockfile(self.repo_root, root_package_json)?)
     }
 
     fn resolve_lockfile(mut self) -> Result<BuildState<'a, ResolvedLockfile>, Error> {
@@ -329,13 +343,12 @@ impl<'a> BuildState<'a, ResolvedWorkspaces> {
 
         let lockfile = match self.populate_lockfile() {
             Ok(lockfile) => Some(lockfile),
-            Err(_) => {
-                // TODO: Re-enable this warning once we have lockfile parsing hooked up
-                // warn!(
-                //     "Issues occurred when constructing package graph. Turbo will function,
-                // but \      some features may not be available: {}",
-                //     e
-                // );
+            Err(e) => {
+                warn!(
+                    "Issues occurred when constructing package graph. Turbo will function, but \
+                     some features may not be available: {}",
+                    e
+                );
                 None
             }
         };
@@ -364,28 +377,6 @@ impl<'a> BuildState<'a, ResolvedWorkspaces> {
 }
 
 impl<'a> BuildState<'a, ResolvedLockfile> {
-    fn all_external_dependencies(&self) -> Result<HashMap<String, HashMap<String, String>>, Error> {
-        self.workspaces
-            .values()
-            .map(|entry| {
-                let workspace_path = entry.package_json_path.to_unix()?;
-                let workspace_string = workspace_path.as_str();
-                let external_deps = entry
-                    .unresolved_external_dependencies
-                    .as_ref()
-                    .map(|deps| {
-                        deps.iter()
-                            .map(|Package { name, version }| {
-                                (name.to_string(), version.to_string())
-                            })
-                            .collect()
-                    })
-                    .unwrap_or_default();
-                Ok((workspace_string.to_string(), external_deps))
-            })
-            .collect()
-    }
-
     fn populate_transitive_dependencies(&mut self) -> Result<(), Error> {
         let Some(lockfile) = self.lockfile.as_deref() else {
             return Ok(());
@@ -393,7 +384,7 @@ impl<'a> BuildState<'a, ResolvedLockfile> {
 
         let mut closures = turborepo_lockfiles::all_transitive_closures(
             lockfile,
-            self.all_external_dependencies()?,
+            external_dependencies_by_workspace(&self.workspaces)?,
         )?;
         for (_, entry) in self.workspaces.iter_mut() {
             entry.transitive_dependencies = closures.remove(&entry.unix_dir_str()?);
@@ -406,6 +397,7 @@ impl<'a> BuildState<'a, ResolvedLockfile> {
             warn!("Unable to calculate transitive closures: {}", e);
         }
         let Self {
+            repo_root,
             package_manager,
             workspaces,
             workspace_graph,
@@ -414,15 +406,38 @@ impl<'a> BuildState<'a, ResolvedLockfile> {
             ..
         } = self;
         PackageGraph {
+            repo_root: repo_root.to_owned(),
             workspace_graph,
             node_lookup,
             workspaces,
             package_manager,
-            lockfile,
+            lockfile: OnceLock::from(lockfile),
         }
     }
 }
 
+/// Maps each workspace's directory to its unresolved external dependencies
+pub(super) fn external_dependencies_by_workspace(
+    workspaces: &HashMap<WorkspaceName, Entry>,
+) -> Result<HashMap<String, HashMap<String, String>>, Error> {
+    workspaces
+        .values()
+        .map(|entry| {
+            let workspace_string = entry.unix_dir_str()?;
+            let external_deps = entry
+                .unresolved_external_dependencies
+                .as_ref()
+                .map(|deps| {
+                    deps.iter()
+                        .map(|Package { name, version }| (name.to_string(), version.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            Ok((workspace_string, external_deps))
+        })
+        .collect()
+}
+
 struct Dependencies {
     internal: HashSet<WorkspaceName>,
     external: HashSet<Package>,
@@ -555,8 +570,9 @@ impl<'a> fmt::Display for DependencyVersion<'a> {
 }
 
 impl Entry {
-    fn unix_dir_str(&self) -> Result<String, Error> {
-        let unix = self.package_json_path.to_unix()?;
+    // Lockfiles refer to workspaces by their directory, not their package.json
+    pub(super) fn unix_dir_str(&self) -> Result<String, Error> {
+        let unix = self.package_path().to_owned().to_unix()?;
         Ok(unix.to_string())
     }
 }
@@ -1,25 +1,32 @@
 use std::{
     collections::{HashMap, HashSet},
     fmt,
+    sync::OnceLock,
 };
 
 use anyhow::Result;
-use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf};
+use tracing::warn;
+use turbopath::{
+    AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPath, AnchoredSystemPathBuf,
+};
 use turborepo_lockfiles::Lockfile;
 
-use crate::{package_json::PackageJson, package_manager::PackageManager};
+use crate::{config::TurboJson, package_json::PackageJson, package_manager::PackageManager};
 
 mod builder;
+mod snapshot;
 
-pub use builder::PackageGraphBuilder;
+pub use builder::{Error, PackageGraphBuilder};
 
 pub struct PackageGraph {
+    repo_root: AbsoluteSystemPathBuf,
     workspace_graph: petgraph::Graph<WorkspaceNode, ()>,
     #[allow(dead_code)]
     node_lookup: HashMap<WorkspaceNode, petgraph::graph::NodeIndex>,
     workspaces: HashMap<WorkspaceName, Entry>,
     package_manager: PackageManager,
-    lockfile: Option<Box<dyn Lockfile>>,
+    // Graphs that come from the daemon only read the lockfile once it's needed
+    lockfile: OnceLock<Option<Box<dyn Lockfile>>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -30,10 +37,57 @@ pub struct Entry {
     transitive_dependencies: Option<HashSet<turborepo_lockfiles::Package>>,
 }
 
+#[cfg(test)]
+impl Entry {
+    pub fn with_package_json_path(package_json_path: AnchoredSystemPathBuf) -> Self {
+        Self {
+            package_json_path,
+            ..Default::default()
+        }
+    }
+}
+
 impl Entry {
     pub fn package_json_path(&self) -> &AnchoredSystemPathBuf {
         &self.package_json_path
     }
+
+    pub fn package_json(&self) -> &PackageJson {
+        &self.package_json
+    }
+
+    /// The directory containing the workspace's package.json, relative to the
+    /// repo root. The root workspace resolves to the repo root itself.
+    pub fn package_path(&self) -> &AnchoredSystemPath {
+        self.package_json_path
+            .parent()
+            .unwrap_or(&self.package_json_path)
+    }
+
+    /// The packages from the lockfile this workspace depends on, directly or
+    /// indirectly. `None` if the lockfile couldn't be used.
+    pub fn transitive_dependencies(&self) -> Option<&HashSet<turborepo_lockfiles::Package>> {
+        self.transitive_dependencies.as_ref()
+    }
+
+    /// Names of the dependencies that aren't other workspaces. Falls back to
+    /// the package.json dependencies if workspace dependencies haven't been
+    /// resolved, e.g. in single package mode.
+    pub fn external_dependency_names(&self) -> HashSet<&str> {
+        match &self.unresolved_external_dependencies {
+            Some(dependencies) => dependencies
+                .iter()
+                .map(|package| package.name.as_str())
+                .collect(),
+            None => self
+                .package_json
+                .dependencies
+                .iter()
+                .flatten()
+                .map(|(name, _)| name.as_str())
+                .collect(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
@@ -79,7 +133,33 @@ impl PackageGraph {
     }
 
     pub fn lockfile(&self) -> Option<&dyn Lockfile> {
-        self.lockfile.as_deref()
+        self.lockfile
+            .get_or_init(|| {
+                let root_package_json = &self.workspaces.get(&WorkspaceName::Root)?.package_json;
+                match self
+                    .package_manager
+                    .read_lockfile(&self.repo_root, root_package_json)
+                {
+                    Ok(lockfile) => Some(lockfile),
+                    Err(e) => {
+                        warn!("Unable to read lockfile: {}", e);
+                        None
+                    }
+                }
+            })
+            .as_deref()
+    }
+
+    /// Whether the graph has lockfile information, without reading the
+    /// lockfile if it hasn't been read yet.
+    pub fn has_lockfile(&self) -> bool {
+        self.lockfile.get().map_or(true, Option::is_some)
+    }
+
+    /// Takes the lockfile out of the graph so it can be reused for the next
+    /// graph without being parsed again.
+    pub fn into_lockfile(self) -> Option<Box<dyn Lockfile>> {
+        self.lockfile.into_inner().flatten()
     }
 
     pub fn package_json(&self, workspace: &WorkspaceName) -> Option<&PackageJson> {
@@ -87,13 +167,47 @@ impl PackageGraph {
         Some(&entry.package_json)
     }
 
+    pub fn workspace_info(&self, workspace: &WorkspaceName) -> Option<&Entry> {
+        self.workspaces.get(workspace)
+    }
+
     pub fn workspaces(&self) -> impl Iterator<Item = (&WorkspaceName, &Entry)> {
         self.workspaces.iter()
     }
 
-    pub fn root_package_json(&self) -> &PackageJson {
-        self.package_json(&WorkspaceName::Root)
-            .expect("package graph was built without root package.json")
+    /// Loads the turbo.json of every workspace that has one, keyed by the
+    /// workspace it belongs to. The root turbo.json is loaded separately.
+    pub fn workspace_turbo_jsons(
+        &self,
+    ) -> Result<HashMap<WorkspaceName, TurboJson>, crate::config::Error> {
+        let mut turbo_jsons = HashMap::new();
+        for (workspace, entry) in &self.workspaces {
+            if matches!(workspace, WorkspaceName::Root) {
+                continue;
+            }
+            if let Some(turbo_json) =
+                TurboJson::load_workspace(&self.repo_root, entry.package_path())?
+            {
+                turbo_jsons.insert(workspace.clone(), turbo_json);
+            }
+        }
+        Ok(turbo_jsons)
+    }
+
+    /// Returns the workspaces that `node` directly depends on. Workspaces
+    /// without any internal dependencies depend on `WorkspaceNode::Root`.
+    pub fn immediate_dependencies(&self, node: &WorkspaceNode) -> Option<HashSet<&WorkspaceNode>> {
+        let idx = self.node_lookup.get(node)?;
+        Some(
+            self.workspace_graph
+                .neighbors_directed(*idx, petgraph::Outgoing)
+                .map(|index| {
+                    self.workspace_graph
+                        .node_weight(index)
+                        .expect("node index from neighbors should be present")
+                })
+                .collect(),
+        )
     }
 
     pub fn transitive_closure(&self, node: &WorkspaceNode) -> Option<HashSet<&WorkspaceNode>> {
@@ -111,6 +225,69 @@ impl PackageGraph {
         Some(visited)
     }
 
+    /// Returns every workspace `node` depends on, directly or indirectly, not
+    /// including `node` itself.
+    pub fn dependencies(&self, node: &WorkspaceNode) -> HashSet<&WorkspaceNode> {
+        self.transitive_neighbors(node, petgraph::Outgoing)
+    }
+
+    /// Returns every workspace that depends on `node`, directly or
+    /// indirectly, not including `node` itself.
+    pub fn ancestors(&self, node: &WorkspaceNode) -> HashSet<&WorkspaceNode> {
+        self.transitive_neighbors(node, petgraph::Incoming)
+    }
+
+    fn transitive_neighbors(
+        &self,
+        node: &WorkspaceNode,
+        direction: petgraph::Direction,
+    ) -> HashSet<&WorkspaceNode> {
+        let Some(idx) = self.node_lookup.get(node) else {
+            return HashSet::new();
+        };
+        let mut visited = HashSet::new();
+        let mut stack = vec![*idx];
+        while let Some(idx) = stack.pop() {
+            for neighbor in self.workspace_graph.neighbors_directed(idx, direction) {
+                let weight = self
+                    .workspace_graph
+                    .node_weight(neighbor)
+                    .expect("node index from neighbors should be present");
+                if visited.insert(weight) {
+                    stack.push(neighbor);
+                }
+            }
+        }
+        visited
+    }
+
+    /// Returns the workspaces whose external dependencies resolve differently
+    /// in `previous_lockfile` than in the current lockfile. Without a current
+    /// lockfile there's no way to tell, so every workspace is returned.
+    pub fn changed_packages_from_lockfile(
+        &self,
+        previous_lockfile: &dyn Lockfile,
+    ) -> Result<HashSet<WorkspaceName>, builder::Error> {
+        let Some(current_lockfile) = self.lockfile() else {
+            return Ok(self.workspaces.keys().cloned().collect());
+        };
+
+        let changed_dirs = turborepo_lockfiles::changed_workspaces(
+            previous_lockfile,
+            current_lockfile,
+            builder::external_dependencies_by_workspace(&self.workspaces)?,
+        )?;
+
+        self.workspaces
+            .iter()
+            .filter_map(|(name, entry)| match entry.unix_dir_str() {
+                Ok(dir) if changed_dirs.contains(&dir) => Some(Ok(name.clone())),
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            })
+            .collect()
+    }
+
     #[allow(dead_code)]
     fn external_dependencies(&self, workspace: &WorkspaceName) -> Option<&HashSet<Package>> {
         let entry = self.workspaces.get(workspace)?;
@@ -202,6 +379,22 @@ mod test {
             .transitive_closure(&WorkspaceNode::Workspace("a".into()))
             .unwrap();
         assert!(closure.contains(&WorkspaceNode::Workspace("b".into())));
+        assert_eq!(
+            pkg_graph.immediate_dependencies(&WorkspaceNode::Workspace("a".into())),
+            Some(HashSet::from([&WorkspaceNode::Workspace("b".into())]))
+        );
+        assert_eq!(
+            pkg_graph.immediate_dependencies(&WorkspaceNode::Workspace("b".into())),
+            Some(HashSet::from([&WorkspaceNode::Root]))
+        );
+        assert_eq!(
+            pkg_graph.dependencies(&WorkspaceNode::Workspace("a".into())),
+            HashSet::from([&WorkspaceNode::Workspace("b".into()), &WorkspaceNode::Root])
+        );
+        assert_eq!(
+            pkg_graph.ancestors(&WorkspaceNode::Workspace("b".into())),
+            HashSet::from([&WorkspaceNode::Workspace("a".into())])
+        );
         let b_external = pkg_graph
             .workspaces
             .get(&WorkspaceName::from("b"))
@@ -268,7 +461,7 @@ mod test {
         .with_package_jsons(Some({
             let mut map = HashMap::new();
             map.insert(
-                root.join_component("package_a"),
+                root.join_components(&["package_a", "package.json"]),
                 PackageJson::from_value(json!({
                     "name": "foo",
                     "dependencies": {
@@ -278,7 +471,7 @@ mod test {
                 .unwrap(),
             );
             map.insert(
-                root.join_component("package_b"),
+                root.join_components(&["package_b", "package.json"]),
                 PackageJson::from_value(json!({
                     "name": "bar",
                     "dependencies": {
@@ -322,4 +515,113 @@ mod test {
             ])
         );
     }
+
+    // Same as `MockLockfile` except `b` resolved to an older version
+    struct PreviousMockLockfile {}
+    impl turborepo_lockfiles::Lockfile for PreviousMockLockfile {
+        fn resolve_package(
+            &self,
+            workspace_path: &str,
+            name: &str,
+            version: &str,
+        ) -> std::result::Result<Option<turborepo_lockfiles::Package>, turborepo_lockfiles::Error>
+        {
+            match name {
+                "b" => Ok(Some(turborepo_lockfiles::Package::new("key:b", "0"))),
+                _ => MockLockfile {}.resolve_package(workspace_path, name, version),
+            }
+        }
+
+        fn all_dependencies(
+            &self,
+            key: &str,
+        ) -> std::result::Result<Option<HashMap<String, String>>, turborepo_lockfiles::Error>
+        {
+            MockLockfile {}.all_dependencies(key)
+        }
+    }
+
+    #[test]
+    fn test_changed_packages_from_lockfile() {
+        let root =
+            AbsoluteSystemPathBuf::new(if cfg!(windows) { r"C:\repo" } else { "/repo" }).unwrap();
+        let pkg_graph = PackageGraph::builder(
+            &root,
+            PackageJson::from_value(json!({ "name": "root" })).unwrap(),
+        )
+        .with_package_manger(Some(PackageManager::Npm))
+        .with_package_jsons(Some({
+            let mut map = HashMap::new();
+            map.insert(
+                root.join_components(&["package_a", "package.json"]),
+                PackageJson::from_value(json!({ "name": "foo", "dependencies": { "a": "1" } }))
+                    .unwrap(),
+            );
+            map.insert(
+                root.join_components(&["package_b", "package.json"]),
+                PackageJson::from_value(json!({ "name": "bar", "dependencies": { "b": "1" } }))
+                    .unwrap(),
+            );
+            map
+        }))
+        .with_lockfile(Some(Box::new(MockLockfile {})))
+        .build()
+        .unwrap();
+
+        assert!(pkg_graph
+            .changed_packages_from_lockfile(&MockLockfile {})
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            pkg_graph
+                .changed_packages_from_lockfile(&PreviousMockLockfile {})
+                .unwrap(),
+            HashSet::from_iter([WorkspaceName::from("bar")])
+        );
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let root =
+            AbsoluteSystemPathBuf::new(if cfg!(windows) { r"C:\repo" } else { "/repo" }).unwrap();
+        let pkg_graph = PackageGraph::builder(
+            &root,
+            PackageJson::from_value(json!({ "name": "root" })).unwrap(),
+        )
+        .with_package_manger(Some(PackageManager::Npm))
+        .with_package_jsons(Some({
+            let mut map = HashMap::new();
+            map.insert(
+                root.join_components(&["package_a", "package.json"]),
+                PackageJson::from_value(
+                    json!({ "name": "foo", "dependencies": { "a": "1", "bar": "*" } }),
+                )
+                .unwrap(),
+            );
+            map.insert(
+                root.join_components(&["package_b", "package.json"]),
+                PackageJson::from_value(json!({ "name": "bar", "dependencies": { "b": "1" } }))
+                    .unwrap(),
+            );
+            map
+        }))
+        .with_lockfile(Some(Box::new(MockLockfile {})))
+        .build()
+        .unwrap();
+
+        let snapshot = pkg_graph.to_snapshot().unwrap();
+        assert!(snapshot.has_lockfile);
+        let from_snapshot = PackageGraph::from_snapshot(&root, snapshot.clone()).unwrap();
+
+        assert_eq!(from_snapshot.workspaces, pkg_graph.workspaces);
+        assert_eq!(from_snapshot.package_manager, pkg_graph.package_manager);
+        for name in [WorkspaceName::Root, "foo".into(), "bar".into()] {
+            let node = WorkspaceNode::Workspace(name);
+            assert_eq!(
+                from_snapshot.immediate_dependencies(&node),
+                pkg_graph.immediate_dependencies(&node)
+            );
+        }
+        assert_eq!(from_snapshot.to_snapshot().unwrap(), snapshot);
+    }
 }
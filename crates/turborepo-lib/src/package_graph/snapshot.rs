@@ -0,0 +1,172 @@
+//! Conversion between a `PackageGraph` and the form the daemon sends it in.
+//!
+//! The lockfile itself isn't sent, a graph built from a snapshot reads it from
+//! disk the first time it's needed.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::OnceLock,
+};
+
+use petgraph::Graph;
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf};
+
+use super::{builder::Error, Entry, Package, PackageGraph, WorkspaceName, WorkspaceNode};
+use crate::{daemon::proto, package_json::PackageJson, package_manager::PackageManager};
+
+impl PackageGraph {
+    pub fn to_snapshot(&self) -> Result<proto::PackageGraph, Error> {
+        let mut workspaces = self
+            .workspaces
+            .iter()
+            .map(|(name, entry)| {
+                let node = WorkspaceNode::Workspace(name.clone());
+                let mut internal_dependencies = self
+                    .immediate_dependencies(&node)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|dependency| match dependency {
+                        WorkspaceNode::Workspace(name) => Some(name.to_string()),
+                        WorkspaceNode::Root => None,
+                    })
+                    .collect::<Vec<_>>();
+                internal_dependencies.sort();
+
+                Ok(proto::Workspace {
+                    name: name.to_string(),
+                    package_json_path: entry.package_json_path.to_unix()?.to_string(),
+                    package_json: serde_json::to_string(&entry.package_json)
+                        .map_err(crate::package_json::Error::from)?,
+                    internal_dependencies,
+                    external_dependencies: entry.unresolved_external_dependencies.as_ref().map(
+                        |dependencies| {
+                            to_dependencies(
+                                dependencies
+                                    .iter()
+                                    .map(|Package { name, version }| (name, version)),
+                            )
+                        },
+                    ),
+                    transitive_dependencies: entry.transitive_dependencies.as_ref().map(
+                        |dependencies| {
+                            to_dependencies(
+                                dependencies
+                                    .iter()
+                                    .map(|package| (&package.key, &package.version)),
+                            )
+                        },
+                    ),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        workspaces.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(proto::PackageGraph {
+            package_manager: self.package_manager.to_string(),
+            has_lockfile: self.has_lockfile(),
+            workspaces,
+        })
+    }
+
+    pub fn from_snapshot(
+        repo_root: &AbsoluteSystemPath,
+        snapshot: proto::PackageGraph,
+    ) -> Result<PackageGraph, Error> {
+        let package_manager = serde_json::from_value::<PackageManager>(serde_json::Value::String(
+            snapshot.package_manager.clone(),
+        ))
+        .map_err(|_| Error::UnknownPackageManager(snapshot.package_manager))?;
+
+        let mut workspace_graph = Graph::new();
+        let mut node_lookup = HashMap::new();
+        let mut add_node = |node: WorkspaceNode| {
+            let idx = workspace_graph.add_node(node.clone());
+            node_lookup.insert(node, idx);
+        };
+        add_node(WorkspaceNode::Root);
+        let mut workspaces = HashMap::new();
+        let mut internal_dependencies = Vec::new();
+        for workspace in snapshot.workspaces {
+            let name = workspace_name(workspace.name);
+            add_node(WorkspaceNode::Workspace(name.clone()));
+
+            let package_json = serde_json::from_str::<PackageJson>(&workspace.package_json)
+                .map_err(crate::package_json::Error::from)?;
+            let entry = Entry {
+                package_json,
+                package_json_path: AnchoredSystemPathBuf::from_raw(&workspace.package_json_path)?,
+                unresolved_external_dependencies: workspace.external_dependencies.map(
+                    |dependencies| {
+                        dependencies
+                            .dependencies
+                            .into_iter()
+                            .map(|proto::Dependency { name, version }| Package { name, version })
+                            .collect()
+                    },
+                ),
+                transitive_dependencies: workspace.transitive_dependencies.map(|dependencies| {
+                    dependencies
+                        .dependencies
+                        .into_iter()
+                        .map(|proto::Dependency { name, version }| {
+                            turborepo_lockfiles::Package::new(name, version)
+                        })
+                        .collect::<HashSet<_>>()
+                }),
+            };
+            internal_dependencies.push((name.clone(), workspace.internal_dependencies));
+            workspaces.insert(name, entry);
+        }
+
+        // Edges are added the same way the builder adds them, workspaces without
+        // any internal dependencies depend on the root node
+        let root_idx = node_lookup[&WorkspaceNode::Root];
+        for (name, dependencies) in internal_dependencies {
+            let idx = node_lookup[&WorkspaceNode::Workspace(name.clone())];
+            if name == WorkspaceName::Root || dependencies.is_empty() {
+                workspace_graph.add_edge(idx, root_idx, ());
+            }
+            for dependency in dependencies {
+                let dependency_idx = *node_lookup
+                    .get(&WorkspaceNode::Workspace(workspace_name(
+                        dependency.clone(),
+                    )))
+                    .ok_or(Error::MissingWorkspace(dependency))?;
+                workspace_graph.add_edge(idx, dependency_idx, ());
+            }
+        }
+
+        Ok(PackageGraph {
+            repo_root: repo_root.to_owned(),
+            workspace_graph,
+            node_lookup,
+            workspaces,
+            package_manager,
+            lockfile: match snapshot.has_lockfile {
+                true => OnceLock::new(),
+                false => OnceLock::from(None),
+            },
+        })
+    }
+}
+
+fn to_dependencies<'a>(
+    dependencies: impl Iterator<Item = (&'a String, &'a String)>,
+) -> proto::Dependencies {
+    let mut dependencies = dependencies
+        .map(|(name, version)| proto::Dependency {
+            name: name.clone(),
+            version: version.clone(),
+        })
+        .collect::<Vec<_>>();
+    // keep snapshots of the same graph identical
+    dependencies.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+    proto::Dependencies { dependencies }
+}
+
+fn workspace_name(name: String) -> WorkspaceName {
+    match name.as_str() {
+        "//" => WorkspaceName::Root,
+        _ => WorkspaceName::Other(name),
+    }
+}
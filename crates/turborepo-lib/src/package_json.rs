@@ -18,6 +18,7 @@ pub struct PackageJson {
     pub legacy_turbo_config: Option<serde_json::Value>,
     #[serde(default)]
     pub scripts: BTreeMap<String, String>,
+    pub resolutions: Option<BTreeMap<String, String>>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -1,3 +1,4 @@
+mod bun;
 mod npm;
 mod pnpm;
 mod yarn;
@@ -6,6 +7,7 @@ use std::{
     backtrace,
     fmt::{self, Display},
     fs,
+    io::Write,
 };
 
 use globwalk::fix_glob_pattern;
@@ -14,12 +16,17 @@ use lazy_regex::{lazy_regex, Lazy};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, RelativeUnixPathBuf};
+use turborepo_lockfiles::{
+    berry_global_change, bun_subgraph, npm_global_change, npm_subgraph, pnpm_global_change,
+    yarn_subgraph, BerryLockfile, BerryManifest, BunLockfile, Lockfile, LockfileData, NpmLockfile,
+    PnpmLockfile, Yarn1Lockfile,
+};
 use wax::{Any, Glob, Pattern};
 
 use crate::{
     package_json::PackageJson,
-    package_manager::{npm::NpmDetector, pnpm::PnpmDetector, yarn::YarnDetector},
+    package_manager::{bun::BunDetector, npm::NpmDetector, pnpm::PnpmDetector, yarn::YarnDetector},
     ui::{UI, UNDERLINE},
 };
 
@@ -58,10 +65,11 @@ impl From<Workspaces> for Vec<String> {
     }
 }
 
-#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum PackageManager {
     Berry,
+    Bun,
     Npm,
     Pnpm,
     Pnpm6,
@@ -74,6 +82,7 @@ impl fmt::Display for PackageManager {
         // packagemanager.go
         match self {
             PackageManager::Berry => write!(f, "berry"),
+            PackageManager::Bun => write!(f, "bun"),
             PackageManager::Npm => write!(f, "npm"),
             PackageManager::Pnpm => write!(f, "pnpm"),
             PackageManager::Pnpm6 => write!(f, "pnpm6"),
@@ -157,6 +166,11 @@ impl WorkspaceGlobs {
         })
     }
 
+    /// Globs matching the package.json of every workspace
+    pub fn package_json_inclusions(&self) -> &[String] {
+        &self.package_json_inclusions
+    }
+
     pub fn target_is_workspace(
         &self,
         root: &AbsoluteSystemPath,
@@ -217,6 +231,10 @@ impl Display for MissingWorkspaceError {
                 "package.json: no workspaces found. Turborepo requires npm workspaces to be \
                  defined in the root package.json"
             }
+            PackageManager::Bun => {
+                "package.json: no workspaces found. Turborepo requires bun workspaces to be \
+                 defined in the root package.json"
+            }
         };
         write!(f, "{}", err)
     }
@@ -271,12 +289,283 @@ pub enum Error {
     WalkError(#[from] globwalk::WalkError),
     #[error("invalid workspace glob {0}: {1}")]
     Glob(String, Box<wax::BuildError>),
+    #[error(transparent)]
+    Lockfile(#[from] turborepo_lockfiles::Error),
+    #[error("failed to read bun lockfile: {0}")]
+    BunLockfile(String),
+}
+
+/// A lockfile containing only a subset of a repository's workspaces
+pub struct PrunedLockfile {
+    pub contents: Vec<u8>,
+    pub patches: Vec<RelativeUnixPathBuf>,
+}
+
+fn read_berry_lockfile(
+    contents: &[u8],
+    root_package_json: &PackageJson,
+) -> Result<BerryLockfile, Error> {
+    let data = LockfileData::from_bytes(contents).map_err(turborepo_lockfiles::Error::from)?;
+    let manifest = root_package_json
+        .resolutions
+        .as_ref()
+        .map(|resolutions| BerryManifest::with_resolutions(resolutions.clone()));
+    Ok(BerryLockfile::new(data, manifest).map_err(turborepo_lockfiles::Error::from)?)
 }
 
 static PACKAGE_MANAGER_PATTERN: Lazy<Regex> =
-    lazy_regex!(r"(?P<manager>npm|pnpm|yarn)@(?P<version>\d+\.\d+\.\d+(-.+)?)");
+    lazy_regex!(r"(?P<manager>bun|npm|pnpm|yarn)@(?P<version>\d+\.\d+\.\d+(-.+)?)");
 
 impl PackageManager {
+    /// The binary used to invoke the package manager
+    pub fn command(&self) -> &'static str {
+        match self {
+            PackageManager::Npm => "npm",
+            PackageManager::Pnpm | PackageManager::Pnpm6 => "pnpm",
+            PackageManager::Yarn | PackageManager::Berry => "yarn",
+            PackageManager::Bun => "bun",
+        }
+    }
+
+    /// The separator, if any, that needs to be placed between `run <script>`
+    /// and the arguments that should be forwarded to the script.
+    pub fn arg_separator(&self, user_args: &[String]) -> Option<&'static str> {
+        match self {
+            PackageManager::Npm | PackageManager::Pnpm6 | PackageManager::Yarn => Some("--"),
+            // pnpm >= 7 no longer needs a separator, but to preserve the behavior
+            // of older versions we add it back if the user passed one.
+            PackageManager::Pnpm if user_args.first().map(|arg| arg.as_str()) == Some("--") => {
+                Some("--")
+            }
+            PackageManager::Pnpm | PackageManager::Berry | PackageManager::Bun => None,
+        }
+    }
+
+    /// The name of the lockfile the package manager writes to the repo root
+    pub fn lockfile_name(&self) -> &'static str {
+        match self {
+            PackageManager::Npm => npm::LOCKFILE,
+            PackageManager::Pnpm | PackageManager::Pnpm6 => pnpm::LOCKFILE,
+            PackageManager::Yarn | PackageManager::Berry => yarn::LOCKFILE,
+            PackageManager::Bun => bun::LOCKFILE,
+        }
+    }
+
+    /// The name of the lockfile written when pruning. We can't produce bun's
+    /// binary lockfile, but bun accepts a yarn v1 lockfile in its place.
+    pub fn pruned_lockfile_name(&self) -> &'static str {
+        match self {
+            PackageManager::Bun => yarn::LOCKFILE,
+            _ => self.lockfile_name(),
+        }
+    }
+
+    /// Reads and parses the lockfile at the repo root. Berry lockfiles
+    /// additionally need the `resolutions` from the root package.json.
+    pub fn read_lockfile(
+        &self,
+        root_path: &AbsoluteSystemPath,
+        root_package_json: &PackageJson,
+    ) -> Result<Box<dyn Lockfile>, Error> {
+        let contents = self.read_lockfile_contents(root_path)?;
+        self.parse_lockfile(root_package_json, &contents)
+    }
+
+    /// Parses lockfile contents, for bun these need to be in the yarn
+    /// compatible text form.
+    pub fn parse_lockfile(
+        &self,
+        root_package_json: &PackageJson,
+        contents: &[u8],
+    ) -> Result<Box<dyn Lockfile>, Error> {
+        let lockfile: Box<dyn Lockfile> = match self {
+            PackageManager::Npm => Box::new(NpmLockfile::load(contents)?),
+            PackageManager::Pnpm | PackageManager::Pnpm6 => {
+                Box::new(PnpmLockfile::from_bytes(contents)?)
+            }
+            PackageManager::Yarn => Box::new(
+                Yarn1Lockfile::from_bytes(contents).map_err(turborepo_lockfiles::Error::from)?,
+            ),
+            PackageManager::Berry => Box::new(read_berry_lockfile(contents, root_package_json)?),
+            PackageManager::Bun => Box::new(BunLockfile::from_bytes(contents)?),
+        };
+        Ok(lockfile)
+    }
+
+    /// Returns the contents of the lockfile. Bun's binary lockfile gets
+    /// converted to its yarn compatible text form by bun itself.
+    pub fn read_lockfile_contents(&self, root_path: &AbsoluteSystemPath) -> Result<Vec<u8>, Error> {
+        let lockfile_path = root_path.join_component(self.lockfile_name());
+        match self {
+            PackageManager::Bun => bun::print_lockfile(lockfile_path.as_std_path()),
+            _ => Ok(fs::read(lockfile_path)?),
+        }
+    }
+
+    /// Converts raw lockfile contents, e.g. a previous version of the lockfile
+    /// read from git, into the form expected by `parse_lockfile`.
+    pub fn decode_lockfile_contents(&self, contents: Vec<u8>) -> Result<Vec<u8>, Error> {
+        match self {
+            PackageManager::Bun => {
+                let mut lockfile = tempfile::Builder::new().suffix(".lockb").tempfile()?;
+                lockfile.write_all(&contents)?;
+                bun::print_lockfile(lockfile.path())
+            }
+            _ => Ok(contents),
+        }
+    }
+
+    /// Checks if the differences between two versions of the lockfile affect
+    /// every workspace, e.g. a change of the lockfile version.
+    pub fn lockfile_global_change(
+        &self,
+        previous_contents: &[u8],
+        current_contents: &[u8],
+    ) -> Result<bool, Error> {
+        Ok(match self {
+            PackageManager::Npm => npm_global_change(previous_contents, current_contents)?,
+            PackageManager::Pnpm | PackageManager::Pnpm6 => {
+                pnpm_global_change(previous_contents, current_contents)?
+            }
+            PackageManager::Berry => berry_global_change(previous_contents, current_contents)
+                .map_err(turborepo_lockfiles::Error::from)?,
+            // These lockfiles don't contain any settings that apply to every package
+            PackageManager::Yarn | PackageManager::Bun => false,
+        })
+    }
+
+    /// Produces a lockfile that only contains the given workspaces and
+    /// external packages, along with the patch files it still references.
+    /// Bun lockfiles need to be in their yarn compatible text form.
+    pub fn prune_lockfile(
+        &self,
+        contents: &[u8],
+        root_package_json: &PackageJson,
+        workspace_packages: &[String],
+        packages: &[String],
+    ) -> Result<PrunedLockfile, Error> {
+        let (contents, patches) = match self {
+            PackageManager::Npm => (
+                npm_subgraph(contents, workspace_packages, packages)?,
+                Vec::new(),
+            ),
+            PackageManager::Pnpm | PackageManager::Pnpm6 => {
+                let lockfile =
+                    PnpmLockfile::from_bytes(contents)?.subgraph(workspace_packages, packages)?;
+                let patches = lockfile.patches();
+                (serde_yaml::to_string(&lockfile)?.into_bytes(), patches)
+            }
+            PackageManager::Yarn => (yarn_subgraph(contents, packages)?, Vec::new()),
+            PackageManager::Berry => {
+                let lockfile = read_berry_lockfile(contents, root_package_json)?
+                    .subgraph(workspace_packages, packages)
+                    .map_err(turborepo_lockfiles::Error::from)?;
+                let patches = lockfile
+                    .patches()
+                    .into_iter()
+                    .map(|patch| patch.to_string_lossy().into_owned())
+                    .collect();
+                let data = lockfile
+                    .lockfile()
+                    .map_err(turborepo_lockfiles::Error::from)?;
+                (data.to_string().into_bytes(), patches)
+            }
+            // The text form gets written as a yarn v1 lockfile
+            PackageManager::Bun => (bun_subgraph(contents, packages)?, Vec::new()),
+        };
+
+        let patches = patches
+            .into_iter()
+            .map(RelativeUnixPathBuf::new)
+            .collect::<Result<_, _>>()?;
+
+        Ok(PrunedLockfile { contents, patches })
+    }
+
+    /// Removes any patched packages from the root package.json that reference
+    /// patches not included in `patches`. Returns whether anything was removed.
+    pub fn prune_patched_packages(
+        &self,
+        package_json: &mut serde_json::Value,
+        patches: &[RelativeUnixPathBuf],
+    ) -> bool {
+        let (patched_packages, is_patch): (_, fn(&str) -> bool) = match self {
+            // Only unused patches cause an error, other resolutions can stay
+            PackageManager::Berry => (package_json.get_mut("resolutions"), |reference: &str| {
+                reference.ends_with(".patch")
+            }),
+            PackageManager::Pnpm | PackageManager::Pnpm6 => (
+                package_json
+                    .get_mut("pnpm")
+                    .and_then(|pnpm| pnpm.get_mut("patchedDependencies")),
+                |_: &str| true,
+            ),
+            PackageManager::Npm | PackageManager::Yarn | PackageManager::Bun => return false,
+        };
+        let Some(patched_packages) = patched_packages.and_then(|value| value.as_object_mut())
+        else {
+            return false;
+        };
+
+        let before = patched_packages.len();
+        patched_packages.retain(|_, reference| {
+            let Some(reference) = reference.as_str() else {
+                return true;
+            };
+            !is_patch(reference)
+                || patches
+                    .iter()
+                    .any(|patch| reference.ends_with(patch.as_str()))
+        });
+        patched_packages.len() != before
+    }
+
+    /// The file, other than package.json, that configures the workspaces
+    pub fn workspace_configuration_path(&self) -> Option<&'static str> {
+        match self {
+            PackageManager::Pnpm | PackageManager::Pnpm6 => Some("pnpm-workspace.yaml"),
+            PackageManager::Npm
+            | PackageManager::Yarn
+            | PackageManager::Berry
+            | PackageManager::Bun => None,
+        }
+    }
+
+    /// Returns the globs the package manager ignores when searching for
+    /// workspaces. These also get applied to `globalDependencies`.
+    pub fn get_workspace_ignores(
+        &self,
+        root_path: &AbsoluteSystemPath,
+    ) -> Result<Vec<String>, Error> {
+        match self {
+            PackageManager::Pnpm | PackageManager::Pnpm6 => {
+                let mut ignores = self.get_default_exclusions().collect::<Vec<_>>();
+                // A missing pnpm-workspace.yaml means we're in a single package repo
+                match self.get_configured_workspace_globs(root_path) {
+                    Ok((_, exclusions)) => ignores.extend(exclusions),
+                    Err(Error::Io(err, _)) if err.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(err) => return Err(err),
+                }
+                Ok(ignores)
+            }
+            // Yarn only ignores node_modules inside of each workspace
+            PackageManager::Yarn => match self.get_configured_workspace_globs(root_path) {
+                Ok((inclusions, _)) => Ok(inclusions
+                    .into_iter()
+                    .map(|inclusion| format!("{inclusion}/node_modules/**"))
+                    .collect()),
+                // Without any workspaces only the root node_modules gets ignored
+                Err(Error::Workspace(_) | Error::ParsingJson(..)) => {
+                    Ok(vec!["node_modules/**".to_string()])
+                }
+                Err(err) => Err(err),
+            },
+            PackageManager::Npm | PackageManager::Berry | PackageManager::Bun => {
+                Ok(self.get_default_exclusions().collect())
+            }
+        }
+    }
+
     /// Returns the set of globs for the workspace.
     pub fn get_workspace_globs(
         &self,
@@ -300,7 +589,7 @@ impl PackageManager {
             PackageManager::Pnpm | PackageManager::Pnpm6 => {
                 ["**/node_modules/**", "**/bower_components/**"].as_slice()
             }
-            PackageManager::Npm => ["**/node_modules/**"].as_slice(),
+            PackageManager::Npm | PackageManager::Bun => ["**/node_modules/**"].as_slice(),
             PackageManager::Berry => ["**/node_modules", "**/.git", "**/.yarn"].as_slice(),
             PackageManager::Yarn => [].as_slice(), // yarn does its own handling above
         };
@@ -322,7 +611,10 @@ impl PackageManager {
                     pnpm_workspace.packages
                 }
             }
-            PackageManager::Berry | PackageManager::Npm | PackageManager::Yarn => {
+            PackageManager::Berry
+            | PackageManager::Npm
+            | PackageManager::Yarn
+            | PackageManager::Bun => {
                 let package_json_text =
                     fs::read_to_string(root_path.join_component("package.json"))?;
                 let package_json: PackageJsonWorkspaces = serde_json::from_str(&package_json_text)?;
@@ -371,6 +663,7 @@ impl PackageManager {
         let version = version.parse()?;
         let manager = match manager {
             "npm" => Some(PackageManager::Npm),
+            "bun" => Some(PackageManager::Bun),
             "yarn" => Some(YarnDetector::detect_berry_or_yarn(&version)?),
             "pnpm" => Some(PnpmDetector::detect_pnpm6_or_pnpm(&version)?),
             _ => None,
@@ -383,6 +676,7 @@ impl PackageManager {
         let mut detected_package_managers = PnpmDetector::new(repo_root)
             .chain(NpmDetector::new(repo_root))
             .chain(YarnDetector::new(repo_root))
+            .chain(BunDetector::new(repo_root))
             .collect::<Result<Vec<_>, Error>>()?;
 
         match detected_package_managers.len() {
@@ -509,11 +803,12 @@ mod tests {
             PackageManager::Berry,
             PackageManager::Pnpm,
             PackageManager::Pnpm6,
+            PackageManager::Bun,
         ] {
             let globs = mgr.get_workspace_globs(&fixtures).unwrap();
             let ignores: HashSet<String> = HashSet::from_iter(globs.raw_exclusions);
             let expected: &[&str] = match mgr {
-                PackageManager::Npm => &["**/node_modules/**"],
+                PackageManager::Npm | PackageManager::Bun => &["**/node_modules/**"],
                 PackageManager::Berry => &["**/node_modules", "**/.git", "**/.yarn"],
                 PackageManager::Yarn => &["apps/*/node_modules/**", "packages/*/node_modules/**"],
                 PackageManager::Pnpm | PackageManager::Pnpm6 => &[
@@ -594,6 +889,13 @@ mod tests {
                 expected_version: "111.0.1".to_owned(),
                 expected_error: false,
             },
+            TestCase {
+                name: "supports bun".to_owned(),
+                package_manager: "bun@1.0.1".to_owned(),
+                expected_manager: "bun".to_owned(),
+                expected_version: "1.0.1".to_owned(),
+                expected_error: false,
+            },
         ];
 
         for case in tests {
@@ -633,6 +935,10 @@ mod tests {
         let package_manager = PackageManager::read_package_manager(&package_json)?;
         assert_eq!(package_manager, Some(PackageManager::Pnpm));
 
+        package_json.package_manager = Some("bun@1.0.1".to_string());
+        let package_manager = PackageManager::read_package_manager(&package_json)?;
+        assert_eq!(package_manager, Some(PackageManager::Bun));
+
         Ok(())
     }
 
@@ -705,4 +1011,31 @@ mod tests {
         assert_eq!(nested.workspaces.as_ref(), vec!["packages/**"]);
         Ok(())
     }
+
+    #[test]
+    fn test_prune_patched_packages() {
+        let patches = vec![RelativeUnixPathBuf::new(".yarn/patches/lodash.patch").unwrap()];
+
+        let mut berry = serde_json::json!({
+            "resolutions": {
+                "lodash@^4.17.21": "patch:lodash@npm%3A4.17.21#./.yarn/patches/lodash.patch",
+                "left-pad@^1.3.0": "patch:left-pad@npm%3A1.3.0#./.yarn/patches/left-pad.patch",
+                "react": "18.2.0"
+            }
+        });
+        assert!(PackageManager::Berry.prune_patched_packages(&mut berry, &patches));
+        assert_eq!(
+            berry["resolutions"],
+            serde_json::json!({
+                "lodash@^4.17.21": "patch:lodash@npm%3A4.17.21#./.yarn/patches/lodash.patch",
+                "react": "18.2.0"
+            })
+        );
+
+        let mut pnpm = serde_json::json!({
+            "pnpm": { "patchedDependencies": { "lodash@4.17.21": ".yarn/patches/lodash.patch" } }
+        });
+        assert!(!PackageManager::Pnpm.prune_patched_packages(&mut pnpm, &patches));
+        assert!(!PackageManager::Npm.prune_patched_packages(&mut berry, &[]));
+    }
 }
@@ -0,0 +1,193 @@
+use globwalk::WalkType;
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+use turborepo_cache::{async_cache::AsyncCache, CacheError, CacheResponse};
+
+use crate::{
+    opts::RunCacheOpts,
+    package_graph::Entry,
+    task_graph::{TaskDefinitionHashable, TaskOutputMode, TaskOutputs},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Cache(#[from] CacheError),
+    #[error("unable to expand task outputs: {0}")]
+    Globwalk(#[from] globwalk::WalkError),
+    #[error(transparent)]
+    Path(#[from] turbopath::PathError),
+}
+
+/// The cache as seen by the tasks of a run, taking `--force` and `--no-cache`
+/// into account
+pub struct RunCache<'a> {
+    cache: &'a AsyncCache,
+    repo_root: &'a AbsoluteSystemPath,
+    reads_disabled: bool,
+    writes_disabled: bool,
+    task_output_mode_override: Option<TaskOutputMode>,
+}
+
+impl<'a> RunCache<'a> {
+    pub fn new(
+        cache: &'a AsyncCache,
+        repo_root: &'a AbsoluteSystemPath,
+        opts: &RunCacheOpts,
+    ) -> Self {
+        Self {
+            cache,
+            repo_root,
+            reads_disabled: opts.skip_reads,
+            writes_disabled: opts.skip_writes,
+            task_output_mode_override: opts.task_output_mode_override.clone(),
+        }
+    }
+
+    pub fn task_cache(
+        &self,
+        task_definition: &TaskDefinitionHashable,
+        workspace_info: &Entry,
+        task: &str,
+        hash: &str,
+    ) -> TaskCache<'_> {
+        let workspace_dir = self.repo_root.resolve(workspace_info.package_path());
+        let log_file = workspace_dir.join_components(&[".turbo", &format!("turbo-{}.log", task)]);
+        // The log always gets cached so that it can be replayed on a hit
+        let mut outputs = task_definition.outputs.clone();
+        outputs
+            .inclusions
+            .push(format!(".turbo/turbo-{}.log", task));
+
+        TaskCache {
+            run_cache: self,
+            // Persistent tasks never finish, restoring them would mean never
+            // starting them
+            caching_disabled: !task_definition.cache || task_definition.persistent,
+            output_mode: self
+                .task_output_mode_override
+                .clone()
+                .unwrap_or_else(|| task_definition.output_mode.clone()),
+            hash: hash.to_string(),
+            workspace_dir,
+            outputs,
+            log_file,
+        }
+    }
+}
+
+/// Restores and saves the outputs of a single task
+pub struct TaskCache<'a> {
+    run_cache: &'a RunCache<'a>,
+    caching_disabled: bool,
+    output_mode: TaskOutputMode,
+    hash: String,
+    workspace_dir: AbsoluteSystemPathBuf,
+    // Relative to the workspace
+    outputs: TaskOutputs,
+    log_file: AbsoluteSystemPathBuf,
+}
+
+impl<'a> TaskCache<'a> {
+    pub fn log_file(&self) -> &AbsoluteSystemPath {
+        &self.log_file
+    }
+
+    /// How much of the task's output gets shown, `--output-logs` takes
+    /// precedence over the task's `outputMode`
+    pub fn output_mode(&self) -> &TaskOutputMode {
+        &self.output_mode
+    }
+
+    /// Restores the task's outputs, returning `None` on a miss or if the
+    /// cache can't be read from for this task
+    pub async fn restore_outputs(&self) -> Result<Option<CacheResponse>, Error> {
+        if self.caching_disabled || self.run_cache.reads_disabled {
+            return Ok(None);
+        }
+
+        match self
+            .run_cache
+            .cache
+            .fetch(self.run_cache.repo_root, &self.hash)
+            .await
+        {
+            Ok((response, _)) => Ok(Some(response)),
+            Err(CacheError::CacheMiss(_)) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Saves the outputs of a task that finished successfully, along with how
+    /// long it took in milliseconds
+    pub async fn save_outputs(&self, duration: u32) -> Result<(), Error> {
+        if self.caching_disabled || self.run_cache.writes_disabled {
+            return Ok(());
+        }
+
+        let mut files = globwalk::globwalk(
+            &self.workspace_dir,
+            &self.outputs.inclusions,
+            &self.outputs.exclusions,
+            WalkType::Files,
+        )?
+        .iter()
+        .map(|file| self.run_cache.repo_root.anchor(file))
+        .collect::<Result<Vec<AnchoredSystemPathBuf>, _>>()?;
+        files.sort();
+
+        self.run_cache
+            .cache
+            .put(
+                self.run_cache.repo_root.to_owned(),
+                self.hash.clone(),
+                files,
+                duration,
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::tempdir;
+    use test_case::test_case;
+    use turborepo_api_client::APIClient;
+    use turborepo_cache::CacheOpts;
+
+    use super::*;
+
+    #[test_case(None, TaskOutputMode::New ; "task output mode")]
+    #[test_case(Some(TaskOutputMode::Hash), TaskOutputMode::Hash ; "overridden")]
+    #[tokio::test]
+    async fn test_task_output_mode(
+        task_output_mode_override: Option<TaskOutputMode>,
+        expected: TaskOutputMode,
+    ) -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::try_from(dir.path())?;
+        let cache = AsyncCache::new(
+            &CacheOpts::default(),
+            &repo_root,
+            APIClient::new("http://localhost", 0, "test", false)?,
+            None,
+        )?;
+        let run_cache = RunCache::new(
+            &cache,
+            &repo_root,
+            &RunCacheOpts {
+                task_output_mode_override,
+                ..Default::default()
+            },
+        );
+
+        let task_definition = TaskDefinitionHashable {
+            output_mode: TaskOutputMode::New,
+            ..Default::default()
+        };
+        let task_cache = run_cache.task_cache(&task_definition, &Entry::default(), "build", "hash");
+        assert_eq!(task_cache.output_mode(), &expected);
+
+        Ok(())
+    }
+}
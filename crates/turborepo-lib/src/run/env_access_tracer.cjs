@@ -0,0 +1,71 @@
+// Records the names of the environment variables a task reads. turbo loads
+// this into node processes through `NODE_OPTIONS=--require` and points
+// TURBO_ENV_ACCESS_LOG at the file to record to. Every variable that gets
+// read or checked for is appended to it once per process, one name per line.
+//
+// The package manager running the task loads this as well. It's told apart
+// from the task by `npm_lifecycle_event`, which turbo unsets and package
+// managers set for the scripts they run.
+"use strict";
+
+const fs = require("fs");
+
+const logFile = process.env.TURBO_ENV_ACCESS_LOG;
+if (logFile && process.env.npm_lifecycle_event !== undefined) {
+  const env = process.env;
+  const seen = new Set();
+  // Spawning a process copies every variable, which isn't a read of them
+  let spawning = 0;
+
+  const record = (name) => {
+    if (spawning > 0 || typeof name !== "string" || seen.has(name)) {
+      return;
+    }
+    seen.add(name);
+    try {
+      fs.appendFileSync(logFile, `${name}\n`);
+    } catch (_) {
+      // Tracing must never break the task
+    }
+  };
+
+  process.env = new Proxy(env, {
+    get(target, name) {
+      record(name);
+      return target[name];
+    },
+    has(target, name) {
+      record(name);
+      return name in target;
+    },
+    set(target, name, value) {
+      target[name] = value;
+      return true;
+    },
+    deleteProperty(target, name) {
+      delete target[name];
+      return true;
+    },
+  });
+
+  const childProcess = require("child_process");
+  for (const method of [
+    "spawn",
+    "spawnSync",
+    "exec",
+    "execSync",
+    "execFile",
+    "execFileSync",
+    "fork",
+  ]) {
+    const original = childProcess[method];
+    childProcess[method] = function (...args) {
+      spawning++;
+      try {
+        return original.apply(this, args);
+      } finally {
+        spawning--;
+      }
+    };
+  }
+}
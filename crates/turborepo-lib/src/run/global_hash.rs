@@ -1,13 +1,26 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use anyhow::Result;
-use turbopath::{AbsoluteSystemPath, RelativeUnixPathBuf};
-use turborepo_env::{BySource, DetailedMap, EnvironmentVariableMap};
-use turborepo_lockfiles::Lockfile;
+use globwalk::WalkType;
+use tracing::debug;
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf, RelativeUnixPathBuf};
+use turborepo_env::{BySource, DetailedMap, EnvironmentVariableMap, DEFAULT_ENV_VARS};
+use turborepo_scm::SCM;
 
-use crate::{cli::EnvMode, package_json::PackageJson, package_manager::PackageManager, ui::UI};
+use crate::{
+    cli::EnvMode,
+    hash::{GlobalHashable, TurboHash},
+    package_manager::PackageManager,
+    run::{
+        summary::{GlobalEnvConfiguration, GlobalEnvVarSummary, GlobalHashSummary},
+        task_hash::get_external_deps_hash,
+    },
+    ui::UI,
+};
 
-static DEFAULT_ENV_VARS: [&str; 1] = ["VERCEL_ANALYTICS_ID"];
+const GLOBAL_CACHE_KEY: &str = "You don't understand! I coulda had class. I coulda been a \
+                                contender. I could've been somebody, instead of a bum, which is \
+                                what I am.";
 
 #[derive(Default)]
 pub struct GlobalHashableInputs {
@@ -17,31 +30,32 @@ pub struct GlobalHashableInputs {
     env: Vec<String>,
     // Only Option to allow #[derive(Default)]
     resolved_env_vars: Option<DetailedMap>,
-    pass_through_env: Vec<String>,
+    pass_through_env: Option<Vec<String>>,
     env_mode: EnvMode,
     framework_inference: bool,
     dot_env: Vec<RelativeUnixPathBuf>,
 }
 
 #[allow(clippy::too_many_arguments)]
-pub fn get_global_hash_inputs<L: ?Sized + Lockfile>(
+pub fn get_global_hash_inputs(
     _ui: &UI,
-    _root_path: &AbsoluteSystemPath,
-    _root_package_json: &PackageJson,
-    _package_manager: &PackageManager,
-    _lockfile: Option<&L>,
-    _global_file_dependencies: Vec<String>,
+    root_path: &AbsoluteSystemPath,
+    root_external_dependencies: Option<&HashSet<turborepo_lockfiles::Package>>,
+    package_manager: &PackageManager,
+    has_lockfile: bool,
+    global_file_dependencies: &[String],
     env_at_execution_start: &EnvironmentVariableMap,
-    global_env: Vec<String>,
-    _global_pass_through_env: Vec<String>,
-    _env_mode: EnvMode,
-    _framework_inference: bool,
-    _dot_env: Vec<RelativeUnixPathBuf>,
+    global_env: &[String],
+    global_pass_through_env: Option<&[String]>,
+    env_mode: EnvMode,
+    framework_inference: bool,
+    dot_env: &[RelativeUnixPathBuf],
+    scm: &SCM,
 ) -> Result<GlobalHashableInputs> {
     let default_env_var_map = env_at_execution_start.from_wildcards(&DEFAULT_ENV_VARS[..])?;
 
     let user_env_var_set =
-        env_at_execution_start.wildcard_map_from_wildcards_unresolved(&global_env)?;
+        env_at_execution_start.wildcard_map_from_wildcards_unresolved(global_env)?;
 
     let mut all_env_var_map = EnvironmentVariableMap::default();
     all_env_var_map.union(&user_env_var_set.inclusions);
@@ -64,8 +78,249 @@ pub fn get_global_hash_inputs<L: ?Sized + Lockfile>(
         },
     };
 
+    debug!(
+        "global hash env vars {:?}",
+        global_hashable_env_vars.all.keys().collect::<Vec<_>>()
+    );
+
+    let mut global_deps = HashSet::new();
+    if !global_file_dependencies.is_empty() {
+        let ignores = package_manager.get_workspace_ignores(root_path)?;
+        let files = globwalk::globwalk(
+            root_path,
+            global_file_dependencies,
+            &ignores,
+            WalkType::Files,
+        )?;
+        global_deps.extend(files);
+    }
+
+    // Without lockfile information the package.json and lockfile are the best we
+    // can do to track changes to external dependencies
+    if !has_lockfile {
+        global_deps.insert(root_path.join_component("package.json"));
+        let lockfile_path = root_path.join_component(package_manager.lockfile_name());
+        if lockfile_path.exists() {
+            global_deps.insert(lockfile_path);
+        }
+    }
+
+    let global_deps_paths = global_deps
+        .iter()
+        .map(|path| root_path.anchor(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut global_file_hash_map = scm.hash_files(root_path, global_deps_paths.into_iter())?;
+
+    // .env files aren't globs, they get hashed separately if they exist
+    if !dot_env.is_empty() {
+        let dot_env_paths = dot_env
+            .iter()
+            .map(|path| AnchoredSystemPathBuf::from_raw(path.as_str()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let dot_env_object = scm.hash_existing_of(root_path, dot_env_paths.into_iter())?;
+        global_file_hash_map.extend(dot_env_object);
+    }
+
     Ok(GlobalHashableInputs {
+        global_cache_key: GLOBAL_CACHE_KEY,
+        global_file_hash_map,
+        root_external_deps_hash: get_external_deps_hash(root_external_dependencies),
+        env: global_env.to_vec(),
         resolved_env_vars: Some(global_hashable_env_vars),
-        ..GlobalHashableInputs::default()
+        pass_through_env: global_pass_through_env.map(|env| env.to_vec()),
+        env_mode,
+        framework_inference,
+        dot_env: dot_env.to_vec(),
     })
 }
+
+impl GlobalHashableInputs {
+    pub fn resolved_env_vars(&self) -> Option<&DetailedMap> {
+        self.resolved_env_vars.as_ref()
+    }
+
+    /// The global environment variables that every task gets in strict mode:
+    /// the hashed ones and the `globalPassThroughEnv` ones
+    pub fn strict_env(
+        &self,
+        env_at_execution_start: &EnvironmentVariableMap,
+    ) -> Result<EnvironmentVariableMap, regex::Error> {
+        let mut env = env_at_execution_start
+            .from_wildcards(self.pass_through_env.as_deref().unwrap_or_default())?;
+        if let Some(resolved_env_vars) = &self.resolved_env_vars {
+            env.union(&resolved_env_vars.all);
+        }
+        Ok(env)
+    }
+
+    /// The inputs as they get reported in the run summary. Environment
+    /// variable values are hashed so they don't leak into the summary.
+    pub fn summary(
+        &self,
+        env_at_execution_start: &EnvironmentVariableMap,
+    ) -> Result<GlobalHashSummary, regex::Error> {
+        let resolved_pass_through_env_vars = env_at_execution_start
+            .from_wildcards(self.pass_through_env.as_deref().unwrap_or_default())?;
+        let (configured, inferred) = self
+            .resolved_env_vars
+            .as_ref()
+            .map(|vars| {
+                (
+                    vars.by_source.explicit.to_secret_hashable(),
+                    vars.by_source.matching.to_secret_hashable(),
+                )
+            })
+            .unwrap_or_default();
+
+        Ok(GlobalHashSummary {
+            root_key: self.global_cache_key,
+            files: self
+                .global_file_hash_map
+                .iter()
+                .map(|(path, hash)| (path.clone(), hash.clone()))
+                .collect(),
+            hash_of_external_dependencies: self.root_external_deps_hash.clone(),
+            global_dot_env: self.dot_env.clone(),
+            environment_variables: GlobalEnvVarSummary {
+                specified: GlobalEnvConfiguration {
+                    env: self.env.clone(),
+                    pass_through_env: self.pass_through_env.clone(),
+                },
+                configured,
+                inferred,
+                pass_through: resolved_pass_through_env_vars.to_secret_hashable(),
+            },
+        })
+    }
+
+    pub fn calculate_global_hash(&self) -> String {
+        let mut env_mode = self.env_mode;
+        let mut pass_through_env = self.pass_through_env.as_deref();
+        match env_mode {
+            // In infer mode any passThroughEnv config, even an empty list, means
+            // the whole object gets hashed so changes to that config are detected
+            EnvMode::Infer if pass_through_env.is_some() => env_mode = EnvMode::Strict,
+            // Pass through env vars are ignored entirely in loose mode
+            EnvMode::Loose => pass_through_env = None,
+            // Missing and empty pass through env vars get rendered the same
+            EnvMode::Infer | EnvMode::Strict => {}
+        }
+
+        GlobalHashable {
+            global_cache_key: self.global_cache_key,
+            global_file_hash_map: &self.global_file_hash_map,
+            root_external_deps_hash: &self.root_external_deps_hash,
+            env: &self.env,
+            resolved_env_vars: self
+                .resolved_env_vars
+                .as_ref()
+                .map(|vars| vars.all.to_hashable())
+                .unwrap_or_default(),
+            pass_through_env,
+            env_mode,
+            framework_inference: self.framework_inference,
+            dot_env: &self.dot_env,
+        }
+        .hash()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn inputs(env_mode: EnvMode, pass_through_env: Option<Vec<String>>) -> GlobalHashableInputs {
+        GlobalHashableInputs {
+            global_cache_key: GLOBAL_CACHE_KEY,
+            global_file_hash_map: [(
+                RelativeUnixPathBuf::new("package.json").unwrap(),
+                "2b4a2b6c5f4a37c4e4c9a8e1ea7d7cd5c4a2d1f5".to_string(),
+            )]
+            .into_iter()
+            .collect(),
+            root_external_deps_hash: "".to_string(),
+            env: vec!["API_URL".to_string()],
+            resolved_env_vars: Some(DetailedMap {
+                all: EnvironmentVariableMap::from(
+                    [("API_URL".to_string(), "https://example.com".to_string())]
+                        .into_iter()
+                        .collect::<HashMap<_, _>>(),
+                ),
+                by_source: BySource {
+                    explicit: EnvironmentVariableMap::default(),
+                    matching: EnvironmentVariableMap::default(),
+                },
+            }),
+            pass_through_env,
+            env_mode,
+            framework_inference: true,
+            dot_env: vec![],
+        }
+    }
+
+    // The same inputs and hashes as `Test_HashGlobalGolden` in
+    // cli/internal/fs/hash_test.go, which checks them against Go's
+    // `HashGlobal`. Changing any of these invalidates every existing cache
+    // entry.
+    #[test_case(EnvMode::Infer, None, "51608f07a0a97cd6" ; "infer")]
+    #[test_case(EnvMode::Infer, Some(vec![]), "2cd3c15f22a1535b" ; "infer with pass through env is strict")]
+    #[test_case(EnvMode::Strict, None, "2cd3c15f22a1535b" ; "strict collapses missing pass through env")]
+    #[test_case(EnvMode::Loose, Some(vec!["AWS_SECRET_KEY".to_string()]), "29ae6014414527bf" ; "loose ignores pass through env")]
+    fn test_global_hash(env_mode: EnvMode, pass_through_env: Option<Vec<String>>, expected: &str) {
+        assert_eq!(
+            inputs(env_mode, pass_through_env).calculate_global_hash(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_strict_env() {
+        let env = EnvironmentVariableMap::from(
+            [
+                ("AWS_SECRET_KEY", "hunter2"),
+                ("AWS_REGION", "us-east-1"),
+                ("HOME", "/home/turbo"),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>(),
+        );
+        let strict_env = inputs(EnvMode::Strict, Some(vec!["AWS_*".to_string()]))
+            .strict_env(&env)
+            .unwrap();
+
+        let mut names = strict_env.keys().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["API_URL", "AWS_REGION", "AWS_SECRET_KEY"]);
+    }
+
+    #[test]
+    fn test_global_hash_summary() {
+        let env = EnvironmentVariableMap::from(
+            [("AWS_SECRET_KEY".to_string(), "hunter2".to_string())]
+                .into_iter()
+                .collect::<HashMap<_, _>>(),
+        );
+        let summary = inputs(EnvMode::Strict, Some(vec!["AWS_SECRET_KEY".to_string()]))
+            .summary(&env)
+            .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&summary.environment_variables).unwrap(),
+            serde_json::json!({
+                "specified": { "env": ["API_URL"], "passThroughEnv": ["AWS_SECRET_KEY"] },
+                "configured": [],
+                "inferred": [],
+                "passthrough": [
+                    "AWS_SECRET_KEY=f52fbd32b2b3b86ff88ef6c490628285f482af15ddcb29541f94bcf526a3f6c7"
+                ],
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(&summary.files).unwrap(),
+            serde_json::json!({ "package.json": "2b4a2b6c5f4a37c4e4c9a8e1ea7d7cd5c4a2d1f5" })
+        );
+    }
+}
@@ -1,30 +1,75 @@
 #![allow(dead_code)]
 
+mod cache;
 mod global_hash;
 mod scope;
+mod summary;
+mod task_env;
+mod task_hash;
 pub mod task_id;
+mod visitor;
+mod watch;
 
-use anyhow::{Context as ErrorContext, Result};
-use tracing::{debug, info};
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Context as ErrorContext, Result};
+use chrono::Local;
+use itertools::Itertools;
+use tracing::{debug, info, warn};
+use turborepo_cache::async_cache::AsyncCache;
 use turborepo_env::EnvironmentVariableMap;
 use turborepo_scm::SCM;
 
+pub use self::task_hash::{framework_env_wildcards, task_env_mode, task_env_vars, workspace_name};
 use crate::{
-    commands::CommandBase, config::TurboJson, daemon::DaemonConnector, manager::Manager,
-    opts::Opts, package_graph::PackageGraph, package_json::PackageJson,
-    run::global_hash::get_global_hash_inputs,
+    cli::EnvMode,
+    commands::CommandBase,
+    config::TurboJson,
+    daemon::{DaemonClient, DaemonConnector},
+    engine::{Engine, ExecutionOptions},
+    manager::Manager,
+    opts::Opts,
+    package_graph::{PackageGraph, WorkspaceName},
+    package_json::PackageJson,
+    run::{
+        cache::RunCache,
+        global_hash::get_global_hash_inputs,
+        summary::RunTracker,
+        task_hash::{PackageInputsHashes, TaskHasher},
+        visitor::Visitor,
+        watch::PersistentTasks,
+    },
 };
 
+/// Any global passThroughEnv config opts the whole run into strict mode
+pub fn global_env_mode(env_mode: EnvMode, global_pass_through_env: Option<&[String]>) -> EnvMode {
+    match env_mode {
+        EnvMode::Infer if global_pass_through_env.is_some() => EnvMode::Strict,
+        env_mode => env_mode,
+    }
+}
+
 #[derive(Debug)]
 pub struct Run {
     base: CommandBase,
     processes: Manager,
+    // Set in watch mode, only these tasks get executed. The rest of the graph
+    // is still walked so that tasks run in dependency order.
+    affected_tasks: Option<HashSet<String>>,
+    // Set in watch mode, persistent tasks are started here instead of in
+    // `processes` so that they outlive the run
+    persistent_tasks: Option<PersistentTasks>,
 }
 
 impl Run {
     pub fn new(base: CommandBase) -> Self {
         let processes = Manager::new();
-        Self { base, processes }
+        Self {
+            base,
+            processes,
+            affected_tasks: None,
+            persistent_tasks: None,
+        }
     }
 
     fn targets(&self) -> &[String] {
@@ -35,8 +80,30 @@ impl Run {
         self.base.args().try_into()
     }
 
-    pub async fn run(&mut self) -> Result<()> {
-        let _start_at = std::time::Instant::now();
+    // A warm daemon already has the package graph, if it can't give it to us
+    // we build it ourselves
+    async fn package_graph_from_daemon(
+        &self,
+        client: &mut DaemonClient<DaemonConnector>,
+    ) -> Option<PackageGraph> {
+        let snapshot = match client.get_package_graph().await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                debug!("failed to get package graph from daemon: {}", e);
+                return None;
+            }
+        };
+        match PackageGraph::from_snapshot(&self.base.repo_root, snapshot) {
+            Ok(pkg_dep_graph) => Some(pkg_dep_graph),
+            Err(e) => {
+                debug!("invalid package graph from daemon: {}", e);
+                None
+            }
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<i32> {
+        let started_at = Local::now();
         let package_json_path = self.base.repo_root.join_component("package.json");
         let root_package_json =
             PackageJson::load(&package_json_path).context("failed to read package.json")?;
@@ -46,19 +113,6 @@ impl Run {
 
         let is_single_package = opts.run_opts.single_package;
 
-        let pkg_dep_graph = PackageGraph::builder(&self.base.repo_root, root_package_json.clone())
-            .with_single_package_mode(opts.run_opts.single_package)
-            .build()?;
-
-        let root_turbo_json =
-            TurboJson::load(&self.base.repo_root, &root_package_json, is_single_package)?;
-
-        opts.cache_opts.remote_cache_opts = root_turbo_json.remote_cache_options.clone();
-
-        if opts.run_opts.experimental_space_id.is_none() {
-            opts.run_opts.experimental_space_id = root_turbo_json.space_id.clone();
-        }
-
         // There's some warning handling code in Go that I'm ignoring
         if self.base.ui.is_ci() && !opts.run_opts.no_daemon {
             info!("skipping turbod since we appear to be in a non-interactive context");
@@ -75,45 +129,294 @@ impl Run {
             opts.runcache_opts.output_watcher = Some(client);
         }
 
+        let daemon_pkg_dep_graph = match opts.runcache_opts.output_watcher.as_mut() {
+            Some(client) if !is_single_package => self.package_graph_from_daemon(client).await,
+            _ => None,
+        };
+        let pkg_dep_graph = match daemon_pkg_dep_graph {
+            Some(pkg_dep_graph) => pkg_dep_graph,
+            None => PackageGraph::builder(&self.base.repo_root, root_package_json.clone())
+                .with_single_package_mode(opts.run_opts.single_package)
+                .build()?,
+        };
+
+        let root_turbo_json =
+            TurboJson::load(&self.base.repo_root, &root_package_json, is_single_package)?;
+
+        opts.cache_opts.remote_cache_opts = root_turbo_json.remote_cache_options.clone();
+
+        let api_auth = self.base.api_auth()?;
+        opts.cache_opts.skip_remote = opts.cache_opts.skip_remote || api_auth.is_none();
+        let api_client = self.base.api_client()?;
+        let cache = AsyncCache::new(&opts.cache_opts, &self.base.repo_root, api_client, api_auth)?;
+
+        if opts.run_opts.experimental_space_id.is_none() {
+            opts.run_opts.experimental_space_id = root_turbo_json.space_id.clone();
+        }
+
         pkg_dep_graph
             .validate()
             .context("Invalid package dependency graph")?;
 
         let scm = SCM::new(&self.base.repo_root);
 
-        let _filtered_pkgs =
-            scope::resolve_packages(&opts.scope_opts, &self.base, &pkg_dep_graph, &scm)?;
-
-        // TODO: Add this back once scope/filter is implemented.
-        //       Currently this code has lifetime issues
-        // if filtered_pkgs.len() != pkg_dep_graph.len() {
-        //     for target in targets {
-        //         let key = task_id::root_task_id(target);
-        //         if pipeline.contains_key(&key) {
-        //             filtered_pkgs.insert(task_id::ROOT_PKG_NAME.to_string());
-        //             break;
-        //         }
-        //     }
-        // }
+        let filtered_pkgs =
+            self.filtered_packages(&opts, &pkg_dep_graph, &root_turbo_json, &scm)?;
+
         let env_at_execution_start = EnvironmentVariableMap::infer();
 
-        let _global_hash_inputs = get_global_hash_inputs(
+        let root_workspace = pkg_dep_graph
+            .workspace_info(&WorkspaceName::Root)
+            .expect("package graph always contains the root workspace");
+        let global_hash_inputs = get_global_hash_inputs(
             &self.base.ui,
             &self.base.repo_root,
-            pkg_dep_graph.root_package_json(),
+            root_workspace.transitive_dependencies(),
             pkg_dep_graph.package_manager(),
-            pkg_dep_graph.lockfile(),
-            // TODO: Fill in these vec![] once turbo.json is ported
-            vec![],
+            pkg_dep_graph.has_lockfile(),
+            &root_turbo_json.global_deps,
             &env_at_execution_start,
-            vec![],
-            vec![],
+            &root_turbo_json.global_env,
+            root_turbo_json.global_pass_through_env.as_deref(),
             opts.run_opts.env_mode,
             opts.run_opts.framework_inference,
-            vec![],
-        )?;
+            &root_turbo_json.global_dot_env,
+            &scm,
+        )
+        .context("failed to collect global hash inputs")?;
+        let global_hash = global_hash_inputs.calculate_global_hash();
+        debug!("global hash: {}", global_hash);
+        let global_hash_summary = global_hash_inputs
+            .summary(&env_at_execution_start)
+            .context("failed to summarize global hash inputs")?;
+        let global_env = global_hash_inputs
+            .strict_env(&env_at_execution_start)
+            .context("failed to resolve global environment variables")?;
 
-        Ok(())
+        let packages = filtered_pkgs
+            .iter()
+            .map(|workspace| workspace.to_string())
+            .sorted()
+            .collect();
+
+        let engine = Self::build_engine(&opts, &pkg_dep_graph, &root_turbo_json, filtered_pkgs)?;
+
+        let package_inputs_hashes = PackageInputsHashes::calculate_file_hashes(
+            &scm,
+            &engine,
+            &pkg_dep_graph,
+            &self.base.repo_root,
+            opts.runcache_opts.output_watcher.as_mut(),
+        )
+        .await
+        .context("error hashing package files")?;
+
+        let global_env_mode = global_env_mode(
+            opts.run_opts.env_mode,
+            root_turbo_json.global_pass_through_env.as_deref(),
+        );
+        let run_tracker = RunTracker::new(
+            started_at,
+            &self.base.repo_root,
+            &opts,
+            self.base.version(),
+            packages,
+            global_env_mode,
+            global_hash_summary,
+            &env_at_execution_start,
+            &scm,
+        );
+
+        let task_hasher = TaskHasher::new(
+            package_inputs_hashes,
+            &env_at_execution_start,
+            &global_hash,
+            global_env_mode,
+            opts.run_opts.framework_inference,
+        );
+
+        let run_cache = RunCache::new(&cache, &self.base.repo_root, &opts.runcache_opts);
+        let env_access_tracer = match opts.run_opts.env_access_tracing {
+            true => match task_env::install_node_tracer() {
+                Ok(tracer) => Some(tracer),
+                Err(err) => {
+                    debug!("unable to install env access tracer: {}", err);
+                    None
+                }
+            },
+            false => None,
+        };
+        let visitor = Visitor {
+            repo_root: &self.base.repo_root,
+            package_graph: &pkg_dep_graph,
+            engine: &engine,
+            task_hasher: &task_hasher,
+            run_cache: &run_cache,
+            global_env: &global_env,
+            run_tracker: &run_tracker,
+            manager: self.processes.clone(),
+            tasks: opts.run_opts.tasks,
+            pass_through_args: opts.run_opts.passthrough_args,
+            log_prefix: opts.run_opts.log_prefix,
+            is_single_package,
+            continue_on_error: opts.run_opts.continue_on_error,
+            dry_run: opts.run_opts.dry_run,
+            env_access_tracer: env_access_tracer.as_deref(),
+            affected_tasks: self.affected_tasks.as_ref(),
+            persistent_tasks: self.persistent_tasks.as_ref(),
+        };
+
+        if opts.run_opts.dry_run {
+            // Nothing gets executed, so there's no point walking the graph
+            // concurrently
+            let errors = engine
+                .execute(
+                    ExecutionOptions {
+                        parallel: false,
+                        concurrency: 1,
+                        continue_on_error: false,
+                    },
+                    |task_id| visitor.visit(task_id),
+                )
+                .await;
+            if !errors.is_empty() {
+                for err in &errors {
+                    eprintln!("{}", err);
+                }
+                return Err(anyhow!("errors occurred during dry-run graph traversal"));
+            }
+
+            run_tracker
+                .finish_dry_run(
+                    &cache,
+                    &pkg_dep_graph,
+                    opts.run_opts.dry_run_json,
+                    &self.base.ui,
+                )
+                .await?;
+            return Ok(0);
+        }
+
+        // Kill any running tasks if we get interrupted
+        let manager = self.processes.clone();
+        let signal_handler = tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                manager.stop();
+            }
+        });
+
+        let errors = engine
+            .execute(
+                ExecutionOptions {
+                    parallel: opts.run_opts.parallel,
+                    concurrency: opts.run_opts.concurrency as usize,
+                    continue_on_error: opts.run_opts.continue_on_error,
+                },
+                |task_id| visitor.visit(task_id),
+            )
+            .await;
+        signal_handler.abort();
+
+        // Don't exit until every pending upload has reached the remote cache
+        if let Err(err) = cache.wait().await {
+            warn!("failed to finish uploading to the remote cache: {}", err);
+        }
+
+        let exit_code = match errors.iter().map(|err| err.exit_code()).max() {
+            Some(exit_code) => exit_code,
+            // Stopping without a failed task means we were interrupted
+            None if self.processes.is_closing() => 1,
+            None => 0,
+        };
+
+        run_tracker.finish(exit_code, &self.base.ui);
+
+        Ok(exit_code)
+    }
+
+    /// Builds the package graph and the task graph of the run without
+    /// executing anything
+    pub async fn task_graph(&self) -> Result<(PackageGraph, Engine)> {
+        let package_json_path = self.base.repo_root.join_component("package.json");
+        let root_package_json =
+            PackageJson::load(&package_json_path).context("failed to read package.json")?;
+        let opts = self.opts()?;
+        let is_single_package = opts.run_opts.single_package;
+
+        let pkg_dep_graph = PackageGraph::builder(&self.base.repo_root, root_package_json.clone())
+            .with_single_package_mode(is_single_package)
+            .build()?;
+        pkg_dep_graph
+            .validate()
+            .context("Invalid package dependency graph")?;
+        let root_turbo_json =
+            TurboJson::load(&self.base.repo_root, &root_package_json, is_single_package)?;
+
+        let scm = SCM::new(&self.base.repo_root);
+        let filtered_pkgs =
+            self.filtered_packages(&opts, &pkg_dep_graph, &root_turbo_json, &scm)?;
+        let engine = Self::build_engine(&opts, &pkg_dep_graph, &root_turbo_json, filtered_pkgs)?;
+
+        Ok((pkg_dep_graph, engine))
+    }
+
+    fn filtered_packages(
+        &self,
+        opts: &Opts,
+        pkg_dep_graph: &PackageGraph,
+        root_turbo_json: &TurboJson,
+        scm: &SCM,
+    ) -> Result<HashSet<WorkspaceName>> {
+        let mut filtered_pkgs =
+            scope::resolve_packages(&opts.scope_opts, &self.base, pkg_dep_graph, scm)?;
+
+        // The root workspace is only in scope if one of the requested tasks is
+        // explicitly configured to run there
+        if filtered_pkgs.len() != pkg_dep_graph.len() {
+            for target in self.targets() {
+                let key = task_id::root_task_id(target);
+                if root_turbo_json.pipeline.contains_key(&key) {
+                    filtered_pkgs.insert(WorkspaceName::Root);
+                    break;
+                }
+            }
+        }
+
+        Ok(filtered_pkgs)
+    }
+
+    fn build_engine(
+        opts: &Opts,
+        pkg_dep_graph: &PackageGraph,
+        root_turbo_json: &TurboJson,
+        filtered_pkgs: HashSet<WorkspaceName>,
+    ) -> Result<Engine> {
+        let workspace_turbo_jsons = match opts.run_opts.single_package {
+            true => HashMap::new(),
+            false => pkg_dep_graph
+                .workspace_turbo_jsons()
+                .context("failed to load workspace turbo.json")?,
+        };
+        let engine = Engine::builder(pkg_dep_graph, &root_turbo_json.pipeline)
+            .with_workspace_turbo_jsons(&workspace_turbo_jsons)
+            .with_workspaces(filtered_pkgs.into_iter().sorted().collect())
+            .with_tasks(opts.run_opts.tasks.to_vec())
+            .with_tasks_only(opts.run_opts.only)
+            .with_parallel(opts.run_opts.parallel)
+            .build()?;
+
+        if let Err(errors) = engine.validate(
+            pkg_dep_graph,
+            opts.run_opts.concurrency,
+            opts.run_opts.parallel,
+        ) {
+            return Err(anyhow!(
+                "Invalid task configuration:\n{}",
+                errors.iter().map(|err| err.to_string()).join("\n")
+            ));
+        }
+
+        Ok(engine)
     }
 }
 
@@ -161,6 +464,153 @@ mod test {
 
         let base = CommandBase::new(args, repo_root, get_version(), ui)?;
         let mut run = Run::new(base);
-        run.run().await
+        run.run().await?;
+        Ok(())
+    }
+
+    // Runs `turbo run build --summarize` and returns the summary it wrote
+    async fn run_build(
+        repo_root: &AbsoluteSystemPathBuf,
+        run_args: RunArgs,
+    ) -> Result<serde_json::Value> {
+        let runs_dir = repo_root.join_components(&[".turbo", "runs"]);
+        if runs_dir.exists() {
+            std::fs::remove_dir_all(&runs_dir)?;
+        }
+
+        let args = Args {
+            command: Some(Command::Run(Box::new(RunArgs {
+                tasks: vec!["build".to_string()],
+                no_daemon: true,
+                summarize: Some(Some(true)),
+                ..run_args
+            }))),
+            ..Default::default()
+        };
+        let base = CommandBase::new(args, repo_root.clone(), get_version(), UI::new(true))?;
+        assert_eq!(Run::new(base).run().await?, 0);
+
+        let summary = std::fs::read_dir(&runs_dir)?
+            .next()
+            .expect("run summary was written")?;
+        Ok(serde_json::from_str(&std::fs::read_to_string(
+            summary.path(),
+        )?)?)
+    }
+
+    #[tokio::test]
+    async fn test_second_run_is_cached() -> Result<()> {
+        let dir = tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::try_from(dir.path())?;
+        repo_root
+            .join_component("package.json")
+            .create_with_contents(r#"{"name": "root", "workspaces": ["apps/*"]}"#)?;
+        repo_root
+            .join_component("package-lock.json")
+            .create_with_contents("")?;
+        repo_root
+            .join_component("turbo.json")
+            .create_with_contents(r#"{"pipeline": {"build": {"outputs": ["dist/**"]}}}"#)?;
+        // Otherwise the outputs of the first run change the hash of the second
+        repo_root
+            .join_component(".gitignore")
+            .create_with_contents("node_modules\n.turbo\ndist\nbuilds.txt\n")?;
+        let app_dir = repo_root.join_components(&["apps", "my-app"]);
+        app_dir.create_dir_all()?;
+        // Every time the script actually runs it leaves a mark in builds.txt
+        app_dir.join_component("package.json").create_with_contents(
+            r#"{
+                "name": "my-app",
+                "scripts": {
+                    "build": "node -e \"const fs = require('fs'); fs.appendFileSync('../../builds.txt', 'x'); fs.mkdirSync('dist', {recursive: true}); fs.writeFileSync('dist/out.txt', 'built'); console.log('building')\""
+                }
+            }"#,
+        )?;
+        let builds = repo_root.join_component("builds.txt");
+        let output = app_dir.join_components(&["dist", "out.txt"]);
+        let log_file = app_dir.join_components(&[".turbo", "turbo-build.log"]);
+
+        let summary = run_build(&repo_root, RunArgs::default()).await?;
+        assert_eq!(summary["execution"]["cached"], 0);
+        assert_eq!(summary["tasks"][0]["cache"]["status"], "MISS");
+        assert_eq!(std::fs::read_to_string(&builds)?, "x");
+        assert!(std::fs::read_to_string(&log_file)?.ends_with("building\n"));
+
+        // The outputs and the log come back from the cache instead of the
+        // script running again
+        std::fs::remove_dir_all(app_dir.join_component("dist"))?;
+        std::fs::remove_file(&log_file)?;
+        let summary = run_build(&repo_root, RunArgs::default()).await?;
+        assert_eq!(std::fs::read_to_string(&builds)?, "x");
+        assert_eq!(std::fs::read_to_string(&output)?, "built");
+        assert!(std::fs::read_to_string(&log_file)?.ends_with("building\n"));
+
+        assert_eq!(summary["execution"]["cached"], 1);
+        assert_eq!(summary["execution"]["success"], 0);
+        let cache = &summary["tasks"][0]["cache"];
+        assert_eq!(cache["status"], "HIT");
+        assert_eq!(cache["source"], "LOCAL");
+        assert_eq!(cache["local"], true);
+        // How long the first run took
+        assert!(cache["timeSaved"].as_u64().unwrap() > 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_env_access_tracing_warns_about_undeclared_env_vars() -> Result<()> {
+        // Unique to this test, the variable is set for the whole process
+        std::env::set_var("TURBO_TEST_UNDECLARED_ENV_VAR", "secret");
+
+        let dir = tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::try_from(dir.path())?;
+        repo_root
+            .join_component("package.json")
+            .create_with_contents(r#"{"name": "root", "workspaces": ["apps/*"]}"#)?;
+        repo_root
+            .join_component("package-lock.json")
+            .create_with_contents("")?;
+        repo_root
+            .join_component("turbo.json")
+            .create_with_contents(r#"{"pipeline": {"build": {"env": ["API_URL"]}}}"#)?;
+        repo_root
+            .join_component(".gitignore")
+            .create_with_contents("node_modules\n.turbo\n")?;
+        let app_dir = repo_root.join_components(&["apps", "my-app"]);
+        app_dir.create_dir_all()?;
+        app_dir.join_component("package.json").create_with_contents(
+            r#"{
+                "name": "my-app",
+                "scripts": {
+                    "build": "node -e \"console.log(process.env.TURBO_TEST_UNDECLARED_ENV_VAR.length)\""
+                }
+            }"#,
+        )?;
+        let log_file = app_dir.join_components(&[".turbo", "turbo-build.log"]);
+
+        let warning = "WARNING: read environment variables that aren't declared in turbo.json and \
+                       would be unset in strict mode: TURBO_TEST_UNDECLARED_ENV_VAR\n";
+        let run_args = || RunArgs {
+            env_access_tracing: true,
+            ..Default::default()
+        };
+
+        let summary = run_build(&repo_root, run_args()).await?;
+        assert_eq!(summary["tasks"][0]["cache"]["status"], "MISS");
+        let log = std::fs::read_to_string(&log_file)?;
+        assert!(
+            log.ends_with(&format!("6\n{}", warning)),
+            "unexpected log: {}",
+            log
+        );
+
+        // The warning is replayed along with the rest of the log
+        std::fs::remove_file(&log_file)?;
+        let summary = run_build(&repo_root, run_args()).await?;
+        assert_eq!(summary["tasks"][0]["cache"]["status"], "HIT");
+        let log = std::fs::read_to_string(&log_file)?;
+        assert!(log.ends_with(warning), "unexpected log: {}", log);
+
+        Ok(())
     }
 }
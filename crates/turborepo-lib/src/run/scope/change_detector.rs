@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+use tracing::debug;
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf};
+use turborepo_scm::SCM;
+use wax::{Any, Glob, Pattern};
+
+use super::filter::ResolutionError;
+use crate::package_graph::{PackageGraph, WorkspaceName};
+
+/// Finds the workspaces that changed between two git refs
+pub trait GitChangeDetector {
+    fn changed_packages(
+        &self,
+        from_ref: &str,
+        to_ref: &str,
+    ) -> Result<HashSet<WorkspaceName>, ResolutionError>;
+}
+
+// turbo.json and the root package.json affect every workspace
+const DEFAULT_GLOBAL_DEPS: [&str; 2] = ["turbo.json", "package.json"];
+
+pub struct ScopeChangeDetector<'a> {
+    turbo_root: &'a AbsoluteSystemPath,
+    scm: &'a SCM,
+    pkg_graph: &'a PackageGraph,
+    global_deps: Any<'static>,
+    ignore_patterns: Option<Any<'static>>,
+}
+
+impl<'a> ScopeChangeDetector<'a> {
+    pub fn new(
+        turbo_root: &'a AbsoluteSystemPath,
+        scm: &'a SCM,
+        pkg_graph: &'a PackageGraph,
+        global_deps: &[String],
+        ignore_patterns: &[String],
+    ) -> Result<Self, ResolutionError> {
+        let global_deps = compile_globs(
+            &global_deps
+                .iter()
+                .map(String::as_str)
+                .chain(DEFAULT_GLOBAL_DEPS)
+                .collect::<Vec<_>>(),
+        )?;
+        let ignore_patterns = match ignore_patterns.is_empty() {
+            true => None,
+            false => Some(compile_globs(
+                &ignore_patterns
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>(),
+            )?),
+        };
+
+        Ok(Self {
+            turbo_root,
+            scm,
+            pkg_graph,
+            global_deps,
+            ignore_patterns,
+        })
+    }
+
+    fn all_packages(&self) -> HashSet<WorkspaceName> {
+        self.pkg_graph
+            .workspaces()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Finds the workspaces affected by changes to the lockfile since
+    /// `from_ref`. Returns `None` if the changes affect every workspace.
+    fn changed_packages_from_lockfile(
+        &self,
+        from_ref: &str,
+    ) -> anyhow::Result<Option<HashSet<WorkspaceName>>> {
+        if self.pkg_graph.lockfile().is_none() {
+            return Ok(None);
+        }
+
+        let package_manager = self.pkg_graph.package_manager();
+        let lockfile_path = self
+            .turbo_root
+            .join_component(package_manager.lockfile_name());
+        let previous_contents = package_manager
+            .decode_lockfile_contents(self.scm.previous_content(from_ref, &lockfile_path)?)?;
+        let current_contents = package_manager.read_lockfile_contents(self.turbo_root)?;
+        if package_manager.lockfile_global_change(&previous_contents, &current_contents)? {
+            return Ok(None);
+        }
+
+        let root_package_json = self
+            .pkg_graph
+            .package_json(&WorkspaceName::Root)
+            .ok_or_else(|| anyhow!("missing root package.json"))?;
+        let previous_lockfile =
+            package_manager.parse_lockfile(root_package_json, &previous_contents)?;
+        let changed_packages = self
+            .pkg_graph
+            .changed_packages_from_lockfile(previous_lockfile.as_ref())?;
+
+        // Changes to the root's dependencies affect every workspace
+        if changed_packages.contains(&WorkspaceName::Root) {
+            return Ok(None);
+        }
+        Ok(Some(changed_packages))
+    }
+
+    /// Maps changed files to the workspace containing them. Files outside of
+    /// any workspace belong to the root workspace.
+    fn changed_packages_from_files(
+        &self,
+        changed_files: &[AnchoredSystemPathBuf],
+    ) -> HashSet<WorkspaceName> {
+        changed_files
+            .iter()
+            .map(|file| {
+                self.pkg_graph
+                    .workspaces()
+                    .find(|(name, entry)| {
+                        **name != WorkspaceName::Root
+                            && file.as_path().starts_with(entry.package_path().as_path())
+                    })
+                    .map_or(WorkspaceName::Root, |(name, _)| name.clone())
+            })
+            .collect()
+    }
+}
+
+impl<'a> GitChangeDetector for ScopeChangeDetector<'a> {
+    fn changed_packages(
+        &self,
+        from_ref: &str,
+        to_ref: &str,
+    ) -> Result<HashSet<WorkspaceName>, ResolutionError> {
+        // We could filter changed files at the git level, since it's possible
+        // that the changes we're interested in are scoped, but we need to handle
+        // global dependencies changing as well.
+        let changed_files = match from_ref.is_empty() {
+            true => Vec::new(),
+            false => self
+                .scm
+                .changed_files(self.turbo_root, Some(from_ref), to_ref)?
+                .into_iter()
+                .collect(),
+        };
+
+        let changed_files = changed_files
+            .into_iter()
+            .map(|file| Ok((file.to_unix()?, file)))
+            .collect::<Result<Vec<_>, ResolutionError>>()?;
+
+        if changed_files
+            .iter()
+            .any(|(unix_file, _)| self.global_deps.is_match(unix_file.as_str()))
+        {
+            return Ok(self.all_packages());
+        }
+
+        let filtered_files = changed_files
+            .into_iter()
+            .filter(|(unix_file, _)| {
+                self.ignore_patterns
+                    .as_ref()
+                    .map_or(true, |ignore| !ignore.is_match(unix_file.as_str()))
+            })
+            .collect::<Vec<_>>();
+
+        // An ignored lockfile doesn't count as a change
+        let lockfile_name = self.pkg_graph.package_manager().lockfile_name();
+        let lockfile_changed = filtered_files
+            .iter()
+            .any(|(unix_file, _)| unix_file.as_str() == lockfile_name);
+        let filtered_files = filtered_files
+            .into_iter()
+            .map(|(_, file)| file)
+            .collect::<Vec<_>>();
+
+        let mut changed_packages = self.changed_packages_from_files(&filtered_files);
+        if lockfile_changed {
+            match self.changed_packages_from_lockfile(from_ref) {
+                Ok(Some(lockfile_changes)) => changed_packages.extend(lockfile_changes),
+                Ok(None) => return Ok(self.all_packages()),
+                Err(err) => {
+                    debug!("unable to diff lockfile, assuming every package changed: {err}");
+                    return Ok(self.all_packages());
+                }
+            }
+        }
+
+        Ok(changed_packages)
+    }
+}
+
+fn compile_globs(patterns: &[&str]) -> Result<Any<'static>, ResolutionError> {
+    let globs = patterns
+        .iter()
+        .map(|pattern| {
+            Glob::new(pattern)
+                .map(|glob| glob.into_owned())
+                .map_err(|err| ResolutionError::InvalidGlob(pattern.to_string(), Box::new(err)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    wax::any(globs).map_err(|err| ResolutionError::InvalidGlob(patterns.join(","), Box::new(err)))
+}
@@ -1,7 +1,15 @@
+use std::collections::HashSet;
+
+use thiserror::Error;
 use tracing::debug;
-use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf};
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPath, AnchoredSystemPathBuf};
+use wax::{Glob, Pattern};
 
-use crate::package_graph;
+use super::{
+    change_detector::GitChangeDetector,
+    target_selector::{InvalidSelectorError, TargetSelector},
+};
+use crate::package_graph::{self, PackageGraph, WorkspaceName, WorkspaceNode};
 
 pub struct PackageInference {
     package_name: Option<String>,
@@ -25,7 +33,7 @@ impl PackageInference {
         );
         let full_inference_path = turbo_root.resolve(pkg_inference_path);
         for (workspace_name, workspace_entry) in pkg_graph.workspaces() {
-            let pkg_path = turbo_root.resolve(workspace_entry.package_json_path());
+            let pkg_path = turbo_root.resolve(workspace_entry.package_path());
             let inferred_path_is_below = pkg_path.contains(&full_inference_path);
             // We skip over the root package as the inferred path will always be below it
             if inferred_path_is_below && (&pkg_path as &AbsoluteSystemPath) != turbo_root {
@@ -34,7 +42,7 @@ impl PackageInference {
                 // do so in a consistent manner
                 return Self {
                     package_name: Some(workspace_name.to_string()),
-                    directory_root: workspace_entry.package_json_path().clone(),
+                    directory_root: workspace_entry.package_path().to_owned(),
                 };
             }
             let inferred_path_is_between_root_and_pkg = full_inference_path.contains(&pkg_path);
@@ -50,4 +58,567 @@ impl PackageInference {
             directory_root: pkg_inference_path.to_owned(),
         }
     }
+
+    fn apply(&self, selector: &mut TargetSelector) {
+        if !selector.name_pattern.is_empty() {
+            // The selector references a package name, don't apply inference
+            return;
+        }
+        if let Some(name) = &self.package_name {
+            selector.name_pattern = name.clone();
+        }
+        if let Some(parent_dir) = &selector.parent_dir {
+            let mut inferred_dir = self.directory_root.clone();
+            inferred_dir.push(parent_dir);
+            selector.parent_dir = Some(inferred_dir);
+        } else if self.package_name.is_none() {
+            // The user didn't set a parent directory and we didn't find a single
+            // package, so use the directory we inferred and select all subdirectories
+            let mut inferred_dir = self.directory_root.clone();
+            inferred_dir.push("**");
+            selector.parent_dir = Some(inferred_dir);
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ResolutionError {
+    #[error(transparent)]
+    InvalidSelector(#[from] InvalidSelectorError),
+    #[error("invalid selector: {0}")]
+    UnusedSelector(String),
+    #[error("No package found with name '{0}' in workspace")]
+    NoPackagesMatchedWithName(String),
+    #[error("invalid directory glob {0}: {1}")]
+    InvalidDirectoryGlob(String, Box<wax::BuildError>),
+    #[error("invalid glob {0}: {1}")]
+    InvalidGlob(String, Box<wax::BuildError>),
+    #[error("invalid package name pattern {0}: {1}")]
+    InvalidNamePattern(String, regex::Error),
+    #[error("failed to get changed files: {0}")]
+    ChangedFiles(#[from] turborepo_scm::Error),
+    #[error(transparent)]
+    Path(#[from] turbopath::PathError),
+}
+
+/// Resolves `--filter` selectors to the set of workspaces they select
+pub struct FilterResolver<'a, T: GitChangeDetector> {
+    pkg_graph: &'a PackageGraph,
+    turbo_root: &'a AbsoluteSystemPath,
+    inference: Option<PackageInference>,
+    change_detector: T,
+}
+
+impl<'a, T: GitChangeDetector> FilterResolver<'a, T> {
+    pub fn new(
+        pkg_graph: &'a PackageGraph,
+        turbo_root: &'a AbsoluteSystemPath,
+        inference: Option<PackageInference>,
+        change_detector: T,
+    ) -> Self {
+        Self {
+            pkg_graph,
+            turbo_root,
+            inference,
+            change_detector,
+        }
+    }
+
+    /// Parses the given patterns and returns the workspaces they select
+    pub fn resolve(&self, patterns: &[String]) -> Result<HashSet<WorkspaceName>, ResolutionError> {
+        let mut selectors = patterns
+            .iter()
+            .map(|pattern| pattern.parse::<TargetSelector>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(inference) = &self.inference {
+            // If there are no patterns, but there is a directory supplied,
+            // synthesize a selector
+            if selectors.is_empty() {
+                selectors.push(TargetSelector::default());
+            }
+            for selector in &mut selectors {
+                inference.apply(selector);
+            }
+        }
+
+        if selectors.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let (exclude_selectors, include_selectors): (Vec<_>, Vec<_>) =
+            selectors.into_iter().partition(|selector| selector.exclude);
+
+        let include = if include_selectors.is_empty() {
+            self.pkg_graph
+                .workspaces()
+                .map(|(name, _)| name.clone())
+                .collect()
+        } else {
+            self.filter_graph_with_selectors(&include_selectors)?
+        };
+        let exclude = self.filter_graph_with_selectors(&exclude_selectors)?;
+
+        Ok(include.difference(&exclude).cloned().collect())
+    }
+
+    fn filter_graph_with_selectors(
+        &self,
+        selectors: &[TargetSelector],
+    ) -> Result<HashSet<WorkspaceName>, ResolutionError> {
+        let mut cherry_picked = HashSet::new();
+        let mut walked_dependencies = HashSet::new();
+        let mut walked_dependents = HashSet::new();
+        let mut walked_dependents_dependencies = HashSet::new();
+
+        for selector in selectors {
+            let entry_packages = self.filter_graph_with_selector(selector)?;
+            if entry_packages.is_empty() {
+                debug!("filter {} did not match any packages", selector.raw);
+            }
+
+            for package in entry_packages {
+                let node = WorkspaceNode::Workspace(package.clone());
+                if selector.include_dependencies {
+                    walked_dependencies.extend(workspace_names(self.pkg_graph.dependencies(&node)));
+                    if !selector.exclude_self {
+                        walked_dependencies.insert(package.clone());
+                    }
+                }
+                if selector.include_dependents {
+                    for dependent in self.pkg_graph.ancestors(&node) {
+                        if let WorkspaceNode::Workspace(name) = dependent {
+                            walked_dependents.insert(name.clone());
+                        }
+                        if selector.include_dependencies {
+                            walked_dependents_dependencies
+                                .extend(workspace_names(self.pkg_graph.dependencies(dependent)));
+                        }
+                    }
+                    if !selector.exclude_self {
+                        walked_dependents.insert(package.clone());
+                    }
+                }
+                if !selector.include_dependencies && !selector.include_dependents {
+                    cherry_picked.insert(package);
+                }
+            }
+        }
+
+        Ok(cherry_picked
+            .into_iter()
+            .chain(walked_dependencies)
+            .chain(walked_dependents)
+            .chain(walked_dependents_dependencies)
+            .collect())
+    }
+
+    fn filter_graph_with_selector(
+        &self,
+        selector: &TargetSelector,
+    ) -> Result<HashSet<WorkspaceName>, ResolutionError> {
+        if selector.match_dependencies {
+            self.filter_subtrees_with_selector(selector)
+        } else {
+            self.filter_nodes_with_selector(selector)
+        }
+    }
+
+    /// Returns the workspaces that match the given selector
+    fn filter_nodes_with_selector(
+        &self,
+        selector: &TargetSelector,
+    ) -> Result<HashSet<WorkspaceName>, ResolutionError> {
+        let mut entry_packages = HashSet::new();
+        let mut selector_used = false;
+
+        if let Some(from_ref) = &selector.from_ref {
+            selector_used = true;
+            let changed_packages = self
+                .change_detector
+                .changed_packages(from_ref, selector.to_ref())?;
+            match &selector.parent_dir {
+                Some(parent_dir) => {
+                    let matcher = self.directory_matcher(parent_dir)?;
+                    entry_packages.extend(
+                        changed_packages
+                            .into_iter()
+                            .filter(|name| self.workspace_matches(&matcher, name)),
+                    );
+                }
+                None => entry_packages.extend(changed_packages),
+            }
+        } else if let Some(parent_dir) = &selector.parent_dir {
+            selector_used = true;
+            if parent_dir.as_str() == "." {
+                entry_packages.insert(WorkspaceName::Root);
+            } else {
+                let matcher = self.directory_matcher(parent_dir)?;
+                entry_packages.extend(
+                    self.pkg_graph
+                        .workspaces()
+                        .map(|(name, _)| name)
+                        .filter(|name| self.workspace_matches(&matcher, name))
+                        .cloned(),
+                );
+            }
+        }
+
+        if !selector.name_pattern.is_empty() {
+            if !selector_used {
+                let all_packages = self
+                    .pkg_graph
+                    .workspaces()
+                    .map(|(name, _)| name.clone())
+                    .collect();
+                entry_packages = match_package_names(&selector.name_pattern, all_packages)?;
+                if entry_packages.is_empty() {
+                    return Err(ResolutionError::NoPackagesMatchedWithName(
+                        selector.name_pattern.clone(),
+                    ));
+                }
+                selector_used = true;
+            } else {
+                entry_packages = match_package_names(&selector.name_pattern, entry_packages)?;
+            }
+        }
+
+        if !selector_used {
+            return Err(ResolutionError::UnusedSelector(selector.raw.clone()));
+        }
+
+        Ok(entry_packages)
+    }
+
+    /// Returns the workspaces matching the selector where either the workspace
+    /// itself or any of its dependencies changed
+    fn filter_subtrees_with_selector(
+        &self,
+        selector: &TargetSelector,
+    ) -> Result<HashSet<WorkspaceName>, ResolutionError> {
+        let from_ref = selector.from_ref.as_deref().unwrap_or_default();
+        let changed_packages = self
+            .change_detector
+            .changed_packages(from_ref, selector.to_ref())?;
+
+        let mut entry_packages = match &selector.parent_dir {
+            Some(parent_dir) => {
+                let matcher = self.directory_matcher(parent_dir)?;
+                self.pkg_graph
+                    .workspaces()
+                    .map(|(name, _)| name)
+                    .filter(|name| self.workspace_matches(&matcher, name))
+                    .cloned()
+                    .collect()
+            }
+            None => self
+                .pkg_graph
+                .workspaces()
+                .map(|(name, _)| name.clone())
+                .collect(),
+        };
+        if !selector.name_pattern.is_empty() {
+            entry_packages = match_package_names(&selector.name_pattern, entry_packages)?;
+        }
+
+        let mut roots = HashSet::new();
+        let mut matched = HashSet::new();
+        for package in entry_packages {
+            if matched.contains(&package) {
+                roots.insert(package);
+                continue;
+            }
+            let dependencies = self
+                .pkg_graph
+                .dependencies(&WorkspaceNode::Workspace(package.clone()));
+            for changed_package in &changed_packages {
+                if !selector.exclude_self && &package == changed_package {
+                    roots.insert(package);
+                    break;
+                }
+                if dependencies.contains(&WorkspaceNode::Workspace(changed_package.clone())) {
+                    roots.insert(package);
+                    matched.insert(changed_package.clone());
+                    break;
+                }
+            }
+        }
+
+        Ok(roots)
+    }
+
+    fn directory_matcher(
+        &self,
+        parent_dir: &AnchoredSystemPath,
+    ) -> Result<DirectoryMatcher, ResolutionError> {
+        let full_path = self.turbo_root.resolve(parent_dir).collapse();
+        // Directories outside of the repo can't contain any workspaces
+        let Ok(relative_path) = self.turbo_root.anchor(&full_path) else {
+            return Ok(DirectoryMatcher::Nothing);
+        };
+        let relative_path = relative_path.to_unix()?;
+        if relative_path.as_str().is_empty() {
+            return Ok(DirectoryMatcher::Root);
+        }
+        let glob = Glob::new(relative_path.as_str()).map_err(|err| {
+            ResolutionError::InvalidDirectoryGlob(relative_path.as_str().to_string(), Box::new(err))
+        })?;
+        Ok(DirectoryMatcher::Glob(glob.into_owned()))
+    }
+
+    fn workspace_matches(&self, matcher: &DirectoryMatcher, name: &WorkspaceName) -> bool {
+        let Some(entry) = self.pkg_graph.workspace_info(name) else {
+            return false;
+        };
+        let Ok(dir) = entry.package_path().to_owned().to_unix() else {
+            return false;
+        };
+        match matcher {
+            DirectoryMatcher::Nothing => false,
+            DirectoryMatcher::Root => dir.as_str().is_empty(),
+            // `**` also matches the directory it is anchored to, including the repo root
+            DirectoryMatcher::Glob(glob) => {
+                glob.is_match(dir.as_str()) || (dir.as_str().is_empty() && glob.is_match("."))
+            }
+        }
+    }
+}
+
+enum DirectoryMatcher {
+    Nothing,
+    Root,
+    Glob(Glob<'static>),
+}
+
+fn workspace_names(nodes: HashSet<&WorkspaceNode>) -> impl Iterator<Item = WorkspaceName> + '_ {
+    nodes.into_iter().filter_map(|node| match node {
+        WorkspaceNode::Workspace(name) => Some(name.clone()),
+        WorkspaceNode::Root => None,
+    })
+}
+
+/// Matches package names against a pattern where `*` matches any sequence of
+/// characters. If an unscoped pattern doesn't match anything, we check if
+/// there's exactly one scoped package that matches it, i.e. `foo` can be used
+/// to select `@scope/foo`.
+fn match_package_names(
+    pattern: &str,
+    packages: HashSet<WorkspaceName>,
+) -> Result<HashSet<WorkspaceName>, ResolutionError> {
+    let matcher = SimpleGlob::new(pattern)?;
+    let matched = packages
+        .iter()
+        .filter(|name| matcher.is_match(&name.to_string()))
+        .cloned()
+        .collect::<HashSet<_>>();
+
+    if matched.is_empty() && !pattern.starts_with('@') && !pattern.contains('/') {
+        let scoped_matcher = SimpleGlob::new(&format!("@*/{}", pattern))?;
+        let mut scoped_matches = packages
+            .into_iter()
+            .filter(|name| scoped_matcher.is_match(&name.to_string()));
+        return Ok(match (scoped_matches.next(), scoped_matches.next()) {
+            (Some(name), None) => HashSet::from([name]),
+            // Either nothing matched or there's more than one scoped package
+            // and we can't disambiguate
+            _ => HashSet::new(),
+        });
+    }
+
+    Ok(matched)
+}
+
+enum SimpleGlob {
+    Any,
+    Exact(String),
+    Regex(regex::Regex),
+}
+
+impl SimpleGlob {
+    fn new(pattern: &str) -> Result<Self, ResolutionError> {
+        if pattern == "*" {
+            return Ok(SimpleGlob::Any);
+        }
+        if !pattern.contains('*') {
+            return Ok(SimpleGlob::Exact(pattern.to_string()));
+        }
+        let regex = pattern
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join(".*");
+        regex::Regex::new(&format!("^{}$", regex))
+            .map(SimpleGlob::Regex)
+            .map_err(|err| ResolutionError::InvalidNamePattern(pattern.to_string(), err))
+    }
+
+    fn is_match(&self, name: &str) -> bool {
+        match self {
+            SimpleGlob::Any => true,
+            SimpleGlob::Exact(pattern) => pattern == name,
+            SimpleGlob::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+    use test_case::test_case;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::*;
+    use crate::{package_json::PackageJson, package_manager::PackageManager};
+
+    struct MockChangeDetector(HashMap<(&'static str, &'static str), Vec<&'static str>>);
+
+    impl GitChangeDetector for MockChangeDetector {
+        fn changed_packages(
+            &self,
+            from_ref: &str,
+            to_ref: &str,
+        ) -> Result<HashSet<WorkspaceName>, ResolutionError> {
+            Ok(self
+                .0
+                .get(&(from_ref, to_ref))
+                .map(|packages| packages.iter().map(|name| workspace_name(name)).collect())
+                .unwrap_or_default())
+        }
+    }
+
+    fn workspace_name(name: &str) -> WorkspaceName {
+        match name {
+            "//" => WorkspaceName::Root,
+            name => WorkspaceName::from(name),
+        }
+    }
+
+    fn repo_root() -> AbsoluteSystemPathBuf {
+        AbsoluteSystemPathBuf::new(if cfg!(windows) { r"C:\repo" } else { "/repo" }).unwrap()
+    }
+
+    // web -> @scope/ui -> utils
+    // docs -> @scope/ui
+    // api -> utils
+    fn package_graph(root: &AbsoluteSystemPath) -> PackageGraph {
+        let workspace = |name: &str, dependencies: &[&str]| {
+            let dependencies = dependencies
+                .iter()
+                .map(|dependency| (dependency.to_string(), json!("workspace:*")))
+                .collect::<serde_json::Map<_, _>>();
+            PackageJson::from_value(json!({ "name": name, "dependencies": dependencies })).unwrap()
+        };
+        PackageGraph::builder(
+            root,
+            PackageJson::from_value(json!({ "name": "root" })).unwrap(),
+        )
+        .with_package_manger(Some(PackageManager::Npm))
+        .with_package_jsons(Some(HashMap::from([
+            (
+                root.join_components(&["apps", "web", "package.json"]),
+                workspace("web", &["@scope/ui"]),
+            ),
+            (
+                root.join_components(&["apps", "docs", "package.json"]),
+                workspace("docs", &["@scope/ui"]),
+            ),
+            (
+                root.join_components(&["apps", "api", "package.json"]),
+                workspace("api", &["utils"]),
+            ),
+            (
+                root.join_components(&["packages", "ui", "package.json"]),
+                workspace("@scope/ui", &["utils"]),
+            ),
+            (
+                root.join_components(&["packages", "utils", "package.json"]),
+                workspace("utils", &[]),
+            ),
+        ])))
+        .build()
+        .unwrap()
+    }
+
+    fn resolve(
+        patterns: &[&str],
+        inference_root: Option<&str>,
+    ) -> Result<HashSet<WorkspaceName>, ResolutionError> {
+        let root = repo_root();
+        let pkg_graph = package_graph(&root);
+        let inference = inference_root.map(|inference_root| {
+            PackageInference::calculate(
+                &root,
+                &AnchoredSystemPathBuf::from_raw(inference_root).unwrap(),
+                &pkg_graph,
+            )
+        });
+        let change_detector = MockChangeDetector(HashMap::from([
+            (("main", "HEAD"), vec!["utils"]),
+            (("main", "feature"), vec!["docs"]),
+            (("root-change", "HEAD"), vec!["//"]),
+        ]));
+        let resolver = FilterResolver::new(&pkg_graph, &root, inference, change_detector);
+        let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        resolver.resolve(&patterns)
+    }
+
+    #[test_case(&["web"], None, &["web"] ; "by name")]
+    #[test_case(&["ui"], None, &["@scope/ui"] ; "unscoped name matches single scoped package")]
+    #[test_case(&["@scope/*"], None, &["@scope/ui"] ; "glob name")]
+    #[test_case(&["*"], None, &["//", "web", "docs", "api", "@scope/ui", "utils"] ; "everything")]
+    #[test_case(&["//"], None, &["//"] ; "root workspace")]
+    #[test_case(&["web..."], None, &["web", "@scope/ui", "utils"] ; "with dependencies")]
+    #[test_case(&["web^..."], None, &["@scope/ui", "utils"] ; "only dependencies")]
+    #[test_case(&["...@scope/ui"], None, &["@scope/ui", "web", "docs"] ; "with dependents")]
+    #[test_case(&["...^@scope/ui"], None, &["web", "docs"] ; "only dependents")]
+    #[test_case(&["...utils..."], None, &["utils", "@scope/ui", "web", "docs", "api"] ; "dependents and their dependencies")]
+    #[test_case(&["!web"], None, &["//", "docs", "api", "@scope/ui", "utils"] ; "exclusion only")]
+    #[test_case(&["./apps/*", "!docs"], None, &["web", "api"] ; "directory with exclusion")]
+    #[test_case(&["{./packages/*}"], None, &["@scope/ui", "utils"] ; "braced directory")]
+    #[test_case(&["."], None, &["//"] ; "root directory")]
+    #[test_case(&["[main]"], None, &["utils"] ; "changed packages")]
+    #[test_case(&["[main...feature]"], None, &["docs"] ; "changed packages with end ref")]
+    #[test_case(&["...[main]"], None, &["utils", "@scope/ui", "web", "docs", "api"] ; "dependents of changed packages")]
+    #[test_case(&["{./apps/*}[main]"], None, &[] ; "changed packages in directory")]
+    #[test_case(&["{./packages/*}[main]"], None, &["utils"] ; "changed packages in other directory")]
+    #[test_case(&["{./apps/*}...[main]"], None, &["web", "docs", "api"] ; "packages with changed dependencies")]
+    #[test_case(&["web...[main]"], None, &["web"] ; "named package with changed dependencies")]
+    #[test_case(&["[root-change]"], None, &["//"] ; "root changed")]
+    #[test_case(&[], Some("apps/web/src"), &["web"] ; "inferred package")]
+    #[test_case(&[], Some("apps"), &["web", "docs", "api"] ; "inferred directory")]
+    #[test_case(&["{./docs}"], Some("apps"), &["docs"] ; "directory relative to inferred directory")]
+    #[test_case(&["utils"], Some("apps/web"), &["utils"] ; "name overrides inference")]
+    fn test_resolve(patterns: &[&str], inference_root: Option<&str>, expected: &[&str]) {
+        let expected = expected
+            .iter()
+            .map(|name| workspace_name(name))
+            .collect::<HashSet<_>>();
+        assert_eq!(resolve(patterns, inference_root).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_unmatched_name_is_an_error() {
+        assert!(matches!(
+            resolve(&["does-not-exist"], None),
+            Err(ResolutionError::NoPackagesMatchedWithName(name)) if name == "does-not-exist"
+        ));
+    }
+
+    #[test]
+    fn test_ambiguous_scoped_name_matches_nothing() {
+        let packages = HashSet::from([WorkspaceName::from("@a/ui"), WorkspaceName::from("@b/ui")]);
+        assert!(match_package_names("ui", packages).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_selector() {
+        assert!(matches!(
+            resolve(&["{}"], None),
+            Err(ResolutionError::InvalidSelector(
+                InvalidSelectorError::EmptyPathSpecification
+            ))
+        ));
+    }
 }
@@ -1,23 +1,52 @@
+mod change_detector;
 mod filter;
+mod target_selector;
 
 use std::collections::HashSet;
 
-use anyhow::Result;
-use filter::PackageInference;
-use tracing::warn;
+use change_detector::ScopeChangeDetector;
+pub use filter::ResolutionError;
+use filter::{FilterResolver, PackageInference};
 use turborepo_scm::SCM;
 
-use crate::{commands::CommandBase, opts::ScopeOpts, package_graph};
+use crate::{
+    commands::CommandBase,
+    opts::ScopeOpts,
+    package_graph::{self, WorkspaceName},
+};
 
+/// Returns the workspaces selected by `--filter` along with the legacy
+/// `--scope` and `--since` flags. Every workspace is selected if there aren't
+/// any filters and turbo wasn't invoked from within a workspace.
 pub fn resolve_packages(
     opts: &ScopeOpts,
     base: &CommandBase,
     pkg_graph: &package_graph::PackageGraph,
-    _scm: &SCM,
-) -> Result<HashSet<String>> {
-    let _pkg_inference = opts.pkg_inference_root.as_ref().map(|pkg_inference_path| {
+    scm: &SCM,
+) -> Result<HashSet<WorkspaceName>, ResolutionError> {
+    let pkg_inference = opts.pkg_inference_root.as_ref().map(|pkg_inference_path| {
         PackageInference::calculate(&base.repo_root, pkg_inference_path, pkg_graph)
     });
-    warn!("resolve packages not implemented yet");
-    Ok(HashSet::new())
+    let change_detector = ScopeChangeDetector::new(
+        &base.repo_root,
+        scm,
+        pkg_graph,
+        &opts.global_deps,
+        &opts.ignore_patterns,
+    )?;
+    let filter_resolver =
+        FilterResolver::new(pkg_graph, &base.repo_root, pkg_inference, change_detector);
+
+    let mut filter_patterns = opts.filter_patterns.clone();
+    filter_patterns.extend(opts.legacy_filter.as_filter_patterns());
+    let is_all_packages = filter_patterns.is_empty() && opts.pkg_inference_root.is_none();
+
+    let mut filtered_pkgs = filter_resolver.resolve(&filter_patterns)?;
+    if is_all_packages {
+        // no filters specified, run every package
+        filtered_pkgs.extend(pkg_graph.workspaces().map(|(name, _)| name.clone()));
+        filtered_pkgs.remove(&WorkspaceName::Root);
+    }
+
+    Ok(filtered_pkgs)
 }
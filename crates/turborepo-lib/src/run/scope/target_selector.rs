@@ -0,0 +1,224 @@
+use std::str::FromStr;
+
+use lazy_regex::{lazy_regex, Lazy};
+use regex::Regex;
+use thiserror::Error;
+use turbopath::AnchoredSystemPathBuf;
+
+/// A parsed `--filter` selector, compatible with pnpm's filtering syntax
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TargetSelector {
+    pub include_dependencies: bool,
+    pub match_dependencies: bool,
+    pub include_dependents: bool,
+    pub exclude: bool,
+    pub exclude_self: bool,
+    pub parent_dir: Option<AnchoredSystemPathBuf>,
+    pub name_pattern: String,
+    pub from_ref: Option<String>,
+    pub to_ref_override: Option<String>,
+    pub raw: String,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum InvalidSelectorError {
+    #[error("cannot use an empty selector")]
+    EmptySelector,
+    #[error("cannot use match dependencies without specifying either a directory or package")]
+    CantMatchDependencies,
+    #[error("empty path specification")]
+    EmptyPathSpecification,
+    #[error("invalid path specification: {0}")]
+    InvalidPathSpecification(String),
+}
+
+// name, optional {directory} and an optional [git range] that can be prefixed
+// with `...` to match packages whose dependencies changed
+static TARGET_SELECTOR_REGEX: Lazy<Regex> = lazy_regex!(
+    r"^(?P<name>[^.](?:[^{}\[\]]*[^{}\[\].])?)?(?P<directory>\{[^}]*\})?(?P<commits>(?:\.{3})?\[[^\]]+\])?$"
+);
+
+impl TargetSelector {
+    /// A selector needs to select by at least one of name, directory or
+    /// changes to be valid
+    pub fn is_valid(&self) -> bool {
+        self.from_ref.is_some() || self.parent_dir.is_some() || !self.name_pattern.is_empty()
+    }
+
+    /// The git ref used as the upper bound when finding changed packages
+    pub fn to_ref(&self) -> &str {
+        self.to_ref_override.as_deref().unwrap_or("HEAD")
+    }
+}
+
+impl FromStr for TargetSelector {
+    type Err = InvalidSelectorError;
+
+    fn from_str(raw_selector: &str) -> Result<Self, Self::Err> {
+        if raw_selector.is_empty() {
+            return Err(InvalidSelectorError::EmptySelector);
+        }
+
+        let (exclude, selector) = match raw_selector.strip_prefix('!') {
+            Some(selector) => (true, selector),
+            None => (false, raw_selector),
+        };
+
+        let mut exclude_self = false;
+        let (include_dependencies, selector) = match selector.strip_suffix("...") {
+            Some(selector) => match selector.strip_suffix('^') {
+                Some(selector) => {
+                    exclude_self = true;
+                    (true, selector)
+                }
+                None => (true, selector),
+            },
+            None => (false, selector),
+        };
+        let (include_dependents, selector) = match selector.strip_prefix("...") {
+            Some(selector) => match selector.strip_prefix('^') {
+                Some(selector) => {
+                    exclude_self = true;
+                    (true, selector)
+                }
+                None => (true, selector),
+            },
+            None => (false, selector),
+        };
+
+        let Some(captures) = TARGET_SELECTOR_REGEX.captures(selector) else {
+            // Selectors like `./apps/docs` don't match the regex as names can't
+            // start with a `.`
+            let (parent_dir, name_pattern) = match selector_by_location(selector) {
+                Some(parent_dir) => (Some(parent_dir), String::new()),
+                None => (None, selector.to_string()),
+            };
+            return Ok(TargetSelector {
+                exclude,
+                exclude_self,
+                include_dependencies,
+                include_dependents,
+                parent_dir,
+                name_pattern,
+                raw: raw_selector.to_string(),
+                ..Default::default()
+            });
+        };
+
+        let name_pattern = captures
+            .name("name")
+            .map_or(String::new(), |name| name.as_str().to_string());
+
+        let parent_dir = match captures.name("directory") {
+            Some(directory) => {
+                // trim the surrounding {}
+                let directory = &directory.as_str()[1..directory.len() - 1];
+                if directory.is_empty() {
+                    return Err(InvalidSelectorError::EmptyPathSpecification);
+                }
+                let parent_dir = AnchoredSystemPathBuf::from_raw(directory).map_err(|_| {
+                    InvalidSelectorError::InvalidPathSpecification(directory.to_string())
+                })?;
+                Some(parent_dir)
+            }
+            None => None,
+        };
+
+        let mut match_dependencies = false;
+        let mut from_ref = None;
+        let mut to_ref_override = None;
+        if let Some(commits) = captures.name("commits") {
+            let mut commits = commits.as_str();
+            if let Some(stripped) = commits.strip_prefix("...") {
+                if parent_dir.is_none() && name_pattern.is_empty() {
+                    return Err(InvalidSelectorError::CantMatchDependencies);
+                }
+                match_dependencies = true;
+                commits = stripped;
+            }
+            // strip the surrounding []
+            let commits = &commits[1..commits.len() - 1];
+            match commits.split_once("...") {
+                Some((from, to)) if !to.contains("...") => {
+                    from_ref = Some(from.to_string());
+                    to_ref_override = Some(to.to_string());
+                }
+                _ => from_ref = Some(commits.to_string()),
+            }
+        }
+
+        Ok(TargetSelector {
+            include_dependencies,
+            match_dependencies,
+            include_dependents,
+            exclude,
+            exclude_self,
+            parent_dir,
+            name_pattern,
+            from_ref,
+            to_ref_override,
+            raw: raw_selector.to_string(),
+        })
+    }
+}
+
+/// Returns the selected directory if the selector is a path relative to the
+/// current directory, i.e. `.`, `..` or starts with `./` or `../`
+fn selector_by_location(selector: &str) -> Option<AnchoredSystemPathBuf> {
+    let is_separator = |c: char| c == '/' || c == '\\';
+    let rest = selector.strip_prefix('.')?;
+    let rest = rest.strip_prefix('.').unwrap_or(rest);
+    if rest.is_empty() || rest.starts_with(is_separator) {
+        AnchoredSystemPathBuf::from_raw(selector).ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn dir(path: &str) -> Option<AnchoredSystemPathBuf> {
+        Some(AnchoredSystemPathBuf::from_raw(path).unwrap())
+    }
+
+    #[test_case("foo", TargetSelector { name_pattern: "foo".into(), raw: "foo".into(), ..Default::default() } ; "name")]
+    #[test_case("foo...", TargetSelector { include_dependencies: true, name_pattern: "foo".into(), raw: "foo...".into(), ..Default::default() } ; "with dependencies")]
+    #[test_case("...foo", TargetSelector { include_dependents: true, name_pattern: "foo".into(), raw: "...foo".into(), ..Default::default() } ; "with dependents")]
+    #[test_case("...foo...", TargetSelector { include_dependencies: true, include_dependents: true, name_pattern: "foo".into(), raw: "...foo...".into(), ..Default::default() } ; "with dependents and dependencies")]
+    #[test_case("foo^...", TargetSelector { include_dependencies: true, exclude_self: true, name_pattern: "foo".into(), raw: "foo^...".into(), ..Default::default() } ; "dependencies without self")]
+    #[test_case("...^foo", TargetSelector { include_dependents: true, exclude_self: true, name_pattern: "foo".into(), raw: "...^foo".into(), ..Default::default() } ; "dependents without self")]
+    #[test_case("!foo", TargetSelector { exclude: true, name_pattern: "foo".into(), raw: "!foo".into(), ..Default::default() } ; "exclusion")]
+    #[test_case("@scope/*", TargetSelector { name_pattern: "@scope/*".into(), raw: "@scope/*".into(), ..Default::default() } ; "glob name")]
+    #[test_case("./foo", TargetSelector { parent_dir: dir("./foo"), raw: "./foo".into(), ..Default::default() } ; "relative directory")]
+    #[test_case("../foo", TargetSelector { parent_dir: dir("../foo"), raw: "../foo".into(), ..Default::default() } ; "parent directory")]
+    #[test_case(".", TargetSelector { parent_dir: dir("."), raw: ".".into(), ..Default::default() } ; "current directory")]
+    #[test_case("...{./foo}", TargetSelector { include_dependents: true, parent_dir: dir("./foo"), raw: "...{./foo}".into(), ..Default::default() } ; "braced directory with dependents")]
+    #[test_case("foo{./bar}", TargetSelector { parent_dir: dir("./bar"), name_pattern: "foo".into(), raw: "foo{./bar}".into(), ..Default::default() } ; "name and directory")]
+    #[test_case("[main]", TargetSelector { from_ref: Some("main".into()), raw: "[main]".into(), ..Default::default() } ; "git range")]
+    #[test_case("[main...feature]", TargetSelector { from_ref: Some("main".into()), to_ref_override: Some("feature".into()), raw: "[main...feature]".into(), ..Default::default() } ; "git range with end")]
+    #[test_case("{foo}[main]", TargetSelector { parent_dir: dir("foo"), from_ref: Some("main".into()), raw: "{foo}[main]".into(), ..Default::default() } ; "directory and git range")]
+    #[test_case("foo...[main]", TargetSelector { match_dependencies: true, name_pattern: "foo".into(), from_ref: Some("main".into()), raw: "foo...[main]".into(), ..Default::default() } ; "match dependencies")]
+    #[test_case("...[HEAD^]...", TargetSelector { include_dependencies: true, include_dependents: true, from_ref: Some("HEAD^".into()), raw: "...[HEAD^]...".into(), ..Default::default() } ; "git range with dependents and dependencies")]
+    fn test_parse_target_selector(raw: &str, expected: TargetSelector) {
+        assert_eq!(raw.parse::<TargetSelector>(), Ok(expected));
+    }
+
+    #[test_case("", InvalidSelectorError::EmptySelector ; "empty")]
+    #[test_case("{}", InvalidSelectorError::EmptyPathSpecification ; "empty directory")]
+    #[test_case("......[main]", InvalidSelectorError::CantMatchDependencies ; "match dependencies without target")]
+    fn test_parse_target_selector_invalid(raw: &str, expected: InvalidSelectorError) {
+        assert_eq!(raw.parse::<TargetSelector>(), Err(expected));
+    }
+
+    #[test]
+    fn test_to_ref_defaults_to_head() {
+        let selector: TargetSelector = "[main]".parse().unwrap();
+        assert_eq!(selector.to_ref(), "HEAD");
+        let selector: TargetSelector = "[main...feature]".parse().unwrap();
+        assert_eq!(selector.to_ref(), "feature");
+    }
+}
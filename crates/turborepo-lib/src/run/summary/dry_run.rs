@@ -0,0 +1,210 @@
+use std::{collections::HashMap, fmt::Write};
+
+use itertools::Itertools;
+
+use super::RunSummary;
+use crate::ui::{BOLD, CYAN, GREY, UI};
+
+impl RunSummary {
+    /// Renders the summary of `turbo run --dry`, `package_dirs` maps each
+    /// package name to its directory in the repo
+    pub(super) fn format_dry_run_text(
+        &self,
+        package_dirs: &HashMap<String, String>,
+        ui: &UI,
+    ) -> String {
+        let mut out = String::new();
+
+        if let Some(packages) = &self.packages {
+            out.push('\n');
+            heading(&mut out, ui, "Packages in Scope");
+            let rows = std::iter::once(vec!["Name".to_string(), "Path".to_string(), String::new()])
+                .chain(packages.iter().map(|package| {
+                    vec![
+                        package.clone(),
+                        package_dirs.get(package).cloned().unwrap_or_default(),
+                        String::new(),
+                    ]
+                }))
+                .collect::<Vec<_>>();
+            for line in tabulate(&rows) {
+                writeln!(out, "{}", line).unwrap();
+            }
+        }
+
+        let global = &self.global_cache_inputs;
+        out.push('\n');
+        heading(&mut out, ui, "Global Hash Inputs");
+        let rows = [
+            ("Global Files", global.files.len().to_string()),
+            (
+                "External Dependencies Hash",
+                global.hash_of_external_dependencies.clone(),
+            ),
+            ("Global Cache Key", global.root_key.to_string()),
+            (
+                "Global .env Files Considered",
+                global.global_dot_env.len().to_string(),
+            ),
+            (
+                "Global Env Vars",
+                global.environment_variables.specified.env.join(", "),
+            ),
+            (
+                "Global Env Vars Values",
+                global.environment_variables.configured.join(", "),
+            ),
+            (
+                "Inferred Global Env Vars Values",
+                global.environment_variables.inferred.join(", "),
+            ),
+            (
+                "Global Passed Through Env Vars",
+                global
+                    .environment_variables
+                    .specified
+                    .pass_through_env
+                    .iter()
+                    .flatten()
+                    .join(", "),
+            ),
+            (
+                "Global Passed Through Env Vars Values",
+                global.environment_variables.pass_through.join(", "),
+            ),
+        ]
+        .into_iter()
+        .map(|(key, value)| vec![format!("  {}", key), "=".to_string(), value])
+        .collect::<Vec<_>>();
+        for line in tabulate(&rows) {
+            writeln!(out, "{}", ui.apply(GREY.apply_to(line))).unwrap();
+        }
+
+        out.push('\n');
+        heading(&mut out, ui, "Tasks to Run");
+        for task in &self.tasks {
+            writeln!(out, "{}", ui.apply(BOLD.apply_to(&task.task_id))).unwrap();
+
+            let env = &task.environment_variables;
+            let mut rows = vec![("Task", task.task.clone())];
+            if self.packages.is_some() {
+                rows.push(("Package", task.package.clone()));
+            }
+            rows.extend([
+                ("Hash", task.hash.clone()),
+                ("Cached (Local)", task.cache.local.to_string()),
+                ("Cached (Remote)", task.cache.remote.to_string()),
+            ]);
+            if self.packages.is_some() {
+                rows.push(("Directory", task.directory.clone()));
+            }
+            rows.extend([
+                ("Command", task.command.clone()),
+                ("Outputs", task.outputs.join(", ")),
+                ("Log File", task.log_file.clone()),
+                ("Dependencies", task.dependencies.join(", ")),
+                // The typo is kept so that output matches the Go implementation
+                ("Dependendents", task.dependents.join(", ")),
+                ("Inputs Files Considered", task.inputs.len().to_string()),
+                (".env Files Considered", task.dot_env.len().to_string()),
+                ("Env Vars", env.specified.env.join(", ")),
+                ("Env Vars Values", env.configured.join(", ")),
+                ("Inferred Env Vars Values", env.inferred.join(", ")),
+                (
+                    "Passed Through Env Vars",
+                    env.specified.pass_through_env.iter().flatten().join(", "),
+                ),
+                (
+                    "Passed Through Env Vars Values",
+                    env.pass_through.join(", "),
+                ),
+            ]);
+            // A definition that can't be rendered shouldn't block the rest of
+            // the output
+            if let Ok(definition) = serde_json::to_string(&task.resolved_task_definition) {
+                rows.push(("ResolvedTaskDefinition", definition));
+            }
+            rows.push(("Framework", task.framework.clone()));
+
+            let rows = rows
+                .into_iter()
+                .map(|(key, value)| {
+                    vec![format!("  {}", key), "=".to_string(), value, String::new()]
+                })
+                .collect::<Vec<_>>();
+            for line in tabulate(&rows) {
+                writeln!(out, "{}", ui.apply(GREY.apply_to(line))).unwrap();
+            }
+        }
+
+        out
+    }
+}
+
+fn heading(out: &mut String, ui: &UI, text: &str) {
+    writeln!(out, "{}", ui.apply(CYAN.apply_to(BOLD.apply_to(text)))).unwrap();
+}
+
+// Lines up the cells of each row into columns separated by a single space.
+// Every cell but the last one in a row is padded to the width of its column,
+// the same way Go's tabwriter lays out the dry run.
+fn tabulate(rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths = Vec::new();
+    for row in rows {
+        let Some((_, cells)) = row.split_last() else {
+            continue;
+        };
+        for (column, cell) in cells.iter().enumerate() {
+            let width = cell.chars().count();
+            match widths.get_mut(column) {
+                Some(max) if *max < width => *max = width,
+                Some(_) => {}
+                None => widths.push(width),
+            }
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            let Some((last, cells)) = row.split_last() else {
+                return String::new();
+            };
+            let mut line = String::new();
+            for (cell, width) in cells.iter().zip(&widths) {
+                write!(line, "{:<width$} ", cell, width = width).unwrap();
+            }
+            line.push_str(last);
+            line
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tabulate() {
+        let rows = vec![
+            vec![
+                "  Task".to_string(),
+                "=".to_string(),
+                "build".to_string(),
+                String::new(),
+            ],
+            vec![
+                "  Log File".to_string(),
+                "=".to_string(),
+                ".turbo/turbo-build.log".to_string(),
+                String::new(),
+            ],
+        ];
+        assert_eq!(
+            tabulate(&rows),
+            vec![
+                "  Task     = build                  ",
+                "  Log File = .turbo/turbo-build.log ",
+            ]
+        );
+    }
+}
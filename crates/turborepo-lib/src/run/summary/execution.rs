@@ -0,0 +1,236 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local};
+use serde::{Serialize, Serializer};
+
+// The events a task reports as it moves through the executor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExecutionEvent {
+    Building,
+    BuildStopped,
+    Built,
+    Cached,
+    BuildFailed,
+}
+
+/// The state of the entire `turbo run`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionSummary {
+    // a synthesized turbo command to produce this invocation
+    command: String,
+    // the (possibly empty) path from the turborepo root to where the command
+    // was run
+    repo_path: String,
+    // number of tasks that exited successfully (does not include cache hits)
+    success: usize,
+    // number of tasks that exited with failure
+    failed: usize,
+    // number of tasks that had a cache hit
+    cached: usize,
+    // number of tasks that started
+    attempted: usize,
+    #[serde(serialize_with = "serialize_millis")]
+    start_time: DateTime<Local>,
+    #[serde(serialize_with = "serialize_millis")]
+    end_time: DateTime<Local>,
+    exit_code: i32,
+}
+
+impl ExecutionSummary {
+    pub fn new(command: String, repo_path: String, start_time: DateTime<Local>) -> Self {
+        Self {
+            command,
+            repo_path,
+            success: 0,
+            failed: 0,
+            cached: 0,
+            attempted: 0,
+            start_time,
+            end_time: start_time,
+            exit_code: 0,
+        }
+    }
+
+    pub fn finish(&mut self, exit_code: i32) {
+        self.end_time = Local::now();
+        self.exit_code = exit_code;
+    }
+
+    pub fn attempted(&self) -> usize {
+        self.attempted
+    }
+
+    /// Tasks that either ran successfully or were restored from the cache
+    pub fn successful(&self) -> usize {
+        self.success + self.cached
+    }
+
+    pub fn cached(&self) -> usize {
+        self.cached
+    }
+
+    pub fn duration(&self) -> chrono::Duration {
+        self.end_time - self.start_time
+    }
+
+    fn record(&mut self, event: ExecutionEvent) {
+        match event {
+            ExecutionEvent::Building => self.attempted += 1,
+            ExecutionEvent::BuildFailed => self.failed += 1,
+            ExecutionEvent::Cached => self.cached += 1,
+            ExecutionEvent::Built => self.success += 1,
+            ExecutionEvent::BuildStopped => {}
+        }
+    }
+}
+
+/// Timing and outcome of a single task
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskExecutionSummary {
+    #[serde(serialize_with = "serialize_millis")]
+    start_time: DateTime<Local>,
+    #[serde(serialize_with = "serialize_millis")]
+    end_time: DateTime<Local>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    // None if the task never exited on its own
+    exit_code: Option<i32>,
+}
+
+impl TaskExecutionSummary {
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Whether the task failed to run or exited unsuccessfully
+    pub fn is_failure(&self) -> bool {
+        self.error.is_some() || self.exit_code.map_or(false, |code| code != 0)
+    }
+}
+
+/// Records the execution of a single task into the run's `ExecutionSummary`.
+/// Each of the terminal methods consumes the tracker, so a task can only
+/// finish once.
+pub struct TaskTracker<'a> {
+    execution: &'a Mutex<ExecutionSummary>,
+    start_time: DateTime<Local>,
+}
+
+impl<'a> TaskTracker<'a> {
+    pub fn new(execution: &'a Mutex<ExecutionSummary>) -> Self {
+        Self {
+            execution,
+            start_time: Local::now(),
+        }
+    }
+
+    /// Marks the task as attempted, called once we know it's going to run
+    pub fn start(&self) {
+        self.record(ExecutionEvent::Building);
+    }
+
+    pub fn built(self, exit_code: i32) -> TaskExecutionSummary {
+        self.finish(ExecutionEvent::Built, None, Some(exit_code))
+    }
+
+    pub fn cached(self) -> TaskExecutionSummary {
+        self.finish(ExecutionEvent::Cached, None, Some(0))
+    }
+
+    pub fn failed(self, error: String, exit_code: Option<i32>) -> TaskExecutionSummary {
+        self.finish(ExecutionEvent::BuildFailed, Some(error), exit_code)
+    }
+
+    /// The task was killed because the run is shutting down
+    pub fn stopped(self) -> TaskExecutionSummary {
+        self.finish(ExecutionEvent::BuildStopped, None, None)
+    }
+
+    fn record(&self, event: ExecutionEvent) {
+        self.execution
+            .lock()
+            .expect("execution summary lock poisoned")
+            .record(event);
+    }
+
+    fn finish(
+        self,
+        event: ExecutionEvent,
+        error: Option<String>,
+        exit_code: Option<i32>,
+    ) -> TaskExecutionSummary {
+        self.record(event);
+        TaskExecutionSummary {
+            start_time: self.start_time,
+            end_time: Local::now(),
+            error,
+            exit_code,
+        }
+    }
+}
+
+// Times are written as milliseconds since the epoch
+fn serialize_millis<S: Serializer>(
+    time: &DateTime<Local>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_i64(time.timestamp_millis())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_execution_counts() {
+        let execution = Mutex::new(ExecutionSummary::new(
+            "turbo run build".to_string(),
+            String::new(),
+            Local::now(),
+        ));
+
+        let built = TaskTracker::new(&execution);
+        built.start();
+        assert_eq!(built.built(0).exit_code(), Some(0));
+
+        let cached = TaskTracker::new(&execution);
+        cached.start();
+        cached.cached();
+
+        let failed = TaskTracker::new(&execution);
+        failed.start();
+        let failed = failed.failed("exited (1)".to_string(), Some(1));
+        assert!(failed.is_failure());
+
+        let stopped = TaskTracker::new(&execution);
+        stopped.start();
+        assert!(!stopped.stopped().is_failure());
+
+        let execution = execution.into_inner().unwrap();
+        assert_eq!(execution.attempted(), 4);
+        assert_eq!(execution.successful(), 2);
+        assert_eq!(execution.cached(), 1);
+        assert_eq!(execution.failed, 1);
+    }
+
+    #[test]
+    fn test_task_execution_json() {
+        let execution = Mutex::new(ExecutionSummary::new(
+            "turbo run build".to_string(),
+            String::new(),
+            Local::now(),
+        ));
+        let tracker = TaskTracker::new(&execution);
+        tracker.start();
+        let summary = serde_json::to_value(tracker.built(0)).unwrap();
+
+        let mut keys = summary.as_object().unwrap().keys().collect::<Vec<_>>();
+        keys.sort();
+        // `error` is left out entirely when the task succeeded
+        assert_eq!(keys, vec!["endTime", "exitCode", "startTime"]);
+        assert_eq!(summary["exitCode"], 0);
+        assert!(summary["startTime"].is_i64());
+    }
+}
@@ -0,0 +1,35 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use turbopath::RelativeUnixPathBuf;
+use turborepo_env::EnvironmentVariablePairs;
+
+/// The environment variable configuration for the global hash
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalEnvConfiguration {
+    pub env: Vec<String>,
+    pub pass_through_env: Option<Vec<String>>,
+}
+
+/// The environment variables that impacted the global hash. Values are hashed
+/// so that secrets don't end up in the summary.
+#[derive(Debug, Serialize)]
+pub struct GlobalEnvVarSummary {
+    pub specified: GlobalEnvConfiguration,
+    pub configured: EnvironmentVariablePairs,
+    pub inferred: EnvironmentVariablePairs,
+    #[serde(rename = "passthrough")]
+    pub pass_through: EnvironmentVariablePairs,
+}
+
+/// The inputs to the global hash, which in turn feeds into every task hash
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalHashSummary {
+    pub root_key: &'static str,
+    pub files: BTreeMap<RelativeUnixPathBuf, String>,
+    pub hash_of_external_dependencies: String,
+    pub global_dot_env: Vec<RelativeUnixPathBuf>,
+    pub environment_variables: GlobalEnvVarSummary,
+}
@@ -0,0 +1,625 @@
+//! Summaries of `turbo run`, written to `.turbo/runs/<id>.json` with
+//! `--summarize` and printed as end-of-run stats. The JSON schema matches the
+//! one written by the Go implementation, since it's parsed by other tools.
+
+mod dry_run;
+mod execution;
+mod global_hash;
+mod scm;
+mod task;
+
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::{DateTime, Local};
+pub use execution::{ExecutionSummary, TaskExecutionSummary, TaskTracker};
+use futures::{stream, StreamExt};
+pub use global_hash::{GlobalEnvConfiguration, GlobalEnvVarSummary, GlobalHashSummary};
+use itertools::Itertools;
+pub use scm::SCMState;
+use serde::{Serialize, Serializer};
+pub use task::{
+    ResolvedTaskDefinition, TaskCacheSummary, TaskEnvConfiguration, TaskEnvVarSummary, TaskSummary,
+};
+use thiserror::Error;
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+use turborepo_cache::async_cache::AsyncCache;
+use turborepo_env::EnvironmentVariableMap;
+use turborepo_scm::SCM;
+
+use crate::{
+    cli::EnvMode,
+    opts::Opts,
+    package_graph::PackageGraph,
+    ui::{BOLD, BOLD_GREEN, BOLD_RED, GREY, UI, YELLOW},
+};
+
+// When changing this, the consumers of run summaries need to be updated to
+// handle the new version first, unknown versions get ignored.
+const RUN_SUMMARY_SCHEMA_VERSION: &str = "1";
+
+/// Shown in dry runs in place of the command for tasks that are missing a
+/// script in their workspace's `package.json`
+pub const MISSING_TASK_LABEL: &str = "<NONEXISTENT>";
+/// Shown in dry runs for workspaces without a detected framework
+pub const NO_FRAMEWORK_DETECTED: &str = "<NO FRAMEWORK DETECTED>";
+/// Shown in dry runs when framework inference was turned off
+pub const FRAMEWORK_DETECTION_SKIPPED: &str = "<FRAMEWORK DETECTION SKIPPED>";
+
+// The most cache lookups a dry run makes at once
+const MAX_PARALLEL_CACHE_REQUESTS: usize = 8;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to write run summary: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to render run summary: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// What happened during a `turbo run` and why
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunSummary {
+    id: String,
+    version: &'static str,
+    turbo_version: String,
+    monorepo: bool,
+    global_cache_inputs: GlobalHashSummary,
+    // Single package repos don't have packages, so the field is left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    packages: Option<Vec<String>>,
+    #[serde(serialize_with = "serialize_env_mode")]
+    env_mode: EnvMode,
+    framework_inference: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    execution: Option<ExecutionSummary>,
+    tasks: Vec<TaskSummary>,
+    user: String,
+    scm: SCMState,
+}
+
+impl RunSummary {
+    fn path(&self, repo_root: &AbsoluteSystemPath) -> AbsoluteSystemPathBuf {
+        repo_root.join_components(&[".turbo", "runs", &format!("{}.json", self.id)])
+    }
+
+    // Puts the summary in the shape it gets rendered in
+    fn normalize(&mut self) {
+        if !self.monorepo {
+            self.packages = None;
+            for task in &mut self.tasks {
+                task.clean_for_single_package();
+            }
+        }
+        self.tasks.sort_by(|a, b| a.task_id.cmp(&b.task_id));
+    }
+
+    fn save(&self, repo_root: &AbsoluteSystemPath) -> Result<(), Error> {
+        let path = self.path(repo_root);
+        path.ensure_dir()?;
+        path.create_with_contents(&serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn failed_tasks(&self) -> Vec<&str> {
+        self.tasks
+            .iter()
+            .filter(|task| {
+                task.execution
+                    .as_ref()
+                    .map_or(false, |execution| execution.is_failure())
+            })
+            .map(|task| task.task_id.as_str())
+            .sorted()
+            .collect()
+    }
+
+    fn print_execution_summary(&self, repo_root: &AbsoluteSystemPath, ui: &UI) {
+        let Some(execution) = &self.execution else {
+            return;
+        };
+        let attempted = execution.attempted();
+        let cached = execution.cached();
+
+        let full_turbo = match cached == attempted && attempted > 0 {
+            true => ui.rainbow(">>> FULL TURBO").to_string(),
+            false => String::new(),
+        };
+
+        let mut lines = vec![
+            (
+                "Tasks",
+                format!(
+                    "{}{}",
+                    ui.apply(BOLD_GREEN.apply_to(format!("{} successful", execution.successful()))),
+                    ui.apply(GREY.apply_to(format!(", {} total", attempted)))
+                ),
+            ),
+            (
+                "Cached",
+                format!(
+                    "{}{}",
+                    ui.apply(BOLD.apply_to(format!("{} cached", cached))),
+                    ui.apply(GREY.apply_to(format!(", {} total", attempted)))
+                ),
+            ),
+            (
+                "Time",
+                format!(
+                    "{} {}",
+                    ui.apply(BOLD.apply_to(format_duration(execution.duration()))),
+                    full_turbo
+                ),
+            ),
+        ];
+
+        let path = self.path(repo_root);
+        if path.exists() {
+            lines.push(("Summary", ui.apply(BOLD.apply_to(path)).to_string()));
+        }
+
+        let failed = self.failed_tasks();
+        if !failed.is_empty() {
+            lines.push((
+                "Failed",
+                failed
+                    .iter()
+                    .map(|task_id| ui.apply(BOLD_RED.apply_to(task_id)).to_string())
+                    .join(", "),
+            ));
+        }
+
+        if attempted == 0 {
+            println!();
+            eprintln!(
+                "{}",
+                ui.apply(YELLOW.apply_to("No tasks were executed as part of this run."))
+            );
+        }
+
+        println!();
+        let header_width = lines
+            .iter()
+            .map(|(header, _)| header.len())
+            .max()
+            .unwrap_or_default();
+        for (header, trailer) in lines {
+            println!(
+                "{}    {}",
+                ui.apply(BOLD.apply_to(format!("{:>width$}:", header, width = header_width))),
+                trailer
+            );
+        }
+        println!();
+    }
+}
+
+/// Collects the summary of a run as it executes. Tasks report their execution
+/// through `track_task` and their summary through `add_task`, everything is
+/// written out once the run finishes.
+#[derive(Debug)]
+pub struct RunTracker {
+    summary: RunSummary,
+    execution: Mutex<ExecutionSummary>,
+    tasks: Mutex<Vec<TaskSummary>>,
+    repo_root: AbsoluteSystemPathBuf,
+    should_save: bool,
+}
+
+impl RunTracker {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        started_at: DateTime<Local>,
+        repo_root: &AbsoluteSystemPath,
+        opts: &Opts,
+        turbo_version: &str,
+        packages: Vec<String>,
+        env_mode: EnvMode,
+        global_hash_summary: GlobalHashSummary,
+        env_at_execution_start: &EnvironmentVariableMap,
+        scm: &SCM,
+    ) -> Self {
+        let repo_path = opts
+            .scope_opts
+            .pkg_inference_root
+            .as_ref()
+            .map(|root| root.to_string())
+            .unwrap_or_default();
+        let execution = ExecutionSummary::new(opts.synthesize_command(), repo_path, started_at);
+
+        Self {
+            summary: RunSummary {
+                id: ksuid(started_at.timestamp(), rand::random()),
+                version: RUN_SUMMARY_SCHEMA_VERSION,
+                turbo_version: turbo_version.to_string(),
+                monorepo: !opts.run_opts.single_package,
+                global_cache_inputs: global_hash_summary,
+                packages: Some(packages),
+                env_mode,
+                framework_inference: opts.run_opts.framework_inference,
+                execution: None,
+                tasks: Vec::new(),
+                user: scm::get_user(env_at_execution_start),
+                scm: SCMState::get(env_at_execution_start, scm, repo_root),
+            },
+            execution: Mutex::new(execution),
+            tasks: Mutex::default(),
+            repo_root: repo_root.to_owned(),
+            should_save: opts.run_opts.summarize,
+        }
+    }
+
+    /// Starts tracking the execution of a task
+    pub fn track_task(&self) -> TaskTracker {
+        TaskTracker::new(&self.execution)
+    }
+
+    /// Adds a task that was part of the run to the summary
+    pub fn add_task(&self, task: TaskSummary) {
+        self.tasks
+            .lock()
+            .expect("run summary lock poisoned")
+            .push(task);
+    }
+
+    /// Wraps up the run, saving the summary if `--summarize` was passed and
+    /// printing the end-of-run stats
+    pub fn finish(self, exit_code: i32, ui: &UI) {
+        let mut execution = self
+            .execution
+            .into_inner()
+            .expect("execution summary lock poisoned");
+        execution.finish(exit_code);
+
+        let mut summary = self.summary;
+        summary.execution = Some(execution);
+        summary.tasks = self.tasks.into_inner().expect("run summary lock poisoned");
+        summary.normalize();
+
+        // Failing to save the summary shouldn't fail the run
+        if self.should_save {
+            if let Err(err) = summary.save(&self.repo_root) {
+                eprintln!(
+                    "{}",
+                    ui.apply(YELLOW.apply_to(format!("Error writing run summary: {}", err)))
+                );
+            }
+        }
+
+        summary.print_execution_summary(&self.repo_root, ui);
+    }
+
+    /// Wraps up `turbo run --dry`, filling in whether each task is already
+    /// cached and printing the summary as either text or JSON. Nothing gets
+    /// saved for dry runs.
+    pub async fn finish_dry_run(
+        self,
+        cache: &AsyncCache,
+        package_graph: &PackageGraph,
+        is_json: bool,
+        ui: &UI,
+    ) -> Result<(), Error> {
+        let mut summary = self.summary;
+        summary.tasks = self.tasks.into_inner().expect("run summary lock poisoned");
+
+        for task in &mut summary.tasks {
+            if task.command.is_empty() {
+                task.command = MISSING_TASK_LABEL.to_string();
+            }
+            if task.framework.is_empty() {
+                task.framework = match summary.framework_inference {
+                    true => NO_FRAMEWORK_DETECTED,
+                    false => FRAMEWORK_DETECTION_SKIPPED,
+                }
+                .to_string();
+            }
+        }
+
+        let cache_summaries = stream::iter(&summary.tasks)
+            .map(|task| async move {
+                // Failing to reach the cache is the same as a miss here
+                let response = cache.exists(&task.hash).await.ok();
+                TaskCacheSummary::from(response.as_ref())
+            })
+            .buffered(MAX_PARALLEL_CACHE_REQUESTS)
+            .collect::<Vec<_>>()
+            .await;
+        for (task, cache_summary) in summary.tasks.iter_mut().zip(cache_summaries) {
+            task.cache = cache_summary;
+        }
+
+        summary.normalize();
+
+        if is_json {
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+        } else {
+            let package_dirs = package_graph
+                .workspaces()
+                .map(|(name, entry)| (name.to_string(), entry.package_path().to_string()))
+                .collect::<HashMap<_, _>>();
+            print!("{}", summary.format_dry_run_text(&package_dirs, ui));
+        }
+
+        Ok(())
+    }
+}
+
+// Env modes are written in lowercase in summaries
+fn serialize_env_mode<S: Serializer>(env_mode: &EnvMode, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(match env_mode {
+        EnvMode::Infer => "infer",
+        EnvMode::Loose => "loose",
+        EnvMode::Strict => "strict",
+    })
+}
+
+// KSUIDs are a timestamp relative to this epoch followed by a random payload,
+// so run ids sort by when the run started
+const KSUID_EPOCH: i64 = 1_400_000_000;
+const KSUID_LENGTH: usize = 27;
+const BASE62: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+fn ksuid(timestamp: i64, payload: [u8; 16]) -> String {
+    let mut bytes = [0; 20];
+    bytes[..4].copy_from_slice(&((timestamp - KSUID_EPOCH) as u32).to_be_bytes());
+    bytes[4..].copy_from_slice(&payload);
+    encode_base62(bytes)
+}
+
+// Encodes the bytes as a big-endian number, left padded to a fixed length
+fn encode_base62(mut number: [u8; 20]) -> String {
+    let mut digits = Vec::with_capacity(KSUID_LENGTH);
+    while number.iter().any(|byte| *byte != 0) {
+        let mut remainder = 0u32;
+        for byte in number.iter_mut() {
+            let accumulator = (remainder << 8) | *byte as u32;
+            *byte = (accumulator / 62) as u8;
+            remainder = accumulator % 62;
+        }
+        digits.push(BASE62[remainder as usize]);
+    }
+    digits.resize(KSUID_LENGTH, b'0');
+    digits.reverse();
+    String::from_utf8(digits).expect("base62 digits are ascii")
+}
+
+// Matches the formatting of Go's time.Duration truncated to milliseconds,
+// e.g. 350ms, 1.5s or 1m2.034s
+fn format_duration(duration: chrono::Duration) -> String {
+    let millis = duration.num_milliseconds().max(0);
+    if millis == 0 {
+        return "0s".to_string();
+    }
+    if millis < 1000 {
+        return format!("{}ms", millis);
+    }
+
+    let hours = millis / 3_600_000;
+    let minutes = millis / 60_000 % 60;
+    let seconds = millis / 1000 % 60;
+    let fraction = millis % 1000;
+
+    let mut formatted = String::new();
+    if hours > 0 {
+        formatted.push_str(&format!("{}h", hours));
+    }
+    if hours > 0 || minutes > 0 {
+        formatted.push_str(&format!("{}m", minutes));
+    }
+    match fraction {
+        0 => formatted.push_str(&format!("{}s", seconds)),
+        _ => {
+            let seconds = format!("{}.{:03}", seconds, fraction);
+            formatted.push_str(seconds.trim_end_matches('0'));
+            formatted.push('s');
+        }
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case([0; 20], "000000000000000000000000000" ; "min")]
+    #[test_case([0xff; 20], "aWgEPTl1tmebfsQzFP4bxwgy80V" ; "max")]
+    fn test_encode_base62(bytes: [u8; 20], expected: &str) {
+        assert_eq!(encode_base62(bytes), expected);
+    }
+
+    #[test]
+    fn test_ksuid() {
+        let payload = [
+            0xb5, 0xa1, 0xcd, 0x34, 0xb5, 0xf9, 0x9d, 0x11, 0x54, 0xfb, 0x68, 0x53, 0x34, 0x5c,
+            0x97, 0x35,
+        ];
+        assert_eq!(
+            ksuid(KSUID_EPOCH + 107608047, payload),
+            "0ujtsYcgvSTl8PAuAdqWYSMnLOv"
+        );
+    }
+
+    #[test_case(0, "0s" ; "zero")]
+    #[test_case(350, "350ms" ; "milliseconds")]
+    #[test_case(1000, "1s" ; "whole seconds")]
+    #[test_case(1500, "1.5s" ; "fractional seconds")]
+    #[test_case(62_034, "1m2.034s" ; "minutes")]
+    #[test_case(3_600_000, "1h0m0s" ; "hours")]
+    fn test_format_duration(millis: i64, expected: &str) {
+        assert_eq!(
+            format_duration(chrono::Duration::milliseconds(millis)),
+            expected
+        );
+    }
+
+    fn task_summary(task_id: &str, dependencies: &[&str]) -> TaskSummary {
+        let (package, task) = task_id.split_once('#').unwrap();
+        TaskSummary {
+            task_id: task_id.to_string(),
+            task: task.to_string(),
+            package: package.to_string(),
+            hash: "0123456789abcdef".to_string(),
+            inputs: BTreeMap::new(),
+            hash_of_external_dependencies: String::new(),
+            cache: TaskCacheSummary::from(None),
+            command: "next build".to_string(),
+            cli_arguments: Vec::new(),
+            outputs: Vec::new(),
+            excluded_outputs: Vec::new(),
+            log_file: ".turbo/turbo-build.log".to_string(),
+            directory: ".".to_string(),
+            dependencies: dependencies.iter().map(|dep| dep.to_string()).collect(),
+            dependents: Vec::new(),
+            resolved_task_definition: ResolvedTaskDefinition::from(&Default::default()),
+            expanded_outputs: Vec::new(),
+            framework: String::new(),
+            env_mode: EnvMode::Loose,
+            environment_variables: TaskEnvVarSummary {
+                specified: TaskEnvConfiguration {
+                    env: Vec::new(),
+                    pass_through_env: None,
+                },
+                configured: Vec::new(),
+                inferred: Vec::new(),
+                pass_through: Vec::new(),
+            },
+            dot_env: Vec::new(),
+            execution: None,
+        }
+    }
+
+    fn run_summary(monorepo: bool, tasks: Vec<TaskSummary>) -> RunSummary {
+        RunSummary {
+            id: ksuid(KSUID_EPOCH, [0; 16]),
+            version: RUN_SUMMARY_SCHEMA_VERSION,
+            turbo_version: "1.10.0".to_string(),
+            monorepo,
+            global_cache_inputs: GlobalHashSummary {
+                root_key: "root key",
+                files: BTreeMap::new(),
+                hash_of_external_dependencies: String::new(),
+                global_dot_env: Vec::new(),
+                environment_variables: GlobalEnvVarSummary {
+                    specified: GlobalEnvConfiguration {
+                        env: Vec::new(),
+                        pass_through_env: None,
+                    },
+                    configured: Vec::new(),
+                    inferred: Vec::new(),
+                    pass_through: Vec::new(),
+                },
+            },
+            packages: Some(vec!["web".to_string()]),
+            env_mode: EnvMode::Infer,
+            framework_inference: true,
+            execution: None,
+            tasks,
+            user: String::new(),
+            scm: SCMState::get(
+                &EnvironmentVariableMap::default(),
+                &SCM::Manual,
+                &AbsoluteSystemPathBuf::cwd().unwrap(),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_run_summary_schema() {
+        let mut summary = run_summary(
+            true,
+            vec![
+                task_summary("web#build", &["ui#build"]),
+                task_summary("ui#build", &[]),
+            ],
+        );
+        summary.normalize();
+        let json = serde_json::to_value(&summary).unwrap();
+
+        let mut keys = json.as_object().unwrap().keys().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "envMode",
+                "frameworkInference",
+                "globalCacheInputs",
+                "id",
+                "monorepo",
+                "packages",
+                "scm",
+                "tasks",
+                "turboVersion",
+                "user",
+                "version"
+            ]
+        );
+        assert_eq!(json["envMode"], "infer");
+        assert_eq!(json["version"], "1");
+        assert_eq!(json["tasks"][0]["taskId"], "ui#build");
+        assert_eq!(json["tasks"][1]["package"], "web");
+        assert_eq!(json["tasks"][1]["envMode"], "loose");
+        assert_eq!(json["tasks"][1]["cache"]["status"], "MISS");
+    }
+
+    #[test]
+    fn test_single_package_summary() {
+        let mut summary = run_summary(false, vec![task_summary("//#build", &["//#codegen"])]);
+        summary.normalize();
+        let json = serde_json::to_value(&summary).unwrap();
+
+        assert!(json.get("packages").is_none());
+        let task = &json["tasks"][0];
+        assert_eq!(task["taskId"], "build");
+        assert_eq!(task["task"], "build");
+        assert_eq!(task["dependencies"], serde_json::json!(["codegen"]));
+        assert!(task.get("package").is_none());
+        assert!(task.get("directory").is_none());
+    }
+
+    #[test]
+    fn test_dry_run_text() {
+        let mut summary = run_summary(true, vec![task_summary("web#build", &["ui#build"])]);
+        summary.normalize();
+        let package_dirs = HashMap::from([("web".to_string(), "apps/web".to_string())]);
+
+        let text = summary.format_dry_run_text(&package_dirs, &UI::new(true));
+
+        assert!(text.contains("Packages in Scope\nName Path     \nweb  apps/web \n"));
+        assert!(text.contains("  Global Files                          = 0\n"));
+        assert!(text.contains("Tasks to Run\nweb#build\n"));
+        assert!(text.contains("  Package                        = web "));
+        assert!(text.contains("  Dependencies                   = ui#build "));
+    }
+
+    #[test]
+    fn test_dry_run_text_single_package() {
+        let mut summary = run_summary(false, vec![task_summary("//#build", &[])]);
+        summary.normalize();
+
+        let text = summary.format_dry_run_text(&HashMap::new(), &UI::new(true));
+
+        assert!(!text.contains("Packages in Scope"));
+        assert!(text.contains("Tasks to Run\nbuild\n"));
+        assert!(!text.contains("  Package "));
+        assert!(!text.contains("  Directory "));
+    }
+
+    #[test]
+    fn test_save_run_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::try_from(dir.path()).unwrap();
+        let summary = run_summary(true, Vec::new());
+
+        summary.save(&repo_root).unwrap();
+
+        let path =
+            repo_root.join_components(&[".turbo", "runs", "000000000000000000000000000.json"]);
+        let contents = std::fs::read_to_string(path).unwrap();
+        let saved: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(saved["id"], "000000000000000000000000000");
+    }
+}
@@ -0,0 +1,133 @@
+use serde::Serialize;
+use tracing::debug;
+use turbopath::AbsoluteSystemPath;
+use turborepo_env::EnvironmentVariableMap;
+use turborepo_scm::SCM;
+
+// CI providers that expose the commit, branch and user that triggered the
+// build through environment variables
+struct CiVendor {
+    // Any of these being set means we're running on this vendor
+    env: &'static [&'static str],
+    sha_env_var: &'static str,
+    branch_env_var: &'static str,
+    username_env_var: &'static str,
+}
+
+const CI_VENDORS: [CiVendor; 2] = [
+    CiVendor {
+        env: &["GITHUB_ACTIONS"],
+        sha_env_var: "GITHUB_SHA",
+        branch_env_var: "GITHUB_REF_NAME",
+        username_env_var: "GITHUB_ACTOR",
+    },
+    CiVendor {
+        env: &["NOW_BUILDER", "VERCEL"],
+        sha_env_var: "VERCEL_GIT_COMMIT_SHA",
+        branch_env_var: "VERCEL_GIT_COMMIT_REF",
+        username_env_var: "VERCEL_GIT_COMMIT_AUTHOR_LOGIN",
+    },
+];
+
+fn ci_vendor(env: &EnvironmentVariableMap) -> Option<&'static CiVendor> {
+    CI_VENDORS
+        .iter()
+        .find(|vendor| vendor.env.iter().any(|var| env.contains_key(*var)))
+}
+
+/// The commit the run happened at
+#[derive(Debug, Serialize)]
+pub struct SCMState {
+    #[serde(rename = "type")]
+    ty: &'static str,
+    sha: String,
+    branch: String,
+}
+
+impl SCMState {
+    /// Prefers the values provided by the CI vendor, falling back to asking
+    /// git. Anything we can't figure out is left empty.
+    pub fn get(env: &EnvironmentVariableMap, scm: &SCM, dir: &AbsoluteSystemPath) -> Self {
+        let vendor_var = |var: Option<&str>| {
+            var.and_then(|var| env.get(var))
+                .filter(|value| !value.is_empty())
+                .cloned()
+        };
+        let vendor = ci_vendor(env);
+
+        let sha = vendor_var(vendor.map(|vendor| vendor.sha_env_var)).unwrap_or_else(|| {
+            scm.get_current_sha(dir).unwrap_or_else(|err| {
+                debug!("unable to get current sha: {}", err);
+                String::new()
+            })
+        });
+        let branch = vendor_var(vendor.map(|vendor| vendor.branch_env_var)).unwrap_or_else(|| {
+            scm.get_current_branch(dir).unwrap_or_else(|err| {
+                debug!("unable to get current branch: {}", err);
+                String::new()
+            })
+        });
+
+        Self {
+            ty: "git",
+            sha,
+            branch,
+        }
+    }
+}
+
+/// The user that triggered the run, only known when running in CI
+pub fn get_user(env: &EnvironmentVariableMap) -> String {
+    ci_vendor(env)
+        .and_then(|vendor| env.get(vendor.username_env_var))
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> EnvironmentVariableMap {
+        EnvironmentVariableMap::from(
+            vars.iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<HashMap<_, _>>(),
+        )
+    }
+
+    #[test]
+    fn test_scm_state_from_ci() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = AbsoluteSystemPathBuf::try_from(dir.path()).unwrap();
+        let env = env(&[
+            ("GITHUB_ACTIONS", "true"),
+            ("GITHUB_SHA", "abc123"),
+            ("GITHUB_REF_NAME", "main"),
+            ("GITHUB_ACTOR", "octocat"),
+        ]);
+
+        let state = SCMState::get(&env, &SCM::Manual, &dir);
+        assert_eq!(
+            serde_json::to_value(state).unwrap(),
+            serde_json::json!({ "type": "git", "sha": "abc123", "branch": "main" })
+        );
+        assert_eq!(get_user(&env), "octocat");
+    }
+
+    #[test]
+    fn test_scm_state_without_git() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = AbsoluteSystemPathBuf::try_from(dir.path()).unwrap();
+        let env = env(&[]);
+
+        let state = SCMState::get(&env, &SCM::Manual, &dir);
+        assert_eq!(state.sha, "");
+        assert_eq!(state.branch, "");
+        assert_eq!(get_user(&env), "");
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use turbopath::RelativeUnixPathBuf;
+use turborepo_cache::{CacheResponse, CacheSource};
+use turborepo_env::EnvironmentVariablePairs;
+
+use super::{execution::TaskExecutionSummary, serialize_env_mode};
+use crate::{
+    cli::EnvMode,
+    run::task_id::strip_package_name,
+    task_graph::{TaskDefinitionHashable, TaskOutputMode},
+};
+
+/// Where a task's outputs came from, along with the deprecated `local` and
+/// `remote` flags that are still read by `--dry=json` consumers
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskCacheSummary {
+    pub local: bool,
+    pub remote: bool,
+    status: CacheStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<CacheSummarySource>,
+    time_saved: u32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum CacheSummarySource {
+    Local,
+    Remote,
+}
+
+impl From<Option<&CacheResponse>> for TaskCacheSummary {
+    fn from(response: Option<&CacheResponse>) -> Self {
+        let source = response.map(|response| match response.source() {
+            CacheSource::Local => CacheSummarySource::Local,
+            CacheSource::Remote => CacheSummarySource::Remote,
+        });
+        Self {
+            local: source == Some(CacheSummarySource::Local),
+            remote: source == Some(CacheSummarySource::Remote),
+            status: match response {
+                Some(_) => CacheStatus::Hit,
+                None => CacheStatus::Miss,
+            },
+            source,
+            time_saved: response.map_or(0, |response| response.time_saved()),
+        }
+    }
+}
+
+/// The environment variable configuration of a task
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskEnvConfiguration {
+    pub env: Vec<String>,
+    pub pass_through_env: Option<Vec<String>>,
+}
+
+/// The environment variables that impacted a task's hash. Values are hashed
+/// so that secrets don't end up in the summary.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskEnvVarSummary {
+    pub specified: TaskEnvConfiguration,
+    pub configured: EnvironmentVariablePairs,
+    pub inferred: EnvironmentVariablePairs,
+    #[serde(rename = "passthrough")]
+    pub pass_through: EnvironmentVariablePairs,
+}
+
+/// A task definition rendered the same way it would be written in
+/// `turbo.json`, with `dependsOn` and `outputs` recombined
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedTaskDefinition {
+    outputs: Vec<String>,
+    cache: bool,
+    depends_on: Vec<String>,
+    inputs: Vec<String>,
+    output_mode: TaskOutputMode,
+    persistent: bool,
+    env: Vec<String>,
+    pass_through_env: Option<Vec<String>>,
+    dot_env: Vec<RelativeUnixPathBuf>,
+}
+
+impl From<&TaskDefinitionHashable> for ResolvedTaskDefinition {
+    fn from(definition: &TaskDefinitionHashable) -> Self {
+        let mut outputs = definition
+            .outputs
+            .inclusions
+            .iter()
+            .cloned()
+            .chain(
+                definition
+                    .outputs
+                    .exclusions
+                    .iter()
+                    .map(|exclusion| format!("!{exclusion}")),
+            )
+            .collect::<Vec<_>>();
+        outputs.sort();
+
+        let mut depends_on = definition
+            .task_dependencies
+            .iter()
+            .cloned()
+            .chain(
+                definition
+                    .topological_dependencies
+                    .iter()
+                    .map(|dependency| format!("^{dependency}")),
+            )
+            .collect::<Vec<_>>();
+        depends_on.sort();
+
+        let pass_through_env = definition.pass_through_env.clone().map(|mut env| {
+            env.sort();
+            env
+        });
+
+        Self {
+            outputs,
+            cache: definition.cache,
+            depends_on,
+            inputs: definition.inputs.clone(),
+            output_mode: definition.output_mode.clone(),
+            persistent: definition.persistent,
+            env: definition.env.clone(),
+            pass_through_env,
+            // The order of .env files matters, so these don't get sorted
+            dot_env: definition.dot_env.clone(),
+        }
+    }
+}
+
+/// Everything we know about a task that was part of the run
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskSummary {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub task_id: String,
+    pub task: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub package: String,
+    pub hash: String,
+    pub inputs: BTreeMap<RelativeUnixPathBuf, String>,
+    pub hash_of_external_dependencies: String,
+    pub cache: TaskCacheSummary,
+    pub command: String,
+    pub cli_arguments: Vec<String>,
+    pub outputs: Vec<String>,
+    pub excluded_outputs: Vec<String>,
+    pub log_file: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub directory: String,
+    pub dependencies: Vec<String>,
+    pub dependents: Vec<String>,
+    pub resolved_task_definition: ResolvedTaskDefinition,
+    pub expanded_outputs: Vec<String>,
+    pub framework: String,
+    #[serde(serialize_with = "serialize_env_mode")]
+    pub env_mode: EnvMode,
+    pub environment_variables: TaskEnvVarSummary,
+    pub dot_env: Vec<RelativeUnixPathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution: Option<TaskExecutionSummary>,
+}
+
+impl TaskSummary {
+    /// Single package repos don't have workspaces, so references to them are
+    /// removed from task ids and the package and directory are dropped
+    pub(super) fn clean_for_single_package(&mut self) {
+        let task = strip_package_name(&self.task_id);
+        for dependency in self
+            .dependencies
+            .iter_mut()
+            .chain(self.dependents.iter_mut())
+        {
+            *dependency = strip_package_name(dependency);
+        }
+        self.task_id = task.clone();
+        self.task = task;
+        self.package = String::new();
+        self.directory = String::new();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::task_graph::TaskOutputs;
+
+    #[test]
+    fn test_resolved_task_definition() {
+        let definition = TaskDefinitionHashable {
+            outputs: TaskOutputs {
+                inclusions: vec!["dist/**".to_string()],
+                exclusions: vec!["dist/cache/**".to_string()],
+            },
+            topological_dependencies: vec!["build".to_string()],
+            task_dependencies: vec!["codegen".to_string()],
+            pass_through_env: Some(vec!["TOKEN".to_string(), "AWS_KEY".to_string()]),
+            output_mode: TaskOutputMode::New,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            serde_json::to_value(ResolvedTaskDefinition::from(&definition)).unwrap(),
+            serde_json::json!({
+                "outputs": ["!dist/cache/**", "dist/**"],
+                "cache": true,
+                "dependsOn": ["^build", "codegen"],
+                "inputs": [],
+                "outputMode": "new-only",
+                "persistent": false,
+                "env": [],
+                "passThroughEnv": ["AWS_KEY", "TOKEN"],
+                "dotEnv": [],
+            })
+        );
+    }
+
+    #[test]
+    fn test_cache_miss_summary() {
+        assert_eq!(
+            serde_json::to_value(TaskCacheSummary::from(None)).unwrap(),
+            serde_json::json!({
+                "local": false,
+                "remote": false,
+                "status": "MISS",
+                "timeSaved": 0,
+            })
+        );
+    }
+}
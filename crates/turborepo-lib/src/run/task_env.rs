@@ -0,0 +1,253 @@
+use std::{collections::BTreeSet, io};
+
+use tokio::process::Command;
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+use turborepo_env::EnvironmentVariableMap;
+
+/// Set for tasks when env access tracing is enabled. A tracer in the task's
+/// runtime appends the name of every environment variable the task reads to
+/// this file, one name per line. Names may repeat and lines that don't name a
+/// variable that was set are ignored, so tracers for other runtimes only need
+/// to append to it. turbo can't observe those reads on its own.
+pub const ENV_ACCESS_LOG_ENV_VAR: &str = "TURBO_ENV_ACCESS_LOG";
+
+// The tracer for node, loaded into every node process of a traced task
+const NODE_TRACER: &str = include_str!("env_access_tracer.cjs");
+
+// Variables that most tools can't run without, passed through in strict mode
+// even though they aren't declared in turbo.json
+const SYSTEM_ENV_VARS: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "SHELL",
+    "TERM",
+    "LANG",
+    "TMPDIR",
+    "TEMP",
+    "TMP",
+    // Windows
+    "SYSTEMROOT",
+    "COMSPEC",
+    "PATHEXT",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "USERPROFILE",
+];
+
+// Environment variable names are case insensitive on Windows
+fn is_system_env_var(name: &str) -> bool {
+    SYSTEM_ENV_VARS
+        .iter()
+        .any(|system_env_var| match cfg!(windows) {
+            true => system_env_var.eq_ignore_ascii_case(name),
+            false => *system_env_var == name,
+        })
+}
+
+/// The environment of a task in strict mode: the global variables, the
+/// variables that went into the task's hash, the task's `passThroughEnv`
+/// variables and the system variables. Everything else is filtered out.
+pub fn strict_env(
+    env_at_execution_start: &EnvironmentVariableMap,
+    global_env: &EnvironmentVariableMap,
+    hashed_env: &EnvironmentVariableMap,
+    pass_through_env: Option<&[String]>,
+) -> Result<EnvironmentVariableMap, regex::Error> {
+    let mut env = EnvironmentVariableMap::from(
+        env_at_execution_start
+            .iter()
+            .filter(|(name, _)| is_system_env_var(name))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect::<std::collections::HashMap<_, _>>(),
+    );
+    env.union(global_env);
+    env.union(hashed_env);
+    env.union(&env_at_execution_start.from_wildcards(pass_through_env.unwrap_or_default())?);
+    Ok(env)
+}
+
+/// Writes the node tracer to the temp dir so that tasks can load it. It's
+/// shared by every run of this version of turbo.
+pub fn install_node_tracer() -> io::Result<AbsoluteSystemPathBuf> {
+    let temp_dir = AbsoluteSystemPathBuf::try_from(std::env::temp_dir())
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    let tracer = temp_dir.join_component(&format!(
+        "turbo-env-access-tracer-{}.cjs",
+        crate::get_version()
+    ));
+    if tracer.exists() {
+        return Ok(tracer);
+    }
+
+    // Concurrent runs can install it at the same time, so it's written next to
+    // its final location and moved into place
+    let partial = temp_dir.join_component(&format!(
+        "turbo-env-access-tracer-{}.cjs.{}",
+        crate::get_version(),
+        std::process::id()
+    ));
+    partial.create_with_contents(NODE_TRACER)?;
+    std::fs::rename(&partial, &tracer)?;
+    Ok(tracer)
+}
+
+/// Points the task at an empty env access log and loads the node tracer into
+/// it. `node_options` are the `NODE_OPTIONS` the task would otherwise get.
+pub fn trace_env_access(
+    command: &mut Command,
+    log_file: &AbsoluteSystemPath,
+    node_tracer: &AbsoluteSystemPath,
+    node_options: Option<&str>,
+) -> io::Result<()> {
+    log_file.ensure_dir()?;
+    match log_file.remove_file() {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    command.env(ENV_ACCESS_LOG_ENV_VAR, log_file.as_std_path());
+    command.env("NODE_OPTIONS", with_required(node_options, node_tracer));
+    // The tracer only records in processes that the package manager runs
+    // scripts in, which it tells apart by this
+    command.env_remove("npm_lifecycle_event");
+    Ok(())
+}
+
+// Adds a `--require` of the script to node options. Paths are quoted, node
+// unescapes backslashes and quotes within them.
+fn with_required(node_options: Option<&str>, script: &AbsoluteSystemPath) -> String {
+    let script = script
+        .to_string()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+    match node_options
+        .map(str::trim)
+        .filter(|options| !options.is_empty())
+    {
+        Some(options) => format!("{} --require \"{}\"", options, script),
+        None => format!("--require \"{}\"", script),
+    }
+}
+
+/// The variables a task read while it ran that aren't part of the environment
+/// it would get in strict mode. Variables that weren't set when the run
+/// started are left out, their absence can't have changed anything.
+pub fn undeclared_env_vars(
+    log_file: &AbsoluteSystemPath,
+    env_at_execution_start: &EnvironmentVariableMap,
+    declared_env: &EnvironmentVariableMap,
+) -> io::Result<Vec<String>> {
+    let log = match std::fs::read_to_string(log_file) {
+        Ok(log) => log,
+        // Nothing got traced
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    Ok(log
+        .lines()
+        .map(str::trim)
+        .filter(|name| {
+            env_at_execution_start.contains_key(*name)
+                && !declared_env.contains_key(*name)
+                && *name != ENV_ACCESS_LOG_ENV_VAR
+        })
+        .map(str::to_string)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use tempfile::tempdir;
+    use test_case::test_case;
+
+    use super::*;
+
+    fn env(names: &[&str]) -> EnvironmentVariableMap {
+        names
+            .iter()
+            .map(|name| (name.to_string(), format!("{}_value", name)))
+            .collect::<HashMap<_, _>>()
+            .into()
+    }
+
+    fn sorted_names(env: &EnvironmentVariableMap) -> Vec<&str> {
+        let mut names = env.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_strict_env() {
+        let env_at_execution_start = env(&[
+            "PATH",
+            "HOME",
+            "CI",
+            "API_URL",
+            "AWS_SECRET_KEY",
+            "GITHUB_TOKEN",
+        ]);
+        let global_env = env(&["CI"]);
+        let hashed_env = env(&["API_URL"]);
+
+        let strict_env = strict_env(
+            &env_at_execution_start,
+            &global_env,
+            &hashed_env,
+            Some(&["AWS_*".to_string()]),
+        )
+        .unwrap();
+        assert_eq!(
+            sorted_names(&strict_env),
+            ["API_URL", "AWS_SECRET_KEY", "CI", "HOME", "PATH"]
+        );
+        assert_eq!(strict_env.get("API_URL").unwrap(), "API_URL_value");
+    }
+
+    #[test]
+    fn test_undeclared_env_vars() {
+        let dir = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::try_from(dir.path()).unwrap();
+        let log_file = repo_root.join_components(&[".turbo", "turbo-build.env-access.log"]);
+        let tracer = repo_root.join_component("tracer.cjs");
+        let mut command = Command::new("node");
+        trace_env_access(&mut command, &log_file, &tracer, None).unwrap();
+        assert_eq!(
+            undeclared_env_vars(&log_file, &env(&[]), &env(&[])).unwrap(),
+            Vec::<String>::new()
+        );
+
+        log_file
+            .create_with_contents("API_URL\nGITHUB_TOKEN\nNOT_SET\nGITHUB_TOKEN\nHOME\n")
+            .unwrap();
+        assert_eq!(
+            undeclared_env_vars(
+                &log_file,
+                &env(&["API_URL", "GITHUB_TOKEN", "HOME"]),
+                &env(&["API_URL", "HOME"])
+            )
+            .unwrap(),
+            vec!["GITHUB_TOKEN"]
+        );
+
+        // Tracing again starts from an empty log
+        trace_env_access(&mut command, &log_file, &tracer, None).unwrap();
+        assert!(!log_file.exists());
+    }
+
+    #[test_case(None, "--require \"/tmp/tracer.cjs\"" ; "no options")]
+    #[test_case(Some(" "), "--require \"/tmp/tracer.cjs\"" ; "blank options")]
+    #[test_case(
+        Some("--max-old-space-size=4096"),
+        "--max-old-space-size=4096 --require \"/tmp/tracer.cjs\""
+        ; "existing options"
+    )]
+    fn test_with_required(node_options: Option<&str>, expected: &str) {
+        let script = AbsoluteSystemPathBuf::new("/tmp/tracer.cjs").unwrap();
+        assert_eq!(with_required(node_options, &script), expected);
+    }
+}
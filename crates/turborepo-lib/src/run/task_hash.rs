@@ -0,0 +1,589 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use thiserror::Error;
+use tracing::debug;
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf};
+use turborepo_env::{BySource, DetailedMap, EnvironmentVariableMap};
+use turborepo_scm::SCM;
+
+use crate::{
+    cli::EnvMode,
+    daemon::{DaemonClient, DaemonConnector},
+    engine::{Engine, TaskNode},
+    framework::{infer_framework, Framework},
+    hash::{FileHashes, LockFilePackages, TaskHashable, TurboHash},
+    package_graph::{Entry, PackageGraph, WorkspaceName},
+    run::{
+        summary::{TaskEnvConfiguration, TaskEnvVarSummary},
+        task_env,
+        task_id::{get_package_task_from_id, ROOT_PKG_NAME},
+    },
+    task_graph::{TaskDefinitionHashable, TaskOutputs},
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("missing pipeline entry {0}")]
+    MissingPipelineEntry(String),
+    #[error("cannot find package {package} for task {task_id}")]
+    MissingPackage { package: String, task_id: String },
+    #[error("cannot find package-file hash for {0}")]
+    MissingPackageFileHash(String),
+    #[error("missing hash for dependent task: {0}")]
+    MissingDependencyTaskHash(String),
+    #[error("missing environment variables for task: {0}")]
+    MissingEnvVars(String),
+    #[error(transparent)]
+    Scm(#[from] turborepo_scm::Error),
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+    #[error(transparent)]
+    Path(#[from] turbopath::PathError),
+}
+
+/// The hash of a workspace's external dependencies. Empty if we weren't able
+/// to compute the workspace's dependency closure from the lockfile.
+pub fn get_external_deps_hash(
+    transitive_dependencies: Option<&HashSet<turborepo_lockfiles::Package>>,
+) -> String {
+    let Some(transitive_dependencies) = transitive_dependencies else {
+        return String::new();
+    };
+    LockFilePackages(transitive_dependencies.iter().cloned().collect()).hash()
+}
+
+// Task ids refer to the root workspace as `//`
+pub fn workspace_name(package: &str) -> WorkspaceName {
+    match package {
+        ROOT_PKG_NAME => WorkspaceName::Root,
+        _ => WorkspaceName::from(package),
+    }
+}
+
+/// A task's env mode only differs from the global one when the global one is
+/// `infer`, in which case configuring `passThroughEnv` for the task opts it
+/// into strict mode.
+pub fn task_env_mode(
+    global_env_mode: EnvMode,
+    task_definition: &TaskDefinitionHashable,
+) -> EnvMode {
+    match global_env_mode {
+        EnvMode::Infer if task_definition.pass_through_env.is_some() => EnvMode::Strict,
+        EnvMode::Infer => EnvMode::Loose,
+        env_mode => env_mode,
+    }
+}
+
+/// The wildcards of the environment variables that a framework exposes to its
+/// client code. The CI vendor's own variables are excluded since they'd
+/// otherwise change the hash of every run.
+pub fn framework_env_wildcards(
+    framework: &Framework,
+    env_at_execution_start: &EnvironmentVariableMap,
+) -> Vec<String> {
+    let mut computed_wildcards = framework
+        .env_wildcards()
+        .iter()
+        .map(|wildcard| wildcard.to_string())
+        .collect::<Vec<_>>();
+    // Vendor excludes are only applied against inferred includes
+    if let Some(exclude_prefix) = env_at_execution_start
+        .get("TURBO_CI_VENDOR_ENV_KEY")
+        .filter(|prefix| !prefix.is_empty())
+    {
+        let computed_exclude = format!("!{}*", exclude_prefix);
+        debug!(
+            "excluding environment variables matching wildcard {}",
+            computed_exclude
+        );
+        computed_wildcards.push(computed_exclude);
+    }
+    computed_wildcards
+}
+
+/// Resolves the environment variables that go into a task's hash: the ones
+/// matching the task's `env` wildcards, plus the ones matching the wildcards of
+/// its framework unless `env` excludes them.
+pub fn task_env_vars(
+    env_at_execution_start: &EnvironmentVariableMap,
+    task_definition: &TaskDefinitionHashable,
+    framework: Option<&Framework>,
+) -> Result<DetailedMap, Error> {
+    let Some(framework) = framework else {
+        let all_env_var_map = env_at_execution_start.from_wildcards(&task_definition.env)?;
+        return Ok(DetailedMap {
+            by_source: BySource {
+                explicit: all_env_var_map.clone(),
+                matching: EnvironmentVariableMap::default(),
+            },
+            all: all_env_var_map,
+        });
+    };
+
+    let inference_env_var_map = env_at_execution_start
+        .from_wildcards(&framework_env_wildcards(framework, env_at_execution_start))?;
+    let user_env_var_set =
+        env_at_execution_start.wildcard_map_from_wildcards_unresolved(&task_definition.env)?;
+
+    let mut all_env_var_map = EnvironmentVariableMap::default();
+    all_env_var_map.union(&user_env_var_set.inclusions);
+    all_env_var_map.union(&inference_env_var_map);
+    all_env_var_map.difference(&user_env_var_set.exclusions);
+
+    let mut explicit_env_var_map = EnvironmentVariableMap::default();
+    explicit_env_var_map.union(&user_env_var_set.inclusions);
+    explicit_env_var_map.difference(&user_env_var_set.exclusions);
+
+    let mut matching_env_var_map = EnvironmentVariableMap::default();
+    matching_env_var_map.union(&inference_env_var_map);
+    matching_env_var_map.difference(&user_env_var_set.exclusions);
+
+    Ok(DetailedMap {
+        all: all_env_var_map,
+        by_source: BySource {
+            explicit: explicit_env_var_map,
+            matching: matching_env_var_map,
+        },
+    })
+}
+
+/// The hashes of the input files of every task, keyed by task id
+#[derive(Debug, Default)]
+pub struct PackageInputsHashes {
+    hashes: HashMap<String, String>,
+    expanded_hashes: HashMap<String, FileHashes>,
+}
+
+impl PackageInputsHashes {
+    /// Hashes the files matching each task's `inputs` along with its `dotEnv`
+    /// files. Needs to happen before any task hashes get calculated.
+    ///
+    /// If a daemon is running, it's asked for the hashes first since it may
+    /// already have them. Should it fail, we stop asking and go to git instead.
+    pub async fn calculate_file_hashes(
+        scm: &SCM,
+        engine: &Engine,
+        package_graph: &PackageGraph,
+        repo_root: &AbsoluteSystemPath,
+        mut daemon: Option<&mut DaemonClient<DaemonConnector>>,
+    ) -> Result<PackageInputsHashes, Error> {
+        let mut hashes = HashMap::new();
+        let mut expanded_hashes = HashMap::new();
+
+        for task_id in engine.tasks() {
+            let task_definition = engine
+                .task_definition(task_id)
+                .ok_or_else(|| Error::MissingPipelineEntry(task_id.to_string()))?;
+            let (package, _) = get_package_task_from_id(task_id);
+            let workspace = package_graph
+                .workspace_info(&workspace_name(&package))
+                .ok_or_else(|| Error::MissingPackage {
+                    package,
+                    task_id: task_id.to_string(),
+                })?;
+
+            let package_path = workspace.package_path().to_owned();
+            let daemon_hashes = match daemon.as_mut() {
+                Some(client) => match client
+                    .get_package_file_hashes(
+                        package_path.to_unix()?.to_string(),
+                        task_definition.inputs.clone(),
+                    )
+                    .await
+                {
+                    Ok(hashes) => Some(hashes),
+                    Err(e) => {
+                        debug!("failed to get file hashes from daemon: {}", e);
+                        daemon = None;
+                        None
+                    }
+                },
+                None => None,
+            };
+            let mut hash_object = match daemon_hashes {
+                Some(hashes) => hashes,
+                None => {
+                    scm.get_package_file_hashes(repo_root, &package_path, &task_definition.inputs)?
+                }
+            };
+
+            // .env files aren't globs so they get hashed separately, relative
+            // to the workspace
+            if !task_definition.dot_env.is_empty() {
+                let absolute_package_path = repo_root.resolve(&package_path);
+                let dot_env_paths = task_definition
+                    .dot_env
+                    .iter()
+                    .map(|path| AnchoredSystemPathBuf::from_raw(path.as_str()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let dot_env_object =
+                    scm.hash_existing_of(&absolute_package_path, dot_env_paths.into_iter())?;
+                hash_object.extend(dot_env_object);
+            }
+
+            let file_hashes = FileHashes(hash_object);
+            hashes.insert(task_id.to_string(), file_hashes.hash());
+            expanded_hashes.insert(task_id.to_string(), file_hashes);
+        }
+
+        Ok(PackageInputsHashes {
+            hashes,
+            expanded_hashes,
+        })
+    }
+
+    pub fn expanded_inputs(&self, task_id: &str) -> Option<&FileHashes> {
+        self.expanded_hashes.get(task_id)
+    }
+}
+
+/// Calculates task hashes. Tasks need to be hashed in topological order since
+/// each hash includes the hashes of the task's dependencies.
+pub struct TaskHasher<'a> {
+    package_inputs_hashes: PackageInputsHashes,
+    env_at_execution_start: &'a EnvironmentVariableMap,
+    global_hash: &'a str,
+    global_env_mode: EnvMode,
+    framework_inference: bool,
+    task_hashes: Mutex<HashMap<String, String>>,
+    task_env_vars: Mutex<HashMap<String, DetailedMap>>,
+    task_frameworks: Mutex<HashMap<String, &'static str>>,
+}
+
+impl<'a> TaskHasher<'a> {
+    pub fn new(
+        package_inputs_hashes: PackageInputsHashes,
+        env_at_execution_start: &'a EnvironmentVariableMap,
+        global_hash: &'a str,
+        global_env_mode: EnvMode,
+        framework_inference: bool,
+    ) -> Self {
+        Self {
+            package_inputs_hashes,
+            env_at_execution_start,
+            global_hash,
+            global_env_mode,
+            framework_inference,
+            task_hashes: Mutex::default(),
+            task_env_vars: Mutex::default(),
+            task_frameworks: Mutex::default(),
+        }
+    }
+
+    pub fn env_at_execution_start(&self) -> &EnvironmentVariableMap {
+        self.env_at_execution_start
+    }
+
+    pub fn global_env_mode(&self) -> EnvMode {
+        self.global_env_mode
+    }
+
+    pub fn task_env_mode(&self, task_definition: &TaskDefinitionHashable) -> EnvMode {
+        task_env_mode(self.global_env_mode, task_definition)
+    }
+
+    pub fn calculate_task_hash(
+        &self,
+        task_id: &str,
+        task_definition: &TaskDefinitionHashable,
+        workspace: &Entry,
+        dependency_set: HashSet<&TaskNode>,
+        pass_thru_args: &[String],
+    ) -> Result<String, Error> {
+        let (_, task) = get_package_task_from_id(task_id);
+        let hash_of_files = self
+            .package_inputs_hashes
+            .hashes
+            .get(task_id)
+            .ok_or_else(|| Error::MissingPackageFileHash(task_id.to_string()))?;
+
+        let framework = self
+            .framework_inference
+            .then(|| infer_framework(workspace))
+            .flatten();
+        if let Some(framework) = framework {
+            debug!(
+                "auto detected framework for {}: {} (env prefix {:?})",
+                task_id,
+                framework.slug(),
+                framework.env_wildcards()
+            );
+        }
+        let env_vars = task_env_vars(self.env_at_execution_start, task_definition, framework)?;
+
+        let hashable_env_pairs = env_vars.all.to_hashable();
+        debug!(
+            "task hash env vars for {}: {:?}",
+            task_id, hashable_env_pairs
+        );
+
+        let task_dependency_hashes = self.calculate_dependency_hashes(dependency_set)?;
+
+        let env_mode = self.task_env_mode(task_definition);
+        let pass_through_env = match env_mode {
+            // Pass through env vars are ignored entirely in loose mode
+            EnvMode::Loose => None,
+            _ => task_definition.pass_through_env.as_deref(),
+        };
+
+        let external_deps_hash = get_external_deps_hash(workspace.transitive_dependencies());
+        let task_hashable = TaskHashable {
+            global_hash: self.global_hash,
+            task_dependency_hashes,
+            package_dir: workspace.package_path().to_owned().to_unix()?,
+            hash_of_files,
+            external_deps_hash,
+            task: &task,
+            outputs: hashable_outputs(&task, &task_definition.outputs),
+            pass_thru_args,
+            env: &task_definition.env,
+            resolved_env_vars: hashable_env_pairs,
+            pass_through_env,
+            env_mode,
+            dot_env: &task_definition.dot_env,
+        };
+        let hash = task_hashable.hash();
+
+        self.task_hashes
+            .lock()
+            .expect("task hash lock poisoned")
+            .insert(task_id.to_string(), hash.clone());
+        self.task_env_vars
+            .lock()
+            .expect("task hash lock poisoned")
+            .insert(task_id.to_string(), env_vars);
+        if let Some(framework) = framework {
+            self.task_frameworks
+                .lock()
+                .expect("task hash lock poisoned")
+                .insert(task_id.to_string(), framework.slug());
+        }
+
+        Ok(hash)
+    }
+
+    // Sorted and deduplicated hashes of the tasks in the dependency set
+    fn calculate_dependency_hashes(
+        &self,
+        dependency_set: HashSet<&TaskNode>,
+    ) -> Result<Vec<String>, Error> {
+        let task_hashes = self.task_hashes.lock().expect("task hash lock poisoned");
+        let mut dependency_hashes = dependency_set
+            .into_iter()
+            .filter_map(|dependency| match dependency {
+                TaskNode::Root => None,
+                TaskNode::Task(dependency) => Some(dependency),
+            })
+            .map(|dependency| {
+                task_hashes
+                    .get(dependency)
+                    .cloned()
+                    .ok_or_else(|| Error::MissingDependencyTaskHash(dependency.to_string()))
+            })
+            .collect::<Result<HashSet<_>, _>>()?
+            .into_iter()
+            .collect::<Vec<_>>();
+        dependency_hashes.sort();
+        Ok(dependency_hashes)
+    }
+
+    pub fn task_hash(&self, task_id: &str) -> Option<String> {
+        self.task_hashes
+            .lock()
+            .expect("task hash lock poisoned")
+            .get(task_id)
+            .cloned()
+    }
+
+    pub fn framework(&self, task_id: &str) -> Option<&'static str> {
+        self.task_frameworks
+            .lock()
+            .expect("task hash lock poisoned")
+            .get(task_id)
+            .copied()
+    }
+
+    pub fn expanded_inputs(&self, task_id: &str) -> Option<&FileHashes> {
+        self.package_inputs_hashes.expanded_inputs(task_id)
+    }
+
+    /// The environment variables that went into the hash of an already hashed
+    /// task, as reported in the run summary
+    pub fn env_var_summary(
+        &self,
+        task_id: &str,
+        task_definition: &TaskDefinitionHashable,
+    ) -> Result<TaskEnvVarSummary, Error> {
+        let task_env_vars = self.task_env_vars.lock().expect("task hash lock poisoned");
+        let env_vars = task_env_vars
+            .get(task_id)
+            .ok_or_else(|| Error::MissingEnvVars(task_id.to_string()))?;
+        let pass_through_env_vars = self.env_at_execution_start.from_wildcards(
+            task_definition
+                .pass_through_env
+                .as_deref()
+                .unwrap_or_default(),
+        )?;
+
+        Ok(TaskEnvVarSummary {
+            specified: TaskEnvConfiguration {
+                env: task_definition.env.clone(),
+                pass_through_env: task_definition.pass_through_env.clone(),
+            },
+            configured: env_vars.by_source.explicit.to_secret_hashable(),
+            inferred: env_vars.by_source.matching.to_secret_hashable(),
+            pass_through: pass_through_env_vars.to_secret_hashable(),
+        })
+    }
+
+    /// The environment an already hashed task runs with in strict mode
+    pub fn strict_env(
+        &self,
+        task_id: &str,
+        task_definition: &TaskDefinitionHashable,
+        global_env: &EnvironmentVariableMap,
+    ) -> Result<EnvironmentVariableMap, Error> {
+        let task_env_vars = self.task_env_vars.lock().expect("task hash lock poisoned");
+        let env_vars = task_env_vars
+            .get(task_id)
+            .ok_or_else(|| Error::MissingEnvVars(task_id.to_string()))?;
+
+        Ok(task_env::strict_env(
+            self.env_at_execution_start,
+            global_env,
+            &env_vars.all,
+            task_definition.pass_through_env.as_deref(),
+        )?)
+    }
+}
+
+// The task's log file is always an output
+fn hashable_outputs(task: &str, outputs: &TaskOutputs) -> TaskOutputs {
+    let mut inclusions = vec![format!(".turbo/turbo-{}.log", task)];
+    inclusions.extend(outputs.inclusions.iter().cloned());
+    inclusions.sort();
+    let mut exclusions = outputs.exclusions.clone();
+    exclusions.sort();
+    TaskOutputs {
+        inclusions,
+        exclusions,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+    use turbopath::RelativeUnixPathBuf;
+
+    use super::*;
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(|s| s.to_string()).collect()
+    }
+
+    // The same inputs and hashes as `Test_HashTaskGolden` in
+    // cli/internal/fs/hash_test.go, which checks them against Go's `HashTask`.
+    // Both implementations have to agree for cache entries to be shared.
+    #[test_case(EnvMode::Infer, Some(&["AWS_SECRET_KEY"]), "83616b1d59d9fd94" ; "infer with pass through env is strict")]
+    #[test_case(EnvMode::Loose, Some(&["AWS_SECRET_KEY"]), "e3d6b6aacdd40a80" ; "loose ignores pass through env")]
+    #[test_case(EnvMode::Strict, None, "74440c073b56a988" ; "strict collapses missing pass through env")]
+    fn test_task_hash(global_env_mode: EnvMode, pass_through_env: Option<&[&str]>, expected: &str) {
+        let env = EnvironmentVariableMap::from(
+            [
+                ("API_URL", "https://example.com"),
+                ("NEXT_PUBLIC_ID", "1"),
+                ("AWS_SECRET_KEY", "hunter2"),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>(),
+        );
+        let package_inputs_hashes = PackageInputsHashes {
+            hashes: [("web#build".to_string(), "7f4a5c4c2f6d8e1b".to_string())]
+                .into_iter()
+                .collect(),
+            expanded_hashes: HashMap::new(),
+        };
+        let hasher = TaskHasher::new(
+            package_inputs_hashes,
+            &env,
+            "a5c1f2b8e5c0f8d4",
+            global_env_mode,
+            false,
+        );
+        let task_definition = TaskDefinitionHashable {
+            outputs: TaskOutputs {
+                inclusions: strings(&[".next/**"]),
+                exclusions: strings(&[".next/cache/**"]),
+            },
+            env: strings(&["API_URL", "NEXT_PUBLIC_*"]),
+            pass_through_env: pass_through_env.map(strings),
+            dot_env: vec![
+                RelativeUnixPathBuf::new(".env.local").unwrap(),
+                RelativeUnixPathBuf::new(".env").unwrap(),
+            ],
+            ..Default::default()
+        };
+        let workspace = Entry::with_package_json_path(
+            AnchoredSystemPathBuf::from_raw(
+                ["apps", "web", "package.json"].join(std::path::MAIN_SEPARATOR_STR),
+            )
+            .unwrap(),
+        );
+
+        let hash = hasher
+            .calculate_task_hash(
+                "web#build",
+                &task_definition,
+                &workspace,
+                HashSet::new(),
+                &strings(&["--watch"]),
+            )
+            .unwrap();
+        assert_eq!(hash, expected);
+    }
+
+    #[test]
+    fn test_hashable_outputs_includes_log_file() {
+        let outputs = TaskOutputs {
+            inclusions: vec!["dist/**".to_string(), ".next/**".to_string()],
+            exclusions: vec![],
+        };
+        assert_eq!(
+            hashable_outputs("build", &outputs).inclusions,
+            vec![".next/**", ".turbo/turbo-build.log", "dist/**"]
+        );
+    }
+
+    #[test]
+    fn test_external_deps_hash_without_closure() {
+        assert_eq!(get_external_deps_hash(None), "");
+    }
+
+    #[test_case(EnvMode::Infer, None, EnvMode::Loose ; "infer without pass through env")]
+    #[test_case(EnvMode::Infer, Some(vec![]), EnvMode::Strict ; "infer with pass through env")]
+    #[test_case(EnvMode::Loose, Some(vec![]), EnvMode::Loose ; "loose")]
+    #[test_case(EnvMode::Strict, None, EnvMode::Strict ; "strict")]
+    fn test_task_env_mode(
+        global_env_mode: EnvMode,
+        pass_through_env: Option<Vec<String>>,
+        expected: EnvMode,
+    ) {
+        let env = EnvironmentVariableMap::default();
+        let hasher = TaskHasher::new(
+            PackageInputsHashes::default(),
+            &env,
+            "global",
+            global_env_mode,
+            true,
+        );
+        let task_definition = TaskDefinitionHashable {
+            pass_through_env,
+            ..Default::default()
+        };
+        assert_eq!(hasher.task_env_mode(&task_definition), expected);
+    }
+}
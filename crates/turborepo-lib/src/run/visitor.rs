@@ -0,0 +1,671 @@
+use std::{collections::HashSet, io, process::Stdio, sync::Mutex, time::Instant};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::{Child, Command},
+};
+use tracing::debug;
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+use turborepo_env::EnvironmentVariableMap;
+
+use crate::{
+    cli::{EnvMode, LogPrefix},
+    engine::Engine,
+    manager::Manager,
+    package_graph::{Entry, PackageGraph, WorkspaceName},
+    run::{
+        cache::RunCache,
+        summary::{RunTracker, TaskCacheSummary, TaskSummary},
+        task_env,
+        task_hash::{self, get_external_deps_hash, TaskHasher},
+        task_id::{get_package_task_from_id, ROOT_PKG_NAME},
+        watch::PersistentTasks,
+    },
+    task_graph::{TaskDefinitionHashable, TaskOutputMode},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("command ({dir}) {command} exited ({exit_code})")]
+    Exit {
+        dir: String,
+        command: String,
+        exit_code: i32,
+    },
+    #[error("unable to run {command}: {err}")]
+    Spawn {
+        command: String,
+        #[source]
+        err: io::Error,
+    },
+    #[error("failed to hash task {task_id}: {err}")]
+    TaskHash {
+        task_id: String,
+        #[source]
+        err: task_hash::Error,
+    },
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Exit { exit_code, .. } => *exit_code,
+            Error::Spawn { .. } | Error::TaskHash { .. } => 1,
+        }
+    }
+}
+
+// Runs a single task from the engine by invoking the package manager's run
+// command in the workspace that owns the task
+pub struct Visitor<'a> {
+    pub repo_root: &'a AbsoluteSystemPath,
+    pub package_graph: &'a PackageGraph,
+    pub engine: &'a Engine,
+    pub task_hasher: &'a TaskHasher<'a>,
+    pub run_cache: &'a RunCache<'a>,
+    // The global part of the environment tasks get in strict mode
+    pub global_env: &'a EnvironmentVariableMap,
+    pub run_tracker: &'a RunTracker,
+    pub dry_run: bool,
+    pub manager: Manager,
+    pub tasks: &'a [String],
+    pub pass_through_args: &'a [String],
+    pub log_prefix: LogPrefix,
+    pub is_single_package: bool,
+    pub continue_on_error: bool,
+    // The node tracer, set when env access tracing is enabled
+    pub env_access_tracer: Option<&'a AbsoluteSystemPath>,
+    // Only these tasks get executed if set, every other task is still hashed
+    pub affected_tasks: Option<&'a HashSet<String>>,
+    pub persistent_tasks: Option<&'a PersistentTasks>,
+}
+
+impl<'a> Visitor<'a> {
+    pub async fn visit(&self, task_id: String) -> Result<(), Error> {
+        let (package, task) = get_package_task_from_id(&task_id);
+        let workspace = match package.as_str() {
+            ROOT_PKG_NAME => WorkspaceName::Root,
+            _ => WorkspaceName::from(package.as_str()),
+        };
+        let workspace_info = self
+            .package_graph
+            .workspace_info(&workspace)
+            .expect("engine only contains tasks for workspaces in the package graph");
+
+        // Every task gets hashed, even ones without a script, since their
+        // hashes feed into the hashes of their dependents
+        let task_definition = self
+            .engine
+            .task_definition(&task_id)
+            .expect("engine has a definition for every task");
+        let dependency_set = self.engine.dependencies(&task_id).unwrap_or_default();
+        // Extra arguments only get forwarded to the tasks that were requested
+        let pass_through_args = match self.tasks.contains(&task) {
+            true => self.pass_through_args,
+            false => &[],
+        };
+        let hash = self
+            .task_hasher
+            .calculate_task_hash(
+                &task_id,
+                task_definition,
+                workspace_info,
+                dependency_set,
+                pass_through_args,
+            )
+            .map_err(|err| Error::TaskHash {
+                task_id: task_id.clone(),
+                err,
+            })?;
+        debug!("task {} hash is {}", task_id, hash);
+
+        if let Some(affected_tasks) = self.affected_tasks {
+            if !affected_tasks.contains(&task_id) {
+                debug!("skipping {}, not affected by the change", task_id);
+                return Ok(());
+            }
+        }
+
+        let script = workspace_info.package_json().scripts.get(&task);
+
+        // Dry runs describe every task, including the ones without a script
+        if self.dry_run {
+            let task_summary = self
+                .task_summary(
+                    &task_id,
+                    workspace_info,
+                    task_definition,
+                    hash,
+                    script.map_or("", String::as_str),
+                    pass_through_args,
+                )
+                .map_err(|err| Error::TaskHash {
+                    task_id: task_id.clone(),
+                    err,
+                })?;
+            self.run_tracker.add_task(task_summary);
+            return Ok(());
+        }
+
+        let Some(script) = script else {
+            debug!("skipping {}, no script defined", task_id);
+            return Ok(());
+        };
+
+        let task_cache = self
+            .run_cache
+            .task_cache(task_definition, workspace_info, &task, &hash);
+        let mut task_summary = self
+            .task_summary(
+                &task_id,
+                workspace_info,
+                task_definition,
+                hash.clone(),
+                script,
+                pass_through_args,
+            )
+            .map_err(|err| Error::TaskHash {
+                task_id: task_id.clone(),
+                err,
+            })?;
+
+        let tracker = self.run_tracker.track_task();
+        tracker.start();
+        let prefix = self.prefix(&package, &task);
+        match task_cache.restore_outputs().await {
+            Ok(Some(response)) => {
+                replay_logs(
+                    &prefix,
+                    task_cache.output_mode(),
+                    task_cache.log_file(),
+                    &hash,
+                );
+                task_summary.cache = TaskCacheSummary::from(Some(&response));
+                task_summary.execution = Some(tracker.cached());
+                self.run_tracker.add_task(task_summary);
+                return Ok(());
+            }
+            Ok(None) => {}
+            // A broken cache shouldn't fail the run, the task just executes
+            Err(err) => eprintln!(
+                "{}WARNING: failed to restore cached outputs: {}",
+                prefix, err
+            ),
+        }
+
+        if matches!(
+            task_cache.output_mode(),
+            TaskOutputMode::Full | TaskOutputMode::New | TaskOutputMode::Hash
+        ) {
+            println!("{}cache miss, executing {}", prefix, hash);
+        }
+        let started_at = Instant::now();
+        let result = self
+            .execute(
+                &task_id,
+                workspace_info,
+                task_definition,
+                pass_through_args,
+                task_cache.output_mode(),
+                task_cache.log_file(),
+            )
+            .await;
+        if let Ok(TaskOutcome::Built) = result {
+            let duration = u32::try_from(started_at.elapsed().as_millis()).unwrap_or(u32::MAX);
+            if let Err(err) = task_cache.save_outputs(duration).await {
+                eprintln!("{}WARNING: error caching output: {}", prefix, err);
+            }
+        }
+        let execution = match &result {
+            Ok(TaskOutcome::Built) => tracker.built(0),
+            // The task keeps running after the run has finished
+            Ok(TaskOutcome::Detached) => tracker.built(0),
+            Ok(TaskOutcome::Stopped) => tracker.stopped(),
+            Err(err @ Error::Exit { exit_code, .. }) => {
+                tracker.failed(err.to_string(), Some(*exit_code))
+            }
+            Err(err) => tracker.failed(err.to_string(), None),
+        };
+        task_summary.execution = Some(execution);
+        self.run_tracker.add_task(task_summary);
+
+        result.map(|_| ())
+    }
+
+    // Describes the task for the run summary, everything but the execution is
+    // known once the task has been hashed
+    fn task_summary(
+        &self,
+        task_id: &str,
+        workspace_info: &Entry,
+        task_definition: &TaskDefinitionHashable,
+        hash: String,
+        script: &str,
+        pass_through_args: &[String],
+    ) -> Result<TaskSummary, task_hash::Error> {
+        let (package, task) = get_package_task_from_id(task_id);
+        let mut log_file = workspace_info.package_path().to_owned();
+        log_file.push(".turbo");
+        log_file.push(format!("turbo-{}.log", task));
+
+        Ok(TaskSummary {
+            task_id: task_id.to_string(),
+            task,
+            package,
+            hash,
+            inputs: self
+                .task_hasher
+                .expanded_inputs(task_id)
+                .map(|inputs| {
+                    inputs
+                        .0
+                        .iter()
+                        .map(|(path, hash)| (path.clone(), hash.clone()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            hash_of_external_dependencies: get_external_deps_hash(
+                workspace_info.transitive_dependencies(),
+            ),
+            // Filled in if the task gets restored from the cache
+            cache: TaskCacheSummary::from(None),
+            command: script.to_string(),
+            cli_arguments: pass_through_args.to_vec(),
+            outputs: task_definition.outputs.inclusions.clone(),
+            excluded_outputs: task_definition.outputs.exclusions.clone(),
+            log_file: log_file.to_string(),
+            directory: workspace_info.package_path().to_string(),
+            dependencies: self
+                .engine
+                .transitive_dependencies(task_id)
+                .unwrap_or_default()
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            dependents: self
+                .engine
+                .transitive_dependents(task_id)
+                .unwrap_or_default()
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            resolved_task_definition: task_definition.into(),
+            expanded_outputs: Vec::new(),
+            framework: self
+                .task_hasher
+                .framework(task_id)
+                .unwrap_or_default()
+                .to_string(),
+            env_mode: self.task_hasher.task_env_mode(task_definition),
+            environment_variables: self.task_hasher.env_var_summary(task_id, task_definition)?,
+            dot_env: task_definition.dot_env.clone(),
+            execution: None,
+        })
+    }
+
+    fn prefix(&self, package: &str, task: &str) -> String {
+        match (self.log_prefix, self.is_single_package) {
+            (LogPrefix::None, _) => String::new(),
+            (_, true) => format!("{}: ", task),
+            (_, false) => format!("{}:{}: ", package, task),
+        }
+    }
+
+    // Runs the task's script, forwarding its output and writing it to the
+    // task's log file
+    async fn execute(
+        &self,
+        task_id: &str,
+        workspace_info: &Entry,
+        task_definition: &TaskDefinitionHashable,
+        pass_through_args: &[String],
+        output_mode: &TaskOutputMode,
+        log_file: &AbsoluteSystemPath,
+    ) -> Result<TaskOutcome, Error> {
+        let (package, task) = get_package_task_from_id(task_id);
+        let prefix = self.prefix(&package, &task);
+
+        let package_manager = self.package_graph.package_manager();
+        let mut args = vec!["run".to_string(), task.clone()];
+        if !pass_through_args.is_empty() {
+            if let Some(separator) = package_manager.arg_separator(pass_through_args) {
+                args.push(separator.to_string());
+            }
+            args.extend(pass_through_args.iter().cloned());
+        }
+        let command_description = format!("{} {}", package_manager.command(), args.join(" "));
+
+        let dir = self.repo_root.resolve(workspace_info.package_path());
+        let mut command = Command::new(package_manager.command());
+        command
+            .args(&args)
+            .current_dir(dir.as_std_path())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let task_hash_error = |err| Error::TaskHash {
+            task_id: task_id.to_string(),
+            err,
+        };
+        // Strict tasks only see the variables declared in turbo.json
+        if matches!(
+            self.task_hasher.task_env_mode(task_definition),
+            EnvMode::Strict
+        ) {
+            let env = self
+                .task_hasher
+                .strict_env(task_id, task_definition, self.global_env)
+                .map_err(task_hash_error)?;
+            command.env_clear().envs(env.iter());
+        }
+
+        // In infer mode tasks can read anything, so we can only point out the
+        // variables that would go missing if they were switched to strict mode.
+        // Persistent tasks never finish, so there's nothing to report on.
+        let env_access_log = match self.env_access_tracer {
+            Some(node_tracer)
+                if matches!(self.task_hasher.global_env_mode(), EnvMode::Infer)
+                    && matches!(
+                        self.task_hasher.task_env_mode(task_definition),
+                        EnvMode::Loose
+                    )
+                    && !task_definition.persistent =>
+            {
+                let log_file =
+                    dir.join_components(&[".turbo", &format!("turbo-{}.env-access.log", task)]);
+                let node_options = self
+                    .task_hasher
+                    .env_at_execution_start()
+                    .get("NODE_OPTIONS")
+                    .map(String::as_str);
+                match task_env::trace_env_access(&mut command, &log_file, node_tracer, node_options)
+                {
+                    Ok(()) => Some(log_file),
+                    Err(err) => {
+                        debug!("unable to trace env access of {}: {}", task_id, err);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        // In watch mode persistent tasks are only ever started once and are
+        // left running when the run finishes
+        let persistent_tasks = self.persistent_tasks.filter(|_| task_definition.persistent);
+        if let Some(persistent_tasks) = persistent_tasks {
+            if !persistent_tasks.start(task_id) {
+                debug!("{} is already running", task_id);
+                return Ok(TaskOutcome::Detached);
+            }
+        }
+        let manager = match persistent_tasks {
+            Some(persistent_tasks) => persistent_tasks.manager().clone(),
+            None => self.manager.clone(),
+        };
+
+        let Some(child) = manager.spawn(command) else {
+            if let Some(persistent_tasks) = persistent_tasks {
+                persistent_tasks.finish(task_id);
+            }
+            // We're shutting down, don't start anything new
+            return Ok(TaskOutcome::Stopped);
+        };
+        let child = child.map_err(|err| {
+            if let Some(persistent_tasks) = persistent_tasks {
+                persistent_tasks.finish(task_id);
+            }
+            Error::Spawn {
+                command: command_description.clone(),
+                err,
+            }
+        })?;
+
+        let running_task = RunningTask {
+            child,
+            manager,
+            prefix: prefix.clone(),
+            output_mode: output_mode.clone(),
+            log_file: log_file.to_owned(),
+            dir: workspace_info.package_path().to_string(),
+            command: command_description,
+            // A failed persistent task shouldn't bring down whichever run is
+            // active when it exits
+            stop_on_failure: match persistent_tasks.is_none() && !self.continue_on_error {
+                true => Some(self.manager.clone()),
+                false => None,
+            },
+        };
+
+        match persistent_tasks {
+            Some(persistent_tasks) => {
+                let persistent_tasks = persistent_tasks.clone();
+                let task_id = task_id.to_string();
+                tokio::spawn(async move {
+                    // Errors have already been reported by the time the task
+                    // exits, all that's left is allowing it to start again
+                    let _ = running_task.wait().await;
+                    persistent_tasks.finish(&task_id);
+                });
+                Ok(TaskOutcome::Detached)
+            }
+            None => {
+                let outcome = running_task.wait().await?;
+                if let Some(env_access_log) = env_access_log {
+                    self.warn_undeclared_env_vars(
+                        task_id,
+                        task_definition,
+                        &env_access_log,
+                        log_file,
+                        &prefix,
+                    )
+                    .map_err(task_hash_error)?;
+                }
+                Ok(outcome)
+            }
+        }
+    }
+
+    fn warn_undeclared_env_vars(
+        &self,
+        task_id: &str,
+        task_definition: &TaskDefinitionHashable,
+        env_access_log: &AbsoluteSystemPath,
+        log_file: &AbsoluteSystemPath,
+        prefix: &str,
+    ) -> Result<(), task_hash::Error> {
+        let declared_env =
+            self.task_hasher
+                .strict_env(task_id, task_definition, self.global_env)?;
+        let undeclared = match task_env::undeclared_env_vars(
+            env_access_log,
+            self.task_hasher.env_at_execution_start(),
+            &declared_env,
+        ) {
+            Ok(undeclared) => undeclared,
+            Err(err) => {
+                debug!("unable to read env access log of {}: {}", task_id, err);
+                return Ok(());
+            }
+        };
+        if undeclared.is_empty() {
+            return Ok(());
+        }
+
+        let warning = format!(
+            "WARNING: read environment variables that aren't declared in turbo.json and would be \
+             unset in strict mode: {}",
+            undeclared.join(", ")
+        );
+        eprintln!("{}{}", prefix, warning);
+        // Part of the task's log so that it's replayed along with the rest of
+        // its output when the task is restored from the cache
+        if let Err(err) = append_log(log_file, &warning) {
+            debug!(
+                "unable to add env access warning to log of {}: {}",
+                task_id, err
+            );
+        }
+        Ok(())
+    }
+}
+
+// A spawned task that hasn't exited yet. It owns everything needed to wait
+// for it so that it can outlive the visitor.
+struct RunningTask {
+    child: Child,
+    manager: Manager,
+    prefix: String,
+    output_mode: TaskOutputMode,
+    log_file: AbsoluteSystemPathBuf,
+    dir: String,
+    command: String,
+    // Stopped if the task fails
+    stop_on_failure: Option<Manager>,
+}
+
+impl RunningTask {
+    async fn wait(self) -> Result<TaskOutcome, Error> {
+        let Self {
+            mut child,
+            manager,
+            prefix,
+            output_mode,
+            log_file,
+            dir,
+            command,
+            stop_on_failure,
+        } = self;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let log = Mutex::new(Vec::new());
+        let (status, stdout_lines, stderr_lines) = tokio::join!(
+            manager.wait(&mut child),
+            forward_lines(stdout, &prefix, &output_mode, false, &log),
+            forward_lines(stderr, &prefix, &output_mode, true, &log),
+        );
+        let status = status.map_err(|err| Error::Spawn {
+            command: command.clone(),
+            err,
+        })?;
+
+        // The task was killed because the run is shutting down
+        let Some(status) = status else {
+            return Ok(TaskOutcome::Stopped);
+        };
+
+        let log = log.into_inner().expect("task log lock poisoned");
+        if let Err(err) = write_log(&log_file, &log) {
+            eprintln!("{}WARNING: failed to write log file: {}", prefix, err);
+        }
+
+        if status.success() {
+            return Ok(TaskOutcome::Built);
+        }
+
+        // Output for tasks that only log errors is buffered until we know the
+        // task failed
+        if matches!(output_mode, TaskOutputMode::Error) {
+            for line in stdout_lines {
+                println!("{}{}", prefix, line);
+            }
+            for line in stderr_lines {
+                eprintln!("{}{}", prefix, line);
+            }
+        }
+
+        let err = Error::Exit {
+            dir,
+            command,
+            exit_code: status.code().unwrap_or(1),
+        };
+        eprintln!("{}ERROR: command finished with error: {}", prefix, err);
+
+        if let Some(manager) = stop_on_failure {
+            manager.stop();
+        }
+
+        Err(err)
+    }
+}
+
+enum TaskOutcome {
+    Built,
+    // A persistent task that was left running in watch mode
+    Detached,
+    // The task was killed, or never started, because the run is shutting down
+    Stopped,
+}
+
+fn write_log(log_file: &AbsoluteSystemPath, log: &[String]) -> io::Result<()> {
+    log_file.ensure_dir()?;
+    let mut contents = log.join("\n");
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+    std::fs::write(log_file, contents)
+}
+
+fn append_log(log_file: &AbsoluteSystemPath, line: &str) -> io::Result<()> {
+    use std::io::Write;
+
+    let mut file = std::fs::OpenOptions::new().append(true).open(log_file)?;
+    writeln!(file, "{}", line)
+}
+
+// Prints the log of a restored task, as much of it as the output mode allows
+fn replay_logs(
+    prefix: &str,
+    output_mode: &TaskOutputMode,
+    log_file: &AbsoluteSystemPath,
+    hash: &str,
+) {
+    match output_mode {
+        TaskOutputMode::Full => {
+            println!("{}cache hit, replaying logs {}", prefix, hash);
+            match std::fs::read_to_string(log_file) {
+                Ok(log) => {
+                    for line in log.lines() {
+                        println!("{}{}", prefix, line);
+                    }
+                }
+                Err(err) => debug!("unable to replay {}: {}", log_file, err),
+            }
+        }
+        TaskOutputMode::New | TaskOutputMode::Hash => {
+            println!("{}cache hit, suppressing logs {}", prefix, hash)
+        }
+        TaskOutputMode::None | TaskOutputMode::Error => {}
+    }
+}
+
+// Prints each line of output with the task prefix, returning the lines that
+// were held back because of the output mode. Every line is also appended to
+// the task's log, interleaved with the lines of the other stream.
+async fn forward_lines(
+    reader: impl AsyncRead + Unpin,
+    prefix: &str,
+    output_mode: &TaskOutputMode,
+    is_stderr: bool,
+    log: &Mutex<Vec<String>>,
+) -> Vec<String> {
+    let mut buffered = Vec::new();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        log.lock()
+            .expect("task log lock poisoned")
+            .push(line.clone());
+        match output_mode {
+            TaskOutputMode::None => {}
+            // Only the hash gets shown, the output only goes to the log
+            TaskOutputMode::Hash => {}
+            TaskOutputMode::Error => buffered.push(line),
+            TaskOutputMode::Full | TaskOutputMode::New if is_stderr => {
+                eprintln!("{}{}", prefix, line)
+            }
+            TaskOutputMode::Full | TaskOutputMode::New => println!("{}{}", prefix, line),
+        }
+    }
+    buffered
+}
@@ -0,0 +1,497 @@
+//! Watch Mode
+//!
+//! Runs the requested tasks once and then waits for files in the repo to
+//! change. Changed files are mapped to the workspace that contains them, and
+//! only the tasks of those workspaces, along with every task that depends on
+//! them, are run again. Changes that come in while a run is still going cancel
+//! it, and the next run picks up whatever it didn't get to.
+//!
+//! Persistent tasks, such as dev servers, are started by the first run and are
+//! left running until watch mode exits.
+
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+use futures::{Stream, StreamExt};
+use globwatch::{ConfigError, GlobWatcher, StopSource, TimedOutError, WatchConfig, Watcher};
+use itertools::Itertools;
+use notify::{event::ModifyKind, Event, EventKind};
+use tokio::time::timeout;
+use tracing::debug;
+use wax::{Glob, Pattern};
+
+use crate::{
+    engine::Engine,
+    manager::Manager,
+    package_graph::{PackageGraph, WorkspaceName},
+    run::{
+        task_id::{get_package_task_from_id, ROOT_PKG_NAME},
+        Run,
+    },
+    ui::GREY,
+};
+
+/// How long to wait for more changes before starting a run, saving a file
+/// tends to produce a burst of events
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+// Directories whose contents never affect a task
+const IGNORED_DIRECTORIES: [&str; 3] = ["node_modules", ".git", ".turbo"];
+
+/// The persistent tasks started in watch mode. They are kept out of the
+/// processes of a single run so that cancelling a run doesn't stop them.
+#[derive(Debug, Clone, Default)]
+pub struct PersistentTasks {
+    manager: Manager,
+    running: Arc<Mutex<HashSet<String>>>,
+}
+
+impl PersistentTasks {
+    pub fn manager(&self) -> &Manager {
+        &self.manager
+    }
+
+    /// Marks the task as running, returns false if it already is
+    pub fn start(&self, task_id: &str) -> bool {
+        self.running
+            .lock()
+            .expect("only fails if poisoned")
+            .insert(task_id.to_string())
+    }
+
+    /// Marks the task as exited, allowing a later run to start it again
+    pub fn finish(&self, task_id: &str) {
+        self.running
+            .lock()
+            .expect("only fails if poisoned")
+            .remove(task_id);
+    }
+
+    pub fn stop(&self) {
+        self.manager.stop();
+    }
+}
+
+impl Run {
+    pub async fn watch(&mut self) -> Result<i32> {
+        let persistent_tasks = PersistentTasks::default();
+        self.persistent_tasks = Some(persistent_tasks.clone());
+
+        let flush_dir = tempfile::tempdir().context("failed to create flush directory")?;
+        let (watcher, config) = GlobWatcher::new(
+            Utf8PathBuf::try_from(flush_dir.path().to_owned())
+                .context("flush directory is not valid UTF-8")?,
+        )?;
+        // file events come in with canonical paths
+        let canonical_repo_root = self.base.repo_root.as_path().canonicalize()?;
+        let stop = StopSource::new();
+        let mut events = watcher.into_stream(stop.token());
+
+        let (mut package_graph, mut engine) = self.task_graph().await?;
+        watch_workspaces(&config, &canonical_repo_root, &package_graph).await;
+
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+
+        loop {
+            self.processes = Manager::new();
+            let processes = self.processes.clone();
+            let (mut changed, cancelled) = {
+                let run = self.run();
+                tokio::pin!(run);
+                tokio::select! {
+                    result = &mut run => {
+                        if let Err(err) = result {
+                            eprintln!("{:?}", err);
+                        }
+                        (Vec::new(), false)
+                    }
+                    Some(changed) = next_changes(&mut events, &canonical_repo_root) => {
+                        processes.stop();
+                        // Whatever went wrong is superseded by the next run
+                        let _ = run.await;
+                        (changed, true)
+                    }
+                    _ = &mut ctrl_c => {
+                        processes.stop();
+                        persistent_tasks.stop();
+                        let _ = run.await;
+                        return Ok(0);
+                    }
+                }
+            };
+
+            // `None` means every task, which is also what a cancelled run that
+            // was running everything leaves behind
+            let mut affected_tasks = match cancelled {
+                true => self.affected_tasks.take(),
+                false => Some(HashSet::new()),
+            };
+            loop {
+                if changed.is_empty() {
+                    changed = tokio::select! {
+                        Some(changed) = next_changes(&mut events, &canonical_repo_root) => changed,
+                        _ = &mut ctrl_c => {
+                            persistent_tasks.stop();
+                            return Ok(0);
+                        }
+                    };
+                }
+
+                if changed.iter().any(|file| is_config_file(file)) {
+                    // Workspaces or task definitions may have changed, start
+                    // over with everything
+                    match self.task_graph().await {
+                        Ok(graphs) => {
+                            (package_graph, engine) = graphs;
+                            watch_workspaces(&config, &canonical_repo_root, &package_graph).await;
+                            affected_tasks = None;
+                        }
+                        Err(err) => eprintln!("{:?}", err),
+                    }
+                } else if let Some(affected_tasks) = &mut affected_tasks {
+                    affected_tasks.extend(get_affected_tasks(&engine, &package_graph, &changed));
+                }
+                changed.clear();
+
+                if affected_tasks
+                    .as_ref()
+                    .map_or(true, |tasks| !tasks.is_empty())
+                {
+                    break;
+                }
+            }
+
+            let message = match &affected_tasks {
+                Some(tasks) => format!(
+                    "> Changes detected, running {}",
+                    tasks.iter().sorted().join(", ")
+                ),
+                None => "> Changes detected, running all tasks".to_string(),
+            };
+            println!("{}", self.base.ui.apply(GREY.apply_to(message)));
+            self.affected_tasks = affected_tasks;
+        }
+    }
+}
+
+// Watches every workspace directory recursively, and the repo root itself so
+// that changes to the root package.json, turbo.json and lockfile get picked up
+async fn watch_workspaces<T: Watcher>(
+    config: &WatchConfig<T>,
+    canonical_repo_root: &Path,
+    package_graph: &PackageGraph,
+) {
+    if let Err(e) = config.include_path(canonical_repo_root).await {
+        debug!("unable to watch repo root: {:?}", e);
+    }
+    for (name, info) in package_graph.workspaces() {
+        if matches!(name, WorkspaceName::Root) {
+            continue;
+        }
+        let glob = format!(
+            "{}/**",
+            info.package_path()
+                .as_path()
+                .to_string_lossy()
+                .replace('\\', "/")
+        );
+        if let Err(e) = config.include(canonical_repo_root, &glob).await {
+            debug!("unable to watch {}: {:?}", name, e);
+        }
+    }
+}
+
+// Waits for files to change, then keeps collecting changes until things have
+// been quiet for a moment. Returns the changed files relative to the repo
+// root, or `None` once the watcher stops.
+async fn next_changes(
+    events: &mut (impl Stream<Item = Result<Result<Event, ConfigError>, TimedOutError>> + Unpin),
+    canonical_repo_root: &Path,
+) -> Option<Vec<PathBuf>> {
+    let mut changed = HashSet::new();
+    loop {
+        let event = match changed.is_empty() {
+            true => events.next().await,
+            false => match timeout(DEBOUNCE, events.next()).await {
+                Ok(event) => event,
+                Err(_) => break,
+            },
+        };
+        let event = match event? {
+            Ok(Ok(event)) => event,
+            Ok(Err(e)) => {
+                debug!("watch error: {:?}", e);
+                continue;
+            }
+            // the watcher was stopped
+            Err(_) => return None,
+        };
+
+        // Only actual changes to files matter, tasks reading files shouldn't
+        // cause them to run again
+        if !matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(_)
+        ) || matches!(event.kind, EventKind::Modify(ModifyKind::Metadata(_)))
+        {
+            continue;
+        }
+
+        changed.extend(
+            event
+                .paths
+                .iter()
+                .filter_map(|path| path.strip_prefix(canonical_repo_root).ok())
+                .filter(|path| !path.as_os_str().is_empty() && !is_ignored(path))
+                .map(Path::to_path_buf),
+        );
+    }
+
+    Some(changed.into_iter().sorted().collect())
+}
+
+fn is_ignored(path: &Path) -> bool {
+    path.components().any(|component| match component {
+        Component::Normal(name) => IGNORED_DIRECTORIES.iter().any(|dir| name == *dir),
+        _ => false,
+    })
+}
+
+// Files that can change the package graph or the task definitions
+fn is_config_file(path: &Path) -> bool {
+    const LOCKFILES: [&str; 5] = [
+        "package-lock.json",
+        "yarn.lock",
+        "pnpm-lock.yaml",
+        "pnpm-workspace.yaml",
+        "bun.lockb",
+    ];
+    let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let is_root = path.parent() == Some(Path::new(""));
+    file_name == "package.json"
+        || file_name == "turbo.json"
+        || (is_root && LOCKFILES.contains(&file_name))
+}
+
+/// The tasks that need to run again after `changed_files` changed: every task
+/// in a workspace containing one of the files, as long as the file is one of
+/// the task's inputs, along with all of the tasks that depend on them.
+///
+/// Files that are the outputs of any task in their workspace are ignored,
+/// otherwise tasks would keep triggering themselves.
+fn get_affected_tasks(
+    engine: &Engine,
+    package_graph: &PackageGraph,
+    changed_files: &[PathBuf],
+) -> HashSet<String> {
+    let task_workspace = |task_id: &str| {
+        let (package, _) = get_package_task_from_id(task_id);
+        match package.as_str() {
+            ROOT_PKG_NAME => WorkspaceName::Root,
+            _ => WorkspaceName::from(package.as_str()),
+        }
+    };
+
+    // The workspace each file belongs to, along with the path of the file
+    // relative to it
+    let changed_files = changed_files
+        .iter()
+        .filter_map(|file| {
+            package_graph
+                .workspaces()
+                .filter_map(|(name, info)| {
+                    Some((name, file.strip_prefix(info.package_path().as_path()).ok()?))
+                })
+                .min_by_key(|(_, relative)| relative.components().count())
+        })
+        .filter(|(workspace, relative)| {
+            !engine.tasks().any(|task_id| {
+                task_workspace(task_id) == **workspace
+                    && engine.task_definition(task_id).map_or(false, |definition| {
+                        matches_globs(
+                            &definition.outputs.inclusions,
+                            &definition.outputs.exclusions,
+                            relative,
+                        )
+                    })
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut affected = HashSet::new();
+    for task_id in engine.tasks() {
+        let Some(definition) = engine.task_definition(task_id) else {
+            continue;
+        };
+        let workspace = task_workspace(task_id);
+        let (inclusions, exclusions): (Vec<_>, Vec<_>) = definition
+            .inputs
+            .iter()
+            .cloned()
+            .partition(|input| !input.starts_with('!'));
+        let exclusions = exclusions
+            .into_iter()
+            .map(|input| input[1..].to_string())
+            .collect::<Vec<_>>();
+
+        let is_affected = changed_files.iter().any(|(file_workspace, relative)| {
+            **file_workspace == workspace
+                && (inclusions.is_empty() || matches_globs(&inclusions, &exclusions, relative))
+        });
+        if is_affected {
+            affected.insert(task_id.to_string());
+            affected.extend(
+                engine
+                    .transitive_dependents(task_id)
+                    .into_iter()
+                    .flatten()
+                    .map(str::to_string),
+            );
+        }
+    }
+
+    affected
+}
+
+fn matches_globs(inclusions: &[String], exclusions: &[String], path: &Path) -> bool {
+    let matches = |globs: &[String]| {
+        globs.iter().any(|glob| {
+            Glob::new(glob.trim_start_matches("./"))
+                .map(|glob| glob.is_match(path))
+                .unwrap_or_else(|e| {
+                    debug!("invalid glob {}: {}", glob, e);
+                    false
+                })
+        })
+    };
+    matches(inclusions) && !matches(exclusions)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{HashMap, HashSet},
+        path::PathBuf,
+    };
+
+    use serde_json::json;
+    use test_case::test_case;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::{get_affected_tasks, is_config_file};
+    use crate::{
+        engine::Engine,
+        package_graph::{PackageGraph, WorkspaceName},
+        package_json::PackageJson,
+        package_manager::PackageManager,
+        task_graph::{BookkeepingTaskDefinition, Pipeline, TaskDefinitionHashable, TaskOutputs},
+    };
+
+    fn package_graph() -> PackageGraph {
+        let root =
+            AbsoluteSystemPathBuf::new(if cfg!(windows) { r"C:\repo" } else { "/repo" }).unwrap();
+        let package_json = |value| PackageJson::from_value(value).unwrap();
+        PackageGraph::builder(&root, package_json(json!({ "name": "root" })))
+            .with_package_manger(Some(PackageManager::Npm))
+            .with_package_jsons(Some(HashMap::from([
+                (
+                    root.join_components(&["apps", "web", "package.json"]),
+                    package_json(json!({
+                        "name": "web",
+                        "dependencies": { "ui": "workspace:*" }
+                    })),
+                ),
+                (
+                    root.join_components(&["packages", "ui", "package.json"]),
+                    package_json(json!({ "name": "ui" })),
+                ),
+                (
+                    root.join_components(&["packages", "ui-docs", "package.json"]),
+                    package_json(json!({ "name": "ui-docs" })),
+                ),
+            ])))
+            .build()
+            .unwrap()
+    }
+
+    fn engine(package_graph: &PackageGraph) -> Engine {
+        let definition = |task_definition| BookkeepingTaskDefinition {
+            task_definition,
+            ..Default::default()
+        };
+        let pipeline = Pipeline::from([
+            (
+                "build".to_string(),
+                definition(TaskDefinitionHashable {
+                    topological_dependencies: vec!["build".to_string()],
+                    outputs: TaskOutputs {
+                        inclusions: vec!["dist/**".to_string()],
+                        exclusions: vec![],
+                    },
+                    ..Default::default()
+                }),
+            ),
+            (
+                "lint".to_string(),
+                definition(TaskDefinitionHashable {
+                    inputs: vec!["src/**".to_string(), "!src/**/*.test.ts".to_string()],
+                    ..Default::default()
+                }),
+            ),
+        ]);
+        Engine::builder(package_graph, &pipeline)
+            .with_workspaces(vec![
+                WorkspaceName::from("web"),
+                WorkspaceName::from("ui"),
+                WorkspaceName::from("ui-docs"),
+            ])
+            .with_tasks(vec!["build".to_string(), "lint".to_string()])
+            .build()
+            .unwrap()
+    }
+
+    #[test_case(&["packages/ui/src/index.ts"], &["ui#build", "ui#lint", "web#build"] ; "dependents run again")]
+    #[test_case(&["apps/web/README.md"], &["web#build"] ; "files outside of inputs are ignored")]
+    #[test_case(&["packages/ui/src/index.test.ts"], &["ui#build", "web#build"] ; "excluded inputs are ignored")]
+    #[test_case(&["packages/ui/dist/index.js"], &[] ; "outputs are ignored")]
+    #[test_case(&["packages/ui-docs/index.md"], &["ui-docs#build"] ; "longest workspace path wins")]
+    #[test_case(&["README.md"], &[] ; "root workspace has no tasks")]
+    fn test_get_affected_tasks(changed_files: &[&str], expected: &[&str]) {
+        let package_graph = package_graph();
+        let engine = engine(&package_graph);
+        let changed_files = changed_files
+            .iter()
+            .map(|file| file.split('/').collect::<PathBuf>())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            get_affected_tasks(&engine, &package_graph, &changed_files),
+            expected
+                .iter()
+                .map(|task_id| task_id.to_string())
+                .collect::<HashSet<_>>()
+        );
+    }
+
+    #[test_case("package.json", true ; "root package.json")]
+    #[test_case("apps/web/package.json", true ; "workspace package.json")]
+    #[test_case("apps/web/turbo.json", true ; "workspace turbo.json")]
+    #[test_case("yarn.lock", true ; "lockfile")]
+    #[test_case("apps/web/yarn.lock", false ; "nested lockfile")]
+    #[test_case("apps/web/src/index.ts", false ; "source file")]
+    fn test_is_config_file(path: &str, expected: bool) {
+        assert_eq!(
+            is_config_file(&path.split('/').collect::<PathBuf>()),
+            expected
+        );
+    }
+}
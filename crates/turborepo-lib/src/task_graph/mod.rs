@@ -13,6 +13,37 @@ pub struct BookkeepingTaskDefinition {
     pub task_definition: TaskDefinitionHashable,
 }
 
+impl BookkeepingTaskDefinition {
+    /// Overrides the fields of this definition with the ones that were
+    /// explicitly configured in `other`, e.g. when a workspace turbo.json
+    /// extends a task from the root turbo.json
+    pub fn merge(&mut self, other: &BookkeepingTaskDefinition) {
+        let definition = &mut self.task_definition;
+        let other_definition = &other.task_definition;
+        for field in &other.defined_fields {
+            match field.as_str() {
+                "Outputs" => definition.outputs = other_definition.outputs.clone(),
+                "Cache" => definition.cache = other_definition.cache,
+                "DependsOn" => {
+                    definition.topological_dependencies =
+                        other_definition.topological_dependencies.clone();
+                    definition.task_dependencies = other_definition.task_dependencies.clone();
+                }
+                "Env" => definition.env = other_definition.env.clone(),
+                "Inputs" => definition.inputs = other_definition.inputs.clone(),
+                "PassThroughEnv" => {
+                    definition.pass_through_env = other_definition.pass_through_env.clone()
+                }
+                "DotEnv" => definition.dot_env = other_definition.dot_env.clone(),
+                "OutputMode" => definition.output_mode = other_definition.output_mode.clone(),
+                "Persistent" => definition.persistent = other_definition.persistent,
+                _ => continue,
+            }
+            self.defined_fields.insert(field.clone());
+        }
+    }
+}
+
 // A list of config fields in a task definition that are considered
 // experimental. We keep these separated so we can compute a global hash without
 // these.
@@ -39,15 +70,28 @@ pub enum TaskOutputMode {
     // None will hide all task output
     None,
     // Hash will display turbo-computed task hashes
+    #[serde(rename = "hash-only")]
     Hash,
     // New will show all new task output and turbo-computed task hashes for cached
     // output
+    #[serde(rename = "new-only")]
     New,
     // Error will show task output for failures only; no cache miss/hit messages are
     // emitted
+    #[serde(rename = "errors-only")]
     Error,
 }
 
+impl TaskOutputMode {
+    pub const ALL: [TaskOutputMode; 5] = [
+        TaskOutputMode::Full,
+        TaskOutputMode::None,
+        TaskOutputMode::Hash,
+        TaskOutputMode::New,
+        TaskOutputMode::Error,
+    ];
+}
+
 // taskDefinitionHashable exists as a definition for PristinePipeline, which is
 // used downstream for calculating the global hash. We want to exclude
 // experimental fields here because we don't want experimental fields to be part
@@ -62,7 +106,9 @@ pub struct TaskDefinitionHashable {
     pub(crate) output_mode: TaskOutputMode,
     pub(crate) persistent: bool,
     pub(crate) env: Vec<String>,
-    pub(crate) pass_through_env: Vec<String>,
+    // None if not configured, which is distinct from an empty list when
+    // resolving the env mode of a task
+    pub(crate) pass_through_env: Option<Vec<String>>,
     pub(crate) dot_env: Vec<RelativeUnixPathBuf>,
 }
 
@@ -77,7 +123,7 @@ impl Default for TaskDefinitionHashable {
             output_mode: TaskOutputMode::default(),
             persistent: false,
             env: Vec::new(),
-            pass_through_env: Vec::new(),
+            pass_through_env: None,
             dot_env: Vec::new(),
         }
     }
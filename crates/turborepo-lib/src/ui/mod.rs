@@ -111,6 +111,9 @@ lazy_static! {
     pub static ref BOLD: Style = Style::new().bold();
     pub static ref MAGENTA: Style = Style::new().magenta();
     pub static ref UNDERLINE: Style = Style::new().underlined();
+    pub static ref YELLOW: Style = Style::new().yellow();
+    pub static ref BOLD_GREEN: Style = Style::new().green().bold();
+    pub static ref BOLD_RED: Style = Style::new().red().bold();
 }
 
 pub const RESET: &str = "\x1b[0m";
@@ -8,7 +8,7 @@ use std::{
     collections::{HashMap, HashSet},
     iter,
     path::Path,
-    rc::Rc,
+    sync::Arc,
 };
 
 use de::SemverString;
@@ -40,11 +40,11 @@ pub enum Error {
 type Map<K, V> = std::collections::BTreeMap<K, V>;
 
 pub struct BerryLockfile {
-    data: Rc<LockfileData>,
+    data: Arc<LockfileData>,
     resolutions: Map<Descriptor<'static>, Locator<'static>>,
     // A mapping from descriptors without protocols to a range with a protocol
     resolver: DescriptorResolver,
-    locator_package: Map<Locator<'static>, Rc<BerryPackage>>,
+    locator_package: Map<Locator<'static>, Arc<BerryPackage>>,
     // Map of regular locators to patch locators that apply to them
     patches: Map<Locator<'static>, Locator<'static>>,
     // Descriptors that come from default package extensions that ship with berry
@@ -60,7 +60,7 @@ pub struct LockfileData {
     #[serde(rename = "__metadata")]
     metadata: Metadata,
     #[serde(flatten)]
-    packages: Map<String, Rc<BerryPackage>>,
+    packages: Map<String, Arc<BerryPackage>>,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Clone)]
@@ -131,7 +131,7 @@ impl BerryLockfile {
             .unwrap_or_default();
 
         let mut this = Self {
-            data: Rc::new(lockfile),
+            data: Arc::new(lockfile),
             resolutions: descriptor_locator,
             locator_package,
             resolver,
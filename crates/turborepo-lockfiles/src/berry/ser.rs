@@ -187,7 +187,7 @@ fn wrap_string(s: &str) -> Cow<str> {
 
 #[cfg(test)]
 mod test {
-    use std::rc::Rc;
+    use std::sync::Arc;
 
     use pretty_assertions::assert_eq;
 
@@ -231,7 +231,7 @@ mod test {
             },
             packages: [(
                 long_key.clone(),
-                Rc::new(BerryPackage {
+                Arc::new(BerryPackage {
                     version: SemverString("1.2.3".to_string()),
                     ..Default::default()
                 }),
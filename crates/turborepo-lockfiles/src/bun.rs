@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use crate::{Error, Lockfile, Package, Yarn1Lockfile};
+
+/// `bun.lockb` is a binary format, but `bun bun.lockb` prints it using
+/// yarn's v1 lockfile syntax. This parses that text representation.
+pub struct BunLockfile {
+    data: Yarn1Lockfile,
+}
+
+impl BunLockfile {
+    pub fn from_bytes(input: &[u8]) -> Result<Self, Error> {
+        let data = Yarn1Lockfile::from_bytes(input)?;
+        Ok(Self { data })
+    }
+
+    pub fn subgraph(&self, packages: &[String]) -> Result<Self, Error> {
+        let data = self.data.subgraph(packages)?;
+        Ok(Self { data })
+    }
+}
+
+impl Lockfile for BunLockfile {
+    fn resolve_package(
+        &self,
+        workspace_path: &str,
+        name: &str,
+        version: &str,
+    ) -> Result<Option<Package>, Error> {
+        self.data.resolve_package(workspace_path, name, version)
+    }
+
+    fn all_dependencies(&self, key: &str) -> Result<Option<HashMap<String, String>>, Error> {
+        self.data.all_dependencies(key)
+    }
+}
+
+/// Returns the text form of the lockfile only containing the given packages.
+/// Bun can't produce a binary lockfile from it, but it accepts a yarn v1
+/// lockfile in its place.
+pub fn bun_subgraph(contents: &[u8], packages: &[String]) -> Result<Vec<u8>, Error> {
+    let lockfile = BunLockfile::from_bytes(contents)?;
+    let pruned_lockfile = lockfile.subgraph(packages)?;
+    Ok(pruned_lockfile.data.to_string().into_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::transitive_closure;
+
+    const LOCKFILE: &[u8] = include_bytes!("../fixtures/bun.lock");
+
+    #[test_case("is-odd", "^3.0.1", Some(("is-odd@^3.0.1", "3.0.1")) ; "direct dependency")]
+    #[test_case("lodash", "^4.17.21", Some(("lodash@^4.17.21", "4.17.21")) ; "lodash")]
+    #[test_case("lodash", "^3.0.0", None ; "missing range")]
+    fn test_resolve_package(name: &str, version: &str, expected: Option<(&str, &str)>) {
+        let lockfile = BunLockfile::from_bytes(LOCKFILE).unwrap();
+        let actual = lockfile.resolve_package("apps/web", name, version).unwrap();
+        assert_eq!(
+            actual,
+            expected.map(|(key, version)| Package::new(key, version))
+        );
+    }
+
+    #[test]
+    fn test_all_dependencies() {
+        let lockfile = BunLockfile::from_bytes(LOCKFILE).unwrap();
+        assert_eq!(
+            lockfile.all_dependencies("kind-of@^3.0.2").unwrap(),
+            Some(HashMap::from([(
+                "is-buffer".to_string(),
+                "^1.1.5".to_string()
+            )]))
+        );
+        assert_eq!(lockfile.all_dependencies("lodash@^4.17.21").unwrap(), None);
+    }
+
+    #[test]
+    fn test_transitive_closure() {
+        let lockfile = BunLockfile::from_bytes(LOCKFILE).unwrap();
+        let closure = transitive_closure(
+            &lockfile,
+            "apps/web",
+            HashMap::from([("is-odd".to_string(), "^3.0.1".to_string())]),
+        )
+        .unwrap();
+        assert_eq!(
+            closure,
+            HashSet::from([
+                Package::new("is-odd@^3.0.1", "3.0.1"),
+                Package::new("is-number@^6.0.0", "6.0.0"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_subgraph() {
+        let pruned = bun_subgraph(
+            LOCKFILE,
+            &["is-odd@^3.0.1".to_string(), "is-number@^6.0.0".to_string()],
+        )
+        .unwrap();
+        let pruned = BunLockfile::from_bytes(&pruned).unwrap();
+        assert!(pruned
+            .resolve_package("apps/web", "is-odd", "^3.0.1")
+            .unwrap()
+            .is_some());
+        assert!(pruned
+            .resolve_package("apps/web", "lodash", "^4.17.21")
+            .unwrap()
+            .is_none());
+    }
+}
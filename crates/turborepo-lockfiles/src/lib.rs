@@ -1,4 +1,5 @@
 mod berry;
+mod bun;
 mod error;
 mod npm;
 mod pnpm;
@@ -7,6 +8,7 @@ mod yarn1;
 use std::collections::{HashMap, HashSet};
 
 pub use berry::{Error as BerryError, *};
+pub use bun::{bun_subgraph, BunLockfile};
 pub use error::Error;
 pub use npm::*;
 pub use pnpm::{pnpm_global_change, pnpm_subgraph, PnpmLockfile};
@@ -22,7 +24,7 @@ pub struct Package {
 // This trait will only be used when migrating the Go lockfile implementations
 // to Rust. Once the migration is complete we will leverage petgraph for doing
 // our graph calculations.
-pub trait Lockfile {
+pub trait Lockfile: Send {
     // Given a workspace, a package it imports and version returns the key, resolved
     // version, and if it was found
     fn resolve_package(
@@ -95,6 +97,31 @@ fn transitive_closure_helper<L: Lockfile + ?Sized>(
     Ok(())
 }
 
+/// Returns the workspaces whose transitive closure of external dependencies
+/// differs between the previous and current versions of a lockfile.
+/// `workspaces` maps each workspace path to its unresolved external
+/// dependencies. Workspaces missing from the previous lockfile were added
+/// since and are always considered changed.
+pub fn changed_workspaces<P: Lockfile + ?Sized, C: Lockfile + ?Sized>(
+    previous: &P,
+    current: &C,
+    workspaces: HashMap<String, HashMap<String, String>>,
+) -> Result<HashSet<String>, Error> {
+    let mut changed = HashSet::new();
+    for (workspace, unresolved_deps) in workspaces {
+        let current_closure = transitive_closure(current, &workspace, unresolved_deps.clone())?;
+        let previous_closure = match transitive_closure(previous, &workspace, unresolved_deps) {
+            Ok(closure) => Some(closure),
+            Err(Error::MissingWorkspace(_)) => None,
+            Err(err) => return Err(err),
+        };
+        if previous_closure.as_ref() != Some(&current_closure) {
+            changed.insert(workspace);
+        }
+    }
+    Ok(changed)
+}
+
 impl Package {
     pub fn new(key: impl Into<String>, version: impl Into<String>) -> Self {
         let key = key.into();
@@ -102,3 +129,252 @@ impl Package {
         Self { key, version }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Maps package names to their version and dependencies
+    type Entries = HashMap<&'static str, (&'static str, Vec<(&'static str, &'static str)>)>;
+
+    struct MockLockfile(Entries);
+
+    impl Lockfile for MockLockfile {
+        fn resolve_package(
+            &self,
+            _workspace_path: &str,
+            name: &str,
+            _version: &str,
+        ) -> Result<Option<Package>, Error> {
+            Ok(self
+                .0
+                .get(name)
+                .map(|(version, _)| Package::new(name, *version)))
+        }
+
+        fn all_dependencies(&self, key: &str) -> Result<Option<HashMap<String, String>>, Error> {
+            Ok(self.0.get(key).map(|(_, deps)| {
+                deps.iter()
+                    .map(|(name, version)| (name.to_string(), version.to_string()))
+                    .collect()
+            }))
+        }
+    }
+
+    #[test]
+    fn test_changed_workspaces() {
+        let previous = MockLockfile(
+            [
+                ("react", ("18.2.0", vec![("loose-envify", "^1.1.0")])),
+                ("loose-envify", ("1.4.0", vec![])),
+                ("lodash", ("4.17.20", vec![])),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        // Only a transitive dependency of react changes
+        let current = MockLockfile(
+            [
+                ("react", ("18.2.0", vec![("loose-envify", "^1.1.0")])),
+                ("loose-envify", ("1.5.0", vec![])),
+                ("lodash", ("4.17.20", vec![])),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let workspaces = workspaces(&[
+            ("apps/web", &[("react", "^18.2.0")]),
+            ("apps/docs", &[("lodash", "^4.17.20")]),
+            (
+                "packages/ui",
+                &[("react", "^18.2.0"), ("lodash", "^4.17.20")],
+            ),
+        ]);
+
+        let changed = changed_workspaces(&previous, &current, workspaces.clone()).unwrap();
+        assert_eq!(changed, names(&["apps/web", "packages/ui"]));
+
+        let unchanged = changed_workspaces(&current, &current, workspaces).unwrap();
+        assert!(unchanged.is_empty());
+    }
+
+    // The tests below diff a fixture against a copy of it from before one of
+    // its workspaces was added and one of its dependencies was bumped
+
+    fn workspaces(
+        workspaces: &[(&str, &[(&str, &str)])],
+    ) -> HashMap<String, HashMap<String, String>> {
+        workspaces
+            .iter()
+            .map(|(workspace, deps)| {
+                (
+                    workspace.to_string(),
+                    deps.iter()
+                        .map(|(name, version)| (name.to_string(), version.to_string()))
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn names(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn replace(contents: &str, from: &str, to: &str) -> String {
+        assert!(contents.contains(from), "fixture doesn't contain {from:?}");
+        contents.replacen(from, to, 1)
+    }
+
+    // Removes the entry starting with `header` up to the next blank line
+    fn remove_entry(contents: &str, header: &str) -> String {
+        let start = contents
+            .find(header)
+            .unwrap_or_else(|| panic!("fixture doesn't contain {header:?}"));
+        let end = contents[start..]
+            .find("\n\n")
+            .map_or(contents.len(), |end| start + end + 2);
+        format!("{}{}", &contents[..start], &contents[end..])
+    }
+
+    #[test]
+    fn test_changed_workspaces_npm() {
+        let contents = include_str!("../fixtures/npm-lock.json");
+        let current = NpmLockfile::load(contents.as_bytes()).unwrap();
+        let mut previous: serde_json::Value = serde_json::from_str(&replace(
+            contents,
+            "\"apps/web/node_modules/lodash\": {\n      \"version\": \"4.17.21\"",
+            "\"apps/web/node_modules/lodash\": {\n      \"version\": \"4.17.20\"",
+        ))
+        .unwrap();
+        previous["packages"]
+            .as_object_mut()
+            .unwrap()
+            .remove("apps/docs");
+        let previous = NpmLockfile::load(&serde_json::to_vec(&previous).unwrap()).unwrap();
+
+        let changed = changed_workspaces(
+            &previous,
+            &current,
+            workspaces(&[
+                ("apps/web", &[("lodash", "^4.17.21"), ("react", "18.2.0")]),
+                ("apps/docs", &[("lodash", "^3.0.0"), ("react", "18.2.0")]),
+                ("packages/ui", &[("react", "^18.2.0")]),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(changed, names(&["apps/web", "apps/docs"]));
+    }
+
+    #[test]
+    fn test_changed_workspaces_pnpm() {
+        let contents = include_str!("../fixtures/pnpm8.yaml");
+        let current = PnpmLockfile::from_bytes(contents.as_bytes()).unwrap();
+        let previous = replace(
+            contents,
+            "specifier: ^4.17.21\n        version: 4.17.21",
+            "specifier: ^4.17.21\n        version: 4.17.20",
+        );
+        let previous = replace(&previous, "/lodash@4.17.21:", "/lodash@4.17.20:");
+        let previous = remove_entry(&previous, "  packages/b:");
+        let previous = PnpmLockfile::from_bytes(previous.as_bytes()).unwrap();
+
+        let changed = changed_workspaces(
+            &previous,
+            &current,
+            workspaces(&[
+                ("packages/a", &[("is-odd", "^3.0.1")]),
+                ("packages/b", &[("is-even", "^1.0.0")]),
+                ("packages/c", &[("lodash", "^4.17.21")]),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(changed, names(&["packages/b", "packages/c"]));
+    }
+
+    #[test]
+    fn test_changed_workspaces_yarn1() {
+        let contents = include_str!("../fixtures/yarn1.lock");
+        let current = Yarn1Lockfile::from_bytes(contents.as_bytes()).unwrap();
+        // Yarn doesn't record workspaces, only the dependencies they brought in
+        let previous = replace(
+            contents,
+            "turbo-linux-64@1.9.3:\n  version \"1.9.3\"",
+            "turbo-linux-64@1.9.3:\n  version \"1.9.2\"",
+        );
+        let previous = remove_entry(&previous, "nextjs@^0.0.3:");
+        let previous = Yarn1Lockfile::from_bytes(previous.as_bytes()).unwrap();
+
+        let changed = changed_workspaces(
+            &previous,
+            &current,
+            workspaces(&[
+                ("apps/web", &[("turbo", "^1.9.3")]),
+                ("apps/docs", &[("nextjs", "^0.0.3")]),
+                ("packages/ui", &[("turbo-darwin-64", "1.9.3")]),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(changed, names(&["apps/web", "apps/docs"]));
+    }
+
+    #[test]
+    fn test_changed_workspaces_berry() {
+        let contents = include_str!("../fixtures/berry.lock");
+        let current =
+            BerryLockfile::new(LockfileData::from_bytes(contents.as_bytes()).unwrap(), None)
+                .unwrap();
+        let previous = replace(
+            contents,
+            "\"@types/react@npm:18.0.17\":\n  version: 18.0.17",
+            "\"@types/react@npm:18.0.17\":\n  version: 18.0.16",
+        );
+        let previous = remove_entry(&previous, "\"docs@workspace:apps/docs\":");
+        let previous =
+            BerryLockfile::new(LockfileData::from_bytes(previous.as_bytes()).unwrap(), None)
+                .unwrap();
+
+        let changed = changed_workspaces(
+            &previous,
+            &current,
+            workspaces(&[
+                (
+                    "apps/web",
+                    &[("@types/react", "18.0.17"), ("react", "18.2.0")],
+                ),
+                ("apps/docs", &[("lodash", "^4.17.21"), ("react", "18.2.0")]),
+                (
+                    "packages/ui",
+                    &[("@types/react", "^17.0.37"), ("react", "^18.2.0")],
+                ),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(changed, names(&["apps/web", "apps/docs"]));
+    }
+
+    #[test]
+    fn test_changed_workspaces_bun() {
+        let contents = include_str!("../fixtures/bun.lock");
+        let current = BunLockfile::from_bytes(contents.as_bytes()).unwrap();
+        let previous = replace(
+            contents,
+            "\"is-number@^6.0.0\":\n  version \"6.0.0\"",
+            "\"is-number@^6.0.0\":\n  version \"6.0.1\"",
+        );
+        let previous = remove_entry(&previous, "\"lodash@^4.17.21\":");
+        let previous = BunLockfile::from_bytes(previous.as_bytes()).unwrap();
+
+        let changed = changed_workspaces(
+            &previous,
+            &current,
+            workspaces(&[
+                ("packages/a", &[("is-odd", "^3.0.1")]),
+                ("packages/b", &[("kind-of", "^3.0.2")]),
+                ("packages/c", &[("lodash", "^4.17.21")]),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(changed, names(&["packages/a", "packages/c"]));
+    }
+}
@@ -1,5 +1,6 @@
 use std::{backtrace::Backtrace, collections::HashSet, path::PathBuf, process::Command};
 
+use tracing::debug;
 use turbopath::{
     AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf, RelativeUnixPath,
 };
@@ -29,6 +30,22 @@ impl SCM {
             Self::Manual => Err(Error::GitRequired(file_path.to_owned())),
         }
     }
+
+    /// The name of the checked out branch, empty if HEAD is detached
+    pub fn get_current_branch(&self, path: &AbsoluteSystemPath) -> Result<String, Error> {
+        match self {
+            Self::Git(git) => git.get_current_branch(),
+            Self::Manual => Err(Error::GitRequired(path.to_owned())),
+        }
+    }
+
+    /// The sha of the commit HEAD points to
+    pub fn get_current_sha(&self, path: &AbsoluteSystemPath) -> Result<String, Error> {
+        match self {
+            Self::Git(git) => git.get_current_sha(),
+            Self::Manual => Err(Error::GitRequired(path.to_owned())),
+        }
+    }
 }
 
 /// Finds the changed files in a repository between index and working directory
@@ -75,11 +92,28 @@ impl Git {
         let turbo_root_relative_to_git_root = self.root.anchor(turbo_root)?;
         let pathspec = turbo_root_relative_to_git_root.as_str();
 
-        let mut files = HashSet::new();
+        let paths = self.changed_paths(pathspec, from_commit, to_commit)?;
+        paths
+            .iter()
+            .map(|path| {
+                let path = RelativeUnixPath::new(path)?;
+                self.reanchor_path_from_git_root_to_turbo_root(turbo_root, path)
+            })
+            .collect()
+    }
+
+    /// The changed files as paths relative to the git root
+    fn changed_paths(
+        &self,
+        pathspec: &str,
+        from_commit: Option<&str>,
+        to_commit: &str,
+    ) -> Result<HashSet<String>, Error> {
+        let mut paths = HashSet::new();
 
         let output = self.execute_git_command(&["diff", "--name-only", to_commit], pathspec)?;
 
-        self.add_files_from_stdout(&mut files, turbo_root, output);
+        add_paths_from_stdout(&mut paths, output)?;
 
         if let Some(from_commit) = from_commit {
             let output = self.execute_git_command(
@@ -91,15 +125,63 @@ impl Git {
                 pathspec,
             )?;
 
-            self.add_files_from_stdout(&mut files, turbo_root, output);
+            add_paths_from_stdout(&mut paths, output)?;
         }
 
         let output =
             self.execute_git_command(&["ls-files", "--others", "--exclude-standard"], pathspec)?;
 
-        self.add_files_from_stdout(&mut files, turbo_root, output);
+        add_paths_from_stdout(&mut paths, output)?;
+
+        // A submodule with changes is reported as a single path, look inside of it
+        // to find out which of its files changed.
+        let submodules = paths
+            .iter()
+            .filter(|path| {
+                RelativeUnixPath::new(path.as_str())
+                    .and_then(|path| self.root.join_unix_path(path))
+                    .map_or(false, |path| path.join_component(".git").exists())
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        for path in submodules {
+            match self.submodule_changed_paths(&path, from_commit, to_commit) {
+                Ok(submodule_paths) => {
+                    paths.remove(&path);
+                    paths.extend(
+                        submodule_paths
+                            .into_iter()
+                            .map(|submodule_path| format!("{}/{}", path, submodule_path)),
+                    );
+                }
+                // e.g. the submodule was just added, or doesn't have the commits
+                // it's pinned to. We still know that something in it changed.
+                Err(e) => debug!("unable to find changed files in submodule {}: {}", path, e),
+            }
+        }
+
+        Ok(paths)
+    }
 
-        Ok(files)
+    fn submodule_changed_paths(
+        &self,
+        path: &str,
+        from_commit: Option<&str>,
+        to_commit: &str,
+    ) -> Result<HashSet<String>, Error> {
+        let submodule = self.submodule(self.root.join_unix_path(RelativeUnixPath::new(path)?)?);
+        let to_commit = self.submodule_commit(to_commit, path)?;
+        let from_commit = from_commit
+            .map(|from_commit| self.submodule_commit(from_commit, path))
+            .transpose()?;
+        submodule.changed_paths("", from_commit.as_deref(), &to_commit)
+    }
+
+    /// The commit that the submodule at `path` is pinned to as of `commit`
+    fn submodule_commit(&self, commit: &str, path: &str) -> Result<String, Error> {
+        let output =
+            self.execute_git_command(&["rev-parse", &format!("{}:{}", commit, path)], "")?;
+        Ok(String::from_utf8(output)?.trim_end().to_string())
     }
 
     fn execute_git_command(&self, args: &[&str], pathspec: &str) -> Result<Vec<u8>, Error> {
@@ -120,23 +202,6 @@ impl Git {
         }
     }
 
-    fn add_files_from_stdout(
-        &self,
-        files: &mut HashSet<AnchoredSystemPathBuf>,
-        turbo_root: &AbsoluteSystemPath,
-        stdout: Vec<u8>,
-    ) {
-        let turbo_root = turbo_root;
-        let stdout = String::from_utf8(stdout).unwrap();
-        for line in stdout.lines() {
-            let path = RelativeUnixPath::new(line).unwrap();
-            let anchored_to_turbo_root_file_path = self
-                .reanchor_path_from_git_root_to_turbo_root(turbo_root, path)
-                .unwrap();
-            files.insert(anchored_to_turbo_root_file_path);
-        }
-    }
-
     fn reanchor_path_from_git_root_to_turbo_root(
         &self,
         turbo_root: &AbsoluteSystemPath,
@@ -147,6 +212,16 @@ impl Git {
         Ok(anchored_to_turbo_root_file_path)
     }
 
+    fn get_current_branch(&self) -> Result<String, Error> {
+        let output = self.execute_git_command(&["branch", "--show-current"], "")?;
+        Ok(String::from_utf8(output)?.trim_end().to_string())
+    }
+
+    fn get_current_sha(&self) -> Result<String, Error> {
+        let output = self.execute_git_command(&["rev-parse", "HEAD"], "")?;
+        Ok(String::from_utf8(output)?.trim_end().to_string())
+    }
+
     fn previous_content(
         &self,
         from_commit: &str,
@@ -171,6 +246,12 @@ impl Git {
     }
 }
 
+fn add_paths_from_stdout(paths: &mut HashSet<String>, stdout: Vec<u8>) -> Result<(), Error> {
+    let stdout = String::from_utf8(stdout)?;
+    paths.extend(stdout.lines().map(|line| line.to_string()));
+    Ok(())
+}
+
 /// Finds the content of a file at a previous commit. Assumes file is in a git
 /// repository
 ///
@@ -211,7 +292,7 @@ mod tests {
     use which::which;
 
     use super::previous_content;
-    use crate::{git::changed_files, Error};
+    use crate::{git::changed_files, Error, SCM};
 
     fn setup_repository() -> Result<(TempDir, Repository), Error> {
         let repo_root = tempfile::tempdir()?;
@@ -564,6 +645,22 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_current_branch_and_sha() -> Result<(), Error> {
+        let (repo_root, repo) = setup_repository()?;
+        let root = AbsoluteSystemPathBuf::try_from(repo_root.path()).unwrap();
+        root.join_component("foo.js")
+            .create_with_contents("let z = 0;")?;
+        let commit_oid = commit_file(&repo, Path::new("foo.js"), None);
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+
+        let scm = SCM::new(&root);
+        assert_eq!(scm.get_current_sha(&root)?, commit_oid.to_string());
+        assert_eq!(scm.get_current_branch(&root)?, branch);
+
+        Ok(())
+    }
+
     #[test]
     fn test_revparse() -> Result<(), Error> {
         let (repo_root, repo) = setup_repository()?;
@@ -655,4 +752,141 @@ mod tests {
 
         Ok(())
     }
+
+    fn git_cmd(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    fn setup_cli_repository(repo_root: &Path) {
+        fs::create_dir_all(repo_root).unwrap();
+        git_cmd(repo_root, &["init", "."]);
+        git_cmd(repo_root, &["config", "--local", "user.name", "test"]);
+        git_cmd(
+            repo_root,
+            &["config", "--local", "user.email", "test@example.com"],
+        );
+    }
+
+    fn commit_all(repo_root: &Path) -> String {
+        git_cmd(repo_root, &["add", "."]);
+        git_cmd(repo_root, &["commit", "-m", "Commit"]);
+        git_cmd(repo_root, &["rev-parse", "HEAD"])
+    }
+
+    #[test]
+    fn test_changed_files_in_submodule() -> Result<(), Error> {
+        let tmp = tempfile::tempdir()?;
+        let lib_root = tmp.path().join("lib");
+        setup_cli_repository(&lib_root);
+        fs::write(lib_root.join("a.js"), "let a = 0;")?;
+        commit_all(&lib_root);
+
+        let repo_root = tmp.path().join("repo");
+        setup_cli_repository(&repo_root);
+        fs::write(repo_root.join("root.js"), "let root = 0;")?;
+        git_cmd(
+            &repo_root,
+            &[
+                "-c",
+                "protocol.file.allow=always",
+                "submodule",
+                "add",
+                lib_root.to_str().unwrap(),
+                "vendor/lib",
+            ],
+        );
+        let first_commit = commit_all(&repo_root);
+        let submodule_root = repo_root.join("vendor").join("lib");
+
+        // Uncommitted changes inside of the submodule are reported per file
+        fs::write(submodule_root.join("a.js"), "let a = 1;")?;
+        let files = changed_files(repo_root.clone(), repo_root.clone(), None, "HEAD")?;
+        assert_eq!(files, HashSet::from(["vendor/lib/a.js".to_string()]));
+
+        // As are the files changed by moving the submodule to a new commit
+        fs::write(submodule_root.join("b.js"), "let b = 0;")?;
+        git_cmd(
+            &submodule_root,
+            &[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "-am",
+                "Commit",
+            ],
+        );
+        let second_commit = commit_all(&repo_root);
+        let files = changed_files(
+            repo_root.clone(),
+            repo_root.clone(),
+            Some(&first_commit),
+            &second_commit,
+        )?;
+        assert_eq!(
+            files,
+            HashSet::from(["vendor/lib/a.js".to_string(), "vendor/lib/b.js".to_string()])
+        );
+
+        // A submodule that wasn't checked out is reported as a whole
+        let clone_root = tmp.path().join("clone");
+        git_cmd(
+            tmp.path(),
+            &[
+                "clone",
+                repo_root.to_str().unwrap(),
+                clone_root.to_str().unwrap(),
+            ],
+        );
+        let files = changed_files(
+            clone_root.clone(),
+            clone_root,
+            Some(&first_commit),
+            &second_commit,
+        )?;
+        assert_eq!(files, HashSet::from(["vendor/lib".to_string()]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_changed_files_in_worktree() -> Result<(), Error> {
+        let tmp = tempfile::tempdir()?;
+        let repo_root = tmp.path().join("repo");
+        setup_cli_repository(&repo_root);
+        fs::write(repo_root.join("foo.js"), "let z = 0;")?;
+        let first_commit = commit_all(&repo_root);
+
+        let worktree_root = tmp.path().join("worktree");
+        git_cmd(
+            &repo_root,
+            &["worktree", "add", worktree_root.to_str().unwrap()],
+        );
+        fs::write(worktree_root.join("bar.js"), "let y = 1;")?;
+        let files = changed_files(worktree_root.clone(), worktree_root.clone(), None, "HEAD")?;
+        assert_eq!(files, HashSet::from(["bar.js".to_string()]));
+
+        let second_commit = commit_all(&worktree_root);
+        let files = changed_files(
+            worktree_root.clone(),
+            worktree_root,
+            Some(&first_commit),
+            &second_commit,
+        )?;
+        assert_eq!(files, HashSet::from(["bar.js".to_string()]));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,663 @@
+//! In-process git index reading
+//!
+//! Lists the files of a package straight from `.git/index` rather than
+//! spawning `git ls-tree` and `git status`. Entries whose stat data still
+//! matches what git recorded reuse the blob id stored in the index, anything
+//! else is re-hashed the way `git hash-object` would, so the hashes are
+//! identical to the ones produced by the git binary.
+
+use std::{
+    collections::HashSet,
+    fs::Metadata,
+    io::Read,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hex::ToHex;
+use ignore::WalkBuilder;
+use sha1::{Digest, Sha1};
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, RelativeUnixPathBuf};
+
+use crate::{package_deps::GitHashes, Error};
+
+const SIGNATURE: &[u8] = b"DIRC";
+const HASH_LEN: usize = 20;
+
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+const EXTENDED_FLAG_SKIP_WORKTREE: u16 = 0x4000;
+const EXTENDED_FLAG_INTENT_TO_ADD: u16 = 0x2000;
+
+const MODE_TYPE_MASK: u32 = 0o170000;
+const MODE_SYMLINK: u32 = 0o120000;
+const MODE_GITLINK: u32 = 0o160000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Timestamp {
+    secs: u32,
+    nsecs: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct IndexEntry {
+    pub path: String,
+    pub hash: String,
+    pub mode: u32,
+    pub mtime: Timestamp,
+    pub size: u32,
+    /// Non-zero for entries that are part of an unresolved merge conflict
+    pub stage: u8,
+    /// Set for entries outside of a sparse checkout, they are not expected to
+    /// exist in the working tree
+    pub skip_worktree: bool,
+    /// Set for entries added with `git add -N`, their hash is not meaningful
+    pub intent_to_add: bool,
+}
+
+impl IndexEntry {
+    fn is_symlink(&self) -> bool {
+        self.mode & MODE_TYPE_MASK == MODE_SYMLINK
+    }
+
+    fn is_gitlink(&self) -> bool {
+        self.mode & MODE_TYPE_MASK == MODE_GITLINK
+    }
+}
+
+/// The entries of a git index, sorted by path
+#[derive(Debug)]
+pub(crate) struct Index {
+    pub entries: Vec<IndexEntry>,
+    /// When the index file was last written, entries modified at or after this
+    /// time can't be trusted to be unchanged based on their stat data alone
+    pub mtime: Timestamp,
+}
+
+impl Index {
+    pub fn read(git_dir: &AbsoluteSystemPath) -> Result<Self, Error> {
+        let index_path = git_dir.join_component("index");
+        let mut file = index_path.open()?;
+        let metadata = file.metadata()?;
+        let mut buffer = Vec::with_capacity(metadata.len() as usize);
+        file.read_to_end(&mut buffer)?;
+        let entries = parse_index(&buffer)?;
+        Ok(Self {
+            entries,
+            mtime: modified_time(&metadata),
+        })
+    }
+}
+
+struct Reader<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.buffer.len())
+            .ok_or_else(|| Error::index_error("unexpected end of file"))?;
+        let bytes = &self.buffer[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn until_nul(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.buffer[self.offset..]
+            .iter()
+            .position(|b| *b == b'\0')
+            .ok_or_else(|| Error::index_error("unterminated path"))?;
+        let bytes = self.take(len)?;
+        self.offset += 1;
+        Ok(bytes)
+    }
+
+    // The variable length integer used by index v4 to encode how much of the
+    // previous path is removed, see `decode_varint` in git's varint.c
+    fn varint(&mut self) -> Result<usize, Error> {
+        let mut byte = self.take(1)?[0];
+        let mut value = (byte & 0x7f) as usize;
+        while byte & 0x80 != 0 {
+            byte = self.take(1)?[0];
+            value = value
+                .checked_add(1)
+                .and_then(|value| value.checked_mul(128))
+                .ok_or_else(|| Error::index_error("path prefix length overflow"))?
+                + (byte & 0x7f) as usize;
+        }
+        Ok(value)
+    }
+
+    fn remaining(&self) -> usize {
+        self.buffer.len() - self.offset
+    }
+}
+
+pub(crate) fn parse_index(buffer: &[u8]) -> Result<Vec<IndexEntry>, Error> {
+    if buffer.len() < 12 + HASH_LEN {
+        return Err(Error::index_error("file is too short"));
+    }
+    let (content, checksum) = buffer.split_at(buffer.len() - HASH_LEN);
+    // index.skipHash writes a null checksum
+    if checksum.iter().any(|b| *b != 0) && Sha1::digest(content).as_slice() != checksum {
+        return Err(Error::index_error("checksum mismatch"));
+    }
+
+    let mut reader = Reader {
+        buffer: content,
+        offset: 0,
+    };
+    if reader.take(4)? != SIGNATURE {
+        return Err(Error::index_error("bad signature"));
+    }
+    let version = reader.u32()?;
+    if !(2..=4).contains(&version) {
+        return Err(Error::index_error(format!(
+            "unsupported version {}",
+            version
+        )));
+    }
+    let count = reader.u32()? as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    let mut previous_path: Vec<u8> = Vec::new();
+    for _ in 0..count {
+        let start = reader.offset;
+        // ctime is never checked, git's own stat comparison ignores it by default
+        reader.take(8)?;
+        let mtime = Timestamp {
+            secs: reader.u32()?,
+            nsecs: reader.u32()?,
+        };
+        // dev and ino
+        reader.take(8)?;
+        let mode = reader.u32()?;
+        // uid and gid
+        reader.take(8)?;
+        let size = reader.u32()?;
+        let hash = reader.take(HASH_LEN)?.encode_hex::<String>();
+        let flags = reader.u16()?;
+        let extended_flags = if version >= 3 && flags & FLAG_EXTENDED != 0 {
+            reader.u16()?
+        } else {
+            0
+        };
+
+        let path = if version == 4 {
+            let strip = reader.varint()?;
+            let keep = previous_path
+                .len()
+                .checked_sub(strip)
+                .ok_or_else(|| Error::index_error("invalid path prefix length"))?;
+            previous_path.truncate(keep);
+            previous_path.extend_from_slice(reader.until_nul()?);
+            previous_path.clone()
+        } else {
+            let name_len = (flags & FLAG_NAME_MASK) as usize;
+            let path = if name_len < FLAG_NAME_MASK as usize {
+                let path = reader.take(name_len)?;
+                reader.offset += 1;
+                path
+            } else {
+                reader.until_nul()?
+            };
+            // entries are padded with NULs to a multiple of 8 bytes
+            let entry_len = reader.offset - start;
+            reader.take((8 - entry_len % 8) % 8)?;
+            path.to_vec()
+        };
+
+        entries.push(IndexEntry {
+            path: String::from_utf8(path)?,
+            hash,
+            mode,
+            mtime,
+            size,
+            stage: ((flags & FLAG_STAGE_MASK) >> 12) as u8,
+            skip_worktree: extended_flags & EXTENDED_FLAG_SKIP_WORKTREE != 0,
+            intent_to_add: extended_flags & EXTENDED_FLAG_INTENT_TO_ADD != 0,
+        });
+    }
+
+    // Extensions are caches we don't need, except for the ones git marks as
+    // required by starting their signature with a lowercase letter. Those
+    // change what the entries mean (e.g. a split or sparse index).
+    while reader.remaining() > 0 {
+        let signature = reader.take(4)?;
+        let len = reader.u32()? as usize;
+        if !signature[0].is_ascii_uppercase() {
+            return Err(Error::index_error(format!(
+                "unsupported extension {}",
+                String::from_utf8_lossy(signature)
+            )));
+        }
+        reader.take(len)?;
+    }
+
+    Ok(entries)
+}
+
+/// Finds the root of the repository containing `path`, stopping at `outermost`
+/// if given. Repositories are found by their `.git` entry, which is a file for
+/// worktrees and submodules.
+pub(crate) fn find_repository_root(
+    path: &AbsoluteSystemPath,
+    outermost: Option<&AbsoluteSystemPath>,
+) -> Option<AbsoluteSystemPathBuf> {
+    for dir in path.ancestors() {
+        if dir.join_component(".git").exists() {
+            return Some(dir.to_owned());
+        }
+        if Some(dir) == outermost {
+            break;
+        }
+    }
+    None
+}
+
+/// Resolves the git directory of a repository. For worktrees and submodules
+/// `.git` is a file pointing at the real git directory, e.g.
+/// `gitdir: ../.git/modules/my-submodule`
+pub(crate) fn resolve_git_dir(
+    repo_root: &AbsoluteSystemPath,
+) -> Result<AbsoluteSystemPathBuf, Error> {
+    let dot_git = repo_root.join_component(".git");
+    if dot_git.as_std_path().is_dir() {
+        return Ok(dot_git);
+    }
+    let contents = std::fs::read_to_string(&dot_git)?;
+    let git_dir = contents
+        .strip_prefix("gitdir:")
+        .map(str::trim)
+        .filter(|git_dir| !git_dir.is_empty())
+        .ok_or_else(|| Error::index_error(format!("{} is not a valid gitdir file", dot_git)))?;
+    Ok(AbsoluteSystemPathBuf::from_unknown(repo_root, git_dir))
+}
+
+/// Hashes the files of a package the same way `git ls-tree` followed by `git
+/// status` and `git hash-object` would, without spawning git. Files inside of
+/// checked out submodules are hashed as if they were part of the package.
+pub(crate) fn get_package_file_hashes_from_git_index(
+    git_root: &AbsoluteSystemPath,
+    full_pkg_path: &AbsoluteSystemPath,
+) -> Result<GitHashes, Error> {
+    // packages inside of a submodule are tracked by the submodule's index
+    let repo_root =
+        find_repository_root(full_pkg_path, Some(git_root)).unwrap_or_else(|| git_root.to_owned());
+    let mut hashes = GitHashes::new();
+    hash_repository_files(&repo_root, full_pkg_path, "", &mut hashes)?;
+    Ok(hashes)
+}
+
+fn hash_repository_files(
+    repo_root: &AbsoluteSystemPath,
+    dir: &AbsoluteSystemPath,
+    key_prefix: &str,
+    hashes: &mut GitHashes,
+) -> Result<(), Error> {
+    let index = Index::read(&resolve_git_dir(repo_root)?)?;
+    let dir_prefix = match repo_root.anchor(dir)?.to_unix()?.as_str() {
+        "" => String::new(),
+        prefix => format!("{}/", prefix),
+    };
+    let key =
+        |relative_path: &str| RelativeUnixPathBuf::new(format!("{}{}", key_prefix, relative_path));
+
+    let mut tracked = HashSet::new();
+    for entry in &index.entries {
+        let Some(relative_path) = entry.path.strip_prefix(&dir_prefix) else {
+            continue;
+        };
+        // conflicted files show up once per stage
+        if !tracked.insert(entry.path.as_str()) {
+            continue;
+        }
+        // files outside of a sparse checkout aren't in the working tree
+        if entry.skip_worktree {
+            hashes.insert(key(relative_path)?, entry.hash.clone());
+            continue;
+        }
+
+        let full_path = repo_root.join_unix_path(RelativeUnixPathBuf::new(entry.path.as_str())?)?;
+        let metadata = match full_path.symlink_metadata() {
+            Ok(metadata) => metadata,
+            // deleted from the working tree
+            Err(e) if e.is_io_error(std::io::ErrorKind::NotFound) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if entry.is_gitlink() {
+            if full_path.join_component(".git").exists() {
+                let key_prefix = format!("{}{}/", key_prefix, relative_path);
+                hash_repository_files(&full_path, &full_path, &key_prefix, hashes)?;
+            } else {
+                // an uninitialized submodule, all we know is the commit it's pinned to
+                hashes.insert(key(relative_path)?, entry.hash.clone());
+            }
+            continue;
+        }
+        if metadata.is_dir() {
+            // replaced by a directory, anything inside is untracked
+            tracked.remove(entry.path.as_str());
+            continue;
+        }
+        let hash = if entry.stage == 0
+            && !entry.intent_to_add
+            && is_unchanged(entry, &metadata, index.mtime)
+        {
+            entry.hash.clone()
+        } else {
+            match hash_path(&full_path, &metadata)? {
+                Some(hash) => hash,
+                None => continue,
+            }
+        };
+        hashes.insert(key(relative_path)?, hash);
+    }
+
+    let walker = WalkBuilder::new(dir)
+        .follow_links(false)
+        .git_ignore(true)
+        .hidden(false)
+        // git doesn't know about `.ignore` files
+        .ignore(false)
+        // skip the repository itself, as well as any nested repositories since
+        // they aren't part of this one. Submodules were hashed above.
+        .filter_entry(|dirent| {
+            dirent.file_name() != ".git"
+                && (dirent.depth() == 0
+                    || !dirent.file_type().map_or(false, |ft| ft.is_dir())
+                    || !dirent.path().join(".git").exists())
+        })
+        .build();
+    for dirent in walker {
+        let dirent = dirent?;
+        let metadata = dirent.metadata()?;
+        // We only hash regular files, untracked symlinks are skipped the same
+        // way the other strategies skip them
+        if !metadata.is_file() {
+            continue;
+        }
+        let path = AbsoluteSystemPath::from_std_path(dirent.path())?;
+        let repo_path = repo_root.anchor(path)?.to_unix()?;
+        if tracked.contains(repo_path.as_str()) {
+            continue;
+        }
+        let Some(hash) = hash_path(path, &metadata)? else {
+            continue;
+        };
+        hashes.insert(key(dir.anchor(path)?.to_unix()?.as_str())?, hash);
+    }
+
+    Ok(())
+}
+
+fn is_unchanged(entry: &IndexEntry, metadata: &Metadata, index_mtime: Timestamp) -> bool {
+    if metadata.is_symlink() != entry.is_symlink() {
+        return false;
+    }
+    let mtime = modified_time(metadata);
+    // The file could have been modified within the same timestamp granularity
+    // that the index was written in, git calls this "racily clean"
+    if entry.mtime >= index_mtime {
+        return false;
+    }
+    // git only records nanoseconds when built with USE_NSEC
+    let mtime_matches = if entry.mtime.nsecs == 0 {
+        entry.mtime.secs == mtime.secs
+    } else {
+        entry.mtime == mtime
+    };
+    // the index stores the size truncated to 32 bits
+    mtime_matches && entry.size == metadata.len() as u32
+}
+
+fn modified_time(metadata: &Metadata) -> Timestamp {
+    let since_epoch = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+        });
+    Timestamp {
+        secs: since_epoch.as_secs() as u32,
+        nsecs: since_epoch.subsec_nanos(),
+    }
+}
+
+// Symlinks are stored by git as a blob containing the link target
+fn hash_path(path: &AbsoluteSystemPath, metadata: &Metadata) -> Result<Option<String>, Error> {
+    if metadata.is_symlink() {
+        let target = path.read_link()?;
+        Ok(Some(hash_blob(
+            target.as_str().replace('\\', "/").as_bytes(),
+        )))
+    } else if metadata.is_file() {
+        let mut contents = Vec::with_capacity(metadata.len() as usize);
+        path.open()?.read_to_end(&mut contents)?;
+        Ok(Some(hash_blob(&contents)))
+    } else {
+        Ok(None)
+    }
+}
+
+pub(crate) fn hash_blob(contents: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(b"blob ");
+    hasher.update(contents.len().to_string().as_bytes());
+    hasher.update([b'\0']);
+    hasher.update(contents);
+    hasher.finalize().encode_hex::<String>()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use test_case::test_case;
+    use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+
+    use super::*;
+    use crate::{Git, SCM};
+
+    fn tmp_dir() -> (tempfile::TempDir, AbsoluteSystemPathBuf) {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = AbsoluteSystemPathBuf::try_from(tmp_dir.path())
+            .unwrap()
+            .to_realpath()
+            .unwrap();
+        (tmp_dir, dir)
+    }
+
+    fn require_git_cmd(repo_root: &AbsoluteSystemPath, args: &[&str]) {
+        let mut cmd = Command::new("git");
+        cmd.args(args).current_dir(repo_root);
+        assert!(cmd.output().unwrap().status.success());
+    }
+
+    fn setup_repository(repo_root: &AbsoluteSystemPath, index_version: &str) {
+        let cmds: &[&[&str]] = &[
+            &["init", "."],
+            &["config", "--local", "user.name", "test"],
+            &["config", "--local", "user.email", "test@example.com"],
+            &["config", "--local", "index.version", index_version],
+        ];
+        for cmd in cmds {
+            require_git_cmd(repo_root, cmd);
+        }
+    }
+
+    fn git(repo_root: &AbsoluteSystemPath) -> Git {
+        match SCM::new(repo_root) {
+            SCM::Git(git) => git,
+            SCM::Manual => panic!("expected git"),
+        }
+    }
+
+    #[test]
+    fn test_hash_blob() {
+        assert_eq!(hash_blob(b""), "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+        assert_eq!(
+            hash_blob(b"committed bytes"),
+            "3a29e62ea9ba15c4a4009d1f605d391cdd262033"
+        );
+    }
+
+    #[test_case("2" ; "version 2")]
+    #[test_case("3" ; "version 3")]
+    #[test_case("4" ; "version 4")]
+    fn test_read_index(index_version: &str) {
+        let (_tmp, repo_root) = tmp_dir();
+        setup_repository(&repo_root, index_version);
+        let files = [
+            "a-file",
+            "my-pkg/package.json",
+            "my-pkg/dir/nested-file",
+            "my-pkg/dir/nested-other-file",
+            "my-pkg/dir/very/deeply/nested/file",
+        ];
+        for file in files {
+            let path = repo_root
+                .join_unix_path(RelativeUnixPathBuf::new(file).unwrap())
+                .unwrap();
+            path.ensure_dir().unwrap();
+            path.create_with_contents(file).unwrap();
+        }
+        require_git_cmd(&repo_root, &["add", "."]);
+        repo_root
+            .join_components(&["my-pkg", "intent"])
+            .create_with_contents("intent")
+            .unwrap();
+        require_git_cmd(&repo_root, &["add", "-N", "my-pkg/intent"]);
+
+        let index = Index::read(&repo_root.join_component(".git")).unwrap();
+        let mut expected = files
+            .iter()
+            .map(|file| (file.to_string(), hash_blob(file.as_bytes())))
+            .collect::<Vec<_>>();
+        // intent to add entries are recorded with the empty blob
+        expected.push(("my-pkg/intent".to_string(), hash_blob(b"")));
+        expected.sort();
+        assert_eq!(
+            index
+                .entries
+                .iter()
+                .map(|entry| (entry.path.clone(), entry.hash.clone()))
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert!(index.entries.iter().all(|entry| entry.stage == 0));
+        assert_eq!(
+            index
+                .entries
+                .iter()
+                .filter(|entry| entry.intent_to_add)
+                .map(|entry| entry.path.as_str())
+                .collect::<Vec<_>>(),
+            vec!["my-pkg/intent"]
+        );
+    }
+
+    #[test]
+    fn test_parse_index_errors() {
+        assert!(parse_index(b"DIRC").is_err());
+        let mut index = b"DIRC\0\0\0\x05\0\0\0\0".to_vec();
+        index.extend(Sha1::digest(&index));
+        assert!(parse_index(&index).is_err());
+        let mut index = b"DIRC\0\0\0\x02\0\0\0\0".to_vec();
+        index.extend([0; HASH_LEN]);
+        assert_eq!(parse_index(&index).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_matches_git() -> Result<(), Error> {
+        // Directory structure:
+        // <root>/
+        //   .gitignore <- ignores *.log
+        //   new-root-file <- new file not added to git
+        //   my-pkg/
+        //     committed-file
+        //     deleted-file
+        //     modified-file
+        //     staged-file <- modified and added to the index
+        //     tracked.log <- committed despite being ignored
+        //     untracked.log <- ignored
+        //     uncommitted-file <- new file not added to git
+        //     link -> committed-file
+        //     dir/
+        //       nested-file
+        let (_tmp, repo_root) = tmp_dir();
+        setup_repository(&repo_root, "2");
+        let my_pkg_dir = repo_root.join_component("my-pkg");
+        repo_root
+            .join_component(".gitignore")
+            .create_with_contents("*.log")?;
+        for file in [
+            "committed-file",
+            "deleted-file",
+            "modified-file",
+            "staged-file",
+            "package.json",
+        ] {
+            let path = my_pkg_dir.join_component(file);
+            path.ensure_dir()?;
+            path.create_with_contents(file)?;
+        }
+        let nested_file = my_pkg_dir.join_components(&["dir", "nested-file"]);
+        nested_file.ensure_dir()?;
+        nested_file.create_with_contents("nested")?;
+        my_pkg_dir
+            .join_component("link")
+            .symlink_to_file("committed-file")?;
+        my_pkg_dir
+            .join_component("tracked.log")
+            .create_with_contents("tracked")?;
+        require_git_cmd(&repo_root, &["add", "."]);
+        require_git_cmd(&repo_root, &["add", "-f", "my-pkg/tracked.log"]);
+        require_git_cmd(&repo_root, &["commit", "-m", "foo"]);
+
+        my_pkg_dir.join_component("deleted-file").remove()?;
+        my_pkg_dir
+            .join_component("modified-file")
+            .create_with_contents("modified")?;
+        my_pkg_dir
+            .join_component("staged-file")
+            .create_with_contents("staged")?;
+        require_git_cmd(&repo_root, &["add", "my-pkg/staged-file"]);
+        my_pkg_dir
+            .join_component("untracked.log")
+            .create_with_contents("ignored")?;
+        my_pkg_dir
+            .join_component("uncommitted-file")
+            .create_with_contents("uncommitted")?;
+        repo_root
+            .join_component("new-root-file")
+            .create_with_contents("new-root")?;
+
+        let git = git(&repo_root);
+        for package_path in ["my-pkg", ""] {
+            let package_path = AnchoredSystemPathBuf::from_raw(package_path)?;
+            let full_pkg_path = repo_root.resolve(&package_path);
+            let expected = git.get_package_file_hashes_from_git_commands(&full_pkg_path)?;
+            let hashes = get_package_file_hashes_from_git_index(&repo_root, &full_pkg_path)?;
+            assert_eq!(hashes, expected);
+        }
+        Ok(())
+    }
+}
@@ -15,6 +15,7 @@ use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, PathError, RelativeUn
 
 pub mod git;
 mod hash_object;
+mod index;
 mod ls_tree;
 pub mod manual;
 pub mod package_deps;
@@ -52,6 +53,8 @@ pub enum Error {
     Glob(Box<wax::BuildError>, backtrace::Backtrace),
     #[error(transparent)]
     Walk(#[from] globwalk::WalkError),
+    #[error("unable to read git index: {0}")]
+    Index(String, #[backtrace] backtrace::Backtrace),
 }
 
 impl From<wax::BuildError> for Error {
@@ -65,6 +68,10 @@ impl Error {
         Error::Git(s.into(), Backtrace::capture())
     }
 
+    pub(crate) fn index_error(s: impl Into<String>) -> Self {
+        Error::Index(s.into(), Backtrace::capture())
+    }
+
     pub(crate) fn git2_error_context(error: git2::Error, context: String) -> Self {
         Error::Git2(error, context, Backtrace::capture())
     }
@@ -151,6 +158,14 @@ impl Git {
             find_git_root(path_in_repo).map_err(|e| GitError::Root(path_in_repo.to_owned(), e))?;
         Ok(Self { root, bin })
     }
+
+    /// The repository checked out at `root`, e.g. a submodule of this one
+    pub(crate) fn submodule(&self, root: AbsoluteSystemPathBuf) -> Git {
+        Git {
+            root,
+            bin: self.bin.clone(),
+        }
+    }
 }
 
 fn find_git_root(turbo_root: &AbsoluteSystemPath) -> Result<AbsoluteSystemPathBuf, Error> {
@@ -193,6 +208,14 @@ impl SCM {
             SCM::Manual
         })
     }
+
+    /// The root of the git repository, `None` when hashing without git
+    pub fn git_root(&self) -> Option<&AbsoluteSystemPath> {
+        match self {
+            SCM::Git(git) => Some(&git.root),
+            SCM::Manual => None,
+        }
+    }
 }
 
 #[cfg(test)]
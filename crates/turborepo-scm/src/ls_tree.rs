@@ -4,13 +4,20 @@ use std::{
 };
 
 use nom::Finish;
-use turbopath::{AbsoluteSystemPathBuf, RelativeUnixPathBuf};
+use turbopath::{AbsoluteSystemPath, RelativeUnixPathBuf};
 
 use crate::{package_deps::GitHashes, wait_for_success, Error, Git};
 
 impl Git {
-    pub fn git_ls_tree(&self, root_path: &AbsoluteSystemPathBuf) -> Result<GitHashes, Error> {
+    /// Lists the files committed at HEAD under `root_path`, along with the
+    /// commits that any submodules are pinned to, since their files aren't
+    /// part of this repository's tree
+    pub fn git_ls_tree(
+        &self,
+        root_path: &AbsoluteSystemPath,
+    ) -> Result<(GitHashes, GitHashes), Error> {
         let mut hashes = GitHashes::new();
+        let mut submodules = GitHashes::new();
         let mut git = Command::new(self.bin.as_std_path())
             .args(["ls-tree", "-r", "-z", "HEAD"])
             .current_dir(root_path)
@@ -26,20 +33,28 @@ impl Git {
             .stderr
             .take()
             .ok_or_else(|| Error::git_error("failed to get stderr for git ls-tree"))?;
-        let parse_result = read_ls_tree(stdout, &mut hashes);
+        let parse_result = read_ls_tree(stdout, &mut hashes, &mut submodules);
         wait_for_success(git, &mut stderr, "git ls-tree", root_path, parse_result)?;
-        Ok(hashes)
+        Ok((hashes, submodules))
     }
 }
 
-fn read_ls_tree<R: Read>(reader: R, hashes: &mut GitHashes) -> Result<(), Error> {
+fn read_ls_tree<R: Read>(
+    reader: R,
+    hashes: &mut GitHashes,
+    submodules: &mut GitHashes,
+) -> Result<(), Error> {
     let mut reader = BufReader::new(reader);
     let mut buffer = Vec::new();
     while reader.read_until(b'\0', &mut buffer)? != 0 {
         let entry = parse_ls_tree(&buffer)?;
         let hash = String::from_utf8(entry.hash.to_vec())?;
         let path = RelativeUnixPathBuf::new(String::from_utf8(entry.filename.to_vec())?)?;
-        hashes.insert(path, hash);
+        if entry.object_type == b"commit" {
+            submodules.insert(path, hash);
+        } else {
+            hashes.insert(path, hash);
+        }
         buffer.clear();
     }
     Ok(())
@@ -47,6 +62,7 @@ fn read_ls_tree<R: Read>(reader: R, hashes: &mut GitHashes) -> Result<(), Error>
 
 struct LsTreeEntry<'a> {
     filename: &'a [u8],
+    object_type: &'a [u8],
     hash: &'a [u8],
 }
 
@@ -64,14 +80,21 @@ fn parse_ls_tree(i: &[u8]) -> Result<LsTreeEntry<'_>, Error> {
 fn nom_parse_ls_tree(i: &[u8]) -> nom::IResult<&[u8], LsTreeEntry<'_>> {
     let (i, _) = nom::bytes::complete::is_not(" ")(i)?;
     let (i, _) = nom::character::complete::space1(i)?;
-    let (i, _) = nom::bytes::complete::is_not(" ")(i)?;
+    let (i, object_type) = nom::bytes::complete::is_not(" ")(i)?;
     let (i, _) = nom::character::complete::space1(i)?;
     let (i, hash) = nom::bytes::complete::take(40usize)(i)?;
     let (i, _) = nom::bytes::complete::take(1usize)(i)?;
     let (i, filename) = nom::bytes::complete::is_not("\0")(i)?;
     // We explicitly support a missing terminator
     let (i, _) = nom::combinator::opt(nom::bytes::complete::tag(&[b'\0']))(i)?;
-    Ok((i, LsTreeEntry { filename, hash }))
+    Ok((
+        i,
+        LsTreeEntry {
+            filename,
+            object_type,
+            hash,
+        },
+    ))
 }
 
 #[cfg(test)]
@@ -133,9 +156,33 @@ mod tests {
         for (input, expected) in tests {
             let input_bytes = input.as_bytes();
             let mut hashes = GitHashes::new();
+            let mut submodules = GitHashes::new();
             let expected = to_hash_map(expected);
-            read_ls_tree(input_bytes, &mut hashes).unwrap();
+            read_ls_tree(input_bytes, &mut hashes, &mut submodules).unwrap();
             assert_eq!(hashes, expected);
+            assert!(submodules.is_empty());
         }
     }
+
+    #[test]
+    fn test_ls_tree_submodules() {
+        let input = concat!(
+            "100644 blob e69de29bb2d1d6434b8b29ae775ad8c2e48c5391\tpackage.json\0",
+            "160000 commit 5b999efa470b056e329b4c23a73904e0794bdc2f\tvendor/my-submodule\0",
+        );
+        let mut hashes = GitHashes::new();
+        let mut submodules = GitHashes::new();
+        read_ls_tree(input.as_bytes(), &mut hashes, &mut submodules).unwrap();
+        assert_eq!(
+            hashes,
+            to_hash_map(&[("package.json", "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391")])
+        );
+        assert_eq!(
+            submodules,
+            to_hash_map(&[(
+                "vendor/my-submodule",
+                "5b999efa470b056e329b4c23a73904e0794bdc2f"
+            )])
+        );
+    }
 }
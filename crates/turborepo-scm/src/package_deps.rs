@@ -1,9 +1,14 @@
 use std::collections::HashMap;
 
 use itertools::{Either, Itertools};
+use tracing::debug;
 use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf, PathError, RelativeUnixPathBuf};
 
-use crate::{hash_object::hash_objects, Error, Git, SCM};
+use crate::{
+    hash_object::hash_objects,
+    index::{find_repository_root, get_package_file_hashes_from_git_index},
+    Error, Git, SCM,
+};
 
 pub type GitHashes = HashMap<RelativeUnixPathBuf, String>;
 
@@ -15,11 +20,24 @@ impl SCM {
         inputs: &[S],
     ) -> Result<GitHashes, Error> {
         match self {
-            SCM::Manual => crate::manual::get_package_file_hashes_from_processing_gitignore(
-                turbo_root,
-                package_path,
-                inputs,
-            ),
+            SCM::Manual => {
+                // Even without a git binary, a checkout still has an index that
+                // produces the same hashes as git would
+                if inputs.is_empty() {
+                    if let Some(git_root) = find_repository_root(turbo_root, None) {
+                        let full_pkg_path = turbo_root.resolve(package_path);
+                        match get_package_file_hashes_from_git_index(&git_root, &full_pkg_path) {
+                            Ok(hashes) => return Ok(hashes),
+                            Err(e) => debug!("{}, continuing with manual hashing", e),
+                        }
+                    }
+                }
+                crate::manual::get_package_file_hashes_from_processing_gitignore(
+                    turbo_root,
+                    package_path,
+                    inputs,
+                )
+            }
             SCM::Git(git) => git.get_package_file_hashes(turbo_root, package_path, inputs),
         }
     }
@@ -67,12 +85,62 @@ impl Git {
         package_path: &AnchoredSystemPathBuf,
     ) -> Result<GitHashes, Error> {
         let full_pkg_path = turbo_root.resolve(package_path);
-        let git_to_pkg_path = self.root.anchor(&full_pkg_path)?;
+        get_package_file_hashes_from_git_index(&self.root, &full_pkg_path).or_else(|e| {
+            debug!("{}, falling back to git ls-tree and git status", e);
+            self.get_package_file_hashes_from_git_commands(&full_pkg_path)
+        })
+    }
+
+    pub(crate) fn get_package_file_hashes_from_git_commands(
+        &self,
+        full_pkg_path: &AbsoluteSystemPath,
+    ) -> Result<GitHashes, Error> {
+        // packages inside of a submodule are tracked by the submodule's repository
+        if let Some(repo_root) = find_repository_root(full_pkg_path, Some(&self.root))
+            .filter(|repo_root| *repo_root != self.root)
+        {
+            return self
+                .submodule(repo_root)
+                .get_package_file_hashes_from_git_commands(full_pkg_path);
+        }
+
+        let git_to_pkg_path = self.root.anchor(full_pkg_path)?;
         let pkg_prefix = git_to_pkg_path.to_unix()?;
-        let mut hashes = self.git_ls_tree(&full_pkg_path)?;
+        let (mut hashes, mut submodules) = self.git_ls_tree(full_pkg_path)?;
         // Note: to_hash is *git repo relative*
-        let to_hash = self.append_git_status(&full_pkg_path, &pkg_prefix, &mut hashes)?;
-        hash_objects(&self.root, &full_pkg_path, to_hash, &mut hashes)?;
+        let mut to_hash = self.append_git_status(full_pkg_path, &pkg_prefix, &mut hashes)?;
+        // git status reports a submodule with changes as a single path, which we
+        // can't hash. Its files are hashed along with the committed submodules.
+        let mut changed_submodules = Vec::new();
+        to_hash.retain(|path| match self.root.join_unix_path(path) {
+            Ok(full_path) if full_path.join_component(".git").exists() => {
+                changed_submodules.push(full_path);
+                false
+            }
+            _ => true,
+        });
+        for full_path in changed_submodules {
+            submodules
+                .entry(full_pkg_path.anchor(&full_path)?.to_unix()?)
+                .or_default();
+        }
+        hash_objects(&self.root, full_pkg_path, to_hash, &mut hashes)?;
+
+        for (path, commit) in submodules {
+            let submodule_root = full_pkg_path.join_unix_path(&path)?;
+            if submodule_root.join_component(".git").exists() {
+                let submodule_hashes = self
+                    .submodule(submodule_root.clone())
+                    .get_package_file_hashes_from_git_commands(&submodule_root)?;
+                for (file, hash) in submodule_hashes {
+                    let file = RelativeUnixPathBuf::new(format!("{}/{}", path, file))?;
+                    hashes.insert(file, hash);
+                }
+            } else if submodule_root.exists() {
+                // an uninitialized submodule, all we know is the commit it's pinned to
+                hashes.insert(path, commit);
+            }
+        }
         Ok(hashes)
     }
 
@@ -157,7 +225,7 @@ impl Git {
 mod tests {
     use std::{collections::HashMap, process::Command};
 
-    use turbopath::{AbsoluteSystemPathBuf, RelativeUnixPathBuf};
+    use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, RelativeUnixPathBuf};
 
     use super::*;
     use crate::{manual::get_package_file_hashes_from_processing_gitignore, SCM};
@@ -341,6 +409,224 @@ mod tests {
         Ok(())
     }
 
+    fn create_files(root: &AbsoluteSystemPath, files: &[(&str, &str)]) {
+        for (path, contents) in files {
+            let path = root
+                .join_unix_path(RelativeUnixPathBuf::new(*path).unwrap())
+                .unwrap();
+            path.ensure_dir().unwrap();
+            path.create_with_contents(contents).unwrap();
+        }
+    }
+
+    fn expect_git(repo_root: &AbsoluteSystemPathBuf) -> Git {
+        match SCM::new(repo_root) {
+            SCM::Git(git) => git,
+            SCM::Manual => panic!("expected git for {}", repo_root),
+        }
+    }
+
+    // Hashes a package with both the in-process index reader and the git
+    // binary, checking that they agree
+    fn hash_package(git: &Git, full_pkg_path: &AbsoluteSystemPath) -> GitHashes {
+        let hashes = get_package_file_hashes_from_git_index(&git.root, full_pkg_path).unwrap();
+        let expected = git
+            .get_package_file_hashes_from_git_commands(full_pkg_path)
+            .unwrap();
+        assert_eq!(hashes, expected);
+        hashes
+    }
+
+    #[test]
+    fn test_get_package_deps_worktree() {
+        let (_tmp, tmp_root) = tmp_dir();
+        let repo_root = tmp_root.join_component("repo");
+        repo_root.create_dir_all().unwrap();
+        create_files(
+            &repo_root,
+            &[
+                ("my-pkg/package.json", "{}"),
+                ("my-pkg/committed-file", "committed bytes"),
+            ],
+        );
+        setup_repository(&repo_root);
+        commit_all(&repo_root);
+
+        let worktree_root = tmp_root.join_component("worktree");
+        require_git_cmd(&repo_root, &["worktree", "add", worktree_root.as_str()]);
+        create_files(
+            &worktree_root,
+            &[("my-pkg/uncommitted-file", "uncommitted bytes")],
+        );
+
+        let git = expect_git(&worktree_root);
+        let package_path = AnchoredSystemPathBuf::from_raw("my-pkg").unwrap();
+        let expected = to_hash_map(&[
+            ("committed-file", "3a29e62ea9ba15c4a4009d1f605d391cdd262033"),
+            (
+                "uncommitted-file",
+                "4e56ad89387e6379e4e91ddfe9872cf6a72c9976",
+            ),
+            ("package.json", "9e26dfeeb6e641a33dae4961196235bdb965b21b"),
+        ]);
+        assert_eq!(
+            hash_package(&git, &worktree_root.resolve(&package_path)),
+            expected
+        );
+        // without a git binary the worktree's index is still found
+        let hashes = SCM::Manual
+            .get_package_file_hashes::<&str>(&worktree_root, &package_path, &[])
+            .unwrap();
+        assert_eq!(hashes, expected);
+    }
+
+    #[test]
+    fn test_get_package_deps_submodule() {
+        // Directory structure:
+        // <root>/
+        //   lib/ <- the repository used as a submodule
+        //     lib-file
+        //   repo/
+        //     my-pkg/
+        //       package.json
+        //       vendor/lib/ <- submodule
+        //         lib-file
+        //         uncommitted-file <- new file not added to the submodule
+        let (_tmp, tmp_root) = tmp_dir();
+        let lib_root = tmp_root.join_component("lib");
+        lib_root.create_dir_all().unwrap();
+        create_files(&lib_root, &[("lib-file", "committed bytes")]);
+        setup_repository(&lib_root);
+        commit_all(&lib_root);
+
+        let repo_root = tmp_root.join_component("repo");
+        repo_root.create_dir_all().unwrap();
+        create_files(&repo_root, &[("my-pkg/package.json", "{}")]);
+        setup_repository(&repo_root);
+        require_git_cmd(
+            &repo_root,
+            &[
+                "-c",
+                "protocol.file.allow=always",
+                "submodule",
+                "add",
+                lib_root.as_str(),
+                "my-pkg/vendor/lib",
+            ],
+        );
+        commit_all(&repo_root);
+        let submodule_root = repo_root.join_components(&["my-pkg", "vendor", "lib"]);
+        create_files(
+            &submodule_root,
+            &[("uncommitted-file", "uncommitted bytes")],
+        );
+
+        let git = expect_git(&repo_root);
+        let hashes = hash_package(&git, &repo_root.join_component("my-pkg"));
+        assert_eq!(
+            hashes,
+            to_hash_map(&[
+                ("package.json", "9e26dfeeb6e641a33dae4961196235bdb965b21b"),
+                (
+                    "vendor/lib/lib-file",
+                    "3a29e62ea9ba15c4a4009d1f605d391cdd262033"
+                ),
+                (
+                    "vendor/lib/uncommitted-file",
+                    "4e56ad89387e6379e4e91ddfe9872cf6a72c9976"
+                ),
+            ])
+        );
+
+        // a package that is itself a submodule
+        let hashes = hash_package(&git, &submodule_root);
+        assert_eq!(
+            hashes,
+            to_hash_map(&[
+                ("lib-file", "3a29e62ea9ba15c4a4009d1f605d391cdd262033"),
+                (
+                    "uncommitted-file",
+                    "4e56ad89387e6379e4e91ddfe9872cf6a72c9976"
+                ),
+            ])
+        );
+
+        // an uninitialized submodule is hashed as the commit it's pinned to
+        let clone_root = tmp_root.join_component("clone");
+        require_git_cmd(
+            &tmp_root,
+            &["clone", repo_root.as_str(), clone_root.as_str()],
+        );
+        let pinned_commit = String::from_utf8(
+            Command::new("git")
+                .args(["rev-parse", "HEAD"])
+                .current_dir(&lib_root)
+                .output()
+                .unwrap()
+                .stdout,
+        )
+        .unwrap();
+        let git = expect_git(&clone_root);
+        let hashes = hash_package(&git, &clone_root.join_component("my-pkg"));
+        assert_eq!(
+            hashes,
+            to_hash_map(&[
+                ("package.json", "9e26dfeeb6e641a33dae4961196235bdb965b21b"),
+                ("vendor/lib", pinned_commit.trim()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_get_package_deps_sparse_checkout() {
+        let (_tmp, repo_root) = tmp_dir();
+        create_files(
+            &repo_root,
+            &[
+                ("package.json", "{}"),
+                ("apps/web/package.json", "{}"),
+                ("packages/ui/committed-file", "committed bytes"),
+            ],
+        );
+        setup_repository(&repo_root);
+        commit_all(&repo_root);
+        require_git_cmd(&repo_root, &["sparse-checkout", "set", "--cone", "apps"]);
+        assert!(!repo_root
+            .join_components(&["packages", "ui", "committed-file"])
+            .exists());
+
+        // files outside of the sparse checkout are still part of the hash
+        let expected = to_hash_map(&[
+            ("package.json", "9e26dfeeb6e641a33dae4961196235bdb965b21b"),
+            (
+                "apps/web/package.json",
+                "9e26dfeeb6e641a33dae4961196235bdb965b21b",
+            ),
+            (
+                "packages/ui/committed-file",
+                "3a29e62ea9ba15c4a4009d1f605d391cdd262033",
+            ),
+        ]);
+        let git = expect_git(&repo_root);
+        assert_eq!(hash_package(&git, &repo_root), expected);
+
+        // a sparse index collapses excluded directories into a single entry,
+        // which we can't expand without reading trees, so we use git instead
+        require_git_cmd(
+            &repo_root,
+            &["sparse-checkout", "set", "--cone", "--sparse-index", "apps"],
+        );
+        assert!(get_package_file_hashes_from_git_index(&repo_root, &repo_root).is_err());
+        let hashes = git
+            .get_package_file_hashes::<&str>(
+                &repo_root,
+                &AnchoredSystemPathBuf::from_raw("").unwrap(),
+                &[],
+            )
+            .unwrap();
+        assert_eq!(hashes, expected);
+    }
+
     fn to_hash_map(pairs: &[(&str, &str)]) -> GitHashes {
         HashMap::from_iter(
             pairs
@@ -1,22 +1,18 @@
 #!/usr/bin/env node
 
-const tsj = require("ts-json-schema-generator");
 const fs = require("fs");
 const path = require("path");
 
-/** @type {import('ts-json-schema-generator/dist/src/Config').Config} */
-const config = {
-  path: path.join(__dirname, "../index.ts"),
-  tsconfig: path.join(__dirname, "../../tsconfig.json"),
-  type: "Schema",
-  minify: true,
-};
+// schema.json is generated from the Rust types that turbo.json is parsed into,
+// run `turbo schema` or `TURBO_UPDATE_SCHEMA=1 cargo test -p turborepo-lib schema`
+// to update it.
+const schemaPath = path.join(__dirname, "../../schema.json");
 
 const outputPath = process.argv[2];
 if (!outputPath) {
   throw new Error("Missing output path");
 }
-const schema = tsj.createGenerator(config).createSchema(config.type);
+const schema = JSON.parse(fs.readFileSync(schemaPath, "utf8"));
 fs.writeFile(outputPath, JSON.stringify(schema), (err) => {
   if (err) throw err;
 });